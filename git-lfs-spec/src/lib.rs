@@ -1,6 +1,2 @@
-#[cfg(test)]
-#[macro_use]
-extern crate pretty_assertions;
-
 mod spec;
 pub use spec::*;
@@ -71,6 +71,9 @@ pub struct Progress {
 }
 
 /// https://github.com/git-lfs/git-lfs/blob/master/docs/custom-transfers.md#protocol
+///
+/// None of the event types deny unknown fields, so fields added by newer git-lfs versions are
+/// ignored.
 #[derive(PartialEq, Eq, Debug, Deserialize, Serialize, Clone)]
 #[serde(tag = "event", rename_all = "camelCase")]
 pub enum Event {
@@ -0,0 +1,297 @@
+use anyhow::{Context, Result};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::git;
+
+/// A Walrus aggregator's HTTP API, for reads the CLI can't do.
+pub struct Aggregator {
+    url: String,
+    http: reqwest::Client,
+}
+
+impl Aggregator {
+    /// Builds an aggregator client from `lfs.walrus.aggregatorurl`, if it is set.
+    /// `lfs.walrus.httpcompression` (default true) controls transport compression, and
+    /// `lfs.walrus.proxy` overrides the `HTTP_PROXY`/`HTTPS_PROXY` environment variables.
+    pub fn from_config() -> Option<Self> {
+        let url = git::config_get("lfs.walrus.aggregatorurl").ok().flatten()?;
+        let compression = git::config_bool_or("lfs.walrus.httpcompression", true);
+        let proxy = git::config_get("lfs.walrus.proxy").ok().flatten();
+        match Self::new(&url, compression, proxy.as_deref()) {
+            Ok(aggregator) => Some(aggregator),
+            Err(err) => {
+                eprintln!("Warning: could not set up the aggregator client: {}", err);
+                None
+            }
+        }
+    }
+
+    /// With `compression`, whole-blob reads offer gzip and zstd (`Accept-Encoding`) and a
+    /// compressed response is decompressed as it streams. Ranged reads are never compressed,
+    /// since the range applies to the encoded bytes.
+    ///
+    /// Without `proxy`, reqwest picks the proxy up from `HTTP_PROXY`, `HTTPS_PROXY` and
+    /// `NO_PROXY`. With it, every request goes through `proxy` (tunnelled with CONNECT for
+    /// HTTPS aggregators) except for the hosts `NO_PROXY` still exempts.
+    fn new(url: &str, compression: bool, proxy: Option<&str>) -> Result<Self> {
+        let mut http = reqwest::Client::builder().gzip(compression).zstd(compression);
+        if let Some(proxy) = proxy {
+            let proxy = reqwest::Proxy::all(proxy)
+                .with_context(|| format!("lfs.walrus.proxy {:?} is not a proxy URL", proxy))?;
+            http = http.proxy(proxy.no_proxy(reqwest::NoProxy::from_env()));
+        }
+        Ok(Self {
+            url: url.trim_end_matches('/').to_string(),
+            http: http.build()?,
+        })
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Writes the whole blob to `writer`, decompressed if the aggregator compressed it.
+    pub async fn read_blob(&self, blob_id: &str, mut writer: impl AsyncWrite + Unpin) -> Result<()> {
+        let mut response = self
+            .http
+            .get(format!("{}/v1/blobs/{}", self.url, blob_id))
+            .send()
+            .await?
+            .error_for_status()?;
+
+        while let Some(chunk) = response.chunk().await? {
+            writer.write_all(&chunk).await?;
+        }
+        writer.flush().await?;
+
+        Ok(())
+    }
+
+    /// Writes `length` bytes of the blob starting at `start` to `writer`.
+    pub async fn read_range(
+        &self,
+        blob_id: &str,
+        start: u64,
+        length: u64,
+        mut writer: impl AsyncWrite + Unpin,
+    ) -> Result<()> {
+        if length == 0 {
+            return Ok(());
+        }
+        let end = start.checked_add(length - 1).ok_or_else(|| {
+            anyhow::anyhow!("A range of {} bytes from {} ends past the largest offset", length, start)
+        })?;
+
+        let mut response = self
+            .http
+            .get(format!("{}/v1/blobs/{}", self.url, blob_id))
+            .header(reqwest::header::RANGE, format!("bytes={}-{}", start, end))
+            .send()
+            .await?
+            .error_for_status()?;
+
+        // An aggregator that ignores the Range header sends the whole blob
+        let mut range = if response.status() == reqwest::StatusCode::PARTIAL_CONTENT {
+            ByteRange::new(0, length)
+        } else {
+            ByteRange::new(start, length)
+        };
+
+        while let Some(chunk) = response.chunk().await? {
+            writer.write_all(range.take(&chunk)).await?;
+            if range.is_done() {
+                break;
+            }
+        }
+        writer.flush().await?;
+
+        if !range.is_done() {
+            return Err(anyhow::anyhow!(
+                "Blob {} ended {} bytes short of the requested range",
+                blob_id,
+                range.remaining
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Tracks which part of a streamed body falls inside the requested range.
+struct ByteRange {
+    skip: u64,
+    remaining: u64,
+}
+
+impl ByteRange {
+    fn new(skip: u64, remaining: u64) -> Self {
+        Self { skip, remaining }
+    }
+
+    fn take<'a>(&mut self, chunk: &'a [u8]) -> &'a [u8] {
+        let skipped = self.skip.min(chunk.len() as u64) as usize;
+        self.skip -= skipped as u64;
+        let chunk = &chunk[skipped..];
+        let taken = self.remaining.min(chunk.len() as u64) as usize;
+        self.remaining -= taken as u64;
+        &chunk[..taken]
+    }
+
+    fn is_done(&self) -> bool {
+        self.remaining == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+
+    /// Answers one HTTP request with `response`, returning the request's headers.
+    async fn serve_once(response: Vec<u8>) -> (String, tokio::task::JoinHandle<String>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            while !request.ends_with(b"\r\n\r\n") {
+                let mut byte = [0; 1];
+                socket.read_exact(&mut byte).await.unwrap();
+                request.push(byte[0]);
+            }
+            socket.write_all(&response).await.unwrap();
+            socket.shutdown().await.unwrap();
+            String::from_utf8(request).unwrap().to_ascii_lowercase()
+        });
+        (url, server)
+    }
+
+    fn http_response(headers: &str, body: &[u8]) -> Vec<u8> {
+        let mut response = format!(
+            "HTTP/1.1 200 OK\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n",
+            headers,
+            body.len()
+        )
+        .into_bytes();
+        response.extend_from_slice(body);
+        response
+    }
+
+    #[tokio::test]
+    async fn compressed_blobs_are_decompressed_on_read() {
+        let content = b"blob content that travels gzip-compressed ".repeat(50);
+        let mut gzip = std::process::Command::new("gzip")
+            .arg("-c")
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        std::io::Write::write_all(gzip.stdin.as_mut().unwrap(), &content).unwrap();
+        let compressed = gzip.wait_with_output().unwrap().stdout;
+        assert!(compressed.len() < content.len());
+
+        let (url, server) =
+            serve_once(http_response("Content-Encoding: gzip\r\n", &compressed)).await;
+        let mut output = Vec::new();
+        Aggregator::new(&url, true, None)
+            .unwrap()
+            .read_blob("blob-1", &mut output)
+            .await
+            .unwrap();
+
+        assert_eq!(output, content);
+        let request = server.await.unwrap();
+        assert!(request.starts_with("get /v1/blobs/blob-1 "), "{}", request);
+        let accepted = request
+            .lines()
+            .find_map(|line| line.strip_prefix("accept-encoding: "))
+            .unwrap_or_else(|| panic!("{}", request));
+        assert!(accepted.contains("gzip") && accepted.contains("zstd"), "{}", request);
+    }
+
+    #[tokio::test]
+    async fn compression_can_be_turned_off() {
+        let (url, server) = serve_once(http_response("", b"plain")).await;
+        let mut output = Vec::new();
+        Aggregator::new(&url, false, None)
+            .unwrap()
+            .read_blob("blob-1", &mut output)
+            .await
+            .unwrap();
+
+        assert_eq!(output, b"plain");
+        let request = server.await.unwrap();
+        assert!(!request.contains("accept-encoding"), "{}", request);
+    }
+
+    #[tokio::test]
+    async fn reads_go_through_the_configured_proxy() {
+        let (proxy, server) = serve_once(http_response("", b"proxied")).await;
+        let mut output = Vec::new();
+        Aggregator::new("http://aggregator.invalid", false, Some(&proxy))
+            .unwrap()
+            .read_blob("blob-1", &mut output)
+            .await
+            .unwrap();
+
+        assert_eq!(output, b"proxied");
+        let request = server.await.unwrap();
+        assert!(
+            request.starts_with("get http://aggregator.invalid/v1/blobs/blob-1 "),
+            "{}",
+            request
+        );
+    }
+
+    #[tokio::test]
+    async fn https_aggregators_are_tunnelled_with_connect() {
+        let refused = b"HTTP/1.1 403 Forbidden\r\nContent-Length: 0\r\n\r\n".to_vec();
+        let (proxy, server) = serve_once(refused).await;
+        let result = Aggregator::new("https://aggregator.invalid", false, Some(&proxy))
+            .unwrap()
+            .read_blob("blob-1", Vec::new())
+            .await;
+
+        assert!(result.is_err());
+        let request = server.await.unwrap();
+        assert!(request.starts_with("connect aggregator.invalid:443 "), "{}", request);
+    }
+
+    #[test]
+    fn an_invalid_proxy_is_an_error() {
+        let err = Aggregator::new("http://aggregator.invalid", false, Some("not a url"))
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("lfs.walrus.proxy"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn ranges_past_the_end_are_errors() {
+        let aggregator = Aggregator::new("http://aggregator.invalid", false, None).unwrap();
+        let err = aggregator
+            .read_range("blob-1", u64::MAX, 2, Vec::new())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("ends past the largest offset"), "{}", err);
+
+        // The body runs out 7 bytes into a 10-byte range
+        let (url, _server) = serve_once(http_response("", b"hello")).await;
+        let mut output = Vec::new();
+        let err = Aggregator::new(&url, false, None)
+            .unwrap()
+            .read_range("blob-1", 2, 10, &mut output)
+            .await
+            .unwrap_err();
+        assert_eq!(output, b"llo");
+        assert_eq!(err.to_string(), "Blob blob-1 ended 7 bytes short of the requested range");
+    }
+
+    #[test]
+    fn byte_range_slices_across_chunks() {
+        let mut range = ByteRange::new(3, 4);
+        assert_eq!(range.take(b"ab"), b"");
+        assert_eq!(range.take(b"cdef"), b"def");
+        assert_eq!(range.take(b"ghij"), b"g");
+        assert!(range.is_done());
+        assert_eq!(range.take(b"klm"), b"");
+    }
+}
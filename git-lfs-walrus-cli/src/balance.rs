@@ -0,0 +1,220 @@
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use std::path::PathBuf;
+use tokio::process::Command;
+
+use crate::git;
+use crate::mapping::load_mappings;
+use crate::pointer::is_pointer;
+use crate::walrus::WalrusClient;
+
+/// Headroom over the estimated cost the wallet should have, for price changes during a run.
+pub const DEFAULT_MARGIN_PERCENT: u64 = 10;
+
+/// 1 WAL is 10^9 FROST.
+const FROST_PER_WAL: f64 = 1_000_000_000.0;
+
+/// Whether the wallet can cover storing a set of files.
+#[derive(Debug, PartialEq)]
+pub struct BalanceReport {
+    /// WAL balance of the active address, in FROST
+    pub balance: u64,
+    /// Sum of the dry-run storage costs of the files that would be uploaded, in FROST
+    pub estimated_cost: u64,
+    /// Estimated cost plus the margin, in FROST
+    pub required: u64,
+    /// Files that would be uploaded; pointers and content already in the mapping are skipped
+    pub files: usize,
+}
+
+impl BalanceReport {
+    pub fn sufficient(&self) -> bool {
+        self.balance >= self.required
+    }
+
+    fn print(&self, margin_percent: u64) {
+        println!("  Files to upload: {}", self.files);
+        println!("  Estimated cost: {}", format_wal(self.estimated_cost));
+        println!(
+            "  Required with {}% margin: {}",
+            margin_percent,
+            format_wal(self.required)
+        );
+        println!("  Wallet balance: {}", format_wal(self.balance));
+    }
+}
+
+/// Reports whether the wallet's WAL balance covers storing `files` (default: all LFS files)
+/// with `margin_percent` to spare, failing if it doesn't.
+pub async fn balance_check(
+    client: WalrusClient,
+    files: Vec<PathBuf>,
+    margin_percent: u64,
+) -> Result<()> {
+    let files = if files.is_empty() {
+        git::lfs_files()?
+    } else {
+        files
+    };
+
+    let report = check_balance(&client, &files, margin_percent).await?;
+    println!("\nSummary:");
+    report.print(margin_percent);
+    if !report.sufficient() {
+        println!("❌ Insufficient balance");
+        return Err(anyhow::anyhow!(
+            "wallet is short by {}",
+            format_wal(report.required - report.balance)
+        ));
+    }
+    println!("✅ Balance is sufficient");
+    Ok(())
+}
+
+/// Preflight for bulk uploads: fails before anything is stored if the wallet can't cover
+/// `files` with the default margin.
+pub async fn require_balance(client: &WalrusClient, files: &[PathBuf]) -> Result<()> {
+    let report = check_balance(client, files, DEFAULT_MARGIN_PERCENT).await?;
+    if report.sufficient() {
+        return Ok(());
+    }
+    report.print(DEFAULT_MARGIN_PERCENT);
+    Err(anyhow::anyhow!(
+        "Insufficient WAL balance: {} needed with a {}% margin, {} available; nothing was uploaded",
+        format_wal(report.required),
+        DEFAULT_MARGIN_PERCENT,
+        format_wal(report.balance)
+    ))
+}
+
+async fn check_balance(
+    client: &WalrusClient,
+    files: &[PathBuf],
+    margin_percent: u64,
+) -> Result<BalanceReport> {
+    let mappings = load_mappings().await?;
+    let mut estimated_cost = 0;
+    let mut uploads = 0;
+    for file in files {
+        let data = tokio::fs::read(file)
+            .await
+            .with_context(|| format!("Could not read {}", file.display()))?;
+        if is_pointer(&data) || mappings.contains_key(&hex::encode(Sha256::digest(&data))) {
+            continue;
+        }
+        estimated_cost += client
+            .estimate_store_cost(&data)
+            .await
+            .with_context(|| format!("Could not estimate the cost of {}", file.display()))?;
+        uploads += 1;
+    }
+
+    Ok(BalanceReport {
+        balance: wal_balance(client).await?,
+        estimated_cost,
+        required: with_margin(estimated_cost, margin_percent),
+        files: uploads,
+    })
+}
+
+fn with_margin(cost: u64, margin_percent: u64) -> u64 {
+    cost.saturating_add(cost.saturating_mul(margin_percent).div_ceil(100))
+}
+
+/// Queries the active address's WAL balance with `sui client balance` (`lfs.walrus.suipath`
+/// names the sui binary, default `sui`), with the `lfs.walrus.env.*` variables set.
+async fn wal_balance(client: &WalrusClient) -> Result<u64> {
+    let sui = git::config_get("lfs.walrus.suipath")
+        .ok()
+        .flatten()
+        .unwrap_or_else(|| "sui".to_string());
+    let mut cmd = Command::new(&sui);
+    client.apply_env(&mut cmd);
+    let output = cmd
+        .args(["client", "balance", "--json", "--with-coins"])
+        .output()
+        .await
+        .with_context(|| {
+            format!(
+                "Could not run `{}` to query the wallet balance (set lfs.walrus.suipath)",
+                sui
+            )
+        })?;
+    if !output.status.success() {
+        return Err(anyhow::anyhow!(
+            "`{} client balance` failed: {}",
+            sui,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    let balances: serde_json::Value = serde_json::from_slice(&output.stdout)
+        .context("Could not parse the output of `sui client balance --json`")?;
+    Ok(sum_wal_coins(&balances))
+}
+
+/// Sums the `balance` of every WAL coin object, wherever it appears in the sui output.
+fn sum_wal_coins(value: &serde_json::Value) -> u64 {
+    match value {
+        serde_json::Value::Array(items) => items.iter().map(sum_wal_coins).sum(),
+        serde_json::Value::Object(fields) => {
+            let is_wal = fields
+                .get("coinType")
+                .and_then(serde_json::Value::as_str)
+                .is_some_and(|coin_type| coin_type.ends_with("::wal::WAL"));
+            if is_wal {
+                return match fields.get("balance") {
+                    Some(serde_json::Value::String(balance)) => balance.parse().unwrap_or(0),
+                    Some(balance) => balance.as_u64().unwrap_or(0),
+                    None => 0,
+                };
+            }
+            fields.values().map(sum_wal_coins).sum()
+        }
+        _ => 0,
+    }
+}
+
+pub fn format_wal(frost: u64) -> String {
+    format!("{:.4} WAL ({} FROST)", frost as f64 / FROST_PER_WAL, frost)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wal_coins_are_summed_across_the_sui_output() {
+        let output = serde_json::json!([
+            [
+                [
+                    {"decimals": 9, "symbol": "WAL"},
+                    [
+                        {"coinType": "0x8270::wal::WAL", "coinObjectId": "0x1", "balance": "1500000000"},
+                        {"coinType": "0x8270::wal::WAL", "coinObjectId": "0x2", "balance": "500000000"}
+                    ]
+                ],
+                [
+                    {"decimals": 9, "symbol": "SUI"},
+                    [{"coinType": "0x2::sui::SUI", "coinObjectId": "0x3", "balance": "9000"}]
+                ]
+            ],
+            false
+        ]);
+        assert_eq!(sum_wal_coins(&output), 2_000_000_000);
+    }
+
+    #[test]
+    fn margin_is_added_and_rounded_up() {
+        assert_eq!(with_margin(1000, 10), 1100);
+        assert_eq!(with_margin(1001, 10), 1102);
+        assert_eq!(with_margin(0, 10), 0);
+        let report = BalanceReport {
+            balance: 1100,
+            estimated_cost: 1000,
+            required: with_margin(1000, 10),
+            files: 1,
+        };
+        assert!(report.sufficient());
+    }
+}
@@ -0,0 +1,168 @@
+use anyhow::Result;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::metrics::percentile;
+use crate::migrate::format_bytes;
+use crate::pending;
+use crate::walrus::WalrusClient;
+
+/// Stores and reads back `iterations` blobs of `size_mb` random MiB, reporting latency and
+/// throughput in each direction. Benchmark blobs are deleted afterwards where walrus allows.
+pub async fn bench(client: WalrusClient, size_mb: u64, iterations: u64) -> Result<()> {
+    if size_mb == 0 || iterations == 0 {
+        return Err(anyhow::anyhow!("size and iterations must both be at least 1"));
+    }
+    let size = size_mb * 1024 * 1024;
+    let temp_dir = client.scratch_dir()?;
+    let mut stores = Vec::new();
+    let mut reads = Vec::new();
+    let mut blob_ids = Vec::new();
+
+    for iteration in 0..iterations {
+        // Fresh content every time, so walrus can't answer with an already certified blob
+        let data = random_bytes(size as usize, seed(iteration));
+
+        let started = Instant::now();
+        let outcome = client.store_bytes(&data, None).await;
+        let store_time = started.elapsed();
+        let outcome = match outcome {
+            Ok(outcome) => outcome,
+            Err(e) => {
+                cleanup(&client, &blob_ids).await;
+                return Err(e);
+            }
+        };
+        blob_ids.push(outcome.blob_id.clone());
+
+        let read_path = temp_dir.path().join(format!("read-{}", iteration));
+        let started = Instant::now();
+        let read = client.read_blob(&outcome.blob_id, &read_path).await;
+        let read_time = started.elapsed();
+        if let Err(e) = read {
+            cleanup(&client, &blob_ids).await;
+            return Err(e);
+        }
+        if tokio::fs::read(&read_path).await? != data {
+            cleanup(&client, &blob_ids).await;
+            return Err(anyhow::anyhow!(
+                "blob {} read back different content",
+                outcome.blob_id
+            ));
+        }
+        tokio::fs::remove_file(&read_path).await?;
+
+        println!(
+            "⏱️ Iteration {}: store {:.2}s, read {:.2}s",
+            iteration + 1,
+            store_time.as_secs_f64(),
+            read_time.as_secs_f64()
+        );
+        stores.push(store_time);
+        reads.push(read_time);
+    }
+
+    cleanup(&client, &blob_ids).await;
+
+    println!("\nSummary ({} x {}):", iterations, format_bytes(size));
+    print_direction("Store", &stores, size);
+    print_direction("Read", &reads, size);
+
+    Ok(())
+}
+
+fn print_direction(name: &str, durations: &[Duration], size: u64) {
+    let stats = DirectionStats::new(durations, size);
+    println!(
+        "  {}: median {:.2}s ({:.2} MiB/s), p95 {:.2}s ({:.2} MiB/s)",
+        name, stats.median_secs, stats.median_mib_per_sec, stats.p95_secs, stats.p95_mib_per_sec
+    );
+}
+
+#[derive(Debug, PartialEq)]
+struct DirectionStats {
+    median_secs: f64,
+    p95_secs: f64,
+    median_mib_per_sec: f64,
+    /// Throughput at the p95 latency, i.e. the slow end
+    p95_mib_per_sec: f64,
+}
+
+impl DirectionStats {
+    fn new(durations: &[Duration], size: u64) -> Self {
+        let mut secs: Vec<f64> = durations.iter().map(Duration::as_secs_f64).collect();
+        secs.sort_by(f64::total_cmp);
+        let median_secs = percentile(&secs, 50);
+        let p95_secs = percentile(&secs, 95);
+        let mib = size as f64 / (1024.0 * 1024.0);
+        let throughput = |secs: f64| if secs > 0.0 { mib / secs } else { 0.0 };
+        Self {
+            median_secs,
+            p95_secs,
+            median_mib_per_sec: throughput(median_secs),
+            p95_mib_per_sec: throughput(p95_secs),
+        }
+    }
+}
+
+/// Deletes the benchmark blobs, warning about any walrus won't delete (e.g. when they weren't
+/// stored as deletable).
+async fn cleanup(client: &WalrusClient, blob_ids: &[String]) {
+    for blob_id in blob_ids {
+        match client.delete_blob(blob_id).await {
+            Ok(()) => pending::resolve(&[blob_id]),
+            Err(e) => eprintln!(
+                "Warning: could not delete benchmark blob {}: {}",
+                blob_id, e
+            ),
+        }
+    }
+}
+
+fn seed(iteration: u64) -> u64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos() as u64)
+        .unwrap_or(0);
+    // xorshift gets stuck at zero
+    (nanos ^ iteration.wrapping_mul(0x9E37_79B9_7F4A_7C15)) | 1
+}
+
+/// Fills `len` bytes from a xorshift64 generator; incompressible enough for a benchmark.
+fn random_bytes(len: usize, mut state: u64) -> Vec<u8> {
+    let mut data = Vec::with_capacity(len + 8);
+    while data.len() < len {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        data.extend_from_slice(&state.to_le_bytes());
+    }
+    data.truncate(len);
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stats_report_median_and_p95_throughput() {
+        let durations: Vec<Duration> = (1..=20).map(Duration::from_secs).collect();
+        let stats = DirectionStats::new(&durations, 20 * 1024 * 1024);
+        assert_eq!(
+            stats,
+            DirectionStats {
+                median_secs: 10.0,
+                p95_secs: 19.0,
+                median_mib_per_sec: 2.0,
+                p95_mib_per_sec: 20.0 / 19.0,
+            }
+        );
+    }
+
+    #[test]
+    fn random_bytes_differ_by_seed() {
+        let a = random_bytes(1000, 1);
+        assert_eq!(a.len(), 1000);
+        assert_ne!(a, random_bytes(1000, 3));
+    }
+}
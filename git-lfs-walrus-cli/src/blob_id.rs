@@ -0,0 +1,52 @@
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+
+/// Walrus blob IDs are 32 bytes.
+const BLOB_ID_LEN: usize = 32;
+
+/// Canonicalizes a blob ID to the unpadded URL-safe base64 that walrus prints and accepts.
+///
+/// Some walrus versions and hand-edited mapping files carry the same ID in standard base64
+/// (`+`, `/` and `=` padding), which walrus then fails to find. Anything that doesn't decode to
+/// a 32-byte ID in either alphabet, such as a chunk manifest, is returned unchanged.
+pub fn normalize(blob_id: &str) -> String {
+    let translated: String = blob_id
+        .trim_end_matches('=')
+        .chars()
+        .map(|c| match c {
+            '+' => '-',
+            '/' => '_',
+            c => c,
+        })
+        .collect();
+    match URL_SAFE_NO_PAD.decode(&translated) {
+        Ok(bytes) if bytes.len() == BLOB_ID_LEN => translated,
+        _ => blob_id.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URL_SAFE: &str = "M4hsZGQ1oCktdzegB6HnI6Mi28S2nqOPHxK-W7_4BUk";
+
+    #[test]
+    fn standard_base64_ids_are_translated_to_url_safe() {
+        assert_eq!(normalize("M4hsZGQ1oCktdzegB6HnI6Mi28S2nqOPHxK+W7/4BUk="), URL_SAFE);
+        assert_eq!(normalize("M4hsZGQ1oCktdzegB6HnI6Mi28S2nqOPHxK+W7/4BUk"), URL_SAFE);
+        assert_eq!(normalize(&format!("{}=", URL_SAFE)), URL_SAFE);
+    }
+
+    #[test]
+    fn canonical_and_foreign_ids_are_unchanged() {
+        assert_eq!(normalize(URL_SAFE), URL_SAFE);
+        for blob_id in [
+            "fake-b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9",
+            "chunked:M4hsZGQ1oCktdzegB6HnI6Mi28S2nqOPHxK-W7_4BUk:10",
+            "blob/with+symbols=",
+        ] {
+            assert_eq!(normalize(blob_id), blob_id);
+        }
+    }
+}
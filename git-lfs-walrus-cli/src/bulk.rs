@@ -0,0 +1,85 @@
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+use crate::walrus::WalrusError;
+
+/// Per-file errors of a bulk command. By default a session-fatal error (see
+/// [`WalrusError::is_fatal`]) stops the run, since every remaining file would fail the same way;
+/// with `--keep-going` every file is attempted and the command fails at the end instead.
+pub struct BulkErrors {
+    keep_going: bool,
+    errors: Vec<(PathBuf, String)>,
+    stopped_by: Option<String>,
+}
+
+impl BulkErrors {
+    pub fn new(keep_going: bool) -> Self {
+        Self {
+            keep_going,
+            errors: Vec::new(),
+            stopped_by: None,
+        }
+    }
+
+    /// Records the error for `file`, returning true if the run should stop here.
+    pub fn record(&mut self, file: &Path, err: &anyhow::Error) -> bool {
+        self.errors.push((file.to_path_buf(), err.to_string()));
+        let fatal = err
+            .downcast_ref::<WalrusError>()
+            .is_some_and(WalrusError::is_fatal);
+        if fatal && !self.keep_going {
+            eprintln!("Stopping: the remaining files would fail the same way (pass --keep-going to attempt them anyway)");
+            self.stopped_by = Some(err.to_string());
+            return true;
+        }
+        false
+    }
+
+    /// Fails if the run was stopped early, or if `--keep-going` collected any errors; those
+    /// are listed again so they aren't lost in the per-file output.
+    pub fn finish(self) -> Result<()> {
+        if let Some(reason) = self.stopped_by {
+            return Err(anyhow::anyhow!("Stopped after a fatal error: {}", reason));
+        }
+        if !self.keep_going || self.errors.is_empty() {
+            return Ok(());
+        }
+
+        eprintln!("\nFailed files:");
+        for (file, err) in &self.errors {
+            eprintln!("  {}: {}", file.display(), err);
+        }
+        Err(anyhow::anyhow!("{} files failed", self.errors.len()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fatal() -> anyhow::Error {
+        WalrusError::InsufficientGas {
+            command: "store",
+            stderr: "gas budget exceeded".to_string(),
+        }
+        .into()
+    }
+
+    #[test]
+    fn fatal_errors_stop_the_run_unless_keeping_going() {
+        let mut errors = BulkErrors::new(false);
+        assert!(!errors.record(Path::new("a.bin"), &anyhow::anyhow!("not found")));
+        assert!(errors.record(Path::new("b.bin"), &fatal()));
+        assert!(errors.finish().is_err());
+
+        let mut errors = BulkErrors::new(false);
+        assert!(!errors.record(Path::new("a.bin"), &anyhow::anyhow!("not found")));
+        assert!(errors.finish().is_ok());
+
+        let mut errors = BulkErrors::new(true);
+        assert!(!errors.record(Path::new("a.bin"), &fatal()));
+        assert!(!errors.record(Path::new("b.bin"), &fatal()));
+        let err = errors.finish().unwrap_err();
+        assert_eq!(err.to_string(), "2 files failed");
+    }
+}
@@ -0,0 +1,167 @@
+use anyhow::Result;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use crate::duration::{format_duration, StorageDuration};
+use crate::prefetch::cache_dir;
+
+/// One file in the local cache. Its modification time is bumped whenever smudge reads it, so
+/// it records when the entry was last used.
+#[derive(Debug)]
+struct CacheEntry {
+    path: PathBuf,
+    size: u64,
+    last_used: SystemTime,
+}
+
+/// Evicts entries from the local cache (`.git/walrus-cache`): those unused for longer than
+/// `max_age`, then the least recently used until the cache fits in `max_size` bytes, or every
+/// entry with `all`.
+pub async fn cache_clean(
+    max_age: Option<StorageDuration>,
+    max_size: Option<u64>,
+    all: bool,
+) -> Result<()> {
+    if max_age.is_none() && max_size.is_none() && !all {
+        return Err(anyhow::anyhow!(
+            "nothing to evict: pass --max-age, --max-size or --all"
+        ));
+    }
+
+    let mut entries = cache_entries()?;
+    entries.sort_by_key(|entry| entry.last_used);
+    let now = SystemTime::now();
+    let evict = if all {
+        entries.len()
+    } else {
+        evictions(&entries, now, max_age.map(|age| age.duration()), max_size)
+    };
+
+    let mut evicted = 0;
+    let mut reclaimed = 0;
+    let mut failed = 0;
+    for entry in &entries[..evict] {
+        let name = entry.path.file_name().unwrap_or_default().to_string_lossy();
+        match std::fs::remove_file(&entry.path) {
+            Ok(()) => {
+                let age = now.duration_since(entry.last_used).unwrap_or_default();
+                println!(
+                    "✅ Evicted {} ({} bytes, last used {} ago)",
+                    name,
+                    entry.size,
+                    format_duration(Duration::from_secs(age.as_secs()))
+                );
+                evicted += 1;
+                reclaimed += entry.size;
+            }
+            // Removed by another cache-clean in the meantime
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => {
+                println!("❌ {}: {}", name, e);
+                failed += 1;
+            }
+        }
+    }
+
+    let total: u64 = entries.iter().map(|entry| entry.size).sum();
+    println!("\nSummary:");
+    println!("  Evicted: {}", evicted);
+    println!("  Bytes reclaimed: {}", reclaimed);
+    println!(
+        "  Remaining: {} ({} bytes)",
+        entries.len() - evicted,
+        total - reclaimed
+    );
+    if failed > 0 {
+        println!("  Errors: {}", failed);
+        return Err(anyhow::anyhow!("could not evict {} cache entries", failed));
+    }
+    Ok(())
+}
+
+/// Every complete entry in the cache. Downloads still in progress (`.<oid>...part`) are left
+/// to the prefetch writing them.
+fn cache_entries() -> Result<Vec<CacheEntry>> {
+    let dir = cache_dir()?;
+    let read_dir = match std::fs::read_dir(&dir) {
+        Ok(read_dir) => read_dir,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut entries = Vec::new();
+    for dir_entry in read_dir {
+        let dir_entry = dir_entry?;
+        if dir_entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let metadata = dir_entry.metadata()?;
+        if !metadata.is_file() {
+            continue;
+        }
+        entries.push(CacheEntry {
+            path: dir_entry.path(),
+            size: metadata.len(),
+            last_used: metadata.modified()?,
+        });
+    }
+    Ok(entries)
+}
+
+/// How many of `entries` (least recently used first) to evict: all those unused for longer
+/// than `max_age`, then more until the rest add up to at most `max_size` bytes.
+fn evictions(
+    entries: &[CacheEntry],
+    now: SystemTime,
+    max_age: Option<Duration>,
+    max_size: Option<u64>,
+) -> usize {
+    let mut evict = match max_age {
+        Some(max_age) => entries
+            .iter()
+            .take_while(|entry| {
+                now.duration_since(entry.last_used)
+                    .is_ok_and(|age| age > max_age)
+            })
+            .count(),
+        None => 0,
+    };
+    if let Some(max_size) = max_size {
+        let mut remaining: u64 = entries[evict..].iter().map(|entry| entry.size).sum();
+        while remaining > max_size {
+            remaining -= entries[evict].size;
+            evict += 1;
+        }
+    }
+    evict
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: Duration = Duration::from_secs(60 * 60);
+
+    fn entry(size: u64, hours_ago: u32, now: SystemTime) -> CacheEntry {
+        CacheEntry {
+            path: PathBuf::new(),
+            size,
+            last_used: now - HOUR * hours_ago,
+        }
+    }
+
+    #[test]
+    fn old_entries_go_first_then_the_least_recently_used_until_the_cache_fits() {
+        let now = SystemTime::now();
+        let entries = [entry(10, 48, now), entry(20, 30, now), entry(30, 5, now), entry(40, 1, now)];
+
+        assert_eq!(evictions(&entries, now, None, None), 0);
+        assert_eq!(evictions(&entries, now, Some(HOUR * 24), None), 2);
+        assert_eq!(evictions(&entries, now, None, Some(70)), 2);
+        assert_eq!(evictions(&entries, now, None, Some(71)), 2);
+        assert_eq!(evictions(&entries, now, None, Some(69)), 3);
+        assert_eq!(evictions(&entries, now, Some(HOUR * 36), Some(90)), 1);
+        assert_eq!(evictions(&entries, now, Some(HOUR * 36), Some(80)), 2);
+        assert_eq!(evictions(&entries, now, None, Some(0)), 4);
+    }
+}
@@ -0,0 +1,90 @@
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+use crate::check_worktree::{file_state, FileState};
+use crate::git;
+use crate::pointer::{extract_content_type, extract_oid, is_pointer};
+use crate::resolve::resolve_blob_id;
+use crate::walrus::WalrusClient;
+
+/// Writes the Walrus content of an LFS-tracked file to `output` (or stdout). With
+/// `content_type`, prints the MIME type recorded in the pointer instead.
+///
+/// Unless `force` is set, an existing `output` is only overwritten if it is empty, still a
+/// pointer, or already holds the pointer's content, so local edits aren't clobbered.
+pub async fn cat(
+    client: WalrusClient,
+    file: PathBuf,
+    output: Option<PathBuf>,
+    content_type: bool,
+    force: bool,
+) -> Result<()> {
+    let pointer = load_pointer(&file).await?;
+
+    if content_type {
+        let mime = extract_content_type(&pointer).ok_or_else(|| {
+            anyhow::anyhow!(
+                "{} has no recorded content type (enable lfs.walrus.contenttype before adding it)",
+                file.display()
+            )
+        })?;
+        println!("{}", mime);
+        return Ok(());
+    }
+
+    let blob_id = resolve_blob_id(client.resolve_order(), &pointer).await?;
+
+    match output {
+        Some(path) => {
+            if !force {
+                check_overwrite(&path, &extract_oid(&pointer)?.hash)?;
+            }
+            let file = tokio::fs::File::create(&path).await?;
+            write_blob(&client, &blob_id, file).await
+        }
+        None => write_blob(&client, &blob_id, tokio::io::stdout()).await,
+    }
+}
+
+/// Refuses to replace `path` when it holds content other than the object `oid`, e.g. local
+/// modifications that would be lost.
+fn check_overwrite(path: &Path, oid: &str) -> Result<()> {
+    if std::fs::metadata(path).is_ok_and(|metadata| metadata.len() == 0) {
+        return Ok(());
+    }
+    match file_state(path, oid)? {
+        FileState::Matches | FileState::NotCheckedOut => Ok(()),
+        FileState::Differs(actual) => Err(anyhow::anyhow!(
+            "refusing to overwrite {}: its content (hash {}) differs from the pointer's OID {}, \
+             so it may hold local changes; pass --force to overwrite it",
+            path.display(),
+            actual,
+            oid
+        )),
+    }
+}
+
+async fn write_blob(
+    client: &WalrusClient,
+    blob_id: &str,
+    mut writer: impl AsyncWrite + Unpin,
+) -> Result<()> {
+    client.read_blob_to_writer(blob_id, &mut writer).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+/// Reads the pointer for `file`: the working-tree copy if it is still a pointer, otherwise the
+/// committed one.
+async fn load_pointer(file: &Path) -> Result<String> {
+    let working_copy = tokio::fs::read(file).await.ok();
+    let pointer = match working_copy {
+        Some(content) if is_pointer(&content) => String::from_utf8(content)?,
+        _ => git::show_head(file)?.ok_or_else(|| {
+            anyhow::anyhow!("{} is not an LFS pointer in HEAD", file.display())
+        })?,
+    };
+
+    Ok(pointer)
+}
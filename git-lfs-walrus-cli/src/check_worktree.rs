@@ -0,0 +1,109 @@
+use anyhow::Result;
+use std::io::Read;
+use std::path::Path;
+
+use crate::dedup_report::parse_pointer_lines;
+use crate::git;
+use crate::oid::HashAlgorithm;
+use crate::pointer::is_pointer;
+
+/// Pointers are well under this size, so larger files are never mistaken for one.
+const MAX_POINTER_SIZE: u64 = 1024;
+
+/// What a working-tree file holds compared to its staged pointer.
+pub enum FileState {
+    /// Content matching the pointer's OID
+    Matches,
+    /// Still the pointer text, or not checked out at all
+    NotCheckedOut,
+    /// Content hashing to something else
+    Differs(String),
+}
+
+/// Re-hashes every checked-out LFS file and compares it to the OID of its staged pointer
+/// (the committed one unless the file has been re-added). A file that differs was changed
+/// without going through the clean filter, e.g. because the filter wasn't installed.
+pub async fn check_worktree() -> Result<()> {
+    let pointers = parse_pointer_lines(&git::index_pointer_lines()?);
+
+    let mut matching = 0;
+    let mut not_checked_out = 0;
+    let mut differing = 0;
+    let mut errors = 0;
+    for (path, fields) in &pointers {
+        let Some(oid) = &fields.oid else {
+            continue; // Not a pointer
+        };
+        match file_state(path, oid) {
+            Ok(FileState::Matches) => {
+                println!("✅ {}", path.display());
+                matching += 1;
+            }
+            Ok(FileState::NotCheckedOut) => not_checked_out += 1,
+            Ok(FileState::Differs(actual)) => {
+                println!(
+                    "❌ {}: content hashes to {}, but its pointer has {} (was it changed without the clean filter?)",
+                    path.display(),
+                    actual,
+                    oid
+                );
+                differing += 1;
+            }
+            Err(e) => {
+                println!("⚠️  {}: {}", path.display(), e);
+                errors += 1;
+            }
+        }
+    }
+
+    println!("\nSummary:");
+    println!("  Matching: {}", matching);
+    println!("  Not checked out: {}", not_checked_out);
+    println!("  Differing: {}", differing);
+    if errors > 0 {
+        println!("  Errors: {}", errors);
+    }
+    if differing > 0 || errors > 0 {
+        return Err(anyhow::anyhow!(
+            "{} files differ from their pointers and {} could not be checked",
+            differing,
+            errors
+        ));
+    }
+    Ok(())
+}
+
+/// Compares the content of `path` with the hex digest `oid`, without reading a file that is
+/// still a pointer past its first block.
+pub fn file_state(path: &Path, oid: &str) -> Result<FileState> {
+    let mut file = match std::fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(FileState::NotCheckedOut),
+        Err(e) => return Err(e.into()),
+    };
+    let size = file.metadata()?.len();
+    let algorithm = HashAlgorithm::for_hash(oid)
+        .ok_or_else(|| anyhow::anyhow!("unrecognized OID {}", oid))?;
+
+    let mut hasher = algorithm.hasher();
+    let mut buffer = vec![0; 64 * 1024];
+    let mut first = true;
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        if first && size <= MAX_POINTER_SIZE && is_pointer(&buffer[..read]) {
+            return Ok(FileState::NotCheckedOut);
+        }
+        first = false;
+        hasher.update(&buffer[..read]);
+    }
+
+    let actual = hasher.finalize_hex();
+    Ok(if actual == oid {
+        FileState::Matches
+    } else {
+        FileState::Differs(actual)
+    })
+}
@@ -0,0 +1,174 @@
+use anyhow::Result;
+use std::fmt;
+
+/// Prefix of a blob ID that names an ordered list of chunk blobs instead of a single blob.
+const MANIFEST_PREFIX: &str = "chunked:";
+
+/// Prefix of a blob ID naming a blob that holds the manifest, for manifests too long to inline.
+const MANIFEST_BLOB_PREFIX: &str = "chunked-blob:";
+
+/// Longest manifest written inline, keeping pointers well under git-lfs' 1024-byte limit.
+/// Each chunk takes about 120 bytes, so this allows a handful of chunks.
+pub const MAX_INLINE_MANIFEST_LEN: usize = 512;
+
+/// Content larger than this is stored in chunks unless `lfs.walrus.maxblobsize` says otherwise.
+/// Walrus' own limit depends on the network's shard count; this stays well below mainnet's.
+pub const DEFAULT_MAX_BLOB_SIZE: u64 = 10 * 1024 * 1024 * 1024;
+
+/// One blob holding a consecutive slice of chunked content.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chunk {
+    pub blob_id: String,
+    pub size: u64,
+    /// SHA256 of the chunk's content; absent from manifests written before it was recorded
+    pub sha256: Option<String>,
+}
+
+/// Content stored as several blobs because it exceeds the maximum blob size.
+///
+/// The manifest goes wherever a blob ID does (the pointer's `ext-0-walrus` line, the mapping
+/// file), written as `chunked:<blob-id>:<size>:<sha256>,<blob-id>:<size>:<sha256>,...`. Walrus
+/// blob IDs are URL-safe base64, so they never contain the separators. A manifest longer than
+/// [`MAX_INLINE_MANIFEST_LEN`] is stored as a blob of its own, and `chunked-blob:<blob-id>`
+/// goes in its place.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkManifest {
+    pub chunks: Vec<Chunk>,
+}
+
+impl ChunkManifest {
+    /// Parses a blob ID that is a manifest. Returns `None` for a plain blob ID.
+    pub fn parse(blob_id: &str) -> Option<Result<Self>> {
+        let list = blob_id.strip_prefix(MANIFEST_PREFIX)?;
+        let chunks = list
+            .split(',')
+            .map(|entry| {
+                let mut fields = entry.split(':');
+                let blob_id = fields.next().filter(|blob_id| !blob_id.is_empty())?;
+                let size = fields.next()?.parse().ok()?;
+                let sha256 = match fields.next() {
+                    Some(hash) if is_sha256_hex(hash) => Some(hash.to_string()),
+                    Some(_) => return None,
+                    None => None,
+                };
+                fields.next().is_none().then(|| Chunk {
+                    blob_id: blob_id.to_string(),
+                    size,
+                    sha256,
+                })
+            })
+            .collect::<Option<Vec<_>>>()
+            .filter(|chunks| !chunks.is_empty())
+            .ok_or_else(|| anyhow::anyhow!("Malformed chunk manifest {:?}", blob_id));
+        Some(chunks.map(|chunks| Self { chunks }))
+    }
+
+    /// Total size of the content, in bytes.
+    pub fn size(&self) -> u64 {
+        self.chunks.iter().map(|chunk| chunk.size).sum()
+    }
+}
+
+impl fmt::Display for ChunkManifest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(MANIFEST_PREFIX)?;
+        for (i, chunk) in self.chunks.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "{}:{}", chunk.blob_id, chunk.size)?;
+            if let Some(sha256) = &chunk.sha256 {
+                write!(f, ":{}", sha256)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The blob holding the manifest, if `blob_id` is a `chunked-blob:` reference.
+pub fn manifest_blob_id(blob_id: &str) -> Option<&str> {
+    blob_id.strip_prefix(MANIFEST_BLOB_PREFIX)
+}
+
+/// The blob ID standing for a manifest stored in blob `manifest_blob_id`.
+pub fn manifest_reference(manifest_blob_id: &str) -> String {
+    format!("{}{}", MANIFEST_BLOB_PREFIX, manifest_blob_id)
+}
+
+fn is_sha256_hex(hash: &str) -> bool {
+    hash.len() == 64 && hash.bytes().all(|byte| byte.is_ascii_hexdigit())
+}
+
+/// Sizes of the chunks `len` bytes are split into, none larger than `max_size`.
+pub fn chunk_sizes(len: u64, max_size: u64) -> Vec<u64> {
+    let max_size = max_size.max(1);
+    let mut sizes = vec![max_size; (len / max_size) as usize];
+    if !len.is_multiple_of(max_size) {
+        sizes.push(len % max_size);
+    }
+    sizes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHA256: &str = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+
+    #[test]
+    fn manifests_round_trip_through_their_blob_id() {
+        let manifest = ChunkManifest {
+            chunks: vec![
+                Chunk {
+                    blob_id: "M4hsZGQ1oCktdzegB6HnI6Mi28S2nqOPHxK-W7_4BUk".to_string(),
+                    size: 10,
+                    sha256: Some(SHA256.to_string()),
+                },
+                Chunk {
+                    blob_id: "fake-abc".to_string(),
+                    size: 3,
+                    sha256: Some(SHA256.to_string()),
+                },
+            ],
+        };
+        let blob_id = manifest.to_string();
+        assert_eq!(
+            blob_id,
+            format!(
+                "chunked:M4hsZGQ1oCktdzegB6HnI6Mi28S2nqOPHxK-W7_4BUk:10:{SHA256},fake-abc:3:{SHA256}"
+            )
+        );
+        assert_eq!(ChunkManifest::parse(&blob_id).unwrap().unwrap(), manifest);
+        assert_eq!(manifest.size(), 13);
+
+        assert!(ChunkManifest::parse("M4hsZGQ1oCktdzegB6HnI6Mi28S2nqOPHxK-W7_4BUk").is_none());
+        assert!(ChunkManifest::parse("chunked:").unwrap().is_err());
+        assert!(ChunkManifest::parse("chunked:a:1,b").unwrap().is_err());
+        assert!(ChunkManifest::parse("chunked:a:1:not-a-hash").unwrap().is_err());
+        assert!(ChunkManifest::parse(&format!("chunked:a:1:{SHA256}:x")).unwrap().is_err());
+    }
+
+    #[test]
+    fn manifests_without_chunk_hashes_still_parse() {
+        let manifest = ChunkManifest::parse("chunked:a:1,b:2").unwrap().unwrap();
+        assert_eq!(manifest.chunks[1].blob_id, "b");
+        assert_eq!(manifest.chunks[1].sha256, None);
+        assert_eq!(manifest.to_string(), "chunked:a:1,b:2");
+    }
+
+    #[test]
+    fn long_manifests_are_referenced_by_blob() {
+        let reference = manifest_reference("fake-manifest");
+        assert_eq!(reference, "chunked-blob:fake-manifest");
+        assert_eq!(manifest_blob_id(&reference), Some("fake-manifest"));
+        assert!(ChunkManifest::parse(&reference).is_none());
+        assert_eq!(manifest_blob_id("chunked:a:1"), None);
+    }
+
+    #[test]
+    fn content_is_split_into_chunks_of_at_most_the_maximum() {
+        assert_eq!(chunk_sizes(25, 10), [10, 10, 5]);
+        assert_eq!(chunk_sizes(20, 10), [10, 10]);
+        assert_eq!(chunk_sizes(0, 10), Vec::<u64>::new());
+    }
+}
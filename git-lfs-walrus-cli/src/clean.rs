@@ -1,21 +1,95 @@
 use std::io::Read;
 use std::path::Path;
 
-use anyhow::Result;
-use serde_json::Value;
+use anyhow::{Context, Result};
 use sha2::{Digest, Sha256};
 use tokio::io::{AsyncWrite, AsyncWriteExt};
 
+use crate::content_type;
+use crate::git;
+use crate::mapping::{store_blob_mapping, MappingEntry};
+use crate::pending;
+use crate::pointer::{
+    canonical_pointer, is_pointer, PointerEol, CONTENT_TYPE_EXTENSION, END_EPOCH_EXTENSION,
+    MODE_EXTENSION,
+};
 use crate::walrus::WalrusClient;
 
+/// What `clean` stored, for `--emit-pointer` to report.
+#[derive(Debug)]
+pub struct CleanOutcome {
+    pub blob_id: String,
+    /// Dry-run storage cost estimate, in FROST
+    pub storage_cost: u64,
+}
+
+/// Behaviour toggles for one clean.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CleanOptions {
+    /// Fail if walrus reports the content as already certified
+    pub fail_on_already_certified: bool,
+    /// Only write the pointer once the blob is certified
+    pub wait_certified: bool,
+    /// Store a symlink's target content instead of refusing it
+    pub dereference_symlinks: bool,
+    /// Only warn when the mapping file can't be written, even with lfs.walrus.requiremapping
+    pub continue_on_mapping_error: bool,
+}
+
 pub async fn clean(
     client: WalrusClient,
     mut input: impl Read + Send + Sync + Unpin + 'static,
-    mut output: impl AsyncWrite + AsyncWriteExt + Unpin,
-) -> Result<()> {
-    // Read all input data
+    mut output: impl AsyncWrite + Unpin,
+    filename: &Path,
+    options: CleanOptions,
+) -> Result<CleanOutcome> {
+    let CleanOptions {
+        fail_on_already_certified,
+        wait_certified,
+        dereference_symlinks,
+        continue_on_mapping_error,
+    } = options;
+    let require_mapping = !continue_on_mapping_error && git::config_flag("lfs.walrus.requiremapping");
+    let eol = PointerEol::from_config()?;
+
+    // Read all input data, but no more than the limit needs to see
+    let max_bytes = max_clean_bytes();
     let mut data = Vec::new();
-    input.read_to_end(&mut data)?;
+    match max_bytes {
+        Some(max_bytes) => (&mut input).take(max_bytes + 1).read_to_end(&mut data)?,
+        None => input.read_to_end(&mut data)?,
+    };
+
+    // What git feeds us for a symlink differs between platforms, so only the target's content
+    // is ever stored, and only when asked for
+    if is_symlink(filename) {
+        if !(dereference_symlinks || git::config_flag("lfs.walrus.dereferencesymlinks")) {
+            return Err(anyhow::anyhow!(
+                "refusing to clean {}: it is a symlink (pass --dereference-symlinks or set lfs.walrus.dereferencesymlinks to store its target's content)",
+                filename.display()
+            ));
+        }
+        data = std::fs::read(filename).with_context(|| {
+            format!("Could not read the target of symlink {}", filename.display())
+        })?;
+    }
+
+    if let Some(max_bytes) = max_bytes
+        && data.len() as u64 > max_bytes
+    {
+        return Err(anyhow::anyhow!(
+            "refusing to clean {}: it is larger than lfs.walrus.maxcleanbytes ({} bytes); check that it should be tracked, or set LFS_WALRUS_ALLOW_LARGE_CLEAN=1 to store it anyway",
+            filename.display(),
+            max_bytes
+        ));
+    }
+
+    // Cleaning a pointer would store the pointer text as the file's content
+    if is_pointer(&data) {
+        return Err(anyhow::anyhow!(
+            "refusing to clean an existing LFS pointer — check your filter configuration"
+        ));
+    }
 
     // Calculate SHA256 hash for the original file
     let mut hasher = Sha256::new();
@@ -24,104 +98,173 @@ pub async fn clean(
     let sha256_hex = hex::encode(hash);
 
     // Perform a dry run to get the estimated cost
-    let dry_run_output = client.store_bytes_dry_run(&data).await?;
-    let json_output: Value = serde_json::from_str(&dry_run_output)?;
-    let _total_cost = if let Some(array) = json_output.as_array() {
-        if let Some(first_item) = array.first() {
-            first_item["storageCost"].as_u64().unwrap_or(0).to_string()
-        } else {
-            "0".to_string()
-        }
-    } else {
-        "0".to_string()
-    };
-
-    
+    let storage_cost = client.estimate_store_cost(&data).await?;
 
     // Store the data in Walrus
-    let blob_id = client.store_bytes(&data).await?;
+    let outcome = client.store_bytes(&data, None).await?;
+    if fail_on_already_certified {
+        outcome.require_newly_created()?;
+    }
+    if wait_certified {
+        client.wait_until_certified(&outcome.blob_id).await?;
+    }
+    let blob_id = outcome.blob_id.clone();
+    let end_epoch = outcome.end_epoch;
+    let entry = MappingEntry::new(outcome.blob_id, outcome.tx_digest, outcome.epochs);
 
     // Store the mapping between SHA256 and Walrus blob ID
-    if let Err(e) = store_blob_mapping(&sha256_hex, &blob_id).await {
-        eprintln!("Warning: Could not store blob mapping: {}", e);
-    }
+    mapping_written(
+        store_blob_mapping(&sha256_hex, &entry).await,
+        "blob mapping",
+        require_mapping,
+    )?;
 
     // Create LFS pointer with Walrus blob ID stored in extension field
-    let lfs_pointer = format!(
-        "version https://git-lfs.github.com/spec/v1\noid sha256:{}\nsize {}\next-0-walrus {}\n",
-        sha256_hex,
-        data.len(),
-        blob_id
-    );
-
-    // Also store mapping with LFS pointer SHA256 (for git lookup)
+    let mut fields = vec![
+        format!("oid sha256:{}", sha256_hex),
+        format!("size {}", data.len()),
+        format!("ext-0-walrus {}", blob_id),
+    ];
+    if git::config_flag("lfs.walrus.preservemode")
+        && let Some(mode) = file_mode(filename)
+    {
+        fields.push(format!("{} {:04o}", MODE_EXTENSION, mode));
+    }
+    if git::config_flag("lfs.walrus.contenttype")
+        && let Some(mime) = content_type::detect(filename, &data)
+    {
+        fields.push(format!("{} {}", CONTENT_TYPE_EXTENSION, mime));
+    }
+    if git::config_flag("lfs.walrus.recordendepoch")
+        && let Some(end_epoch) = end_epoch
+    {
+        fields.push(format!("{} {}", END_EPOCH_EXTENSION, end_epoch));
+    }
+    let canonical = canonical_pointer(fields);
+    let lfs_pointer = eol.apply(&canonical);
+
+    // Also store mapping with LFS pointer SHA256 (for git lookup), over the pointer as git
+    // stores it: CRLF is turned back into LF for text paths
+    let stored_pointer = if eol == PointerEol::Crlf && git::normalizes_line_endings(filename)? {
+        &canonical
+    } else {
+        &lfs_pointer
+    };
     let mut pointer_hasher = Sha256::new();
-    pointer_hasher.update(lfs_pointer.as_bytes());
+    pointer_hasher.update(stored_pointer.as_bytes());
     let pointer_hash = pointer_hasher.finalize();
     let pointer_sha256_hex = hex::encode(pointer_hash);
     
-    if let Err(e) = store_blob_mapping(&pointer_sha256_hex, &blob_id).await {
-        eprintln!("Warning: Could not store pointer mapping: {}", e);
-    }
+    mapping_written(
+        store_blob_mapping(&pointer_sha256_hex, &entry).await,
+        "pointer mapping",
+        require_mapping,
+    )?;
 
     output.write_all(lfs_pointer.as_bytes()).await?;
+    // tokio's stdout hands writes to a background thread; flush before the runtime shuts down
+    output.flush().await?;
+    // The pointer refers to the blob even if its mapping couldn't be written
+    pending::resolve(&[&blob_id]);
+
+    Ok(CleanOutcome {
+        blob_id,
+        storage_cost,
+    })
+}
+
+/// Runs `clean` on `filename` itself rather than stdin and writes the pointer to
+/// `pointer_path`, reporting the blob ID and estimated cost on stderr.
+pub async fn clean_to_file(
+    client: WalrusClient,
+    filename: &Path,
+    pointer_path: &Path,
+    options: CleanOptions,
+) -> Result<()> {
+    let input = std::fs::File::open(filename)
+        .with_context(|| format!("Could not open {}", filename.display()))?;
+    let output = tokio::fs::File::create(pointer_path)
+        .await
+        .with_context(|| format!("Could not create {}", pointer_path.display()))?;
+    let outcome = clean(client, input, output, filename, options).await?;
 
+    eprintln!("Pointer written to {}", pointer_path.display());
+    eprintln!("Blob ID: {}", outcome.blob_id);
+    eprintln!("Estimated cost: {} FROST", outcome.storage_cost);
     Ok(())
 }
 
-async fn store_blob_mapping(sha256_hex: &str, blob_id: &str) -> Result<()> {
-    let mapping_file = get_mapping_file_path()?;
-    
-    // Read existing mappings
-    let mut mappings = if mapping_file.exists() {
-        let content = tokio::fs::read_to_string(&mapping_file).await?;
-        serde_json::from_str(&content).unwrap_or_else(|_| serde_json::Map::new())
-    } else {
-        serde_json::Map::new()
-    };
-    
-    // Add new mapping
-    mappings.insert(sha256_hex.to_string(), serde_json::Value::String(blob_id.to_string()));
-    
-    // Write back to file
-    let content = serde_json::to_string_pretty(&mappings)?;
-    if let Some(parent) = mapping_file.parent() {
-        tokio::fs::create_dir_all(parent).await?;
+/// The pointer carries the blob ID inline, so a mapping file that can't be written is only
+/// worth a warning, unless `require_mapping` (`lfs.walrus.requiremapping`) makes it fatal.
+fn mapping_written(result: Result<()>, what: &str, require_mapping: bool) -> Result<()> {
+    match result {
+        Ok(()) => Ok(()),
+        Err(e) if require_mapping => Err(e.context(format!(
+            "Could not store {} (lfs.walrus.requiremapping is set; pass \
+             --continue-on-mapping-error to only warn)",
+            what
+        ))),
+        Err(e) => {
+            eprintln!("Warning: Could not store {}: {}", what, e);
+            Ok(())
+        }
     }
-    tokio::fs::write(&mapping_file, content).await?;
-    
-    Ok(())
 }
 
-fn get_mapping_file_path() -> Result<std::path::PathBuf> {
-    // Try to find git root directory
-    let output = std::process::Command::new("git")
-        .args(&["rev-parse", "--git-dir"])
-        .output()?;
-    
-    if output.status.success() {
-        let git_dir = String::from_utf8(output.stdout)?.trim().to_string();
-        Ok(Path::new(&git_dir).join("walrus-mapping.json"))
-    } else {
-        // Fallback to current directory
-        Ok(std::env::current_dir()?.join(".walrus-mapping.json"))
+/// `lfs.walrus.maxcleanbytes`, a guard against storing something enormous by mistake. Unset
+/// or 0 means no limit, and `LFS_WALRUS_ALLOW_LARGE_CLEAN` lifts it for one run.
+fn max_clean_bytes() -> Option<u64> {
+    if std::env::var_os("LFS_WALRUS_ALLOW_LARGE_CLEAN").is_some_and(|value| !value.is_empty()) {
+        return None;
+    }
+    let value = git::config_get("lfs.walrus.maxcleanbytes").ok().flatten()?;
+    match value.trim().parse() {
+        Ok(0) => None,
+        Ok(max_bytes) => Some(max_bytes),
+        Err(_) => {
+            eprintln!(
+                "Warning: lfs.walrus.maxcleanbytes {:?} is not a number of bytes, ignoring it",
+                value
+            );
+            None
+        }
     }
 }
 
-pub async fn get_blob_id_from_sha(sha256_hex: &str) -> Result<Option<String>> {
-    let mapping_file = get_mapping_file_path()?;
-    
-    if !mapping_file.exists() {
-        return Ok(None);
+/// The tag to label stored blobs with: `--tag`, or with `lfs.walrus.tagfilenames` the name of
+/// the file being cleaned.
+pub fn blob_tag(tag: Option<String>, filename: &Path) -> Option<String> {
+    tag.or_else(|| {
+        git::config_flag("lfs.walrus.tagfilenames").then(|| filename.to_string_lossy().into_owned())
+    })
+}
+
+fn is_symlink(filename: &Path) -> bool {
+    std::fs::symlink_metadata(filename).is_ok_and(|metadata| metadata.file_type().is_symlink())
+}
+
+/// Permission bits of the working-tree file git is cleaning, if it can be read.
+#[cfg(unix)]
+fn file_mode(filename: &Path) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+
+    match std::fs::metadata(filename) {
+        Ok(metadata) => Some(metadata.permissions().mode() & 0o7777),
+        Err(err) => {
+            eprintln!(
+                "Warning: could not read the mode of {}: {}",
+                filename.display(),
+                err
+            );
+            None
+        }
     }
-    
-    let content = tokio::fs::read_to_string(&mapping_file).await?;
-    let mappings: serde_json::Map<String, serde_json::Value> = 
-        serde_json::from_str(&content).unwrap_or_default();
-    
-    Ok(mappings.get(sha256_hex)
-        .and_then(|v| v.as_str())
-        .map(|s| s.to_string()))
+}
+
+/// Only the executable bit exists outside Unix, and git already tracks that.
+#[cfg(not(unix))]
+fn file_mode(_filename: &Path) -> Option<u32> {
+    None
 }
 
 #[cfg(test)]
@@ -132,17 +275,49 @@ mod tests {
 
     const FILE: &[u8] = b"hello world";
 
+    #[tokio::test]
+    async fn clean_refuses_an_lfs_pointer() {
+        let pointer = b"version https://git-lfs.github.com/spec/v1
+oid sha256:b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9
+size 11
+";
+        let mut cursor = Cursor::new(vec![]);
+        let err = clean(client(), &pointer[..], &mut cursor, Path::new("hello.txt"), CleanOptions::default())
+            .await
+            .unwrap_err();
+
+        assert!(err.to_string().contains("refusing to clean an existing LFS pointer"));
+        assert!(cursor.into_inner().is_empty());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn clean_refuses_a_symlink_by_default() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("target.bin"), FILE).unwrap();
+        let link = dir.path().join("link.bin");
+        std::os::unix::fs::symlink("target.bin", &link).unwrap();
+
+        let mut cursor = Cursor::new(vec![]);
+        let err = clean(client(), &b"target.bin"[..], &mut cursor, &link, CleanOptions::default())
+            .await
+            .unwrap_err();
+
+        assert!(err.to_string().contains("is a symlink"), "{}", err);
+        assert!(cursor.into_inner().is_empty());
+    }
+
     #[tokio::test]
     #[ignore] // Requires Walrus to be installed and configured
     async fn clean_converts_file_into_lfs_pointer() {
         let client = client();
         let mut cursor = Cursor::new(vec![]);
-        clean(client, FILE, &mut cursor).await.unwrap();
+        clean(client, FILE, &mut cursor, Path::new("hello.txt"), CleanOptions::default()).await.unwrap();
 
         let result = String::from_utf8(cursor.into_inner()).unwrap();
         assert!(result.contains("version https://git-lfs.github.com/spec/v1"));
         assert!(result.contains("oid sha256:"));
         assert!(result.contains("size 11"));
-        assert!(result.contains("# walrus-blob-id:"));
+        assert!(result.contains("ext-0-walrus "));
     }
 }
@@ -0,0 +1,241 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+use crate::git;
+use crate::mapping::{self, get_mapping_file_path};
+use crate::prefetch;
+use crate::walrus::WalrusClient;
+use crate::walrus_check::status_timeout;
+
+/// Global options as given to this run, which take precedence over git config.
+pub struct CommandLine {
+    pub walrus_path: Option<PathBuf>,
+    pub duration: bool,
+    pub gas_coin: bool,
+    pub verify_after_store: bool,
+    pub network: bool,
+    pub metrics: Option<PathBuf>,
+    pub trace_walrus: Option<PathBuf>,
+}
+
+/// One resolved setting and where its value came from.
+#[derive(Debug, PartialEq, Serialize)]
+struct Setting {
+    key: String,
+    value: String,
+    source: String,
+}
+
+/// Prints every setting in effect, with its source: the command line, an environment
+/// variable, a git config scope, or the built-in default.
+pub async fn show_config(client: &WalrusClient, command_line: CommandLine, json: bool) -> Result<()> {
+    let settings = resolve_settings(client, &command_line)?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&settings)?);
+        return Ok(());
+    }
+
+    let width = settings.iter().map(|setting| setting.key.len()).max().unwrap_or(0);
+    for setting in &settings {
+        println!(
+            "{:width$} = {}  ({})",
+            setting.key,
+            setting.value,
+            setting.source,
+            width = width
+        );
+    }
+    Ok(())
+}
+
+fn resolve_settings(client: &WalrusClient, command_line: &CommandLine) -> Result<Vec<Setting>> {
+    let mut settings = vec![Setting {
+        key: "walrus path".to_string(),
+        value: command_line
+            .walrus_path
+            .as_ref()
+            .map_or("walrus (from PATH)".to_string(), |path| path.display().to_string()),
+        source: match &command_line.walrus_path {
+            Some(path) => flag_source("--walrus-path", "WALRUS_CLI_PATH", path),
+            None => "default".to_string(),
+        },
+    }];
+
+    for (key, value) in client.settings() {
+        let source = match key {
+            "lfs.walrus.offline" if std::env::var_os("LFS_WALRUS_OFFLINE").is_some() => {
+                "environment (LFS_WALRUS_OFFLINE)".to_string()
+            }
+            "lfs.walrus.defaultepochs" | "lfs.walrus.defaultduration" if command_line.duration => {
+                "command line (--duration)".to_string()
+            }
+            "lfs.walrus.config" if value != "(not set)" && git::config_scope(key)?.is_none() => {
+                "discovered (standard location)".to_string()
+            }
+            "lfs.walrus.env" if value != "(not set)" => "git config (lfs.walrus.env.*)".to_string(),
+            "lfs.walrus.gascoin" if command_line.gas_coin => {
+                if std::env::var_os("LFS_WALRUS_GAS_COIN").is_some() {
+                    "environment (LFS_WALRUS_GAS_COIN)".to_string()
+                } else {
+                    "command line (--gas-coin)".to_string()
+                }
+            }
+            "lfs.walrus.verifyafterstore" if command_line.verify_after_store => {
+                "command line (--verify-after-store)".to_string()
+            }
+            _ => git_source(key)?,
+        };
+        settings.push(Setting {
+            key: key.to_string(),
+            value,
+            source,
+        });
+    }
+
+    let flags = [
+        "lfs.walrus.prefetch",
+        "lfs.walrus.preservemode",
+        "lfs.walrus.contenttype",
+        "lfs.walrus.recordendepoch",
+        "lfs.walrus.dereferencesymlinks",
+        "lfs.walrus.refreshlog",
+        "lfs.walrus.tagfilenames",
+        "lfs.walrus.requiremapping",
+    ];
+    for key in flags {
+        settings.push(Setting {
+            key: key.to_string(),
+            value: git::config_get_bool(key)?.unwrap_or(false).to_string(),
+            source: git_source(key)?,
+        });
+    }
+    settings.push(Setting {
+        key: "lfs.walrus.verifysmudge".to_string(),
+        value: git::config_get_bool("lfs.walrus.verifysmudge")?
+            .unwrap_or(true)
+            .to_string(),
+        source: git_source("lfs.walrus.verifysmudge")?,
+    });
+    settings.push(Setting {
+        key: "lfs.walrus.httpcompression".to_string(),
+        value: git::config_get_bool("lfs.walrus.httpcompression")?
+            .unwrap_or(true)
+            .to_string(),
+        source: git_source("lfs.walrus.httpcompression")?,
+    });
+    settings.push(Setting {
+        key: "lfs.walrus.proxy".to_string(),
+        value: git::config_get("lfs.walrus.proxy")?
+            .unwrap_or_else(|| "(HTTP_PROXY/HTTPS_PROXY)".to_string()),
+        source: git_source("lfs.walrus.proxy")?,
+    });
+    settings.push(Setting {
+        key: "lfs.walrus.pointereol".to_string(),
+        value: git::config_get("lfs.walrus.pointereol")?.unwrap_or_else(|| "lf".to_string()),
+        source: git_source("lfs.walrus.pointereol")?,
+    });
+    settings.push(Setting {
+        key: "lfs.walrus.maxcleanbytes".to_string(),
+        value: git::config_get("lfs.walrus.maxcleanbytes")?
+            .unwrap_or_else(|| "(no limit)".to_string()),
+        source: git_source("lfs.walrus.maxcleanbytes")?,
+    });
+    settings.push(Setting {
+        key: "lfs.walrus.statustimeoutsecs".to_string(),
+        value: status_timeout().as_secs().to_string(),
+        source: git_source("lfs.walrus.statustimeoutsecs")?,
+    });
+    settings.push(Setting {
+        key: "lfs.walrus.checkcachettl".to_string(),
+        value: git::config_get("lfs.walrus.checkcachettl")?
+            .unwrap_or_else(|| "(not set, no caching)".to_string()),
+        source: git_source("lfs.walrus.checkcachettl")?,
+    });
+    settings.push(Setting {
+        key: "lfs.walrus.suipath".to_string(),
+        value: git::config_get("lfs.walrus.suipath")?.unwrap_or_else(|| "sui".to_string()),
+        source: git_source("lfs.walrus.suipath")?,
+    });
+
+    settings.push(env_setting("LFS_WALRUS_EVENTLOG"));
+    settings.push(env_setting("LFS_WALRUS_ERRORLOG"));
+    settings.push(env_setting("LFS_WALRUS_ALLOW_LARGE_CLEAN"));
+    settings.push(Setting {
+        key: "metrics file".to_string(),
+        value: command_line
+            .metrics
+            .as_ref()
+            .map_or("(not set)".to_string(), |path| path.display().to_string()),
+        source: match &command_line.metrics {
+            Some(path) => flag_source("--metrics", "LFS_WALRUS_METRICS", path),
+            None => "default".to_string(),
+        },
+    });
+    settings.push(Setting {
+        key: "walrus trace dir".to_string(),
+        value: command_line
+            .trace_walrus
+            .as_ref()
+            .map_or("(not set)".to_string(), |path| path.display().to_string()),
+        source: match &command_line.trace_walrus {
+            Some(path) => flag_source("--trace-walrus", "LFS_WALRUS_TRACE", path),
+            None => "default".to_string(),
+        },
+    });
+
+    settings.push(Setting {
+        key: "cache dir".to_string(),
+        value: prefetch::cache_dir()?.display().to_string(),
+        source: "derived from the git directory".to_string(),
+    });
+    settings.push(Setting {
+        key: "lfs.walrus.network".to_string(),
+        value: mapping::network()?.unwrap_or_else(|| "(default scope)".to_string()),
+        source: if command_line.network {
+            "command line (--network)".to_string()
+        } else {
+            git_source("lfs.walrus.network")?
+        },
+    });
+    settings.push(Setting {
+        key: "mapping file".to_string(),
+        value: get_mapping_file_path()?.display().to_string(),
+        source: "derived from the git directory".to_string(),
+    });
+
+    Ok(settings)
+}
+
+fn git_source(key: &str) -> Result<String> {
+    Ok(match git::config_scope(key)? {
+        Some(scope) if scope == git::LFSCONFIG_SCOPE => scope,
+        Some(scope) => format!("git config ({})", scope),
+        None => "default".to_string(),
+    })
+}
+
+/// Where an option that structopt also reads from the environment came from.
+fn flag_source(flag: &str, env: &str, value: &Path) -> String {
+    if std::env::var_os(env).is_some_and(|env_value| Path::new(&env_value) == value) {
+        format!("environment ({})", env)
+    } else {
+        format!("command line ({})", flag)
+    }
+}
+
+fn env_setting(name: &str) -> Setting {
+    match std::env::var(name) {
+        Ok(value) => Setting {
+            key: name.to_string(),
+            value,
+            source: format!("environment ({})", name),
+        },
+        Err(_) => Setting {
+            key: name.to_string(),
+            value: "(not set)".to_string(),
+            source: "default".to_string(),
+        },
+    }
+}
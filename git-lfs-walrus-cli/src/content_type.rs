@@ -0,0 +1,89 @@
+use std::path::Path;
+
+/// MIME types by lowercase file extension, for the formats most often kept in LFS.
+const BY_EXTENSION: &[(&str, &str)] = &[
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("svg", "image/svg+xml"),
+    ("ico", "image/vnd.microsoft.icon"),
+    ("avif", "image/avif"),
+    ("mp4", "video/mp4"),
+    ("webm", "video/webm"),
+    ("mov", "video/quicktime"),
+    ("mp3", "audio/mpeg"),
+    ("wav", "audio/wav"),
+    ("ogg", "audio/ogg"),
+    ("flac", "audio/flac"),
+    ("pdf", "application/pdf"),
+    ("zip", "application/zip"),
+    ("gz", "application/gzip"),
+    ("tar", "application/x-tar"),
+    ("wasm", "application/wasm"),
+    ("json", "application/json"),
+    ("csv", "text/csv"),
+    ("txt", "text/plain"),
+    ("html", "text/html"),
+    ("css", "text/css"),
+    ("js", "text/javascript"),
+    ("woff", "font/woff"),
+    ("woff2", "font/woff2"),
+    ("ttf", "font/ttf"),
+    ("glb", "model/gltf-binary"),
+    ("gltf", "model/gltf+json"),
+];
+
+/// Leading bytes of formats whose files often lack a telling extension.
+const BY_MAGIC: &[(&[u8], &str)] = &[
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+    (b"%PDF-", "application/pdf"),
+    (b"PK\x03\x04", "application/zip"),
+    (b"\x1f\x8b", "application/gzip"),
+    (b"\0asm", "application/wasm"),
+];
+
+/// Guesses the MIME type of a file from its extension, falling back to its leading bytes.
+pub fn detect(filename: &Path, data: &[u8]) -> Option<&'static str> {
+    let by_extension = filename
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .and_then(|extension| {
+            let extension = extension.to_ascii_lowercase();
+            BY_EXTENSION
+                .iter()
+                .find(|(known, _)| *known == extension)
+                .map(|(_, mime)| *mime)
+        });
+
+    by_extension.or_else(|| {
+        BY_MAGIC
+            .iter()
+            .find(|(magic, _)| data.starts_with(magic))
+            .map(|(_, mime)| *mime)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn known_extensions_are_detected() {
+        assert_eq!(detect(Path::new("assets/logo.PNG"), b""), Some("image/png"));
+        assert_eq!(detect(Path::new("docs/manual.pdf"), b""), Some("application/pdf"));
+        assert_eq!(detect(Path::new("model.glb"), b""), Some("model/gltf-binary"));
+        assert_eq!(detect(Path::new("fonts/body.woff2"), b""), Some("font/woff2"));
+    }
+
+    #[test]
+    fn content_is_sniffed_when_the_extension_is_unknown() {
+        assert_eq!(detect(Path::new("photo.bin"), b"\xff\xd8\xff\xe0rest"), Some("image/jpeg"));
+        assert_eq!(detect(Path::new("archive"), b"PK\x03\x04rest"), Some("application/zip"));
+        assert_eq!(detect(Path::new("data.bin"), b"no magic here"), None);
+    }
+}
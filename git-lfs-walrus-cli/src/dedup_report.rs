@@ -0,0 +1,174 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use crate::git;
+use crate::mapping::{load_mappings, Mappings};
+use crate::migrate::format_bytes;
+use crate::oid::Oid;
+
+/// The pointer fields of one staged file.
+#[derive(Debug, Default)]
+pub struct PointerFields {
+    pub oid: Option<String>,
+    pub size: Option<u64>,
+    pub blob_id: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct SharedBlob {
+    blob_id: String,
+    size: u64,
+    files: Vec<PathBuf>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct DedupReport {
+    logical_files: usize,
+    unique_blobs: usize,
+    logical_bytes: u64,
+    stored_bytes: u64,
+    saved_bytes: u64,
+    /// Blobs backing more than one file
+    shared_blobs: Vec<SharedBlob>,
+}
+
+/// Reports how many staged LFS files share a Walrus blob, and the bytes that saves.
+pub async fn dedup_report(json: bool) -> Result<()> {
+    let pointers = parse_pointer_lines(&git::index_pointer_lines()?);
+    let report = build_report(pointers, &load_mappings().await?);
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    for shared in &report.shared_blobs {
+        println!(
+            "{} ({}) is shared by {} files:",
+            shared.blob_id,
+            format_bytes(shared.size),
+            shared.files.len()
+        );
+        for file in &shared.files {
+            println!("  {}", file.display());
+        }
+    }
+
+    println!("\nSummary:");
+    println!(
+        "  Files: {} backed by {} blobs",
+        report.logical_files, report.unique_blobs
+    );
+    println!("  Logical size: {}", format_bytes(report.logical_bytes));
+    println!("  Stored size: {}", format_bytes(report.stored_bytes));
+    println!("  Saved by dedup: {}", format_bytes(report.saved_bytes));
+
+    Ok(())
+}
+
+/// Parses `git grep --cached --null` output into pointer fields per path.
+pub fn parse_pointer_lines(output: &str) -> BTreeMap<PathBuf, PointerFields> {
+    let mut pointers: BTreeMap<PathBuf, PointerFields> = BTreeMap::new();
+
+    for record in output.lines() {
+        let Some((path, line)) = record.split_once('\0') else {
+            continue;
+        };
+        let fields = pointers.entry(PathBuf::from(path)).or_default();
+        if let Some(Ok(oid)) = Oid::parse_line(line) {
+            fields.oid = Some(oid.hash);
+        } else if let Some(size) = line.strip_prefix("size ") {
+            fields.size = size.trim().parse().ok();
+        } else if let Some(blob_id) = line.strip_prefix("ext-0-walrus ") {
+            fields.blob_id = Some(blob_id.trim().to_string());
+        }
+    }
+
+    pointers
+}
+
+/// Groups files by the blob backing them: the inline blob ID, else the mapping entry for the
+/// OID, else the OID itself (identical content is stored once either way).
+fn build_report(pointers: BTreeMap<PathBuf, PointerFields>, mappings: &Mappings) -> DedupReport {
+    let mut blobs: BTreeMap<String, SharedBlob> = BTreeMap::new();
+
+    for (path, fields) in pointers {
+        let (Some(oid), Some(size)) = (fields.oid, fields.size) else {
+            continue; // Not a pointer
+        };
+        let blob_id = fields
+            .blob_id
+            .or_else(|| mappings.get(&oid).map(|entry| entry.blob_id.clone()))
+            .unwrap_or(oid);
+
+        blobs
+            .entry(blob_id.clone())
+            .or_insert_with(|| SharedBlob {
+                blob_id,
+                size,
+                files: Vec::new(),
+            })
+            .files
+            .push(path);
+    }
+
+    let logical_files = blobs.values().map(|blob| blob.files.len()).sum();
+    let logical_bytes = blobs
+        .values()
+        .map(|blob| blob.size * blob.files.len() as u64)
+        .sum();
+    let stored_bytes = blobs.values().map(|blob| blob.size).sum();
+
+    DedupReport {
+        logical_files,
+        unique_blobs: blobs.len(),
+        logical_bytes,
+        stored_bytes,
+        saved_bytes: logical_bytes - stored_bytes,
+        shared_blobs: blobs
+            .into_values()
+            .filter(|blob| blob.files.len() > 1)
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapping::MappingEntry;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn report_groups_files_by_blob() {
+        let output = "a.bin\0oid sha256:aaa\n\
+                      a.bin\0size 100\n\
+                      a.bin\0ext-0-walrus blob-1\n\
+                      b.bin\0oid sha256:aaa\n\
+                      b.bin\0size 100\n\
+                      c.bin\0oid sha256:ccc\n\
+                      c.bin\0size 40\n\
+                      notes.txt\0size matters\n";
+        let mut mappings = Mappings::new();
+        mappings.insert("aaa".to_string(), MappingEntry::from_blob_id("blob-1".to_string()));
+
+        let report = build_report(parse_pointer_lines(output), &mappings);
+
+        assert_eq!(
+            report,
+            DedupReport {
+                logical_files: 3,
+                unique_blobs: 2,
+                logical_bytes: 240,
+                stored_bytes: 140,
+                saved_bytes: 100,
+                shared_blobs: vec![SharedBlob {
+                    blob_id: "blob-1".to_string(),
+                    size: 100,
+                    files: vec![PathBuf::from("a.bin"), PathBuf::from("b.bin")],
+                }],
+            }
+        );
+    }
+}
@@ -0,0 +1,67 @@
+use anyhow::Result;
+use std::path::PathBuf;
+
+use crate::dedup_report::parse_pointer_lines;
+use crate::git;
+use crate::mapping::{load_mappings, remove_blob_mappings};
+use crate::walrus::WalrusClient;
+
+/// Deletes a blob from Walrus, then removes every mapping entry that points at it. Refuses a
+/// blob that a staged or committed (HEAD) pointer still resolves to unless `force`.
+pub async fn delete_blob(client: WalrusClient, blob_id: String, force: bool) -> Result<()> {
+    let referencing = referencing_files(&client, &blob_id).await?;
+    if !referencing.is_empty() {
+        let files: Vec<_> = referencing.iter().map(|path| path.display().to_string()).collect();
+        if !force {
+            return Err(anyhow::anyhow!(
+                "blob {} is still referenced by {}; pass --force to delete it anyway",
+                blob_id,
+                files.join(", ")
+            ));
+        }
+        println!(
+            "⚠️  Deleting blob {} although {} still reference it",
+            blob_id,
+            files.join(", ")
+        );
+    }
+
+    client.delete_blob(&blob_id).await?;
+    println!("✅ Deleted blob {}", blob_id);
+
+    let removed = remove_blob_mappings(&blob_id).await?;
+    println!("\nSummary:");
+    println!("  Mapping entries removed: {}", removed);
+    Ok(())
+}
+
+/// The staged and HEAD files whose pointers resolve to `blob_id`.
+async fn referencing_files(client: &WalrusClient, blob_id: &str) -> Result<Vec<PathBuf>> {
+    let mut pointers = parse_pointer_lines(&git::index_pointer_lines()?);
+    // An unborn HEAD has no files
+    if let Ok(head) = git::tree_pointer_lines("HEAD") {
+        pointers.extend(parse_pointer_lines(&head));
+    }
+
+    let order = client.resolve_order();
+    let mappings = if order.uses_mapping() {
+        load_mappings().await?
+    } else {
+        Default::default()
+    };
+    Ok(pointers
+        .into_iter()
+        .filter(|(_, fields)| {
+            fields.oid.as_deref().is_some_and(|oid| {
+                order
+                    .pick(
+                        oid,
+                        fields.blob_id.as_deref(),
+                        mappings.get(oid).map(|entry| entry.blob_id.as_str()),
+                    )
+                    .is_some_and(|resolved| resolved == blob_id)
+            })
+        })
+        .map(|(path, _)| path)
+        .collect())
+}
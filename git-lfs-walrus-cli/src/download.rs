@@ -0,0 +1,42 @@
+use anyhow::Result;
+use tokio::io::AsyncWriteExt;
+
+use crate::oid::{HashingWriter, Oid};
+use crate::prefetch;
+use crate::resolve::resolve_blob_id;
+use crate::walrus::WalrusClient;
+
+/// Streams one blob to stdout, outside the git-lfs transfer protocol.
+///
+/// `blob_id_or_oid` is either a Walrus blob ID, or an OID (`sha256:<hash>` or the bare hash)
+/// resolved through the prefetch cache, then `lfs.walrus.resolveorder` (a staged pointer's
+/// inline field, the mapping file). Content fetched by OID is hashed as it streams; since it
+/// has already been written by then, a mismatch only shows in the exit status.
+pub async fn download(client: WalrusClient, blob_id_or_oid: String) -> Result<()> {
+    let Some(oid) = Oid::parse_standalone(&blob_id_or_oid) else {
+        let mut stdout = tokio::io::stdout();
+        client.read_blob_to_writer(&blob_id_or_oid, &mut stdout).await?;
+        stdout.flush().await?;
+        return Ok(());
+    };
+
+    let mut writer = HashingWriter::new(tokio::io::stdout(), oid.algorithm);
+    if let Some(file) = prefetch::open_cached(&oid).await {
+        client.copy_stream(file, &mut writer).await?;
+    } else {
+        let blob_id = resolve_blob_id(client.resolve_order(), &oid.hash).await?;
+        client.read_blob_to_writer(&blob_id, &mut writer).await?;
+    }
+    writer.flush().await?;
+
+    let (hash, _) = writer.finish();
+    if hash != oid.hash {
+        return Err(anyhow::anyhow!(
+            "Downloaded content for {} has {} {}; the output is corrupt",
+            oid.hash,
+            oid.algorithm,
+            hash
+        ));
+    }
+    Ok(())
+}
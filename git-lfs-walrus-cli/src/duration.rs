@@ -0,0 +1,118 @@
+use anyhow::Result;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// How long to keep stored blobs, in calendar time: `30d`, `12h`, `2w`, ... A bare number is
+/// a number of days.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StorageDuration {
+    text: String,
+    duration: Duration,
+}
+
+impl StorageDuration {
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+}
+
+impl FromStr for StorageDuration {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        let text = value.trim();
+        let split = text
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(text.len());
+        let (number, unit) = text.split_at(split);
+        let unit_secs = match unit {
+            "s" => 1,
+            "m" => 60,
+            "h" => 60 * 60,
+            "" | "d" => 24 * 60 * 60,
+            "w" => 7 * 24 * 60 * 60,
+            _ => 0,
+        };
+        let secs = number
+            .parse::<u64>()
+            .ok()
+            .and_then(|n| n.checked_mul(unit_secs))
+            .filter(|&secs| secs > 0)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "{:?} is not a storage duration; expected e.g. 30d, 12h or 2w",
+                    value
+                )
+            })?;
+        Ok(Self {
+            text: text.to_string(),
+            duration: Duration::from_secs(secs),
+        })
+    }
+}
+
+impl fmt::Display for StorageDuration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+/// The epoch count closest to `duration` (at least one), and whether it covers it exactly.
+pub fn duration_to_epochs(duration: Duration, epoch: Duration) -> (u64, bool) {
+    let epochs = ((duration.as_secs_f64() / epoch.as_secs_f64()).round() as u64).max(1);
+    let exact = epoch.as_nanos() * u128::from(epochs) == duration.as_nanos();
+    (epochs, exact)
+}
+
+/// Formats a duration in the largest unit that divides it evenly, e.g. `1d` or `90m`.
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs == 0 || duration.subsec_nanos() != 0 {
+        return format!("{}ms", duration.as_millis());
+    }
+    let units = [("w", 7 * 24 * 60 * 60), ("d", 24 * 60 * 60), ("h", 60 * 60), ("m", 60)];
+    for (unit, unit_secs) in units {
+        if secs.is_multiple_of(unit_secs) {
+            return format!("{}{}", secs / unit_secs, unit);
+        }
+    }
+    format!("{}s", secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    #[test]
+    fn durations_parse_with_units_and_default_to_days() {
+        assert_eq!("30d".parse::<StorageDuration>().unwrap().duration(), DAY * 30);
+        assert_eq!("30".parse::<StorageDuration>().unwrap().duration(), DAY * 30);
+        assert_eq!("2w".parse::<StorageDuration>().unwrap().duration(), DAY * 14);
+        assert_eq!(
+            "12h".parse::<StorageDuration>().unwrap().duration(),
+            Duration::from_secs(12 * 60 * 60)
+        );
+        assert!("0d".parse::<StorageDuration>().is_err());
+        assert!("30 days".parse::<StorageDuration>().is_err());
+        assert!("d".parse::<StorageDuration>().is_err());
+    }
+
+    #[test]
+    fn durations_round_to_the_nearest_epoch() {
+        assert_eq!(duration_to_epochs(DAY * 30, DAY), (30, true));
+        assert_eq!(duration_to_epochs(DAY * 30, DAY * 14), (2, false));
+        assert_eq!(duration_to_epochs(DAY * 3, DAY * 2), (2, false));
+        assert_eq!(duration_to_epochs(Duration::from_secs(60), DAY), (1, false));
+    }
+
+    #[test]
+    fn durations_are_formatted_in_the_largest_even_unit() {
+        assert_eq!(format_duration(DAY), "1d");
+        assert_eq!(format_duration(DAY * 14), "2w");
+        assert_eq!(format_duration(Duration::from_secs(90 * 60)), "90m");
+        assert_eq!(format_duration(Duration::from_millis(1500)), "1500ms");
+    }
+}
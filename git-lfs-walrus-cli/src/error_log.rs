@@ -0,0 +1,115 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::walrus::WalrusError;
+
+/// How much of walrus's stderr is kept, from the end, where its error is.
+const MAX_STDERR: usize = 2000;
+
+/// One failure, as appended to the `LFS_WALRUS_ERRORLOG` file.
+#[derive(Debug, Serialize)]
+struct ErrorRecord {
+    /// Milliseconds since the Unix epoch
+    timestamp_ms: u128,
+    /// The command, or transfer operation, that failed
+    operation: String,
+    /// The [`WalrusError`] kind, or `other` for errors outside walrus
+    kind: &'static str,
+    /// The walrus subcommand that failed, if one ran
+    #[serde(skip_serializing_if = "Option::is_none")]
+    walrus_command: Option<&'static str>,
+    /// The tail of walrus's stderr
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stderr: Option<String>,
+    message: String,
+}
+
+/// Appends `err` to the file named by `LFS_WALRUS_ERRORLOG`, if it is set, for attaching to a
+/// bug report. Nothing is ever sent anywhere; the home directory is replaced by `~`.
+pub fn record(operation: &str, err: &anyhow::Error) {
+    let Some(path) = std::env::var_os("LFS_WALRUS_ERRORLOG") else {
+        return;
+    };
+    if let Err(log_err) = append(Path::new(&path), operation, err) {
+        eprintln!("Warning: could not write to the error log: {}", log_err);
+    }
+}
+
+fn append(path: &Path, operation: &str, err: &anyhow::Error) -> Result<()> {
+    let home = dirs::home_dir().map(|home| home.display().to_string());
+    let anonymize = |text: &str| match &home {
+        Some(home) if home.len() > 1 => text.replace(home.as_str(), "~"),
+        _ => text.to_string(),
+    };
+
+    let walrus_error = err.downcast_ref::<WalrusError>();
+    let record = ErrorRecord {
+        timestamp_ms: SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis(),
+        operation: operation.to_string(),
+        kind: walrus_error.map_or("other", WalrusError::kind),
+        walrus_command: walrus_error.and_then(WalrusError::command),
+        stderr: walrus_error
+            .and_then(WalrusError::stderr)
+            .map(|stderr| anonymize(truncate_start(stderr.trim(), MAX_STDERR))),
+        message: anonymize(&format!("{:#}", err)),
+    };
+
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", serde_json::to_string(&record)?)?;
+    Ok(())
+}
+
+/// The last `max` bytes of `text`, moved forward to a character boundary.
+fn truncate_start(text: &str, max: usize) -> &str {
+    let mut start = text.len().saturating_sub(max);
+    while !text.is_char_boundary(start) {
+        start += 1;
+    }
+    &text[start..]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors_are_appended_with_their_walrus_kind_and_stderr_tail() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("errors.jsonl");
+        let stderr = format!("{}Error: blob not found", "log line\n".repeat(500));
+        let walrus_error = WalrusError::CommandFailed {
+            command: "read",
+            stderr: stderr.clone(),
+        };
+        append(&path, "smudge", &walrus_error.into()).unwrap();
+        append(&path, "fsck", &anyhow::anyhow!("mapping file is corrupt")).unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<serde_json::Value> = content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["operation"], "smudge");
+        assert_eq!(lines[0]["kind"], "command_failed");
+        assert_eq!(lines[0]["walrus_command"], "read");
+        let logged = lines[0]["stderr"].as_str().unwrap();
+        assert_eq!(logged.len(), MAX_STDERR);
+        assert!(logged.ends_with("Error: blob not found"));
+        assert!(lines[0]["timestamp_ms"].as_u64().unwrap() > 0);
+
+        assert_eq!(lines[1]["kind"], "other");
+        assert_eq!(lines[1]["message"], "mapping file is corrupt");
+        assert!(lines[1].get("stderr").is_none());
+    }
+
+    #[test]
+    fn truncation_keeps_whole_characters() {
+        assert_eq!(truncate_start("ééé", 3), "é");
+        assert_eq!(truncate_start("short", 100), "short");
+    }
+}
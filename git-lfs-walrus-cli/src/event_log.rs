@@ -0,0 +1,99 @@
+use anyhow::Result;
+use git_lfs_spec::transfer::custom::Event;
+use serde::Serialize;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Appends transfer protocol events to a JSON Lines file, for watching long transfers.
+pub struct EventLog {
+    file: Mutex<File>,
+}
+
+#[derive(Serialize)]
+struct LogLine {
+    /// Milliseconds since the Unix epoch
+    timestamp_ms: u128,
+    direction: Direction,
+    /// The event as it appears on the wire
+    event: serde_json::Value,
+}
+
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// Received from git-lfs
+    In,
+    /// Sent to git-lfs
+    Out,
+}
+
+impl EventLog {
+    /// Opens the log named by `LFS_WALRUS_EVENTLOG`, if it is set.
+    pub fn from_env() -> Result<Option<Self>> {
+        match std::env::var_os("LFS_WALRUS_EVENTLOG") {
+            Some(path) => Ok(Some(Self::open(Path::new(&path))?)),
+            None => Ok(None),
+        }
+    }
+
+    fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Appends `event`. Failures are reported on stderr, never to the protocol stream.
+    pub fn record(&self, direction: Direction, event: &Event) {
+        if let Err(err) = self.try_record(direction, event) {
+            eprintln!("Warning: could not write to the transfer event log: {}", err);
+        }
+    }
+
+    fn try_record(&self, direction: Direction, event: &Event) -> Result<()> {
+        let line = serde_json::to_string(&LogLine {
+            timestamp_ms: SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis(),
+            direction,
+            // The init acknowledgement isn't serializable; on the wire it is an empty object
+            event: match event {
+                Event::AcknowledgeInit => serde_json::json!({}),
+                event => serde_json::to_value(event)?,
+            },
+        })?;
+        let mut file = self
+            .file
+            .lock()
+            .map_err(|_| anyhow::anyhow!("event log lock was poisoned"))?;
+        writeln!(file, "{}", line)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_are_appended_as_json_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("events.jsonl");
+        let log = EventLog::open(&path).unwrap();
+        log.record(Direction::In, &Event::Terminate);
+        log.record(Direction::Out, &Event::AcknowledgeInit);
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<serde_json::Value> = content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["direction"], "in");
+        assert_eq!(lines[0]["event"], serde_json::json!({"event": "terminate"}));
+        assert_eq!(lines[1]["direction"], "out");
+        assert_eq!(lines[1]["event"], serde_json::json!({}));
+        assert!(lines[1]["timestamp_ms"].as_u64().unwrap() > 0);
+    }
+}
@@ -0,0 +1,208 @@
+use anyhow::Result;
+use futures::StreamExt;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use tokio::io::AsyncWriteExt;
+
+use crate::git;
+use crate::mapping::load_mappings;
+use crate::oid::{HashingWriter, Oid};
+use crate::pointer::{extract_oid, extract_walrus_blob_id, is_pointer};
+use crate::walrus::{is_not_found, WalrusClient};
+
+/// Pointer files are well under this size; anything larger is file content.
+const MAX_POINTER_SIZE: u64 = 1024;
+
+/// One distinct LFS object to verify.
+struct FsckObject {
+    /// First path naming the object, for messages
+    path: PathBuf,
+    oid: Oid,
+    size: Option<u64>,
+    blob_id: Option<String>,
+}
+
+enum FsckResult {
+    Ok,
+    Missing(String),
+    Corrupt(String),
+    Error(anyhow::Error),
+}
+
+/// Checks that every LFS object reachable from the branch tips and HEAD (or, with `all`, from
+/// any commit in the history) can be read back from Walrus and matches its OID and size.
+///
+/// Content is hashed as it streams and then discarded, `jobs` objects at a time.
+pub async fn fsck(client: WalrusClient, jobs: usize, all: bool) -> Result<()> {
+    let rev_args: &[&str] = if all {
+        &["--all"]
+    } else {
+        &["--no-walk", "--branches", "HEAD"]
+    };
+    let objects = unique_objects(git::reachable_small_blobs(rev_args, MAX_POINTER_SIZE)?);
+    let order = client.resolve_order();
+    let mappings = if !order.uses_mapping() {
+        Default::default()
+    } else {
+        load_mappings().await?
+    };
+
+    let mut unresolved = 0;
+    let mut checks = Vec::new();
+    for object in objects.into_values() {
+        let blob_id = order.pick(
+            &object.oid.hash,
+            object.blob_id.as_deref(),
+            mappings.get(&object.oid.hash).map(|entry| entry.blob_id.as_str()),
+        );
+        match blob_id {
+            Some(blob_id) => checks.push((object, blob_id)),
+            None => {
+                println!(
+                    "⚠️  {} - No Walrus blob ID in the pointer or mapping ({} {})",
+                    object.path.display(),
+                    object.oid.algorithm,
+                    object.oid.hash
+                );
+                unresolved += 1;
+            }
+        }
+    }
+
+    let client = &client;
+    let mut results = futures::stream::iter(checks)
+        .map(|(object, blob_id)| async move {
+            let result = check_object(client, &object, &blob_id).await;
+            (object, blob_id, result)
+        })
+        .buffer_unordered(jobs.max(1));
+
+    let mut ok = 0;
+    let mut missing = 0;
+    let mut corrupt = 0;
+    let mut errors = 0;
+    while let Some((object, blob_id, result)) = results.next().await {
+        let path = object.path.display();
+        match result {
+            FsckResult::Ok => {
+                println!("✅ {} - {}", path, blob_id);
+                ok += 1;
+            }
+            FsckResult::Missing(reason) => {
+                println!("❌ {} - Missing blob {}: {}", path, blob_id, reason);
+                missing += 1;
+            }
+            FsckResult::Corrupt(reason) => {
+                println!("❌ {} - Corrupt blob {}: {}", path, blob_id, reason);
+                corrupt += 1;
+            }
+            FsckResult::Error(e) => {
+                println!("⚠️  {} - Could not check blob {}: {}", path, blob_id, e);
+                errors += 1;
+            }
+        }
+    }
+
+    println!("\nSummary:");
+    println!("  OK: {}", ok);
+    println!("  Missing: {}", missing);
+    println!("  Corrupt: {}", corrupt);
+    println!("  Unresolved: {}", unresolved);
+    println!("  Errors: {}", errors);
+
+    let problems = missing + corrupt + unresolved + errors;
+    if problems > 0 {
+        return Err(anyhow::anyhow!(
+            "{} of {} LFS objects failed verification",
+            problems,
+            ok + problems
+        ));
+    }
+    Ok(())
+}
+
+/// Parses the pointers among `blobs`, keeping one object per OID: the first path naming it,
+/// and the inline blob ID from whichever pointer has one.
+fn unique_objects(blobs: Vec<(PathBuf, Vec<u8>)>) -> BTreeMap<String, FsckObject> {
+    let mut objects: BTreeMap<String, FsckObject> = BTreeMap::new();
+    for (path, content) in blobs {
+        if !is_pointer(&content) {
+            continue;
+        }
+        let content = String::from_utf8_lossy(&content);
+        let Ok(oid) = extract_oid(&content) else {
+            continue;
+        };
+        let size = content
+            .lines()
+            .find_map(|line| line.strip_prefix("size "))
+            .and_then(|size| size.trim().parse().ok());
+        let blob_id = extract_walrus_blob_id(&content).ok();
+
+        let object = objects
+            .entry(oid.hash.clone())
+            .or_insert_with(|| FsckObject {
+                path,
+                oid,
+                size,
+                blob_id: None,
+            });
+        if object.blob_id.is_none() {
+            object.blob_id = blob_id;
+        }
+    }
+    objects
+}
+
+async fn check_object(client: &WalrusClient, object: &FsckObject, blob_id: &str) -> FsckResult {
+    let mut writer = HashingWriter::new(tokio::io::sink(), object.oid.algorithm);
+    let read = client.read_blob_to_writer(blob_id, &mut writer).await;
+    if let Err(e) = read {
+        return if is_not_found(&e) {
+            FsckResult::Missing(e.to_string())
+        } else {
+            FsckResult::Error(e)
+        };
+    }
+    if let Err(e) = writer.flush().await {
+        return FsckResult::Error(e.into());
+    }
+
+    let (hash, written) = writer.finish();
+    if hash != object.oid.hash {
+        return FsckResult::Corrupt(format!(
+            "content has {} {}, expected {}",
+            object.oid.algorithm, hash, object.oid.hash
+        ));
+    }
+    if let Some(size) = object.size.filter(|&size| size != written) {
+        return FsckResult::Corrupt(format!("{} bytes, expected {}", written, size));
+    }
+    FsckResult::Ok
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn objects_are_deduplicated_by_oid_and_keep_an_inline_blob_id() {
+        let oid = "4d7a214614ab2935c943f9e0ff69d22eadbb8f32b1258daaa5e2ca24d17e2393";
+        let bare = format!(
+            "version https://git-lfs.github.com/spec/v1\noid sha256:{}\nsize 12345\n",
+            oid
+        );
+        let inline = format!("{}ext-0-walrus blob-1\n", bare);
+        let objects = unique_objects(vec![
+            (PathBuf::from("a.bin"), bare.into_bytes()),
+            (PathBuf::from("b.bin"), inline.into_bytes()),
+            (PathBuf::from("notes.txt"), b"not a pointer\n".to_vec()),
+        ]);
+
+        assert_eq!(objects.len(), 1);
+        let object = &objects[oid];
+        assert_eq!(object.path, PathBuf::from("a.bin"));
+        assert_eq!(object.size, Some(12345));
+        assert_eq!(object.blob_id.as_deref(), Some("blob-1"));
+    }
+}
@@ -0,0 +1,676 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::sync::OnceLock;
+
+/// Why a git invocation could not produce what we asked for.
+#[derive(Debug)]
+pub enum GitError {
+    /// The `git` executable could not be spawned at all
+    GitNotAvailable(std::io::Error),
+    /// git ran, but the current directory is not inside a repository
+    NotARepo,
+    /// git ran inside a repository and exited unsuccessfully
+    CommandFailed { args: String, stderr: String },
+    /// A config value git itself would reject for its type
+    InvalidConfig {
+        key: String,
+        value: String,
+        expected: &'static str,
+    },
+}
+
+impl fmt::Display for GitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GitError::GitNotAvailable(err) => write!(
+                f,
+                "git is not available ({}); make sure git is installed and on your PATH",
+                err
+            ),
+            GitError::NotARepo => write!(
+                f,
+                "not inside a git repository; run this command from within a repository that uses git-lfs-walrus"
+            ),
+            GitError::CommandFailed { args, stderr } => {
+                write!(f, "`git {}` failed: {}", args, stderr.trim())
+            }
+            GitError::InvalidConfig {
+                key,
+                value,
+                expected,
+            } => write!(f, "{} {:?} is not {}", key, value, expected),
+        }
+    }
+}
+
+impl std::error::Error for GitError {}
+
+/// Runs git with `args`, classifying spawn failures and "not a repository" errors.
+pub fn run(args: &[&str]) -> Result<Output, GitError> {
+    let output = Command::new("git")
+        .args(args)
+        .output()
+        .map_err(GitError::GitNotAvailable)?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if stderr.contains("not a git repository") {
+            return Err(GitError::NotARepo);
+        }
+    }
+
+    Ok(output)
+}
+
+/// Runs git with `args` and returns its stdout, treating a non-zero exit as an error.
+pub fn stdout(args: &[&str]) -> Result<String, GitError> {
+    let output = run(args)?;
+
+    if !output.status.success() {
+        return Err(GitError::CommandFailed {
+            args: args.join(" "),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        });
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// The scope [`config_scope`] reports for a value read from `.lfsconfig`.
+pub const LFSCONFIG_SCOPE: &str = ".lfsconfig";
+
+/// Settings naming something on this machine (an executable, a wallet, a coin) or where its
+/// requests go, which a committed `.lfsconfig` may not set.
+const LOCAL_ONLY_KEYS: &[&str] = &[
+    "lfs.walrus.suipath",
+    "lfs.walrus.config",
+    "lfs.walrus.wallet",
+    "lfs.walrus.gascoin",
+    "lfs.walrus.tmpdir",
+    "lfs.walrus.proxy",
+    "lfs.walrus.aggregatorurl",
+];
+
+/// Reads a value from git config, returning `None` when the key is unset.
+pub fn config_get(key: &str) -> Result<Option<String>, GitError> {
+    Ok(config_entry(key)?.map(|value| value.unwrap_or_default().to_string()))
+}
+
+/// Like [`config_get`], with a leading `~/` expanded to the home directory as git does.
+pub fn config_get_path(key: &str) -> Result<Option<String>, GitError> {
+    Ok(config_get(key)?.map(|value| expand_home(&value)))
+}
+
+/// Returns the scope (`local`, `global`, `system`, ...) of the config file setting `key`, or
+/// [`LFSCONFIG_SCOPE`] when only `.lfsconfig` sets it, or `None` if it isn't set.
+pub fn config_scope(key: &str) -> Result<Option<String>, GitError> {
+    let args = ["config", "--show-scope", "--get", key];
+    let output = run(&args)?;
+
+    // Exit status 1 means the key is unset
+    if output.status.code() == Some(1) {
+        let shared =
+            shared_key(key) && config_snapshot()?.lfsconfig.contains_key(&canonical_key(key));
+        return Ok(shared.then(|| LFSCONFIG_SCOPE.to_string()));
+    }
+    if !output.status.success() {
+        return Err(GitError::CommandFailed {
+            args: args.join(" "),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        });
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .split_once('\t')
+        .map(|(scope, _)| scope.to_string()))
+}
+
+/// Reads a boolean from git config, accepting `yes`/`on`/`1` and friends as git does. A value
+/// git wouldn't accept is an error, not unset.
+pub fn config_get_bool(key: &str) -> Result<Option<bool>, GitError> {
+    config_entry(key)?
+        .map(|value| parse_bool(key, value))
+        .transpose()
+}
+
+/// Reads an opt-in boolean setting, such as `lfs.walrus.preservemode` or
+/// `lfs.walrus.refreshlog`, off when unset.
+pub fn config_flag(key: &str) -> bool {
+    config_bool_or(key, false)
+}
+
+/// Reads a boolean setting, falling back to `default` when it is unset, or with a warning when
+/// it can't be read.
+pub fn config_bool_or(key: &str, default: bool) -> bool {
+    match config_get_bool(key) {
+        Ok(value) => value.unwrap_or(default),
+        Err(err) => {
+            eprintln!("Warning: {}, using {}", err, default);
+            default
+        }
+    }
+}
+
+/// Every git config entry under `section` (e.g. `lfs.walrus.env`) as `(name, value)` pairs,
+/// with a leading `~/` in values expanded. Names are the part after the section, lowercased
+/// as git stores them. `.lfsconfig` is not consulted.
+pub fn config_entries_under(section: &str) -> Result<Vec<(String, String)>, GitError> {
+    let pattern = format!("^{}\\.", section.replace('.', "\\."));
+    let output = run(&["config", "--type=path", "-z", "--get-regexp", &pattern])?;
+    // Exit status 1 means nothing matched
+    if !output.status.success() && output.status.code() != Some(1) {
+        return Err(GitError::CommandFailed {
+            args: format!("config --get-regexp {}", pattern),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        });
+    }
+    let prefix = format!("{}.", section);
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .split('\0')
+        .filter_map(|record| {
+            let (key, value) = record.split_once('\n').unwrap_or((record, ""));
+            let name = key.strip_prefix(&prefix)?;
+            Some((name.to_string(), value.to_string()))
+        })
+        .collect())
+}
+
+/// Config entries as `git config -z --list` prints them, by key. The last value of a key wins,
+/// as with `git config --get`; a key set without `= value` maps to `None`.
+type ConfigValues = HashMap<String, Option<String>>;
+
+/// Git config and `.lfsconfig`, each read once per process: smudge runs once per file of a
+/// checkout, and reads dozens of settings.
+struct ConfigSnapshot {
+    git: ConfigValues,
+    lfsconfig: ConfigValues,
+}
+
+fn config_snapshot() -> Result<&'static ConfigSnapshot, GitError> {
+    static SNAPSHOT: OnceLock<ConfigSnapshot> = OnceLock::new();
+    if let Some(snapshot) = SNAPSHOT.get() {
+        return Ok(snapshot);
+    }
+    let git = parse_config_list(&stdout(&["config", "-z", "--list"])?);
+    let lfsconfig = match lfsconfig_path() {
+        Some(path) => {
+            let file = path.to_string_lossy();
+            parse_config_list(&stdout(&["config", "-z", "--file", &file, "--list"])?)
+        }
+        None => ConfigValues::new(),
+    };
+    Ok(SNAPSHOT.get_or_init(|| ConfigSnapshot { git, lfsconfig }))
+}
+
+/// Reads `key` from git config, then from the `.lfsconfig` committed at the top of the
+/// working tree, as git-lfs does: any git config scope takes precedence over the shared file.
+/// A key set without a value is `Some(None)`.
+fn config_entry(key: &str) -> Result<Option<Option<&'static str>>, GitError> {
+    let snapshot = config_snapshot()?;
+    let canonical = canonical_key(key);
+    let value = match snapshot.git.get(&canonical) {
+        Some(value) => Some(value),
+        None if shared_key(key) => snapshot.lfsconfig.get(&canonical),
+        None => None,
+    };
+    Ok(value.map(Option::as_deref))
+}
+
+/// Whether `.lfsconfig` may set `key`. git-lfs settings (transfer agents, extensions) only
+/// ever come from git config.
+fn shared_key(key: &str) -> bool {
+    let key = canonical_key(key);
+    key.starts_with("lfs.walrus.") && !LOCAL_ONLY_KEYS.contains(&key.as_str())
+}
+
+/// Splits `git config -z --list` output: `key\nvalue` records, or a bare `key`, ended by NUL.
+fn parse_config_list(output: &str) -> ConfigValues {
+    output
+        .split('\0')
+        .filter(|record| !record.is_empty())
+        .map(|record| match record.split_once('\n') {
+            Some((key, value)) => (key.to_string(), Some(value.to_string())),
+            None => (record.to_string(), None),
+        })
+        .collect()
+}
+
+/// `key` as git lists it: section and variable names are case-insensitive and lowercased,
+/// while a subsection keeps its case.
+fn canonical_key(key: &str) -> String {
+    match (key.find('.'), key.rfind('.')) {
+        (Some(first), Some(last)) => format!(
+            "{}{}{}",
+            key[..first].to_ascii_lowercase(),
+            &key[first..last],
+            key[last..].to_ascii_lowercase()
+        ),
+        _ => key.to_ascii_lowercase(),
+    }
+}
+
+/// Parses a boolean as `git config --type=bool` does; a key without a value is true.
+fn parse_bool(key: &str, value: Option<&str>) -> Result<bool, GitError> {
+    let Some(value) = value else {
+        return Ok(true);
+    };
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "yes" | "on" => Ok(true),
+        "false" | "no" | "off" | "" => Ok(false),
+        number => number
+            .parse::<i64>()
+            .map(|number| number != 0)
+            .map_err(|_| GitError::InvalidConfig {
+                key: key.to_string(),
+                value: value.to_string(),
+                expected: "a boolean",
+            }),
+    }
+}
+
+/// Expands a leading `~` or `~/` to the home directory, as `git config --type=path` does.
+fn expand_home(value: &str) -> String {
+    let home = || std::env::var("HOME").ok();
+    if value == "~" {
+        return home().unwrap_or_else(|| value.to_string());
+    }
+    match (value.strip_prefix("~/"), home()) {
+        (Some(rest), Some(home)) => Path::new(&home).join(rest).display().to_string(),
+        _ => value.to_string(),
+    }
+}
+
+/// `.lfsconfig` at the top of the working tree, if there is one. Looked up once per process,
+/// along with the rest of the config.
+fn lfsconfig_path() -> Option<&'static Path> {
+    static LFSCONFIG: OnceLock<Option<PathBuf>> = OnceLock::new();
+    LFSCONFIG
+        .get_or_init(|| {
+            let toplevel = stdout(&["rev-parse", "--show-toplevel"]).ok()?;
+            Some(PathBuf::from(toplevel.trim()).join(".lfsconfig")).filter(|path| path.is_file())
+        })
+        .as_deref()
+}
+
+pub fn git_dir() -> Result<PathBuf, GitError> {
+    Ok(PathBuf::from(stdout(&["rev-parse", "--git-dir"])?.trim()))
+}
+
+/// Returns the content of `path` as committed at HEAD, or `None` if it isn't there.
+pub fn show_head(path: &Path) -> Result<Option<String>, GitError> {
+    match stdout(&["show", &format!("HEAD:{}", path.display())]) {
+        Ok(content) => Ok(Some(content)),
+        Err(GitError::CommandFailed { .. }) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Returns the content of `path` as staged in the index, or `None` if it isn't there.
+pub fn show_index(path: &Path) -> Result<Option<String>, GitError> {
+    match stdout(&["show", &format!(":{}", path.display())]) {
+        Ok(content) => Ok(Some(content)),
+        Err(GitError::CommandFailed { .. }) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+/// Finds a pointer staged in the index whose OID hash is `hash` (any algorithm), returning its
+/// content.
+pub fn index_pointer_for_oid(hash: &str) -> Result<Option<String>, GitError> {
+    let oid_suffix = format!(":{}", hash);
+    let paths = match stdout(&["grep", "--cached", "-l", "-F", "-e", &oid_suffix]) {
+        Ok(paths) => paths,
+        // git grep exits unsuccessfully when nothing matches
+        Err(GitError::CommandFailed { .. }) => return Ok(None),
+        Err(err) => return Err(err),
+    };
+
+    for path in paths.lines().filter(|line| !line.is_empty()) {
+        if let Some(content) = show_index(Path::new(path))?
+            && content
+                .lines()
+                .any(|line| line.starts_with("oid ") && line.ends_with(&oid_suffix))
+        {
+            return Ok(Some(content));
+        }
+    }
+    Ok(None)
+}
+
+/// Returns the `oid`, `size`, `ext-0-walrus` and `ext-1-walrus-mode` lines of every staged file, as
+/// NUL-separated `path\0line` records from `git grep --cached --null`.
+pub fn index_pointer_lines() -> Result<String, GitError> {
+    grep_pointer_lines("--cached")
+}
+
+/// Like [`index_pointer_lines`], for the files of `revision`; paths are `revision:path`.
+pub fn tree_pointer_lines(revision: &str) -> Result<String, GitError> {
+    grep_pointer_lines(revision)
+}
+
+fn grep_pointer_lines(source: &str) -> Result<String, GitError> {
+    let args = [
+        "grep",
+        "--null",
+        "-e",
+        "^oid ",
+        "-e",
+        "^size ",
+        "-e",
+        "^ext-0-walrus ",
+        "-e",
+        "^ext-1-walrus-mode ",
+        source,
+    ];
+    match stdout(&args) {
+        Ok(lines) => Ok(lines),
+        // git grep exits unsuccessfully when nothing matches
+        Err(GitError::CommandFailed { stderr, .. }) if stderr.trim().is_empty() => {
+            Ok(String::new())
+        }
+        Err(err) => Err(err),
+    }
+}
+
+/// Runs git with `args` and `input` on stdin, returning its stdout. stdin is written from a
+/// separate thread, so large inputs and outputs can't deadlock on full pipes.
+fn stdout_with_input(args: &[&str], input: Vec<u8>) -> Result<Vec<u8>, GitError> {
+    let mut child = Command::new("git")
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(GitError::GitNotAvailable)?;
+    let writer = child.stdin.take().map(|mut stdin| {
+        std::thread::spawn(move || stdin.write_all(&input))
+    });
+    let output = child.wait_with_output().map_err(GitError::GitNotAvailable)?;
+    let written = writer.map_or(Ok(()), |writer| {
+        writer
+            .join()
+            .unwrap_or_else(|_| Err(std::io::Error::other("stdin writer panicked")))
+    });
+
+    if !output.status.success() {
+        return Err(GitError::CommandFailed {
+            args: args.join(" "),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        });
+    }
+    written.map_err(|err| GitError::CommandFailed {
+        args: args.join(" "),
+        stderr: err.to_string(),
+    })?;
+    Ok(output.stdout)
+}
+
+/// Writes `content` to the object database and stages it at `path`, keeping the path's mode.
+pub fn stage_content(path: &Path, content: &[u8]) -> Result<(), GitError> {
+    let path = path.to_string_lossy();
+    let mode = stdout(&["ls-files", "--stage", "--", &path])?
+        .split_whitespace()
+        .next()
+        .unwrap_or("100644")
+        .to_string();
+
+    let output = stdout_with_input(&["hash-object", "-w", "--stdin"], content.to_vec())?;
+    let oid = String::from_utf8_lossy(&output).trim().to_string();
+
+    stdout(&[
+        "update-index",
+        "--cacheinfo",
+        &format!("{},{},{}", mode, oid, path),
+    ])?;
+    Ok(())
+}
+
+/// Returns the path and content of every distinct blob of at most `max_size` bytes reachable
+/// through `git rev-list --objects <rev_args>`. A blob found under several paths is returned
+/// once, with the first path.
+pub fn reachable_small_blobs(
+    rev_args: &[&str],
+    max_size: u64,
+) -> Result<Vec<(PathBuf, Vec<u8>)>, GitError> {
+    let mut args = vec!["rev-list", "--objects"];
+    args.extend_from_slice(rev_args);
+    let listing = stdout(&args)?;
+
+    // Commits are listed without a path and the root tree with an empty one
+    let mut seen = HashSet::new();
+    let objects: Vec<(&str, &str)> = listing
+        .lines()
+        .filter_map(|line| line.split_once(' '))
+        .filter(|(id, path)| !path.is_empty() && seen.insert(*id))
+        .collect();
+    if objects.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let ids: String = objects.iter().map(|(id, _)| format!("{}\n", id)).collect();
+    let checks = stdout_with_input(
+        &[
+            "cat-file",
+            "--batch-check=%(objectname) %(objecttype) %(objectsize)",
+        ],
+        ids.into_bytes(),
+    )?;
+    let small: HashSet<&str> = std::str::from_utf8(&checks)
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split(' ');
+            let (id, kind, size) = (fields.next()?, fields.next()?, fields.next()?);
+            let size: u64 = size.parse().ok()?;
+            (kind == "blob" && size <= max_size).then_some(id)
+        })
+        .collect();
+    let wanted: Vec<(&str, &str)> = objects
+        .into_iter()
+        .filter(|(id, _)| small.contains(id))
+        .collect();
+
+    let ids: String = wanted.iter().map(|(id, _)| format!("{}\n", id)).collect();
+    let batch = stdout_with_input(&["cat-file", "--batch"], ids.into_bytes())?;
+    let mut contents = parse_cat_file_batch(&batch).into_iter();
+    Ok(wanted
+        .into_iter()
+        .filter_map(|(_, path)| Some((PathBuf::from(path), contents.next()?)))
+        .collect())
+}
+
+/// Splits `git cat-file --batch` output (`<id> <type> <size>\n<content>\n` per object) into
+/// the objects' contents.
+fn parse_cat_file_batch(mut output: &[u8]) -> Vec<Vec<u8>> {
+    let mut contents = Vec::new();
+    while let Some(header_end) = output.iter().position(|&b| b == b'\n') {
+        let header = String::from_utf8_lossy(&output[..header_end]);
+        let Some(size) = header
+            .rsplit(' ')
+            .next()
+            .and_then(|size| size.parse::<usize>().ok())
+        else {
+            break;
+        };
+        let start = header_end + 1;
+        let Some(content) = output.get(start..start + size) else {
+            break;
+        };
+        contents.push(content.to_vec());
+        output = output.get(start + size + 1..).unwrap_or_default();
+    }
+    contents
+}
+
+/// Lists the paths of all files tracked by git-lfs.
+pub fn lfs_files() -> Result<Vec<PathBuf>, GitError> {
+    let files_output = stdout(&["lfs", "ls-files", "--name-only"])?;
+    Ok(files_output
+        .lines()
+        .map(|line| line.trim_end_matches('\r'))
+        .filter(|line| !line.trim().is_empty())
+        .map(unquote_path)
+        .collect())
+}
+
+/// Whether git turns CRLF into LF in text that `path`'s clean filter outputs before storing it:
+/// the path is `text` or `text=auto`, has an `eol` attribute, or `core.autocrlf` is `true` or
+/// `input` and `text` isn't set either way.
+pub fn normalizes_line_endings(path: &Path) -> Result<bool, GitError> {
+    let path = path.to_string_lossy();
+    let output = stdout(&["check-attr", "-z", "text", "eol", "--", &path])?;
+    // Records are <path> NUL <attribute> NUL <value> NUL
+    let fields: Vec<&str> = output.split('\0').collect();
+    let attribute = |name: &str| {
+        fields
+            .chunks(3)
+            .find(|record| record.get(1) == Some(&name))
+            .and_then(|record| record.get(2).copied())
+            .unwrap_or("unspecified")
+    };
+    let autocrlf = config_get("core.autocrlf")?;
+    Ok(line_endings_normalized(
+        attribute("text"),
+        attribute("eol"),
+        autocrlf.as_deref(),
+    ))
+}
+
+fn line_endings_normalized(text: &str, eol: &str, autocrlf: Option<&str>) -> bool {
+    match text {
+        "set" | "auto" => true,
+        "unset" => false,
+        _ if eol != "unspecified" => true,
+        _ => matches!(
+            autocrlf.map(str::to_ascii_lowercase).as_deref(),
+            Some("true" | "yes" | "on" | "1" | "input")
+        ),
+    }
+}
+
+/// Undoes the C-style quoting git applies to names with special characters when
+/// `core.quotePath` is on (`"caf\303\251 menu.bin"`); other names are returned as they are.
+fn unquote_path(name: &str) -> PathBuf {
+    let Some(quoted) = name
+        .strip_prefix('"')
+        .and_then(|name| name.strip_suffix('"'))
+    else {
+        return PathBuf::from(name);
+    };
+
+    let mut bytes = Vec::with_capacity(quoted.len());
+    let mut chars = quoted.bytes().peekable();
+    while let Some(byte) = chars.next() {
+        if byte != b'\\' {
+            bytes.push(byte);
+            continue;
+        }
+        match chars.next() {
+            Some(b'a') => bytes.push(0x07),
+            Some(b'b') => bytes.push(0x08),
+            Some(b'f') => bytes.push(0x0c),
+            Some(b'n') => bytes.push(b'\n'),
+            Some(b'r') => bytes.push(b'\r'),
+            Some(b't') => bytes.push(b'\t'),
+            Some(b'v') => bytes.push(0x0b),
+            Some(digit @ b'0'..=b'7') => {
+                let mut value = u32::from(digit - b'0');
+                for _ in 0..2 {
+                    match chars.peek() {
+                        Some(digit @ b'0'..=b'7') => {
+                            value = value * 8 + u32::from(digit - b'0');
+                            chars.next();
+                        }
+                        _ => break,
+                    }
+                }
+                bytes.push(value as u8);
+            }
+            // `\\` and `\"`, and anything unexpected, stand for the character itself
+            Some(other) => bytes.push(other),
+            None => bytes.push(b'\\'),
+        }
+    }
+    path_from_bytes(bytes)
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    use std::os::unix::ffi::OsStringExt;
+    PathBuf::from(std::ffi::OsString::from_vec(bytes))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: Vec<u8>) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quoted_names_are_unescaped() {
+        assert_eq!(unquote_path("plain.bin"), PathBuf::from("plain.bin"));
+        assert_eq!(unquote_path("with space.bin"), PathBuf::from("with space.bin"));
+        assert_eq!(unquote_path("\"foo bar.bin\""), PathBuf::from("foo bar.bin"));
+        assert_eq!(
+            unquote_path("\"caf\\303\\251 men\\303\\274.bin\""),
+            PathBuf::from("café menü.bin")
+        );
+        assert_eq!(
+            unquote_path("\"dir/\\346\\227\\245\\346\\234\\254.bin\""),
+            PathBuf::from("dir/日本.bin")
+        );
+        assert_eq!(
+            unquote_path("\"tab\\there \\\"quoted\\\" back\\\\slash\""),
+            PathBuf::from("tab\there \"quoted\" back\\slash")
+        );
+    }
+
+    #[test]
+    fn config_lists_are_read_as_git_config_get_would() {
+        let values = parse_config_list(
+            "core.bare\nfalse\0lfs.walrus.offline\0lfs.walrus.tag\nfirst\0lfs.walrus.tag\nlast\0",
+        );
+        assert_eq!(values["core.bare"].as_deref(), Some("false"));
+        // A key without a value is true, and the last of several values wins
+        assert_eq!(values["lfs.walrus.offline"], None);
+        assert_eq!(values["lfs.walrus.tag"].as_deref(), Some("last"));
+
+        assert_eq!(canonical_key("LFS.Walrus.VerifySmudge"), "lfs.Walrus.verifysmudge");
+        assert_eq!(canonical_key("core.autoCRLF"), "core.autocrlf");
+    }
+
+    #[test]
+    fn booleans_are_parsed_as_git_does() {
+        for (value, expected) in [
+            (None, true),
+            (Some("yes"), true),
+            (Some("On"), true),
+            (Some("2"), true),
+            (Some(""), false),
+            (Some("off"), false),
+            (Some("0"), false),
+        ] {
+            assert_eq!(parse_bool("lfs.walrus.offline", value).unwrap(), expected, "{:?}", value);
+        }
+        let err = parse_bool("lfs.walrus.offline", Some("maybee")).unwrap_err();
+        assert_eq!(err.to_string(), "lfs.walrus.offline \"maybee\" is not a boolean");
+    }
+
+    #[test]
+    fn line_endings_follow_the_text_attribute_then_autocrlf() {
+        assert!(line_endings_normalized("set", "unspecified", None));
+        assert!(line_endings_normalized("auto", "unspecified", Some("false")));
+        assert!(!line_endings_normalized("unset", "crlf", Some("true")));
+        assert!(line_endings_normalized("unspecified", "lf", None));
+        assert!(line_endings_normalized("unspecified", "unspecified", Some("input")));
+        assert!(line_endings_normalized("unspecified", "unspecified", Some("TRUE")));
+        assert!(!line_endings_normalized("unspecified", "unspecified", Some("false")));
+        assert!(!line_endings_normalized("unspecified", "unspecified", None));
+    }
+}
@@ -0,0 +1,218 @@
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+use crate::git;
+
+/// What a configured value has to be for git-lfs to use this binary.
+enum Expect {
+    /// Exactly this value
+    Value(&'static str),
+    /// A command line that runs this binary with this subcommand
+    Runs(&'static str),
+    /// A path to this binary
+    Binary,
+}
+
+/// One git config entry that wires git-lfs up to git-lfs-walrus.
+struct Entry {
+    key: &'static str,
+    /// Also accepted in place of `key` (a direct git filter instead of an LFS extension)
+    alternative: Option<&'static str>,
+    expect: Expect,
+    /// What `--fix` writes
+    value: String,
+}
+
+enum EntryState {
+    Ok { key: &'static str, value: String },
+    Missing,
+    Elsewhere(String),
+}
+
+/// Checks that git config routes the clean and smudge filters and the custom transfer to
+/// this binary, as set up in the README, and writes the missing or wrong entries to the
+/// repository's config with `fix`.
+pub async fn install_check(walrus_path: Option<PathBuf>, fix: bool) -> Result<()> {
+    let exe = std::env::current_exe()?;
+    let entries = expected_entries(&exe, walrus_path.as_deref());
+
+    let mut ok = 0;
+    let mut missing = 0;
+    let mut elsewhere = 0;
+    let mut fixed = 0;
+    for entry in &entries {
+        let state = entry_state(entry, &exe)?;
+        match &state {
+            EntryState::Ok { key, value } => {
+                println!("✅ {} = {}", key, value);
+                ok += 1;
+                continue;
+            }
+            EntryState::Missing => {
+                println!("❌ {} is not set", entry.key);
+                missing += 1;
+            }
+            EntryState::Elsewhere(value) => {
+                println!("⚠️  {} = {} does not run {}", entry.key, value, exe.display());
+                elsewhere += 1;
+            }
+        }
+        if fix {
+            git::stdout(&["config", "--local", entry.key, &entry.value])?;
+            println!("🔄 Set {} = {}", entry.key, entry.value);
+            fixed += 1;
+        }
+    }
+
+    println!("\nSummary:");
+    println!("  Configured: {}", ok);
+    println!("  Missing: {}", missing);
+    println!("  Pointing elsewhere: {}", elsewhere);
+    if fix {
+        println!("  Fixed: {}", fixed);
+    } else if missing + elsewhere > 0 {
+        return Err(anyhow::anyhow!(
+            "git is not set up to use this binary; run `git-lfs-walrus-cli install-check --fix`"
+        ));
+    }
+    Ok(())
+}
+
+fn expected_entries(exe: &Path, walrus_path: Option<&Path>) -> Vec<Entry> {
+    let exe_arg = exe.display().to_string();
+    let walrus_args = match walrus_path {
+        Some(path) => format!("--walrus-path {} ", path.display()),
+        None => String::new(),
+    };
+    let entry = |key, alternative, expect, value: String| Entry {
+        key,
+        alternative,
+        expect,
+        value,
+    };
+    vec![
+        entry(
+            "lfs.standalonetransferagent",
+            None,
+            Expect::Value("walrus"),
+            "walrus".to_string(),
+        ),
+        entry("lfs.customtransfer.walrus.path", None, Expect::Binary, exe_arg.clone()),
+        entry(
+            "lfs.customtransfer.walrus.args",
+            None,
+            Expect::Runs("transfer"),
+            format!("{}transfer", walrus_args),
+        ),
+        entry(
+            "lfs.customtransfer.walrus.direction",
+            None,
+            Expect::Value("both"),
+            "both".to_string(),
+        ),
+        entry(
+            "lfs.extension.walrus.clean",
+            Some("filter.walrus.clean"),
+            Expect::Runs("clean"),
+            format!("{} {}clean %f", exe_arg, walrus_args),
+        ),
+        entry(
+            "lfs.extension.walrus.smudge",
+            Some("filter.walrus.smudge"),
+            Expect::Runs("smudge"),
+            format!("{} {}smudge %f", exe_arg, walrus_args),
+        ),
+        entry(
+            "lfs.extension.walrus.priority",
+            Some("filter.walrus.clean"),
+            Expect::Value("0"),
+            "0".to_string(),
+        ),
+    ]
+}
+
+fn entry_state(entry: &Entry, exe: &Path) -> Result<EntryState> {
+    let mut state = EntryState::Missing;
+    for key in std::iter::once(entry.key).chain(entry.alternative) {
+        let Some(value) = git::config_get(key)? else {
+            continue;
+        };
+        // A direct filter has no priority; that it is set at all is enough
+        if key != entry.key && matches!(entry.expect, Expect::Value(_)) {
+            return Ok(EntryState::Ok {
+                key: entry.key,
+                value: format!("(not needed, {} is set)", key),
+            });
+        }
+        let matches = match entry.expect {
+            Expect::Value(expected) => value == expected,
+            Expect::Binary => runs_binary(&value, exe),
+            Expect::Runs(subcommand) => {
+                let words: Vec<&str> = value.split_whitespace().collect();
+                words.contains(&subcommand)
+                    // The transfer args hold only the arguments; its path is checked on its own
+                    && (subcommand == "transfer" || words.iter().any(|word| runs_binary(word, exe)))
+            }
+        };
+        if matches {
+            return Ok(EntryState::Ok { key, value });
+        }
+        state = EntryState::Elsewhere(value);
+    }
+    Ok(state)
+}
+
+/// Whether `program` (a path, a name looked up in `PATH`, or `$VAR`/`${VAR}` as the shell
+/// git runs it through would expand it) is the same file as `exe`.
+fn runs_binary(program: &str, exe: &Path) -> bool {
+    let program = match program
+        .strip_prefix("${")
+        .and_then(|name| name.strip_suffix('}'))
+        .or_else(|| program.strip_prefix('$'))
+    {
+        Some(name) => match std::env::var(name) {
+            Ok(value) => value,
+            Err(_) => return false,
+        },
+        None => program.to_string(),
+    };
+
+    let candidates: Vec<PathBuf> = if program.contains(std::path::MAIN_SEPARATOR) {
+        vec![PathBuf::from(&program)]
+    } else {
+        std::env::var_os("PATH")
+            .map(|path| std::env::split_paths(&path).map(|dir| dir.join(&program)).collect())
+            .unwrap_or_default()
+    };
+    let Ok(exe) = exe.canonicalize() else {
+        return false;
+    };
+    candidates
+        .iter()
+        .filter_map(|candidate| candidate.canonicalize().ok())
+        .any(|candidate| candidate == exe)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn programs_are_matched_by_path_name_or_variable() {
+        let dir = tempfile::tempdir().unwrap();
+        let exe = dir.path().join("git-lfs-walrus-cli");
+        let other = dir.path().join("other-cli");
+        std::fs::write(&exe, "").unwrap();
+        std::fs::write(&other, "").unwrap();
+        let link = dir.path().join("linked-cli");
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&exe, &link).unwrap();
+
+        assert!(runs_binary(&exe.display().to_string(), &exe));
+        assert!(!runs_binary(&other.display().to_string(), &exe));
+        #[cfg(unix)]
+        assert!(runs_binary(&link.display().to_string(), &exe));
+        assert!(!runs_binary("$LFS_WALRUS_INSTALL_CHECK_UNSET_VARIABLE", &exe));
+        assert!(!runs_binary("git-lfs-walrus-cli-not-on-path", &exe));
+    }
+}
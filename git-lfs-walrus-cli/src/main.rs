@@ -5,15 +5,54 @@ use std::path::PathBuf;
 use structopt::StructOpt;
 use tokio::io::{stdin, stdout, BufReader};
 
-use crate::{clean::clean, smudge::smudge, walrus::WalrusClient, walrus_check::walrus_check, walrus_refresh::walrus_refresh, walrus_blob_id::walrus_blob_id};
+use crate::{balance::balance_check, bench::bench, cache_clean::cache_clean, cat::cat, check_worktree::check_worktree, clean::{blob_tag, clean, clean_to_file}, config::{show_config, CommandLine}, dedup_report::dedup_report, delete_blob::delete_blob, download::download, fsck::fsck, install_check::install_check, mapping_audit::mapping_audit, mapping_rebuild::mapping_rebuild, mapping_set::mapping_set, migrate::migrate, orphans::orphans, pointer_repair::pointer_repair, prefetch::prefetch, read_range::read_range, reconcile_pending::reconcile_pending, restore_modes::restore_modes, smudge::smudge, snapshot::{export_snapshot, import_snapshot}, storage_diff::storage_diff, walrus::WalrusClient, walrus_check::walrus_check, walrus_refresh::walrus_refresh, walrus_blob_id::walrus_blob_id};
 
+mod aggregator;
+mod balance;
+mod bench;
+mod blob_id;
+mod bulk;
+mod cache_clean;
+mod check_worktree;
+mod chunks;
+mod cat;
 mod clean;
+mod config;
+mod content_type;
+mod dedup_report;
+mod delete_blob;
+mod download;
+mod duration;
+mod error_log;
+mod event_log;
+mod fsck;
+mod git;
+mod install_check;
+mod mapping;
+mod mapping_audit;
+mod mapping_rebuild;
+mod mapping_set;
+mod metrics;
+mod migrate;
+mod oid;
+mod orphans;
+mod pending;
+mod pointer;
+mod pointer_repair;
+mod prefetch;
+mod read_range;
+mod reconcile_pending;
+mod resolve;
+mod restore_modes;
 mod smudge;
+mod snapshot;
+mod storage_diff;
 mod transfer;
 mod walrus;
 mod walrus_check;
 mod walrus_refresh;
 mod walrus_blob_id;
+mod walrus_trace;
 
 #[derive(Debug, StructOpt)]
 #[structopt(author, about)]
@@ -23,6 +62,34 @@ struct GitLfsWalrus {
 
     #[structopt(long, env = "WALRUS_CLI_PATH")]
     walrus_path: Option<PathBuf>,
+
+    /// Store blobs for this long (e.g. 30d, 12h, 2w), rounded to whole epochs; overrides
+    /// lfs.walrus.defaultduration and lfs.walrus.defaultepochs
+    #[structopt(long, global = true)]
+    duration: Option<duration::StorageDuration>,
+
+    /// Pay for store transactions from this SUI coin object; overrides lfs.walrus.gascoin
+    #[structopt(long, global = true, env = "LFS_WALRUS_GAS_COIN", parse(try_from_str = walrus::parse_gas_coin))]
+    gas_coin: Option<String>,
+
+    /// Use the mapping file of this Walrus network (e.g. mainnet), so blob IDs stored on
+    /// different networks never mix; overrides lfs.walrus.network
+    #[structopt(long, global = true, parse(try_from_str = mapping::parse_network))]
+    network: Option<String>,
+
+    /// Read every stored blob back and check its SHA256 before reporting the store as done;
+    /// slower, but proves the content is fetchable (lfs.walrus.verifyafterstore)
+    #[structopt(long, global = true)]
+    verify_after_store: bool,
+
+    /// Write a JSON summary of walrus operation counts and latencies to this file on exit
+    #[structopt(long, env = "LFS_WALRUS_METRICS")]
+    metrics: Option<PathBuf>,
+
+    /// Record the request and verbatim response of each walrus JSON command to files in this
+    /// directory, for debugging or bug reports
+    #[structopt(long, env = "LFS_WALRUS_TRACE")]
+    trace_walrus: Option<PathBuf>,
 }
 
 #[derive(Debug, StructOpt)]
@@ -33,55 +100,411 @@ enum Command {
     Smudge {
         /// Name of the file
         _filename: PathBuf,
+        /// Write the content even if it doesn't match the pointer's OID (also
+        /// lfs.walrus.verifysmudge false)
+        #[structopt(long)]
+        no_verify: bool,
     },
     /// git-lfs clean filter extension for Walrus
     ///
     /// <https://github.com/git-lfs/git-lfs/blob/main/docs/extensions.md#clean>
     Clean {
         /// Name of the file
-        _filename: PathBuf,
+        filename: PathBuf,
+        /// Fail if walrus reports the content as already certified
+        #[structopt(long)]
+        fail_on_already_certified: bool,
+        /// Wait until each stored blob is certified (lfs.walrus.certifytimeoutsecs)
+        #[structopt(long)]
+        wait_certified: bool,
+        /// Store the content a symlink points to instead of refusing it
+        #[structopt(long)]
+        dereference_symlinks: bool,
+        /// Label the stored blob with this `tag` attribute on Walrus (defaults to the filename
+        /// with lfs.walrus.tagfilenames)
+        #[structopt(long)]
+        tag: Option<String>,
+        /// Only warn when the mapping file can't be written, even with
+        /// lfs.walrus.requiremapping (for ephemeral CI checkouts)
+        #[structopt(long)]
+        continue_on_mapping_error: bool,
+        /// Clean the named file itself and write its pointer here instead of stdout, reporting
+        /// the blob ID and cost on stderr (for debugging the filter without git)
+        #[structopt(long, hidden = true)]
+        emit_pointer: Option<PathBuf>,
     },
     /// git-lfs custom transfer for Walrus
     ///
     /// <https://github.com/git-lfs/git-lfs/blob/main/docs/custom-transfers.md>
-    Transfer,
+    Transfer {
+        /// Acknowledge every request without touching Walrus (for testing the git-lfs wiring
+        /// only; downloads produce placeholder files)
+        #[structopt(long)]
+        dry_run: bool,
+        /// Fail uploads whose content walrus reports as already certified
+        #[structopt(long)]
+        fail_on_already_certified: bool,
+        /// Wait until each stored blob is certified (lfs.walrus.certifytimeoutsecs)
+        #[structopt(long)]
+        wait_certified: bool,
+    },
     /// Check if files stored in Walrus have expired
     WalrusCheck {
         /// Files to check (if none provided, checks all LFS files)
         files: Vec<PathBuf>,
+        /// Attempt every file even after an error that would stop the run, then fail if any did
+        #[structopt(long)]
+        keep_going: bool,
+        /// Judge expiry from the end epoch recorded in pointers, with one current-epoch query
+        #[structopt(long)]
+        fast: bool,
+        /// Query walrus for every blob even if it was checked within lfs.walrus.checkcachettl
+        #[structopt(long)]
+        no_cache: bool,
+        /// Also show the `tag` attribute each blob was stored with
+        #[structopt(long)]
+        show_tags: bool,
     },
     /// Refresh expired files in Walrus
     WalrusRefresh {
-        /// Files to refresh (if none provided, refreshes all expired LFS files)
+        /// Files to refresh if expired (if none provided, refreshes all expired LFS files)
         files: Vec<PathBuf>,
+        /// Only refresh blobs with fewer than this many epochs of storage left
+        #[structopt(long)]
+        expiring_within: Option<u64>,
+        /// Attempt every file even after an error that would stop the run, then fail if any did
+        #[structopt(long)]
+        keep_going: bool,
+        /// Store the files again even if their blobs are still live
+        #[structopt(long, conflicts_with = "expiring-within")]
+        force: bool,
+        /// Print the counts and each file's old and new blob ID as JSON
+        #[structopt(long)]
+        json: bool,
+        /// Append each blob ID change to .git/walrus-refresh-log.jsonl (or set lfs.walrus.refreshlog)
+        #[structopt(long)]
+        log: bool,
     },
     /// Show the actual Walrus blob ID for a file
     WalrusBlobId {
         /// File to get blob ID for
         file: PathBuf,
+        /// "id" for the bare blob ID, "json", or "full" for the human-readable report
+        #[structopt(long, default_value = "full")]
+        format: walrus_blob_id::BlobIdFormat,
+    },
+    /// Record a known SHA256 -> Walrus blob ID mapping without re-uploading
+    MappingSet {
+        /// SHA256 of the file content
+        sha256: Option<String>,
+        /// Walrus blob ID holding that content
+        blob_id: Option<String>,
+        /// Import `sha256,blob_id` lines from a CSV file instead
+        #[structopt(long)]
+        from_csv: Option<PathBuf>,
+        /// Check that each blob exists in Walrus before recording it
+        #[structopt(long)]
+        verify: bool,
+    },
+    /// Report LFS pointers whose inline blob ID disagrees with the mapping file
+    MappingAudit {
+        /// Files to audit (if none provided, audits all LFS files)
+        files: Vec<PathBuf>,
+        /// Reconcile mismatches instead of only reporting them
+        #[structopt(long)]
+        fix: bool,
+        /// Which blob ID --fix keeps: "pointer" or "mapping"
+        #[structopt(long, default_value = "pointer")]
+        prefer: mapping_audit::SourceOfTruth,
+    },
+    /// Regenerate the mapping file from the inline blob IDs of every pointer in the history
+    /// and the index (the previous file is kept as a backup)
+    MappingRebuild,
+    /// Write the Walrus content of an LFS-tracked file without checking it out
+    Cat {
+        /// Path of the LFS-tracked file
+        file: PathBuf,
+        /// Write to this file instead of stdout
+        #[structopt(long, short)]
+        output: Option<PathBuf>,
+        /// Print the MIME type recorded by lfs.walrus.contenttype instead of the content
+        #[structopt(long)]
+        content_type: bool,
+        /// Overwrite --output even if it holds content other than the pointer's
+        #[structopt(long)]
+        force: bool,
+    },
+    /// Store the content of LFS files in Walrus and record their mappings
+    Migrate {
+        /// Files to migrate (if none provided, migrates all LFS files)
+        files: Vec<PathBuf>,
+        /// Print the summary as JSON
+        #[structopt(long)]
+        json: bool,
+        /// Count files whose content walrus reports as already certified as errors
+        #[structopt(long)]
+        fail_on_already_certified: bool,
+        /// Wait until each stored blob is certified (lfs.walrus.certifytimeoutsecs)
+        #[structopt(long)]
+        wait_certified: bool,
+        /// Attempt every file even after an error that would stop the run, then fail if any did
+        #[structopt(long)]
+        keep_going: bool,
+        /// Skip the files up to and including this one (default: resume an unfinished run over
+        /// the same files)
+        #[structopt(long)]
+        resume_from: Option<PathBuf>,
+        /// Abort before uploading anything if the wallet can't cover the estimated cost
+        #[structopt(long)]
+        check_balance: bool,
+        /// List which files would be uploaded or skipped, with sizes and dry-run cost
+        /// estimates, without uploading anything
+        #[structopt(long)]
+        plan: bool,
+    },
+    /// Check that the wallet's WAL balance covers storing files, from dry-run cost estimates
+    BalanceCheck {
+        /// Files to estimate (if none provided, estimates all LFS files)
+        files: Vec<PathBuf>,
+        /// Extra balance to require over the estimate, in percent
+        #[structopt(long, default_value = "10")]
+        margin: u64,
+    },
+    /// Add the inline Walrus blob ID back to LFS pointers that lost it, using the mapping file
+    PointerRepair {
+        /// Pointer files to repair
+        files: Vec<PathBuf>,
+        /// Repair every LFS file in the repository
+        #[structopt(long)]
+        all: bool,
+    },
+    /// Report how many LFS files share Walrus storage, and the bytes that saves
+    DedupReport {
+        /// Print the report as JSON
+        #[structopt(long)]
+        json: bool,
+    },
+    /// Report the LFS content a ref adds over a base ref, its size and its storage cost
+    StorageDiff {
+        /// Ref the content is compared against, e.g. the merge target
+        base: String,
+        /// Ref whose new content is reported
+        head: String,
+        /// Print the report as JSON
+        #[structopt(long)]
+        json: bool,
+        /// Skip the dry-run cost estimate (no walrus calls)
+        #[structopt(long)]
+        no_cost: bool,
+    },
+    /// Measure store and read throughput against the configured Walrus network
+    #[structopt(setting = structopt::clap::AppSettings::Hidden)]
+    Bench {
+        /// Size of each benchmark blob, in MiB
+        #[structopt(long, default_value = "10")]
+        size_mb: u64,
+        /// Number of store/read round trips
+        #[structopt(long, default_value = "5")]
+        iterations: u64,
+    },
+    /// Apply the file modes recorded by lfs.walrus.preservemode (run from a post-checkout hook)
+    RestoreModes {
+        /// Files to restore (if none provided, restores every file with a recorded mode)
+        files: Vec<PathBuf>,
+    },
+    /// Download the content of LFS files into the local cache that smudge reads from
+    Prefetch {
+        /// Revision whose files to fetch (default: the index and HEAD)
+        revision: Option<String>,
+        /// Started by smudge: stay quiet, and exit if another prefetch is running
+        #[structopt(long, hidden = true)]
+        background: bool,
+    },
+    /// Evict entries from the local cache that smudge reads from, and report the bytes reclaimed
+    CacheClean {
+        /// Evict entries not used for this long (e.g. 30d, 12h, 2w)
+        #[structopt(long)]
+        max_age: Option<duration::StorageDuration>,
+        /// Then evict the least recently used entries until the cache is at most this many bytes
+        #[structopt(long)]
+        max_size: Option<u64>,
+        /// Evict every entry
+        #[structopt(long, conflicts_with_all = &["max-age", "max-size"])]
+        all: bool,
+    },
+    /// Stream one blob to stdout, by Walrus blob ID or by OID (without a git-lfs transfer)
+    Download {
+        /// Walrus blob ID, or an OID as `sha256:<hash>` or the bare hash
+        blob_id_or_oid: String,
+    },
+    /// Show every setting in effect and where its value comes from
+    Config {
+        /// Print the settings as JSON
+        #[structopt(long)]
+        json: bool,
+    },
+    /// Re-hash checked-out LFS files and report any whose content no longer matches its
+    /// pointer (changed without the clean filter)
+    CheckWorktree,
+    /// Store the mapping file as a single Walrus blob and print its blob ID, so a fresh clone
+    /// can bootstrap from it
+    ExportSnapshot {
+        /// Include every staged pointer (path, OID, size and inline blob ID)
+        #[structopt(long)]
+        with_pointers: bool,
+    },
+    /// Fetch a snapshot stored by export-snapshot and add its entries to the mapping file
+    ImportSnapshot {
+        /// Walrus blob ID printed by export-snapshot
+        blob_id: String,
+    },
+    /// Check that git config routes the filter and custom transfer to this binary
+    InstallCheck {
+        /// Write the missing or wrong entries to the repository's git config
+        #[structopt(long)]
+        fix: bool,
+    },
+    /// Delete a blob stored as deletable from Walrus and remove its mapping entries
+    DeleteBlob {
+        /// Walrus blob ID to delete
+        blob_id: String,
+        /// Delete the blob even if a staged or committed pointer still refers to it
+        #[structopt(long)]
+        force: bool,
+    },
+    /// List blobs the mapping file records that no pointer in any ref's history refers to
+    Orphans {
+        /// Delete them from Walrus (they must be deletable) and remove their mapping entries
+        #[structopt(long)]
+        delete: bool,
+    },
+    /// Resolve blobs stored by interrupted runs that no mapping entry or pointer refers to
+    ReconcilePending {
+        /// Read each one back and add a mapping entry for its content
+        #[structopt(long, conflicts_with = "delete")]
+        adopt: bool,
+        /// Delete each one from Walrus (it must be deletable)
+        #[structopt(long)]
+        delete: bool,
+    },
+    /// Verify that every reachable LFS object can be read from Walrus and matches its OID
+    Fsck {
+        /// Number of objects to verify concurrently
+        #[structopt(long, default_value = "4")]
+        jobs: usize,
+        /// Check objects from the whole history instead of the branch tips and HEAD
+        #[structopt(long)]
+        all: bool,
+    },
+    /// Fetch a byte range of a blob through the configured aggregator (no SHA256 check)
+    ReadRange {
+        /// Walrus blob ID to read from
+        blob_id: String,
+        /// Offset of the first byte to read
+        #[structopt(long, default_value = "0")]
+        start: u64,
+        /// Number of bytes to read
+        #[structopt(long)]
+        length: u64,
+        /// Write to this file instead of stdout
+        #[structopt(long)]
+        output: Option<PathBuf>,
     },
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let args = GitLfsWalrus::from_args();
-    let client = if let Some(path) = args.walrus_path {
+    let matches = GitLfsWalrus::clap().get_matches();
+    let operation = matches.subcommand_name().unwrap_or_default().to_string();
+    let args = GitLfsWalrus::from_clap(&matches);
+    let command_line = CommandLine {
+        walrus_path: args.walrus_path.clone(),
+        duration: args.duration.is_some(),
+        gas_coin: args.gas_coin.is_some(),
+        verify_after_store: args.verify_after_store,
+        network: args.network.is_some(),
+        metrics: args.metrics.clone(),
+        trace_walrus: args.trace_walrus.clone(),
+    };
+    let mut client = if let Some(path) = args.walrus_path {
         WalrusClient::with_path(path)
     } else {
         crate::walrus::client()
     };
+    if let Some(duration) = args.duration {
+        client.set_storage_duration(duration);
+    }
+    if let Some(coin) = args.gas_coin {
+        client.set_gas_coin(coin);
+    }
+    if let Some(network) = args.network {
+        mapping::set_network(network);
+    }
+    if args.verify_after_store {
+        client.set_verify_after_store();
+    }
+    if let Some(dir) = args.trace_walrus {
+        client.set_trace_dir(dir);
+    }
 
-    match args.command {
-        Command::Smudge { .. } => smudge(client, stdin(), stdout()).await,
-        Command::Clean { .. } => clean(client, std::io::stdin(), stdout()).await,
-        Command::Transfer => {
+    let result = match args.command {
+        Command::Smudge { no_verify, .. } => smudge(client, stdin(), stdout(), !no_verify).await,
+        Command::Clean {
+            filename,
+            fail_on_already_certified,
+            wait_certified,
+            dereference_symlinks,
+            tag,
+            continue_on_mapping_error,
+            emit_pointer,
+        } => {
+            if let Some(tag) = blob_tag(tag, &filename) {
+                client.set_blob_tag(tag);
+            }
+            let options = clean::CleanOptions {
+                fail_on_already_certified,
+                wait_certified,
+                dereference_symlinks,
+                continue_on_mapping_error,
+            };
+            match emit_pointer {
+                Some(pointer_path) => clean_to_file(client, &filename, &pointer_path, options).await,
+                None => clean(client, std::io::stdin(), stdout(), &filename, options)
+                    .await
+                    .map(drop),
+            }
+        }
+        Command::Transfer {
+            dry_run,
+            fail_on_already_certified,
+            wait_certified,
+        } => {
+            if dry_run {
+                eprintln!(
+                    "Warning: transfer is running with --dry-run; nothing is stored in or read from Walrus"
+                );
+            }
+            let event_log = event_log::EventLog::from_env()?;
             let buffered_stdin = BufReader::new(stdin());
-            let input_event_stream = transfer::read_events(buffered_stdin);
+            let input_event_stream =
+                transfer::read_events(buffered_stdin).inspect(|event| {
+                    if let (Some(log), Ok(event)) = (&event_log, event) {
+                        log.record(event_log::Direction::In, event);
+                    }
+                });
             let download_folder = std::env::current_dir()?;
+            let options = transfer::TransferOptions {
+                dry_run,
+                fail_on_already_certified,
+                wait_certified,
+            };
             let output_event_stream =
-                transfer::transfer(client, input_event_stream, download_folder);
+                transfer::transfer(client, input_event_stream, download_folder, options);
             futures_util::pin_mut!(output_event_stream);
             while let Some(output_event) = output_event_stream.next().await.transpose()? {
+                if let Some(log) = &event_log {
+                    log.record(event_log::Direction::Out, &output_event);
+                }
                 if Event::AcknowledgeInit == output_event {
                     println!("{{ }}");
                 } else {
@@ -90,8 +513,106 @@ async fn main() -> Result<()> {
             }
             Ok(())
         }
-        Command::WalrusCheck { files } => walrus_check(client, files).await,
-        Command::WalrusRefresh { files } => walrus_refresh(client, files).await,
-        Command::WalrusBlobId { file } => walrus_blob_id(client, file).await,
+        Command::WalrusCheck {
+            files,
+            keep_going,
+            fast,
+            no_cache,
+            show_tags,
+        } => walrus_check(client, files, keep_going, fast, no_cache, show_tags).await,
+        Command::WalrusRefresh {
+            files,
+            expiring_within,
+            keep_going,
+            force,
+            json,
+            log,
+        } => {
+            walrus_refresh(client, files, expiring_within, keep_going, force, json, log).await
+        }
+        Command::WalrusBlobId { file, format } => walrus_blob_id(client, file, format).await,
+        Command::MappingSet {
+            sha256,
+            blob_id,
+            from_csv,
+            verify,
+        } => mapping_set(client, sha256, blob_id, from_csv, verify).await,
+        Command::MappingAudit { files, fix, prefer } => mapping_audit(files, fix, prefer).await,
+        Command::MappingRebuild => mapping_rebuild().await,
+        Command::Cat {
+            file,
+            output,
+            content_type,
+            force,
+        } => cat(client, file, output, content_type, force).await,
+        Command::Migrate {
+            files,
+            json,
+            fail_on_already_certified,
+            wait_certified,
+            keep_going,
+            resume_from,
+            check_balance,
+            plan,
+        } => {
+            let options = migrate::MigrateOptions {
+                json,
+                fail_on_already_certified,
+                wait_certified,
+                keep_going,
+                resume_from,
+                check_balance,
+                plan,
+            };
+            migrate(client, files, options).await
+        }
+        Command::BalanceCheck { files, margin } => balance_check(client, files, margin).await,
+        Command::PointerRepair { files, all } => pointer_repair(files, all).await,
+        Command::DedupReport { json } => dedup_report(json).await,
+        Command::StorageDiff {
+            base,
+            head,
+            json,
+            no_cost,
+        } => storage_diff(client, base, head, json, no_cost).await,
+        Command::Bench {
+            size_mb,
+            iterations,
+        } => bench(client, size_mb, iterations).await,
+        Command::RestoreModes { files } => restore_modes(files).await,
+        Command::Prefetch {
+            revision,
+            background,
+        } => prefetch(client, revision, background).await,
+        Command::CacheClean {
+            max_age,
+            max_size,
+            all,
+        } => cache_clean(max_age, max_size, all).await,
+        Command::Download { blob_id_or_oid } => download(client, blob_id_or_oid).await,
+        Command::Config { json } => show_config(&client, command_line, json).await,
+        Command::CheckWorktree => check_worktree().await,
+        Command::ExportSnapshot { with_pointers } => export_snapshot(client, with_pointers).await,
+        Command::ImportSnapshot { blob_id } => import_snapshot(client, blob_id).await,
+        Command::InstallCheck { fix } => install_check(command_line.walrus_path.clone(), fix).await,
+        Command::DeleteBlob { blob_id, force } => delete_blob(client, blob_id, force).await,
+        Command::Orphans { delete } => orphans(client, delete).await,
+        Command::ReconcilePending { adopt, delete } => reconcile_pending(client, adopt, delete).await,
+        Command::Fsck { jobs, all } => fsck(client, jobs, all).await,
+        Command::ReadRange {
+            blob_id,
+            start,
+            length,
+            output,
+        } => read_range(client, blob_id, start, length, output).await,
+    };
+
+    if let Err(err) = &result {
+        error_log::record(&operation, err);
+    }
+    if let Some(path) = args.metrics {
+        metrics::write_summary(&path)?;
     }
+
+    result
 }
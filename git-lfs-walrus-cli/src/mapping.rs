@@ -0,0 +1,492 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::blob_id;
+use crate::git::{self, GitError};
+use crate::pending;
+
+/// What we know about a blob stored for a given SHA256.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MappingEntry {
+    pub blob_id: String,
+    /// Sui transaction that created (or certified) the blob, when walrus reported one
+    pub tx_digest: Option<String>,
+    /// Unix timestamp of the store
+    pub stored_at: Option<u64>,
+    pub epochs: Option<u64>,
+}
+
+impl MappingEntry {
+    pub fn new(blob_id: String, tx_digest: Option<String>, epochs: u64) -> Self {
+        let stored_at = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .ok();
+        Self {
+            blob_id,
+            tx_digest,
+            stored_at,
+            epochs: Some(epochs),
+        }
+    }
+
+    /// An entry for a blob stored elsewhere, of which only the ID is known.
+    pub fn from_blob_id(blob_id: String) -> Self {
+        Self {
+            blob_id: blob_id::normalize(&blob_id),
+            tx_digest: None,
+            stored_at: None,
+            epochs: None,
+        }
+    }
+}
+
+/// Mapping values as found on disk: older versions stored the bare blob ID.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredValue {
+    Legacy(String),
+    Entry(MappingEntry),
+}
+
+impl From<StoredValue> for MappingEntry {
+    fn from(value: StoredValue) -> Self {
+        match value {
+            StoredValue::Legacy(blob_id) => MappingEntry::from_blob_id(blob_id),
+            StoredValue::Entry(entry) => MappingEntry {
+                blob_id: blob_id::normalize(&entry.blob_id),
+                ..entry
+            },
+        }
+    }
+}
+
+pub type Mappings = BTreeMap<String, MappingEntry>;
+
+/// Version of the mapping file format written by this build.
+///
+/// Version 1 was a bare `{ sha256: blob_id }` object without an envelope.
+const MAPPING_VERSION: u64 = 2;
+
+#[derive(Serialize)]
+struct MappingFile<'a> {
+    version: u64,
+    entries: &'a Mappings,
+}
+
+/// The mapping file was written by a newer version of git-lfs-walrus.
+#[derive(Debug)]
+pub struct UnsupportedMappingVersion(pub u64);
+
+impl std::fmt::Display for UnsupportedMappingVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "mapping file version {} is newer than the supported version {}; upgrade git-lfs-walrus",
+            self.0, MAPPING_VERSION
+        )
+    }
+}
+
+impl std::error::Error for UnsupportedMappingVersion {}
+
+fn parse_mappings(content: &str) -> Result<Mappings> {
+    let mut value: serde_json::Value = serde_json::from_str(content)?;
+
+    // Anything without a version is a legacy bare map
+    let entries = match value.get("version") {
+        Some(version) => {
+            let version = version
+                .as_u64()
+                .ok_or_else(|| anyhow::anyhow!("mapping file version is not a number"))?;
+            if version > MAPPING_VERSION {
+                return Err(UnsupportedMappingVersion(version).into());
+            }
+            value
+                .get_mut("entries")
+                .map(serde_json::Value::take)
+                .unwrap_or_default()
+        }
+        None => value,
+    };
+
+    let stored: BTreeMap<String, StoredValue> = serde_json::from_value(entries)?;
+    Ok(stored.into_iter().map(|(k, v)| (k, v.into())).collect())
+}
+
+fn serialize_mappings(mappings: &Mappings) -> Result<String> {
+    Ok(serde_json::to_string_pretty(&MappingFile {
+        version: MAPPING_VERSION,
+        entries: mappings,
+    })?)
+}
+
+async fn read_mappings(mapping_file: &Path) -> Result<Mappings> {
+    if !mapping_file.exists() {
+        return Ok(Mappings::new());
+    }
+    let content = tokio::fs::read_to_string(mapping_file).await?;
+    match parse_mappings(&content) {
+        Ok(mappings) => Ok(mappings),
+        // Never treat a newer file as empty: the next write would drop its entries
+        Err(err) if err.is::<UnsupportedMappingVersion>() => Err(err),
+        Err(err) => {
+            // Start fresh, but keep the unreadable file so its entries can be recovered by hand
+            let backup = backup_path(mapping_file);
+            tokio::fs::rename(mapping_file, &backup).await.map_err(|rename_err| {
+                anyhow::anyhow!(
+                    "{} is corrupt ({}) and could not be backed up: {}",
+                    mapping_file.display(),
+                    err,
+                    rename_err
+                )
+            })?;
+            eprintln!(
+                "WARNING: {} is corrupt ({}); moved it to {} and starting a new mapping. Recover entries from the backup with `git-lfs-walrus-cli mapping-set`.",
+                mapping_file.display(),
+                err,
+                backup.display()
+            );
+            Ok(Mappings::new())
+        }
+    }
+}
+
+/// `<mapping file>.bak`, or `.bak.1`, `.bak.2`, ... if earlier backups exist.
+fn backup_path(mapping_file: &Path) -> PathBuf {
+    let mut backup = mapping_file.as_os_str().to_owned();
+    backup.push(".bak");
+    let base = PathBuf::from(backup);
+    let mut candidate = base.clone();
+    let mut n = 0;
+    while candidate.exists() {
+        n += 1;
+        let mut numbered = base.as_os_str().to_owned();
+        numbered.push(format!(".{}", n));
+        candidate = PathBuf::from(numbered);
+    }
+    candidate
+}
+
+pub async fn store_blob_mapping(sha256_hex: &str, entry: &MappingEntry) -> Result<()> {
+    store_blob_mappings(vec![(sha256_hex.to_string(), entry.clone())]).await
+}
+
+/// Adds all of `entries` to the mapping file with a single rewrite.
+pub async fn store_blob_mappings(entries: Vec<(String, MappingEntry)>) -> Result<()> {
+    let mapping_file = get_mapping_file_path()?;
+
+    // Read existing mappings
+    let mut mappings = read_mappings(&mapping_file).await?;
+
+    let blob_ids: Vec<String> = entries.iter().map(|(_, entry)| entry.blob_id.clone()).collect();
+
+    // Add new mappings
+    mappings.extend(entries);
+
+    write_mappings(&mapping_file, &mappings).await?;
+    pending::resolve(&blob_ids.iter().map(String::as_str).collect::<Vec<_>>());
+    Ok(())
+}
+
+/// Removes every entry pointing at `blob_id` (both its content-SHA and pointer-SHA keys),
+/// returning how many were removed.
+pub async fn remove_blob_mappings(blob_id: &str) -> Result<usize> {
+    let mapping_file = get_mapping_file_path()?;
+    let mut mappings = read_mappings(&mapping_file).await?;
+    let removed = remove_blob_id(&mut mappings, blob_id);
+    if removed > 0 {
+        write_mappings(&mapping_file, &mappings).await?;
+    }
+    Ok(removed)
+}
+
+/// Replaces the whole mapping file with `mappings`, first copying an existing file aside as
+/// for a corrupt one. Returns where the copy went.
+pub async fn replace_mappings(mappings: &Mappings) -> Result<Option<PathBuf>> {
+    let mapping_file = get_mapping_file_path()?;
+    let backup = if mapping_file.exists() {
+        let backup = backup_path(&mapping_file);
+        tokio::fs::copy(&mapping_file, &backup).await?;
+        Some(backup)
+    } else {
+        None
+    };
+    write_mappings(&mapping_file, mappings).await?;
+    Ok(backup)
+}
+
+fn remove_blob_id(mappings: &mut Mappings, blob_id: &str) -> usize {
+    let before = mappings.len();
+    mappings.retain(|_, entry| entry.blob_id != blob_id);
+    before - mappings.len()
+}
+
+/// Replaces the mapping file through a rename, so a crash mid-write never truncates it.
+async fn write_mappings(mapping_file: &Path, mappings: &Mappings) -> Result<()> {
+    let content = serialize_mappings(mappings)?;
+    let parent = match mapping_file.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    tokio::fs::create_dir_all(parent).await?;
+
+    let temp_file = tempfile::NamedTempFile::new_in(parent)?;
+    tokio::fs::write(temp_file.path(), content).await?;
+    temp_file
+        .persist(mapping_file)
+        .map_err(|err| anyhow::Error::new(err.error))?;
+    Ok(())
+}
+
+/// Mapping entries recorded during a bulk operation, written in batches instead of rewriting
+/// the mapping file for every entry.
+pub struct MappingBatch {
+    /// Entries from the mapping file plus those recorded since
+    known: Mappings,
+    pending: Vec<(String, MappingEntry)>,
+}
+
+impl MappingBatch {
+    /// Pending entries are written once this many have accumulated.
+    const FLUSH_EVERY: usize = 100;
+
+    pub async fn load() -> Result<Self> {
+        Ok(Self {
+            known: load_mappings().await?,
+            pending: Vec::new(),
+        })
+    }
+
+    pub fn get(&self, sha256_hex: &str) -> Option<&MappingEntry> {
+        self.known.get(sha256_hex)
+    }
+
+    pub async fn insert(&mut self, sha256_hex: &str, entry: MappingEntry) -> Result<()> {
+        self.known.insert(sha256_hex.to_string(), entry.clone());
+        self.pending.push((sha256_hex.to_string(), entry));
+        if self.pending.len() >= Self::FLUSH_EVERY {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+
+    /// Writes the pending entries, merged into the file as it is now.
+    pub async fn flush(&mut self) -> Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        store_blob_mappings(std::mem::take(&mut self.pending)).await
+    }
+}
+
+/// The network given with `--network`, which takes precedence over `lfs.walrus.network`.
+static NETWORK: OnceLock<String> = OnceLock::new();
+
+/// Scopes the mapping file to `network` for the rest of this process.
+pub fn set_network(network: String) {
+    let _ = NETWORK.set(network);
+}
+
+/// Checks a network name, which becomes part of the mapping file's name.
+pub fn parse_network(network: &str) -> Result<String> {
+    if network.is_empty()
+        || !network
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(anyhow::anyhow!(
+            "Network name {:?} may only contain letters, digits, '-' and '_'",
+            network
+        ));
+    }
+    Ok(network.to_string())
+}
+
+/// The network whose blob IDs the mapping file holds: `--network`, else `lfs.walrus.network`.
+/// `None` (or `default`) is the default scope, which is where mappings made before networks
+/// could be told apart live.
+pub fn network() -> Result<Option<String>> {
+    let network = match NETWORK.get() {
+        Some(network) => network.clone(),
+        None => match git::config_get("lfs.walrus.network")? {
+            Some(network) => parse_network(network.trim())
+                .map_err(|err| anyhow::anyhow!("lfs.walrus.network: {}", err))?,
+            None => return Ok(None),
+        },
+    };
+    Ok((network != DEFAULT_NETWORK).then_some(network))
+}
+
+/// The network name standing for the default scope.
+pub const DEFAULT_NETWORK: &str = "default";
+
+/// `walrus-mapping.json` for the default scope, or `walrus-mapping.<network>.json`.
+fn mapping_file_name(network: Option<&str>) -> String {
+    match network {
+        Some(network) => format!("walrus-mapping.{}.json", network),
+        None => "walrus-mapping.json".to_string(),
+    }
+}
+
+pub fn get_mapping_file_path() -> Result<PathBuf> {
+    repo_file_path(&mapping_file_name(network()?.as_deref()))
+}
+
+/// `name` in the git directory or, outside a repository (or without git), `.<name>` in the
+/// current directory.
+pub fn repo_file_path(name: &str) -> Result<PathBuf> {
+    match git::git_dir() {
+        Ok(git_dir) => Ok(git_dir.join(name)),
+        Err(GitError::GitNotAvailable(_) | GitError::NotARepo) => {
+            Ok(std::env::current_dir()?.join(format!(".{}", name)))
+        }
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Reads every entry in the mapping file.
+pub async fn load_mappings() -> Result<Mappings> {
+    read_mappings(&get_mapping_file_path()?).await
+}
+
+pub async fn get_mapping_entry(sha256_hex: &str) -> Result<Option<MappingEntry>> {
+    let mapping_file = get_mapping_file_path()?;
+    let mut mappings = read_mappings(&mapping_file).await?;
+    Ok(mappings.remove(sha256_hex))
+}
+
+pub async fn get_blob_id_from_sha(sha256_hex: &str) -> Result<Option<String>> {
+    Ok(get_mapping_entry(sha256_hex)
+        .await?
+        .map(|entry| entry.blob_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn parse_mappings_accepts_legacy_and_object_values() {
+        let mappings = parse_mappings(
+            r#"{
+                "aaaa": "legacy-blob-id",
+                "bbbb": {"blob_id": "new-blob-id", "tx_digest": "digest", "stored_at": 1700000000, "epochs": 5}
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            mappings["aaaa"],
+            MappingEntry {
+                blob_id: "legacy-blob-id".to_string(),
+                tx_digest: None,
+                stored_at: None,
+                epochs: None,
+            }
+        );
+        assert_eq!(
+            mappings["bbbb"],
+            MappingEntry {
+                blob_id: "new-blob-id".to_string(),
+                tx_digest: Some("digest".to_string()),
+                stored_at: Some(1700000000),
+                epochs: Some(5),
+            }
+        );
+    }
+
+    #[test]
+    fn parse_mappings_canonicalizes_standard_base64_blob_ids() {
+        let mappings = parse_mappings(
+            r#"{
+                "aaaa": "M4hsZGQ1oCktdzegB6HnI6Mi28S2nqOPHxK+W7/4BUk=",
+                "bbbb": {"blob_id": "M4hsZGQ1oCktdzegB6HnI6Mi28S2nqOPHxK+W7/4BUk"}
+            }"#,
+        )
+        .unwrap();
+
+        for entry in mappings.values() {
+            assert_eq!(entry.blob_id, "M4hsZGQ1oCktdzegB6HnI6Mi28S2nqOPHxK-W7_4BUk");
+        }
+    }
+
+    #[test]
+    fn each_network_has_its_own_mapping_file() {
+        assert_eq!(mapping_file_name(None), "walrus-mapping.json");
+        assert_eq!(mapping_file_name(Some("mainnet")), "walrus-mapping.mainnet.json");
+        assert_eq!(parse_network("test_net-2").unwrap(), "test_net-2");
+        assert!(parse_network("../mainnet").is_err());
+        assert!(parse_network("").is_err());
+    }
+
+    #[test]
+    fn parse_mappings_reads_versioned_envelope() {
+        let mut mappings = Mappings::new();
+        mappings.insert(
+            "aaaa".to_string(),
+            MappingEntry::new("blob-id".to_string(), None, 5),
+        );
+
+        let content = serialize_mappings(&mappings).unwrap();
+        assert!(content.contains("\"version\": 2"));
+        assert_eq!(parse_mappings(&content).unwrap(), mappings);
+    }
+
+    #[tokio::test]
+    async fn write_mappings_replaces_the_file_without_leftovers() {
+        let dir = tempfile::tempdir().unwrap();
+        let mapping_file = dir.path().join("walrus-mapping.json");
+        std::fs::write(&mapping_file, "old content").unwrap();
+        let mut mappings = Mappings::new();
+        mappings.insert("aaaa".to_string(), MappingEntry::from_blob_id("blob-id".to_string()));
+
+        write_mappings(&mapping_file, &mappings).await.unwrap();
+
+        assert_eq!(read_mappings(&mapping_file).await.unwrap(), mappings);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn corrupt_mapping_is_backed_up_before_starting_fresh() {
+        let dir = tempfile::tempdir().unwrap();
+        let mapping_file = dir.path().join("walrus-mapping.json");
+        let corrupt = r#"{"version": 2, "entries": {"aaaa": {"blob_id": "bl"#;
+        std::fs::write(&mapping_file, corrupt).unwrap();
+        std::fs::write(dir.path().join("walrus-mapping.json.bak"), "older backup").unwrap();
+
+        assert_eq!(read_mappings(&mapping_file).await.unwrap(), Mappings::new());
+
+        assert!(!mapping_file.exists());
+        let backup = dir.path().join("walrus-mapping.json.bak.1");
+        assert_eq!(std::fs::read_to_string(backup).unwrap(), corrupt);
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("walrus-mapping.json.bak")).unwrap(),
+            "older backup"
+        );
+    }
+
+    #[test]
+    fn removing_a_blob_id_drops_every_key_pointing_at_it() {
+        let mut mappings = Mappings::new();
+        mappings.insert("content".to_string(), MappingEntry::from_blob_id("gone".to_string()));
+        mappings.insert("pointer".to_string(), MappingEntry::from_blob_id("gone".to_string()));
+        mappings.insert("other".to_string(), MappingEntry::from_blob_id("kept".to_string()));
+
+        assert_eq!(remove_blob_id(&mut mappings, "gone"), 2);
+        assert_eq!(mappings.keys().collect::<Vec<_>>(), ["other"]);
+        assert_eq!(remove_blob_id(&mut mappings, "gone"), 0);
+    }
+
+    #[test]
+    fn parse_mappings_refuses_newer_versions() {
+        let err = parse_mappings(r#"{"version": 3, "entries": {"aaaa": {"blob_id": "x"}}}"#)
+            .unwrap_err();
+        assert!(err.is::<UnsupportedMappingVersion>());
+    }
+}
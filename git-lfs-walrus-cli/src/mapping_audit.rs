@@ -0,0 +1,165 @@
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::git;
+use crate::mapping::{get_blob_id_from_sha, store_blob_mapping, MappingEntry};
+use crate::pointer::{
+    extract_oid, extract_walrus_blob_id, load_pointer, replace_walrus_blob_id, write_pointer,
+};
+
+/// Which side wins when `--fix` reconciles a pointer with the mapping.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceOfTruth {
+    /// Keep the committed pointer's blob ID and update the mapping
+    Pointer,
+    /// Keep the mapping's blob ID and rewrite the pointer
+    Mapping,
+}
+
+impl FromStr for SourceOfTruth {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "pointer" => Ok(SourceOfTruth::Pointer),
+            "mapping" => Ok(SourceOfTruth::Mapping),
+            _ => Err(anyhow::anyhow!(
+                "unknown source of truth {:?}, expected \"pointer\" or \"mapping\"",
+                value
+            )),
+        }
+    }
+}
+
+enum AuditResult {
+    Consistent,
+    /// The pointer or the mapping has no blob ID, so there is nothing to compare
+    Incomplete,
+    Mismatch { pointer: String, mapping: String },
+    Fixed { pointer: String, mapping: String },
+}
+
+/// Compares each pointer's inline blob ID with the mapping entry for its SHA256.
+pub async fn mapping_audit(files: Vec<PathBuf>, fix: bool, prefer: SourceOfTruth) -> Result<()> {
+    let files = if files.is_empty() {
+        git::lfs_files()?
+    } else {
+        files
+    };
+
+    let mut consistent_count = 0;
+    let mut incomplete_count = 0;
+    let mut mismatch_count = 0;
+    let mut fixed_count = 0;
+    let mut error_count = 0;
+
+    for file_path in files {
+        match audit_file(&file_path, fix.then_some(prefer)).await {
+            Ok(AuditResult::Consistent) => {
+                consistent_count += 1;
+                println!("✅ {} - Pointer matches mapping", file_path.display());
+            }
+            Ok(AuditResult::Incomplete) => {
+                incomplete_count += 1;
+                println!(
+                    "➖ {} - Blob ID only in the pointer or only in the mapping",
+                    file_path.display()
+                );
+            }
+            Ok(AuditResult::Mismatch { pointer, mapping }) => {
+                mismatch_count += 1;
+                println!(
+                    "❌ {} - Pointer has {}, mapping has {}",
+                    file_path.display(),
+                    pointer,
+                    mapping
+                );
+            }
+            Ok(AuditResult::Fixed { pointer, mapping }) => {
+                fixed_count += 1;
+                let kept = match prefer {
+                    SourceOfTruth::Pointer => &pointer,
+                    SourceOfTruth::Mapping => &mapping,
+                };
+                println!(
+                    "🔧 {} - Pointer had {}, mapping had {}; both now use {}",
+                    file_path.display(),
+                    pointer,
+                    mapping,
+                    kept
+                );
+            }
+            Err(e) => {
+                error_count += 1;
+                println!("⚠️  {} - Error: {}", file_path.display(), e);
+            }
+        }
+    }
+
+    println!("\nSummary:");
+    println!("  Consistent: {}", consistent_count);
+    println!("  Incomplete: {}", incomplete_count);
+    println!("  Mismatched: {}", mismatch_count);
+    if fix {
+        println!("  Fixed: {}", fixed_count);
+    }
+    println!("  Errors: {}", error_count);
+
+    if mismatch_count > 0 {
+        return Err(anyhow::anyhow!(
+            "{} pointers disagree with the mapping; rerun with --fix to reconcile them",
+            mismatch_count
+        ));
+    }
+    Ok(())
+}
+
+async fn audit_file(file_path: &Path, fix: Option<SourceOfTruth>) -> Result<AuditResult> {
+    let (pointer, location) = load_pointer(file_path).await?;
+    let oid = extract_oid(&pointer)?;
+
+    let (Ok(pointer_id), Some(mapping_id)) = (
+        extract_walrus_blob_id(&pointer),
+        get_blob_id_from_sha(&oid.hash).await?,
+    ) else {
+        return Ok(AuditResult::Incomplete);
+    };
+    if pointer_id == mapping_id {
+        return Ok(AuditResult::Consistent);
+    }
+
+    match fix {
+        None => Ok(AuditResult::Mismatch {
+            pointer: pointer_id,
+            mapping: mapping_id,
+        }),
+        Some(SourceOfTruth::Pointer) => {
+            store_blob_mapping(&oid.hash, &MappingEntry::from_blob_id(pointer_id.clone())).await?;
+            Ok(AuditResult::Fixed {
+                pointer: pointer_id,
+                mapping: mapping_id,
+            })
+        }
+        Some(SourceOfTruth::Mapping) => {
+            let updated = replace_walrus_blob_id(&pointer, &mapping_id);
+            write_pointer(file_path, location, &updated).await?;
+            Ok(AuditResult::Fixed {
+                pointer: pointer_id,
+                mapping: mapping_id,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn source_of_truth_parses_known_values() {
+        assert_eq!("pointer".parse::<SourceOfTruth>().unwrap(), SourceOfTruth::Pointer);
+        assert_eq!("mapping".parse::<SourceOfTruth>().unwrap(), SourceOfTruth::Mapping);
+        assert!("index".parse::<SourceOfTruth>().is_err());
+    }
+}
@@ -0,0 +1,175 @@
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use crate::dedup_report::{parse_pointer_lines, PointerFields};
+use crate::git;
+use crate::mapping::{replace_mappings, MappingEntry, Mappings};
+use crate::pointer::{extract_oid, extract_walrus_blob_id, is_pointer};
+
+/// Pointer files are well under this size; anything larger is file content.
+const MAX_POINTER_SIZE: u64 = 1024;
+
+/// The mapping recovered from a set of pointers.
+#[derive(Debug, Default)]
+struct Rebuilt {
+    mappings: Mappings,
+    /// Distinct OIDs recovered, not counting the pointer-SHA keys
+    recovered: usize,
+    /// First path of each OID whose pointers carry no inline blob ID
+    without_blob_id: BTreeMap<String, PathBuf>,
+    /// OIDs whose pointers disagree on the blob ID; the first one seen is kept
+    conflicts: BTreeMap<String, PathBuf>,
+}
+
+/// Regenerates the mapping file from the `ext-0-walrus` blob IDs of every pointer in the
+/// history of all refs and in the index, for when the mapping file has been lost. An existing
+/// mapping file is copied aside first.
+pub async fn mapping_rebuild() -> Result<()> {
+    let blobs = git::reachable_small_blobs(&["--all"], MAX_POINTER_SIZE)?;
+    let staged = parse_pointer_lines(&git::index_pointer_lines()?);
+    let rebuilt = rebuild(blobs, staged);
+
+    for (oid, path) in &rebuilt.without_blob_id {
+        println!("⚠️  {} - No inline ext-0-walrus blob ID for sha256 {}", path.display(), oid);
+    }
+    for (oid, path) in &rebuilt.conflicts {
+        println!(
+            "⚠️  {} - Pointers for sha256 {} name different blob IDs; kept {}",
+            path.display(),
+            oid,
+            rebuilt.mappings[oid].blob_id
+        );
+    }
+
+    let backup = replace_mappings(&rebuilt.mappings).await?;
+    println!("✅ Rebuilt the mapping file");
+
+    println!("\nSummary:");
+    println!("  Recovered: {}", rebuilt.recovered);
+    println!("  Without inline blob ID: {}", rebuilt.without_blob_id.len());
+    if !rebuilt.conflicts.is_empty() {
+        println!("  Conflicting blob IDs: {}", rebuilt.conflicts.len());
+    }
+    if let Some(backup) = backup {
+        println!("  Previous mapping file: {}", backup.display());
+    }
+    Ok(())
+}
+
+/// Builds mapping entries from pointer contents (`blobs`, newest first) and the staged
+/// pointers. Like clean, each pointer is recorded under both its OID and the SHA256 of the
+/// pointer text.
+fn rebuild(blobs: Vec<(PathBuf, Vec<u8>)>, staged: BTreeMap<PathBuf, PointerFields>) -> Rebuilt {
+    let mut rebuilt = Rebuilt::default();
+    let mut missing = BTreeMap::new();
+
+    let committed = blobs.into_iter().filter_map(|(path, content)| {
+        if !is_pointer(&content) {
+            return None;
+        }
+        let text = String::from_utf8_lossy(&content);
+        let oid = extract_oid(&text).ok()?.hash;
+        let blob_id = extract_walrus_blob_id(&text).ok();
+        let pointer_sha = blob_id.is_some().then(|| hex::encode(Sha256::digest(&content)));
+        Some((path, oid, blob_id, pointer_sha))
+    });
+    let staged = staged.into_iter().filter_map(|(path, fields)| {
+        Some((path, fields.oid?, fields.blob_id, None))
+    });
+
+    for (path, oid, blob_id, pointer_sha) in committed.chain(staged) {
+        let Some(blob_id) = blob_id else {
+            missing.entry(oid).or_insert(path);
+            continue;
+        };
+        if let Some(pointer_sha) = pointer_sha {
+            rebuilt
+                .mappings
+                .entry(pointer_sha)
+                .or_insert_with(|| MappingEntry::from_blob_id(blob_id.clone()));
+        }
+        match rebuilt.mappings.get(&oid) {
+            Some(entry) if entry.blob_id != blob_id => {
+                rebuilt.conflicts.entry(oid).or_insert(path);
+            }
+            Some(_) => {}
+            None => {
+                rebuilt.recovered += 1;
+                rebuilt.mappings.insert(oid, MappingEntry::from_blob_id(blob_id));
+            }
+        }
+    }
+
+    // An OID is only unrecovered if none of its pointers had a blob ID
+    missing.retain(|oid, _| !rebuilt.mappings.contains_key(oid));
+    rebuilt.without_blob_id = missing;
+    rebuilt
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OID_A: &str = "4d7a214614ab2935c943f9e0ff69d22eadbb8f32b1258daaa5e2ca24d17e2393";
+    const OID_B: &str = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+
+    fn pointer(oid: &str, blob_id: Option<&str>) -> Vec<u8> {
+        let mut pointer = format!(
+            "version https://git-lfs.github.com/spec/v1\noid sha256:{}\nsize 5\n",
+            oid
+        );
+        if let Some(blob_id) = blob_id {
+            pointer.push_str(&format!("ext-0-walrus {}\n", blob_id));
+        }
+        pointer.into_bytes()
+    }
+
+    #[test]
+    fn inline_blob_ids_are_recovered_under_oid_and_pointer_keys() {
+        let inline = pointer(OID_A, Some("blob-a"));
+        let rebuilt = rebuild(
+            vec![
+                (PathBuf::from("a.bin"), inline.clone()),
+                (PathBuf::from("old/a.bin"), pointer(OID_A, None)),
+                (PathBuf::from("b.bin"), pointer(OID_B, None)),
+                (PathBuf::from("notes.txt"), b"not a pointer\n".to_vec()),
+            ],
+            BTreeMap::new(),
+        );
+
+        assert_eq!(rebuilt.recovered, 1);
+        assert_eq!(rebuilt.mappings[OID_A].blob_id, "blob-a");
+        assert_eq!(rebuilt.mappings[&hex::encode(Sha256::digest(&inline))].blob_id, "blob-a");
+        assert_eq!(rebuilt.mappings.len(), 2);
+        assert_eq!(rebuilt.without_blob_id.keys().collect::<Vec<_>>(), [OID_B]);
+        assert!(rebuilt.conflicts.is_empty());
+    }
+
+    #[test]
+    fn staged_pointers_fill_in_and_the_newest_blob_id_wins_a_conflict() {
+        let staged = BTreeMap::from([(
+            PathBuf::from("b.bin"),
+            PointerFields {
+                oid: Some(OID_B.to_string()),
+                size: Some(5),
+                blob_id: Some("blob-b".to_string()),
+            },
+        )]);
+        let rebuilt = rebuild(
+            vec![
+                (PathBuf::from("a.bin"), pointer(OID_A, Some("blob-new"))),
+                (PathBuf::from("a.bin"), pointer(OID_A, Some("blob-old"))),
+                (PathBuf::from("b.bin"), pointer(OID_B, None)),
+            ],
+            staged,
+        );
+
+        assert_eq!(rebuilt.recovered, 2);
+        assert_eq!(rebuilt.mappings[OID_A].blob_id, "blob-new");
+        assert_eq!(rebuilt.mappings[OID_B].blob_id, "blob-b");
+        assert_eq!(rebuilt.conflicts.keys().collect::<Vec<_>>(), [OID_A]);
+        assert!(rebuilt.without_blob_id.is_empty());
+    }
+}
@@ -0,0 +1,113 @@
+use anyhow::Result;
+use std::path::PathBuf;
+
+use crate::mapping::{store_blob_mappings, MappingEntry};
+use crate::walrus::WalrusClient;
+
+pub async fn mapping_set(
+    client: WalrusClient,
+    sha256: Option<String>,
+    blob_id: Option<String>,
+    from_csv: Option<PathBuf>,
+    verify: bool,
+) -> Result<()> {
+    let pairs = match (sha256, blob_id, from_csv) {
+        (Some(sha256), Some(blob_id), None) => vec![(sha256, blob_id)],
+        (None, None, Some(csv_path)) => {
+            let content = tokio::fs::read_to_string(&csv_path).await?;
+            parse_csv(&content)?
+        }
+        _ => {
+            return Err(anyhow::anyhow!(
+                "Provide either a SHA256 and a blob ID, or --from-csv"
+            ));
+        }
+    };
+
+    for (sha256, _) in &pairs {
+        if !is_sha256_hex(sha256) {
+            return Err(anyhow::anyhow!("Not a SHA256 hex digest: {}", sha256));
+        }
+    }
+
+    if verify {
+        for (sha256, blob_id) in &pairs {
+            if client.blob_status(blob_id).await?.is_none() {
+                return Err(anyhow::anyhow!(
+                    "Blob {} (for {}) was not found in Walrus",
+                    blob_id,
+                    sha256
+                ));
+            }
+        }
+    }
+
+    let count = pairs.len();
+    let entries = pairs
+        .into_iter()
+        .map(|(sha256, blob_id)| (sha256, MappingEntry::from_blob_id(blob_id)))
+        .collect();
+    store_blob_mappings(entries).await?;
+
+    println!("Added {} mapping entries", count);
+
+    Ok(())
+}
+
+/// Parses `sha256,blob_id` lines, skipping blank lines and an optional header row.
+fn parse_csv(content: &str) -> Result<Vec<(String, String)>> {
+    let mut pairs = Vec::new();
+
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let Some((sha256, blob_id)) = line.split_once(',') else {
+            return Err(anyhow::anyhow!(
+                "Line {}: expected `sha256,blob_id`, got: {}",
+                index + 1,
+                line
+            ));
+        };
+        let (sha256, blob_id) = (sha256.trim(), blob_id.trim());
+
+        if index == 0 && !is_sha256_hex(sha256) {
+            continue; // Header row
+        }
+
+        pairs.push((sha256.to_string(), blob_id.to_string()));
+    }
+
+    Ok(pairs)
+}
+
+fn is_sha256_hex(s: &str) -> bool {
+    s.len() == 64 && s.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const SHA: &str = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+
+    #[test]
+    fn parse_csv_skips_header_and_blank_lines() {
+        let csv = format!("sha256,blob_id\n{},blob-1\n\n{} , blob-2\n", SHA, SHA);
+        assert_eq!(
+            parse_csv(&csv).unwrap(),
+            vec![
+                (SHA.to_string(), "blob-1".to_string()),
+                (SHA.to_string(), "blob-2".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_csv_rejects_lines_without_separator() {
+        assert!(parse_csv(&format!("{}\n", SHA)).is_err());
+    }
+}
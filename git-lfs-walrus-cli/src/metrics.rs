@@ -0,0 +1,154 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Latencies of every timed operation in this process, keyed by operation name.
+static LATENCIES: Mutex<BTreeMap<&'static str, Vec<Duration>>> = Mutex::new(BTreeMap::new());
+
+/// Prefetch cache lookups served from the cache, and those that fell through to Walrus.
+static CACHE_HITS: AtomicUsize = AtomicUsize::new(0);
+static CACHE_MISSES: AtomicUsize = AtomicUsize::new(0);
+
+/// Counts one prefetch cache lookup, for the hit rate in the summary.
+pub fn record_cache_lookup(hit: bool) {
+    let counter = if hit { &CACHE_HITS } else { &CACHE_MISSES };
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Records how long an operation took when dropped, whether or not it succeeded.
+pub struct Timer {
+    operation: &'static str,
+    started: Instant,
+}
+
+impl Timer {
+    pub fn start(operation: &'static str) -> Self {
+        Self {
+            operation,
+            started: Instant::now(),
+        }
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        if let Ok(mut latencies) = LATENCIES.lock() {
+            latencies
+                .entry(self.operation)
+                .or_default()
+                .push(self.started.elapsed());
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct OperationSummary {
+    count: usize,
+    total_ms: f64,
+    mean_ms: f64,
+    p50_ms: f64,
+    p95_ms: f64,
+    max_ms: f64,
+}
+
+impl OperationSummary {
+    fn new(latencies: &[Duration]) -> Self {
+        let mut millis: Vec<f64> = latencies.iter().map(|d| d.as_secs_f64() * 1000.0).collect();
+        millis.sort_by(f64::total_cmp);
+        let total_ms: f64 = millis.iter().sum();
+        Self {
+            count: millis.len(),
+            total_ms,
+            mean_ms: total_ms / millis.len().max(1) as f64,
+            p50_ms: percentile(&millis, 50),
+            p95_ms: percentile(&millis, 95),
+            max_ms: millis.last().copied().unwrap_or(0.0),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct CacheSummary {
+    hits: usize,
+    /// Lookups that had to read from Walrus
+    misses: usize,
+    hit_rate_percent: f64,
+}
+
+impl CacheSummary {
+    /// `None` when the cache was never consulted.
+    fn new(hits: usize, misses: usize) -> Option<Self> {
+        let lookups = hits + misses;
+        (lookups > 0).then(|| Self {
+            hits,
+            misses,
+            hit_rate_percent: hits as f64 * 100.0 / lookups as f64,
+        })
+    }
+}
+
+#[derive(Serialize)]
+struct Summary<'a> {
+    #[serde(flatten)]
+    operations: BTreeMap<&'a str, OperationSummary>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cache: Option<CacheSummary>,
+}
+
+/// Nearest-rank percentile of sorted values.
+pub fn percentile(sorted: &[f64], pct: usize) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = (pct * sorted.len()).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+/// Writes a JSON summary of the operations timed so far to `path`, with a `cache` entry for
+/// the prefetch cache hit rate once the cache has been consulted.
+pub fn write_summary(path: &Path) -> Result<()> {
+    let operations = LATENCIES
+        .lock()
+        .map_err(|_| anyhow::anyhow!("Metrics lock was poisoned"))?
+        .iter()
+        .map(|(operation, latencies)| (*operation, OperationSummary::new(latencies)))
+        .collect();
+    let summary = Summary {
+        operations,
+        cache: CacheSummary::new(
+            CACHE_HITS.load(Ordering::Relaxed),
+            CACHE_MISSES.load(Ordering::Relaxed),
+        ),
+    };
+    std::fs::write(path, serde_json::to_string_pretty(&summary)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summary_reports_counts_and_percentiles() {
+        let latencies: Vec<Duration> = (1..=20).map(Duration::from_millis).collect();
+        let summary = OperationSummary::new(&latencies);
+        assert_eq!(summary.count, 20);
+        assert_eq!(summary.total_ms, 210.0);
+        assert_eq!(summary.mean_ms, 10.5);
+        assert_eq!(summary.p50_ms, 10.0);
+        assert_eq!(summary.p95_ms, 19.0);
+        assert_eq!(summary.max_ms, 20.0);
+    }
+
+    #[test]
+    fn cache_summary_reports_the_hit_rate() {
+        let summary = CacheSummary::new(3, 1).unwrap();
+        assert_eq!((summary.hits, summary.misses), (3, 1));
+        assert_eq!(summary.hit_rate_percent, 75.0);
+        assert_eq!(CacheSummary::new(0, 0), None);
+    }
+}
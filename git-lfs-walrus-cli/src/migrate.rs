@@ -0,0 +1,457 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::balance::require_balance;
+use crate::bulk::BulkErrors;
+use crate::git;
+use crate::mapping::{MappingBatch, MappingEntry};
+use crate::pointer::is_pointer;
+use crate::walrus::WalrusClient;
+
+/// Totals for a migrate run.
+#[derive(Debug, Default, Serialize)]
+struct MigrateSummary {
+    uploaded_files: u64,
+    uploaded_bytes: u64,
+    /// Sum of the dry-run storage cost estimates, in FROST
+    estimated_cost: u64,
+    skipped_files: u64,
+    error_count: u64,
+}
+
+/// Progress of an unfinished run, kept in `.git/walrus-migrate-state.json` so a rerun over the
+/// same files picks up after the last file completed. Removed once a run finishes cleanly.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct MigrateState {
+    /// SHA256 of the file list, so a run over other files doesn't resume from this one
+    files_digest: String,
+    /// Every file up to and including this one was migrated
+    last_completed: PathBuf,
+}
+
+enum MigrateResult {
+    Uploaded { bytes: u64, cost: u64 },
+    AlreadyStored,
+    NotCheckedOut,
+}
+
+/// Command-line switches for a migrate run.
+#[derive(Debug, Default)]
+pub struct MigrateOptions {
+    /// Print the summary as JSON instead of per-file lines
+    pub json: bool,
+    pub fail_on_already_certified: bool,
+    pub wait_certified: bool,
+    pub keep_going: bool,
+    /// Skip the files up to and including this one
+    pub resume_from: Option<PathBuf>,
+    /// Abort before uploading if the wallet can't cover the estimated cost
+    pub check_balance: bool,
+    /// Only report what a run would do, with dry-run cost estimates; upload nothing
+    pub plan: bool,
+}
+
+/// Stores the content of LFS files in Walrus and records their mappings.
+pub async fn migrate(
+    client: WalrusClient,
+    files: Vec<PathBuf>,
+    options: MigrateOptions,
+) -> Result<()> {
+    let MigrateOptions {
+        json,
+        fail_on_already_certified,
+        wait_certified,
+        keep_going,
+        resume_from,
+        check_balance,
+        plan,
+    } = options;
+    let files = if files.is_empty() {
+        git::lfs_files()?
+    } else {
+        files
+    };
+
+    let state_path = git::git_dir()?.join("walrus-migrate-state.json");
+    let files_digest = files_digest(&files);
+    let resume_after = match resume_from {
+        Some(file) => Some(file),
+        None => load_state(&state_path)
+            .await
+            .filter(|state| state.files_digest == files_digest)
+            .map(|state| state.last_completed),
+    };
+    let start = match &resume_after {
+        Some(file) => {
+            let position = files.iter().position(|f| f == file).ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Cannot resume after {}: it is not among the files to migrate",
+                    file.display()
+                )
+            })?;
+            if !json {
+                println!(
+                    "Resuming after {} ({} files already done)",
+                    file.display(),
+                    position + 1
+                );
+            }
+            position + 1
+        }
+        None => 0,
+    };
+    let mut last_completed = resume_after;
+
+    if plan {
+        return plan_migration(&client, &files[start..], json).await;
+    }
+
+    if check_balance {
+        require_balance(&client, &files[start..]).await?;
+    }
+
+    if !json {
+        println!("Migrating {} LFS files to Walrus...", files.len() - start);
+    }
+
+    let mut summary = MigrateSummary::default();
+    let mut errors = BulkErrors::new(keep_going);
+    let mut mappings = MappingBatch::load().await?;
+    let interrupt = tokio::signal::ctrl_c();
+    tokio::pin!(interrupt);
+    let mut interrupted = false;
+
+    for file_path in files.into_iter().skip(start) {
+        let result = tokio::select! {
+            result = migrate_file(
+                &client,
+                &mut mappings,
+                &file_path,
+                fail_on_already_certified,
+                wait_certified,
+            ) => result,
+            _ = &mut interrupt => {
+                interrupted = true;
+                break;
+            }
+        };
+        // Only an unbroken run of completed files can be skipped on resume
+        if result.is_ok() && summary.error_count == 0 {
+            last_completed = Some(file_path.clone());
+        }
+        match result {
+            Ok(MigrateResult::Uploaded { bytes, cost }) => {
+                summary.uploaded_files += 1;
+                summary.uploaded_bytes += bytes;
+                summary.estimated_cost += cost;
+                if !json {
+                    println!("⬆️  {} - Uploaded ({})", file_path.display(), format_bytes(bytes));
+                }
+            }
+            Ok(MigrateResult::AlreadyStored) => {
+                summary.skipped_files += 1;
+                if !json {
+                    println!("✅ {} - Already in Walrus", file_path.display());
+                }
+            }
+            Ok(MigrateResult::NotCheckedOut) => {
+                summary.skipped_files += 1;
+                if !json {
+                    println!(
+                        "⏭️  {} - Skipped (only the LFS pointer is checked out)",
+                        file_path.display()
+                    );
+                }
+            }
+            Err(e) => {
+                summary.error_count += 1;
+                if json {
+                    eprintln!("{} - Error: {}", file_path.display(), e);
+                } else {
+                    println!("⚠️  {} - Error: {}", file_path.display(), e);
+                }
+                if errors.record(&file_path, &e) {
+                    break;
+                }
+            }
+        }
+    }
+
+    // Keep what was stored before an interruption or error, so a rerun skips it
+    mappings.flush().await?;
+    if interrupted || summary.error_count > 0 {
+        if let Some(last_completed) = last_completed {
+            let state = MigrateState {
+                files_digest,
+                last_completed,
+            };
+            tokio::fs::write(&state_path, serde_json::to_string_pretty(&state)?).await?;
+        }
+    } else if state_path.exists() {
+        tokio::fs::remove_file(&state_path).await?;
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&summary)?);
+    } else {
+        println!(
+            "\nUploaded {} files, {}, est. cost {} FROST",
+            summary.uploaded_files,
+            format_bytes(summary.uploaded_bytes),
+            summary.estimated_cost
+        );
+        println!("  Skipped: {}", summary.skipped_files);
+        println!("  Errors: {}", summary.error_count);
+    }
+
+    if interrupted {
+        return Err(anyhow::anyhow!(
+            "Interrupted; mappings for the files migrated so far were saved, and a rerun resumes where this one stopped"
+        ));
+    }
+    errors.finish()
+}
+
+/// What a migrate run would do with one file.
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+enum PlannedAction {
+    Upload { bytes: u64, estimated_cost: u64 },
+    AlreadyStored,
+    /// Same content as an earlier file in the run, which is the only one uploaded
+    Duplicate { of: PathBuf },
+    NotCheckedOut,
+    Error { error: String },
+}
+
+#[derive(Debug, Serialize)]
+struct PlannedFile {
+    path: PathBuf,
+    #[serde(flatten)]
+    action: PlannedAction,
+}
+
+/// Totals for `migrate --plan`.
+#[derive(Debug, Default, Serialize)]
+struct MigratePlan {
+    files: Vec<PlannedFile>,
+    upload_files: u64,
+    upload_bytes: u64,
+    /// Sum of the dry-run storage cost estimates, in FROST
+    estimated_cost: u64,
+    skipped_files: u64,
+    error_count: u64,
+}
+
+/// Reports, for each file, whether a migrate run would upload it (with its size and dry-run
+/// cost) or skip it, without storing anything or touching the mapping file.
+async fn plan_migration(client: &WalrusClient, files: &[PathBuf], json: bool) -> Result<()> {
+    let mappings = MappingBatch::load().await?;
+    // Content a real run would have stored by the time it reached a later file
+    let mut planned: HashMap<String, PathBuf> = HashMap::new();
+    let mut plan = MigratePlan::default();
+
+    if !json {
+        println!("Planning the migration of {} LFS files (nothing is uploaded)...", files.len());
+    }
+    for file_path in files {
+        let action = match plan_file(client, &mappings, &planned, file_path).await {
+            Ok((action, Some(sha256_hex))) => {
+                planned.insert(sha256_hex, file_path.clone());
+                action
+            }
+            Ok((action, None)) => action,
+            Err(e) => PlannedAction::Error {
+                error: e.to_string(),
+            },
+        };
+        match &action {
+            PlannedAction::Upload {
+                bytes,
+                estimated_cost,
+            } => {
+                plan.upload_files += 1;
+                plan.upload_bytes += bytes;
+                plan.estimated_cost += estimated_cost;
+            }
+            PlannedAction::Error { .. } => plan.error_count += 1,
+            _ => plan.skipped_files += 1,
+        }
+        if !json {
+            println!("{}", describe_planned(file_path, &action));
+        }
+        plan.files.push(PlannedFile {
+            path: file_path.clone(),
+            action,
+        });
+    }
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&plan)?);
+    } else {
+        println!(
+            "\nWould upload {} files, {}, est. cost {} FROST",
+            plan.upload_files,
+            format_bytes(plan.upload_bytes),
+            plan.estimated_cost
+        );
+        println!("  Skipped: {}", plan.skipped_files);
+        println!("  Errors: {}", plan.error_count);
+    }
+    if plan.error_count > 0 {
+        return Err(anyhow::anyhow!(
+            "{} files could not be planned",
+            plan.error_count
+        ));
+    }
+    Ok(())
+}
+
+/// The planned action for one file, and the SHA256 of content it would upload.
+async fn plan_file(
+    client: &WalrusClient,
+    mappings: &MappingBatch,
+    planned: &HashMap<String, PathBuf>,
+    file_path: &Path,
+) -> Result<(PlannedAction, Option<String>)> {
+    let data = tokio::fs::read(file_path).await?;
+    if is_pointer(&data) {
+        return Ok((PlannedAction::NotCheckedOut, None));
+    }
+
+    let sha256_hex = hex::encode(Sha256::digest(&data));
+    if mappings.get(&sha256_hex).is_some() {
+        return Ok((PlannedAction::AlreadyStored, None));
+    }
+    if let Some(first) = planned.get(&sha256_hex) {
+        return Ok((PlannedAction::Duplicate { of: first.clone() }, None));
+    }
+
+    let estimated_cost = client.estimate_store_cost(&data).await?;
+    let action = PlannedAction::Upload {
+        bytes: data.len() as u64,
+        estimated_cost,
+    };
+    Ok((action, Some(sha256_hex)))
+}
+
+fn describe_planned(file_path: &Path, action: &PlannedAction) -> String {
+    let file = file_path.display();
+    match action {
+        PlannedAction::Upload {
+            bytes,
+            estimated_cost,
+        } => format!(
+            "⬆️  {} - Would upload ({}, est. cost {} FROST)",
+            file,
+            format_bytes(*bytes),
+            estimated_cost
+        ),
+        PlannedAction::AlreadyStored => format!("✅ {} - Already in Walrus", file),
+        PlannedAction::Duplicate { of } => {
+            format!("✅ {} - Same content as {}, uploaded once", file, of.display())
+        }
+        PlannedAction::NotCheckedOut => format!(
+            "⏭️  {} - Would skip (only the LFS pointer is checked out)",
+            file
+        ),
+        PlannedAction::Error { error } => format!("⚠️  {} - Error: {}", file, error),
+    }
+}
+
+async fn migrate_file(
+    client: &WalrusClient,
+    mappings: &mut MappingBatch,
+    file_path: &Path,
+    fail_on_already_certified: bool,
+    wait_certified: bool,
+) -> Result<MigrateResult> {
+    let data = tokio::fs::read(file_path).await?;
+    if is_pointer(&data) {
+        return Ok(MigrateResult::NotCheckedOut);
+    }
+
+    let sha256_hex = hex::encode(Sha256::digest(&data));
+    if mappings.get(&sha256_hex).is_some() {
+        return Ok(MigrateResult::AlreadyStored);
+    }
+
+    let cost = client.estimate_store_cost(&data).await?;
+    let outcome = client.store_bytes(&data, None).await?;
+    if fail_on_already_certified {
+        outcome.require_newly_created()?;
+    }
+    if wait_certified {
+        client.wait_until_certified(&outcome.blob_id).await?;
+    }
+    let entry = MappingEntry::new(outcome.blob_id, outcome.tx_digest, outcome.epochs);
+    mappings.insert(&sha256_hex, entry).await?;
+
+    Ok(MigrateResult::Uploaded {
+        bytes: data.len() as u64,
+        cost,
+    })
+}
+
+fn files_digest(files: &[PathBuf]) -> String {
+    let mut hasher = Sha256::new();
+    for file in files {
+        hasher.update(file.to_string_lossy().as_bytes());
+        hasher.update(b"\0");
+    }
+    hex::encode(hasher.finalize())
+}
+
+/// Reads the state of an unfinished run; an unreadable one is ignored, since the mapping
+/// still keeps a full rerun from uploading anything twice.
+async fn load_state(path: &Path) -> Option<MigrateState> {
+    let content = tokio::fs::read_to_string(path).await.ok()?;
+    match serde_json::from_str(&content) {
+        Ok(state) => Some(state),
+        Err(e) => {
+            eprintln!("Warning: ignoring unreadable {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// Formats a byte count with a binary unit, e.g. `1.5 KB`.
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn files_digest_depends_on_the_files_and_their_order() {
+        let files = [PathBuf::from("a.bin"), PathBuf::from("b.bin")];
+        let reversed = [PathBuf::from("b.bin"), PathBuf::from("a.bin")];
+        let joined = [PathBuf::from("a.binb.bin")];
+        assert_ne!(files_digest(&files), files_digest(&reversed));
+        assert_ne!(files_digest(&files), files_digest(&joined));
+    }
+
+    #[test]
+    fn format_bytes_picks_a_readable_unit() {
+        assert_eq!(format_bytes(0), "0 B");
+        assert_eq!(format_bytes(1023), "1023 B");
+        assert_eq!(format_bytes(1536), "1.5 KB");
+        assert_eq!(format_bytes(56 * 1024 * 1024 * 1024), "56.0 GB");
+    }
+}
@@ -0,0 +1,212 @@
+use anyhow::Result;
+use sha2::{Digest, Sha256, Sha512};
+use std::fmt;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::task::{Context, Poll};
+use tokio::io::AsyncWrite;
+
+/// Hash algorithms a pointer's `oid <algorithm>:<hash>` line may name. git-lfs itself only
+/// writes sha256.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HashAlgorithm {
+    Sha256,
+    Sha512,
+}
+
+impl HashAlgorithm {
+    /// Guesses the algorithm from the length of a hex digest, for callers that only get the
+    /// hash (like the transfer agent).
+    pub fn for_hash(hash: &str) -> Option<Self> {
+        match hash.len() {
+            64 => Some(HashAlgorithm::Sha256),
+            128 => Some(HashAlgorithm::Sha512),
+            _ => None,
+        }
+    }
+
+    pub fn hasher(self) -> OidHasher {
+        match self {
+            HashAlgorithm::Sha256 => OidHasher::Sha256(Sha256::new()),
+            HashAlgorithm::Sha512 => OidHasher::Sha512(Sha512::new()),
+        }
+    }
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "sha256" => Ok(HashAlgorithm::Sha256),
+            "sha512" => Ok(HashAlgorithm::Sha512),
+            _ => Err(anyhow::anyhow!("unsupported OID hash algorithm {:?}", value)),
+        }
+    }
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HashAlgorithm::Sha256 => write!(f, "sha256"),
+            HashAlgorithm::Sha512 => write!(f, "sha512"),
+        }
+    }
+}
+
+/// Incremental digest for one of the [`HashAlgorithm`]s.
+pub enum OidHasher {
+    Sha256(Sha256),
+    Sha512(Sha512),
+}
+
+impl OidHasher {
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            OidHasher::Sha256(hasher) => hasher.update(data),
+            OidHasher::Sha512(hasher) => hasher.update(data),
+        }
+    }
+
+    pub fn finalize_hex(self) -> String {
+        match self {
+            OidHasher::Sha256(hasher) => hex::encode(hasher.finalize()),
+            OidHasher::Sha512(hasher) => hex::encode(hasher.finalize()),
+        }
+    }
+}
+
+/// Hashes and counts everything written through it, for checking streamed content against
+/// an OID.
+pub struct HashingWriter<W> {
+    inner: W,
+    hasher: OidHasher,
+    written: u64,
+}
+
+impl<W> HashingWriter<W> {
+    pub fn new(inner: W, algorithm: HashAlgorithm) -> Self {
+        Self {
+            inner,
+            hasher: algorithm.hasher(),
+            written: 0,
+        }
+    }
+
+    /// Returns the hex digest and the number of bytes written.
+    pub fn finish(self) -> (String, u64) {
+        (self.hasher.finalize_hex(), self.written)
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for HashingWriter<W> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(written)) = poll {
+            self.hasher.update(&buf[..written]);
+            self.written += written as u64;
+        }
+        poll
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+/// A pointer's object ID.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Oid {
+    pub algorithm: HashAlgorithm,
+    /// Hex digest; also the key of the object's mapping entry
+    pub hash: String,
+}
+
+impl Oid {
+    /// Parses an `oid <algorithm>:<hash>` line, returning `None` for other lines.
+    pub fn parse_line(line: &str) -> Option<Result<Self>> {
+        let value = line.strip_prefix("oid ")?;
+        Some(match value.trim().split_once(':') {
+            Some((algorithm, hash)) if !hash.is_empty() => algorithm.parse().map(|algorithm| Oid {
+                algorithm,
+                hash: hash.to_string(),
+            }),
+            _ => Err(anyhow::anyhow!("malformed LFS pointer oid line {:?}", line)),
+        })
+    }
+
+    /// Parses an OID given on its own: `<algorithm>:<hash>`, or a bare hex digest whose length
+    /// names the algorithm. Returns `None` for anything else, such as a Walrus blob ID.
+    pub fn parse_standalone(value: &str) -> Option<Self> {
+        let (algorithm, hash) = match value.split_once(':') {
+            Some((algorithm, hash)) => (algorithm.parse().ok()?, hash),
+            None => (HashAlgorithm::for_hash(value)?, value),
+        };
+        let is_hex = !hash.is_empty() && hash.bytes().all(|b| b.is_ascii_hexdigit());
+        is_hex.then(|| Oid {
+            algorithm,
+            hash: hash.to_ascii_lowercase(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn oid_lines_name_their_algorithm() {
+        let oid = Oid::parse_line("oid sha512:abcd").unwrap().unwrap();
+        assert_eq!(oid.algorithm, HashAlgorithm::Sha512);
+        assert_eq!(oid.hash, "abcd");
+
+        let err = Oid::parse_line("oid md5:abcd").unwrap().unwrap_err();
+        assert!(err.to_string().contains("unsupported OID hash algorithm \"md5\""));
+        assert!(Oid::parse_line("oid abcd").unwrap().is_err());
+        assert!(Oid::parse_line("size 11").is_none());
+    }
+
+    #[test]
+    fn standalone_oids_are_told_apart_from_blob_ids() {
+        let hash = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+        let oid = Oid::parse_standalone(hash).unwrap();
+        assert_eq!(oid.algorithm, HashAlgorithm::Sha256);
+        assert_eq!(Oid::parse_standalone(&format!("sha256:{}", hash)), Some(oid));
+        assert_eq!(
+            Oid::parse_standalone("sha512:ABCD").map(|oid| oid.hash),
+            Some("abcd".to_string())
+        );
+        assert!(Oid::parse_standalone("M5jdVqzKx0jZbMvnsGcyCw3WfHLTl1Q6w1s6i7bMMe8").is_none());
+        assert!(Oid::parse_standalone("md5:abcd").is_none());
+    }
+
+    #[test]
+    fn hashers_match_their_algorithm() {
+        let mut sha256 = HashAlgorithm::Sha256.hasher();
+        sha256.update(b"hello world");
+        let digest = sha256.finalize_hex();
+        assert_eq!(
+            digest,
+            "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9"
+        );
+        assert_eq!(HashAlgorithm::for_hash(&digest), Some(HashAlgorithm::Sha256));
+
+        let mut sha512 = HashAlgorithm::Sha512.hasher();
+        sha512.update(b"hello world");
+        assert_eq!(
+            HashAlgorithm::for_hash(&sha512.finalize_hex()),
+            Some(HashAlgorithm::Sha512)
+        );
+    }
+}
@@ -0,0 +1,156 @@
+use anyhow::Result;
+use std::collections::{BTreeSet, HashSet};
+
+use crate::dedup_report::{parse_pointer_lines, PointerFields};
+use crate::git;
+use crate::mapping::{load_mappings, remove_blob_mappings, Mappings};
+use crate::migrate::format_bytes;
+use crate::pointer::{extract_oid, extract_walrus_blob_id, is_pointer};
+use crate::walrus::WalrusClient;
+
+/// Pointer files are well under this size; anything larger is file content.
+const MAX_POINTER_SIZE: u64 = 1024;
+
+/// Lists the blobs the mapping file records that no pointer in the history of any ref, nor a
+/// staged one, refers to: content dropped from history, whose storage can be reclaimed. With
+/// `delete`, each one is deleted from Walrus (it must have been stored as deletable) and its
+/// mapping entries are removed.
+pub async fn orphans(client: WalrusClient, delete: bool) -> Result<()> {
+    let mappings = load_mappings().await?;
+    let orphaned = orphaned_blob_ids(&mappings, repository_pointers()?);
+
+    let mut orphaned_bytes = 0;
+    let mut deleted = 0;
+    let mut failed = 0;
+    for blob_id in &orphaned {
+        let (size, status, in_walrus) = match client.blob_status(blob_id).await {
+            Ok(Some(status)) => {
+                let size = status.blob_object.as_ref().map(|blob| blob.size);
+                (size, status.status, true)
+            }
+            Ok(None) => (None, "not found in Walrus".to_string(), false),
+            Err(e) => {
+                println!("⚠️  {} - Could not get its status: {}", blob_id, e);
+                failed += 1;
+                continue;
+            }
+        };
+        orphaned_bytes += size.unwrap_or(0);
+        let size = size.map_or("unknown size".to_string(), format_bytes);
+
+        if !delete {
+            println!("⚠️  {} - {} | Status: {}", blob_id, size, status);
+            continue;
+        }
+        // A blob that is already gone only leaves mapping entries to scrub
+        let result = if in_walrus {
+            client.delete_blob(blob_id).await
+        } else {
+            Ok(())
+        };
+        match result {
+            Ok(()) => {
+                remove_blob_mappings(blob_id).await?;
+                println!("✅ {} - Deleted ({})", blob_id, size);
+                deleted += 1;
+            }
+            Err(e) => {
+                println!("❌ {} - Could not delete: {}", blob_id, e);
+                failed += 1;
+            }
+        }
+    }
+
+    println!("\nSummary:");
+    println!("  Orphaned blobs: {}", orphaned.len());
+    println!("  Orphaned storage: {}", format_bytes(orphaned_bytes));
+    if delete {
+        println!("  Deleted: {}", deleted);
+    }
+    if failed > 0 {
+        println!("  Failed: {}", failed);
+        return Err(anyhow::anyhow!("{} orphaned blobs could not be handled", failed));
+    }
+    Ok(())
+}
+
+/// Every pointer in the history of any ref, plus the staged ones.
+pub fn repository_pointers() -> Result<Vec<PointerFields>> {
+    let committed = git::reachable_small_blobs(&["--all"], MAX_POINTER_SIZE)?
+        .into_iter()
+        .filter_map(|(_, content)| pointer_fields(&content));
+    let staged = parse_pointer_lines(&git::index_pointer_lines()?).into_values();
+    Ok(committed.chain(staged).collect())
+}
+
+fn pointer_fields(content: &[u8]) -> Option<PointerFields> {
+    if !is_pointer(content) {
+        return None;
+    }
+    let content = String::from_utf8_lossy(content);
+    Some(PointerFields {
+        oid: Some(extract_oid(&content).ok()?.hash),
+        size: None,
+        blob_id: extract_walrus_blob_id(&content).ok(),
+    })
+}
+
+/// The blob IDs in `mappings` that none of `pointers` can resolve to, whether through its
+/// inline blob ID or the mapping entry for its OID.
+fn orphaned_blob_ids(
+    mappings: &Mappings,
+    pointers: impl IntoIterator<Item = PointerFields>,
+) -> BTreeSet<String> {
+    let mut referenced = HashSet::new();
+    for fields in pointers {
+        if let Some(entry) = fields.oid.as_ref().and_then(|oid| mappings.get(oid)) {
+            referenced.insert(entry.blob_id.clone());
+        }
+        referenced.extend(fields.blob_id);
+    }
+    mappings
+        .values()
+        .filter(|entry| !referenced.contains(&entry.blob_id))
+        .map(|entry| entry.blob_id.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapping::MappingEntry;
+
+    const OID_A: &str = "4d7a214614ab2935c943f9e0ff69d22eadbb8f32b1258daaa5e2ca24d17e2393";
+    const OID_B: &str = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+    const OID_C: &str = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+
+    fn entry(blob_id: &str) -> MappingEntry {
+        MappingEntry::from_blob_id(blob_id.to_string())
+    }
+
+    #[test]
+    fn only_blobs_no_pointer_resolves_to_are_orphaned() {
+        let mappings = Mappings::from([
+            (OID_A.to_string(), entry("blob-a")),
+            ("pointer-sha-of-a".to_string(), entry("blob-a")),
+            (OID_B.to_string(), entry("blob-b-old")),
+            (OID_C.to_string(), entry("blob-c")),
+        ]);
+        let pointers = [
+            // Referenced through the mapping entry for its OID
+            PointerFields {
+                oid: Some(OID_A.to_string()),
+                ..Default::default()
+            },
+            // Referenced through its inline blob ID, though the mapping lacks its OID
+            PointerFields {
+                oid: Some("0".repeat(64)),
+                size: None,
+                blob_id: Some("blob-b-old".to_string()),
+            },
+        ];
+
+        let orphaned = orphaned_blob_ids(&mappings, pointers);
+        assert_eq!(orphaned.into_iter().collect::<Vec<_>>(), ["blob-c"]);
+    }
+}
@@ -0,0 +1,261 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::chunks::{manifest_blob_id, ChunkManifest};
+use crate::mapping;
+
+/// A blob stored by a run that wasn't done with it yet: walrus had created it, but no mapping
+/// entry or pointer may refer to it. Runs that finish resolve their records, so whatever is
+/// left in `.git/walrus-pending.jsonl` was stored by an interrupted one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PendingStore {
+    pub blob_id: String,
+    /// Unix timestamp of the store
+    pub stored_at: u64,
+    /// A chunk of a larger file, useless without its manifest
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub chunk: bool,
+    /// For a chunk manifest stored as a blob, the chunks it lists
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub chunks: Vec<String>,
+}
+
+impl PendingStore {
+    pub fn new(blob_id: String) -> Self {
+        let stored_at = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        Self {
+            blob_id,
+            stored_at,
+            chunk: false,
+            chunks: Vec::new(),
+        }
+    }
+}
+
+/// `walrus-pending.jsonl` beside the mapping file, scoped to the same network.
+pub fn pending_file_path() -> Result<PathBuf> {
+    let name = match mapping::network()? {
+        Some(network) => format!("walrus-pending.{}.jsonl", network),
+        None => "walrus-pending.jsonl".to_string(),
+    };
+    mapping::repo_file_path(&name)
+}
+
+/// Appends `store` to the pending list, as soon as its blob ID is known. A failure only warns:
+/// the store itself succeeded.
+pub fn record(store: &PendingStore) {
+    warn_unless_recorded(store, pending_file_path().and_then(|path| try_record(&path, store)));
+}
+
+/// Like [`record`], to the pending list at `path`.
+pub fn record_in(path: &Path, store: &PendingStore) {
+    warn_unless_recorded(store, try_record(path, store));
+}
+
+fn try_record(path: &Path, store: &PendingStore) -> Result<()> {
+    with_lock(path, || {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        file.write_all(format!("{}\n", serde_json::to_string(store)?).as_bytes())?;
+        Ok(())
+    })
+}
+
+fn warn_unless_recorded(store: &PendingStore, result: Result<()>) {
+    if let Err(err) = result {
+        eprintln!(
+            "Warning: could not record pending store of blob {}: {:#}",
+            store.blob_id, err
+        );
+    }
+}
+
+/// Every pending store, skipping lines that can't be parsed.
+pub fn load() -> Result<Vec<PendingStore>> {
+    load_from(&pending_file_path()?)
+}
+
+fn load_from(path: &Path) -> Result<Vec<PendingStore>> {
+    // Nothing was ever recorded here, so there is no lock to take either
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    with_lock(path, || {
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+        Ok(parse(&std::fs::read_to_string(path)?, path))
+    })
+}
+
+fn parse(content: &str, path: &Path) -> Vec<PendingStore> {
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| match serde_json::from_str(line) {
+            Ok(store) => Some(store),
+            Err(err) => {
+                eprintln!("Warning: skipping unreadable line in {}: {}", path.display(), err);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Drops the pending records of `blob_ids`, now that a run has finished with them, along with
+/// those of the chunks and manifest blob behind a chunked ID. A failure only warns.
+pub fn resolve(blob_ids: &[&str]) {
+    if let Err(err) = pending_file_path().and_then(|path| try_resolve(&path, blob_ids)) {
+        eprintln!("Warning: could not update the pending store list: {:#}", err);
+    }
+}
+
+fn try_resolve(path: &Path, blob_ids: &[&str]) -> Result<()> {
+    if !path.exists() {
+        return Ok(());
+    }
+    with_lock(path, || {
+        if !path.exists() {
+            return Ok(());
+        }
+        let records = parse(&std::fs::read_to_string(path)?, path);
+        let covered = covered_blob_ids(blob_ids.iter().copied(), &records);
+        let remaining: Vec<&PendingStore> = records
+            .iter()
+            .filter(|store| !covered.contains(&store.blob_id))
+            .collect();
+        if remaining.len() == records.len() {
+            return Ok(());
+        }
+        if remaining.is_empty() {
+            std::fs::remove_file(path)?;
+            return Ok(());
+        }
+
+        let mut content = String::new();
+        for store in remaining {
+            content.push_str(&serde_json::to_string(store)?);
+            content.push('\n');
+        }
+        let parent = path.parent().unwrap_or(Path::new("."));
+        let temp_file = tempfile::NamedTempFile::new_in(parent)?;
+        std::fs::write(temp_file.path(), content)?;
+        temp_file
+            .persist(path)
+            .map_err(|err| anyhow::Error::new(err.error))?;
+        Ok(())
+    })
+}
+
+/// Runs `f` holding an exclusive lock on the file beside the pending list at `path`, so
+/// processes storing and resolving at once can't lose each other's records: a record appended
+/// while [`try_resolve`] rewrites the list would otherwise be dropped. The list itself is
+/// replaced by each rewrite, so it can't carry the lock.
+fn with_lock<T>(path: &Path, f: impl FnOnce() -> Result<T>) -> Result<T> {
+    let lock = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path.with_extension("lock"))?;
+    lock.lock()?;
+    f()
+}
+
+/// `blob_ids` plus every blob they are made of: the chunks listed in an inline manifest, and
+/// the manifest blob behind a `chunked-blob:` reference with the chunks its record lists.
+pub fn covered_blob_ids<'a>(
+    blob_ids: impl IntoIterator<Item = &'a str>,
+    records: &[PendingStore],
+) -> HashSet<String> {
+    let mut covered = HashSet::new();
+    for blob_id in blob_ids {
+        covered.insert(blob_id.to_string());
+        if let Some(Ok(manifest)) = ChunkManifest::parse(blob_id) {
+            covered.extend(manifest.chunks.into_iter().map(|chunk| chunk.blob_id));
+        }
+        if let Some(manifest_id) = manifest_blob_id(blob_id) {
+            covered.insert(manifest_id.to_string());
+            for store in records.iter().filter(|store| store.blob_id == manifest_id) {
+                covered.extend(store.chunks.iter().cloned());
+            }
+        }
+    }
+    covered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn store(blob_id: &str, chunks: &[&str]) -> PendingStore {
+        PendingStore {
+            chunks: chunks.iter().map(|chunk| chunk.to_string()).collect(),
+            ..PendingStore::new(blob_id.to_string())
+        }
+    }
+
+    #[test]
+    fn chunked_ids_cover_their_chunks_and_manifest_blob() {
+        let records = [store("manifest", &["c1", "c2"]), store("other", &[])];
+
+        let covered = covered_blob_ids(["chunked:a:3,b:4"], &records);
+        assert_eq!(covered, HashSet::from(["chunked:a:3,b:4", "a", "b"].map(String::from)));
+
+        let covered = covered_blob_ids(["chunked-blob:manifest"], &records);
+        assert_eq!(
+            covered,
+            HashSet::from(["chunked-blob:manifest", "manifest", "c1", "c2"].map(String::from))
+        );
+
+        assert_eq!(covered_blob_ids(["plain"], &records), HashSet::from(["plain".to_string()]));
+    }
+
+    #[test]
+    fn records_appended_while_others_resolve_are_kept() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("walrus-pending.jsonl");
+        for i in 0..50 {
+            record_in(&path, &store(&format!("done-{}", i), &[]));
+        }
+
+        std::thread::scope(|scope| {
+            for thread in 0..4 {
+                let path = &path;
+                scope.spawn(move || {
+                    for i in 0..25 {
+                        record_in(path, &store(&format!("kept-{}-{}", thread, i), &[]));
+                    }
+                });
+            }
+            for i in 0..50 {
+                try_resolve(&path, &[&format!("done-{}", i)]).unwrap();
+            }
+        });
+
+        let records = load_from(&path).unwrap();
+        assert_eq!(records.len(), 100);
+        assert!(records.iter().all(|store| store.blob_id.starts_with("kept-")));
+    }
+
+    #[test]
+    fn records_round_trip_through_json_lines() {
+        let chunk = PendingStore {
+            chunk: true,
+            ..store("c1", &[])
+        };
+        let records = [store("plain", &[]), chunk, store("manifest", &["c1"])];
+        let content: String = records
+            .iter()
+            .map(|store| format!("{}\n", serde_json::to_string(store).unwrap()))
+            .collect();
+        assert!(!content.lines().next().unwrap().contains("chunk"));
+
+        let parsed = parse(&format!("{}not json\n", content), Path::new("pending"));
+        assert_eq!(parsed, records);
+    }
+}
@@ -0,0 +1,288 @@
+use anyhow::Result;
+use std::path::Path;
+
+use crate::blob_id;
+use crate::git;
+use crate::oid::Oid;
+
+/// First line of every git-lfs pointer file.
+const POINTER_VERSION_LINE: &str = "version https://git-lfs.github.com/spec/v1";
+
+/// Returns true if `content` looks like a git-lfs pointer rather than file content.
+pub fn is_pointer(content: &[u8]) -> bool {
+    content.starts_with(POINTER_VERSION_LINE.as_bytes())
+}
+
+/// Returns the blob ID from the pointer's `ext-0-walrus` line.
+pub fn extract_walrus_blob_id(content: &str) -> Result<String> {
+    for line in content.lines() {
+        if line.starts_with("ext-0-walrus ")
+            && let Some((_, blob_id)) = line.split_once(' ')
+        {
+            return Ok(blob_id::normalize(blob_id.trim()));
+        }
+    }
+    Err(anyhow::anyhow!("No Walrus blob ID found in LFS pointer"))
+}
+
+/// Returns the algorithm and hash from the pointer's `oid <algorithm>:<hash>` line.
+pub fn extract_oid(content: &str) -> Result<Oid> {
+    content
+        .lines()
+        .find_map(Oid::parse_line)
+        .unwrap_or_else(|| Err(anyhow::anyhow!("LFS pointer has no OID")))
+}
+
+/// Pointer extension recording the file's permission bits, in octal.
+pub const MODE_EXTENSION: &str = "ext-1-walrus-mode";
+
+/// Parses an `ext-1-walrus-mode` line, ignoring values that aren't octal permission bits.
+pub fn parse_mode_line(line: &str) -> Option<u32> {
+    let value = line.strip_prefix(MODE_EXTENSION)?.strip_prefix(' ')?;
+    u32::from_str_radix(value.trim(), 8)
+        .ok()
+        .filter(|mode| *mode <= 0o7777)
+}
+
+/// Pointer extension recording the file's MIME type.
+pub const CONTENT_TYPE_EXTENSION: &str = "ext-2-walrus-content-type";
+
+/// Returns the MIME type from the pointer's `ext-2-walrus-content-type` line.
+pub fn extract_content_type(content: &str) -> Option<&str> {
+    content.lines().find_map(|line| {
+        line.strip_prefix(CONTENT_TYPE_EXTENSION)?
+            .strip_prefix(' ')
+            .map(str::trim)
+    })
+}
+
+/// Pointer extension recording the epoch at which the blob's storage ends.
+pub const END_EPOCH_EXTENSION: &str = "ext-3-walrus-endepoch";
+
+/// Returns the storage end epoch from the pointer's `ext-3-walrus-endepoch` line.
+pub fn extract_end_epoch(content: &str) -> Option<u64> {
+    content.lines().find_map(|line| {
+        line.strip_prefix(END_EPOCH_EXTENSION)?
+            .strip_prefix(' ')?
+            .trim()
+            .parse()
+            .ok()
+    })
+}
+
+/// Builds a pointer from its `key value` lines in the spec's canonical order: `version` first,
+/// then the other keys sorted. Extension keys (`ext-<priority>-<name>`) thereby come before
+/// `oid` and `size`, ordered by priority. A `version` line among `lines` is replaced.
+pub fn canonical_pointer<S: AsRef<str>>(lines: impl IntoIterator<Item = S>) -> String {
+    let mut lines: Vec<String> = lines
+        .into_iter()
+        .map(|line| line.as_ref().trim_end().to_string())
+        .filter(|line| !line.is_empty() && !line.starts_with("version "))
+        .collect();
+    lines.sort_by(|a, b| pointer_key(a).cmp(pointer_key(b)));
+
+    let mut pointer = format!("{}\n", POINTER_VERSION_LINE);
+    for line in lines {
+        pointer.push_str(&line);
+        pointer.push('\n');
+    }
+    pointer
+}
+
+/// Line ending `clean` writes pointers with, from `lfs.walrus.pointereol`. The spec's LF is the
+/// default; `native` picks CRLF on Windows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PointerEol {
+    Lf,
+    Crlf,
+}
+
+impl PointerEol {
+    pub fn from_config() -> Result<Self> {
+        match git::config_get("lfs.walrus.pointereol")? {
+            Some(value) => Self::parse(&value),
+            None => Ok(PointerEol::Lf),
+        }
+    }
+
+    fn parse(value: &str) -> Result<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "lf" => Ok(PointerEol::Lf),
+            "crlf" => Ok(PointerEol::Crlf),
+            "native" if cfg!(windows) => Ok(PointerEol::Crlf),
+            "native" => Ok(PointerEol::Lf),
+            _ => Err(anyhow::anyhow!(
+                "invalid lfs.walrus.pointereol {:?}: expected lf, crlf or native",
+                value
+            )),
+        }
+    }
+
+    /// `pointer`, as [`canonical_pointer`] builds it, with this line ending.
+    pub fn apply(self, pointer: &str) -> String {
+        match self {
+            PointerEol::Lf => pointer.to_string(),
+            PointerEol::Crlf => pointer.replace('\n', "\r\n"),
+        }
+    }
+}
+
+fn pointer_key(line: &str) -> &str {
+    line.split_once(' ').map_or(line, |(key, _)| key)
+}
+
+/// Adds an `ext-0-walrus` line to a pointer that lacks one.
+pub fn with_walrus_blob_id(content: &str, blob_id: &str) -> String {
+    let line = format!("ext-0-walrus {}", blob_id);
+    canonical_pointer(content.lines().chain(std::iter::once(line.as_str())))
+}
+
+/// Replaces the blob ID on the pointer's `ext-0-walrus` line, adding one if it is missing.
+pub fn replace_walrus_blob_id(content: &str, blob_id: &str) -> String {
+    if extract_walrus_blob_id(content).is_err() {
+        return with_walrus_blob_id(content, blob_id);
+    }
+    canonical_pointer(content.lines().map(|line| {
+        if line.starts_with("ext-0-walrus ") {
+            format!("ext-0-walrus {}", blob_id)
+        } else {
+            line.to_string()
+        }
+    }))
+}
+
+/// Where a pointer was read from, so an updated pointer goes back to the same place.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PointerLocation {
+    /// The file isn't checked out; the working tree holds the pointer
+    WorkingTree,
+    /// The file is checked out; only the index holds the pointer
+    Index,
+}
+
+/// Reads the pointer for `file`: from the working tree if the file isn't checked out,
+/// otherwise from the index.
+pub async fn load_pointer(file: &Path) -> Result<(String, PointerLocation)> {
+    match tokio::fs::read(file).await.ok() {
+        Some(content) if is_pointer(&content) => {
+            Ok((String::from_utf8(content)?, PointerLocation::WorkingTree))
+        }
+        _ => {
+            let staged = git::show_index(file)?
+                .filter(|content| is_pointer(content.as_bytes()))
+                .ok_or_else(|| anyhow::anyhow!("Not an LFS pointer in the index"))?;
+            Ok((staged, PointerLocation::Index))
+        }
+    }
+}
+
+/// Writes an updated pointer back to where [`load_pointer`] found it; an index pointer is
+/// staged, ready to be committed.
+pub async fn write_pointer(file: &Path, location: PointerLocation, pointer: &str) -> Result<()> {
+    match location {
+        PointerLocation::WorkingTree => tokio::fs::write(file, pointer.as_bytes()).await?,
+        PointerLocation::Index => git::stage_content(file, pointer.as_bytes())?,
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LFS_POINTER: &str = r#"version https://git-lfs.github.com/spec/v1
+oid sha256:b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9
+size 11
+"#;
+
+    #[test]
+    fn pointer_fields_are_extracted() {
+        assert!(is_pointer(LFS_POINTER.as_bytes()));
+        assert!(!is_pointer(b"hello world"));
+        let oid = extract_oid(LFS_POINTER).unwrap();
+        assert_eq!(oid.algorithm, crate::oid::HashAlgorithm::Sha256);
+        assert_eq!(
+            oid.hash,
+            "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9"
+        );
+        assert!(extract_oid("version https://git-lfs.github.com/spec/v1\nsize 11\n").is_err());
+        assert!(extract_walrus_blob_id(LFS_POINTER).is_err());
+    }
+
+    #[test]
+    fn mode_extension_is_parsed_as_octal() {
+        assert_eq!(parse_mode_line(&format!("{} 0755", MODE_EXTENSION)), Some(0o755));
+        assert_eq!(parse_mode_line("ext-0-walrus 0755"), None);
+        assert_eq!(parse_mode_line("ext-1-walrus-mode 0999"), None);
+        assert_eq!(parse_mode_line("ext-1-walrus-mode 170000"), None);
+    }
+
+    #[test]
+    fn content_type_extension_is_extracted() {
+        let pointer = format!("{}{} image/png\n", LFS_POINTER, CONTENT_TYPE_EXTENSION);
+        assert_eq!(extract_content_type(&pointer), Some("image/png"));
+        assert_eq!(extract_content_type(LFS_POINTER), None);
+    }
+
+    const CANONICAL_POINTER: &str = r#"version https://git-lfs.github.com/spec/v1
+ext-0-walrus blob-1
+ext-1-walrus-mode 0644
+oid sha256:b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9
+size 11
+"#;
+
+    #[test]
+    fn pointers_are_written_in_canonical_order() {
+        let pointer = canonical_pointer([
+            "size 11",
+            "oid sha256:b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9",
+            "ext-1-walrus-mode 0644",
+            "ext-0-walrus blob-1",
+        ]);
+        assert_eq!(pointer, CANONICAL_POINTER);
+        assert_eq!(canonical_pointer(CANONICAL_POINTER.lines()), CANONICAL_POINTER);
+    }
+
+    #[test]
+    fn end_epoch_extension_is_extracted() {
+        let pointer = format!("{}{} 120\n", LFS_POINTER, END_EPOCH_EXTENSION);
+        assert_eq!(extract_end_epoch(&pointer), Some(120));
+        assert_eq!(extract_end_epoch(LFS_POINTER), None);
+        assert_eq!(extract_end_epoch(&format!("{} soon\n", END_EPOCH_EXTENSION)), None);
+    }
+
+    #[test]
+    fn replace_walrus_blob_id_rewrites_only_that_line() {
+        assert_eq!(
+            replace_walrus_blob_id(CANONICAL_POINTER, "new"),
+            CANONICAL_POINTER.replace("blob-1", "new")
+        );
+        assert_eq!(
+            replace_walrus_blob_id(LFS_POINTER, "new"),
+            with_walrus_blob_id(LFS_POINTER, "new")
+        );
+    }
+
+    #[test]
+    fn with_walrus_blob_id_inserts_the_inline_field_in_order() {
+        let pointer = format!("{}ext-1-walrus-mode 0644\n", LFS_POINTER);
+        let repaired = with_walrus_blob_id(&pointer, "blob-1");
+        assert_eq!(repaired, CANONICAL_POINTER);
+        assert_eq!(extract_walrus_blob_id(&repaired).unwrap(), "blob-1");
+    }
+
+    #[test]
+    fn crlf_pointers_keep_their_fields() {
+        assert_eq!(PointerEol::parse("CRLF").unwrap(), PointerEol::Crlf);
+        assert_eq!(PointerEol::parse("lf").unwrap(), PointerEol::Lf);
+        assert!(PointerEol::parse("cr").is_err());
+
+        let pointer = PointerEol::Crlf.apply(CANONICAL_POINTER);
+        assert!(pointer.ends_with("size 11\r\n") && !pointer.contains("\r\r"));
+        assert_eq!(PointerEol::Lf.apply(CANONICAL_POINTER), CANONICAL_POINTER);
+        assert!(is_pointer(pointer.as_bytes()));
+        assert_eq!(extract_walrus_blob_id(&pointer).unwrap(), "blob-1");
+        assert_eq!(extract_oid(&pointer).unwrap().hash, extract_oid(CANONICAL_POINTER).unwrap().hash);
+        assert_eq!(canonical_pointer(pointer.lines()), CANONICAL_POINTER);
+    }
+}
@@ -0,0 +1,83 @@
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+use crate::git;
+use crate::mapping::get_blob_id_from_sha;
+use crate::pointer::{
+    extract_oid, extract_walrus_blob_id, load_pointer, with_walrus_blob_id, write_pointer,
+    PointerLocation,
+};
+
+enum RepairResult {
+    /// The pointer was rewritten in the working tree
+    RepairedWorkingTree,
+    /// The pointer was rewritten in the index, ready to be committed
+    RepairedIndex,
+    AlreadyInline,
+}
+
+/// Adds the `ext-0-walrus` line back to pointers that lost it, using the mapping file.
+pub async fn pointer_repair(files: Vec<PathBuf>, all: bool) -> Result<()> {
+    let files = match (files.is_empty(), all) {
+        (true, true) => git::lfs_files()?,
+        (false, false) => files,
+        _ => {
+            return Err(anyhow::anyhow!(
+                "Provide either files to repair or --all"
+            ));
+        }
+    };
+
+    let mut repaired_count = 0;
+    let mut inline_count = 0;
+    let mut error_count = 0;
+
+    for file_path in files {
+        match repair_file(&file_path).await {
+            Ok(RepairResult::RepairedWorkingTree) => {
+                repaired_count += 1;
+                println!("🔧 {} - Repaired pointer", file_path.display());
+            }
+            Ok(RepairResult::RepairedIndex) => {
+                repaired_count += 1;
+                println!("🔧 {} - Repaired pointer (staged)", file_path.display());
+            }
+            Ok(RepairResult::AlreadyInline) => {
+                inline_count += 1;
+                println!("✅ {} - Blob ID already inline", file_path.display());
+            }
+            Err(e) => {
+                error_count += 1;
+                println!("⚠️  {} - Error: {}", file_path.display(), e);
+            }
+        }
+    }
+
+    println!("\nSummary:");
+    println!("  Repaired: {}", repaired_count);
+    println!("  Already inline: {}", inline_count);
+    println!("  Errors: {}", error_count);
+
+    Ok(())
+}
+
+/// Repairs the working-tree pointer if the file isn't checked out, otherwise the staged one.
+async fn repair_file(file_path: &Path) -> Result<RepairResult> {
+    let (pointer, location) = load_pointer(file_path).await?;
+
+    if extract_walrus_blob_id(&pointer).is_ok() {
+        return Ok(RepairResult::AlreadyInline);
+    }
+
+    let oid = extract_oid(&pointer)?;
+    let blob_id = get_blob_id_from_sha(&oid.hash)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("No mapping entry for {} {}", oid.algorithm, oid.hash))?;
+    let repaired = with_walrus_blob_id(&pointer, &blob_id);
+
+    write_pointer(file_path, location, &repaired).await?;
+    Ok(match location {
+        PointerLocation::WorkingTree => RepairResult::RepairedWorkingTree,
+        PointerLocation::Index => RepairResult::RepairedIndex,
+    })
+}
@@ -0,0 +1,356 @@
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+
+use crate::bulk::BulkErrors;
+use crate::dedup_report::{parse_pointer_lines, PointerFields};
+use crate::git;
+use crate::mapping::{self, load_mappings};
+use crate::metrics;
+use crate::oid::Oid;
+use crate::transfer::verify_download;
+use crate::walrus::WalrusClient;
+
+/// Blobs up to this size are read many at a time and held in memory on the way to the cache;
+/// larger ones are downloaded one by one straight to disk.
+const BATCH_READ_MAX_SIZE: u64 = 1024 * 1024;
+
+/// A lock older than this is left over from a prefetch that was killed, and is taken over.
+const STALE_LOCK: Duration = Duration::from_secs(60 * 60);
+
+/// Directory of verified blob content fetched ahead of smudge, one file per OID hash.
+pub fn cache_dir() -> Result<PathBuf> {
+    Ok(git::git_dir()?.join("walrus-cache"))
+}
+
+/// Opens the cached content for `oid`, if a prefetch has fetched it.
+///
+/// Entries are only ever renamed into place complete, so an opened entry is never torn even
+/// while a prefetch writes the same OID. An entry that can't be opened, e.g. because it was
+/// replaced or removed since the lookup, is treated as a miss and fetched from Walrus.
+/// Every lookup counts towards the cache hit rate in the `--metrics` summary.
+pub async fn open_cached(oid: &Oid) -> Option<tokio::fs::File> {
+    let file = lookup_cached(oid).await;
+    metrics::record_cache_lookup(file.is_some());
+    file
+}
+
+async fn lookup_cached(oid: &Oid) -> Option<tokio::fs::File> {
+    let path = cache_dir().ok()?.join(&oid.hash);
+    match tokio::fs::File::open(&path).await {
+        Ok(file) => {
+            // The modification time records the last use, for `cache-clean --max-age/--max-size`
+            let file = file.into_std().await;
+            if let Err(err) = file.set_modified(std::time::SystemTime::now()) {
+                eprintln!("Warning: could not mark {} as used: {}", path.display(), err);
+            }
+            Some(tokio::fs::File::from_std(file))
+        }
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+        Err(err) => {
+            eprintln!(
+                "Warning: could not read the cached {}, fetching it instead: {}",
+                path.display(),
+                err
+            );
+            None
+        }
+    }
+}
+
+fn lock_path() -> Result<PathBuf> {
+    Ok(git::git_dir()?.join("walrus-prefetch.lock"))
+}
+
+/// `lfs.walrus.prefetch`: start a background prefetch when smudge misses the cache.
+pub fn enabled() -> bool {
+    git::config_flag("lfs.walrus.prefetch")
+}
+
+/// Starts `prefetch --background` as a detached process, unless one is already running.
+pub fn start_in_background(client: &WalrusClient) -> Result<()> {
+    if lock_path().is_ok_and(|lock| holds_live_lock(&lock, STALE_LOCK)) {
+        return Ok(());
+    }
+
+    std::process::Command::new(std::env::current_exe()?)
+        .args(background_args(client.walrus_path(), mapping::network()?.as_deref()))
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    Ok(())
+}
+
+/// Arguments for a background prefetch that reads from the same walrus and resolves blob IDs
+/// through the same network's mapping file as this process. The network is always given, so
+/// `--network default` here isn't overridden by `lfs.walrus.network` there.
+fn background_args(walrus_path: Option<&Path>, network: Option<&str>) -> Vec<OsString> {
+    let mut args: Vec<OsString> = Vec::new();
+    if let Some(walrus_path) = walrus_path {
+        args.extend(["--walrus-path".into(), walrus_path.into()]);
+    }
+    args.extend([
+        "--network".into(),
+        network.unwrap_or(mapping::DEFAULT_NETWORK).into(),
+        "prefetch".into(),
+        "--background".into(),
+    ]);
+    args
+}
+
+/// Downloads the blobs of every LFS file in `revision` (default: the index and HEAD) into the
+/// cache, so smudging them needs no Walrus round trip.
+///
+/// In the background (started by smudge) a running prefetch is not an error, and nothing is
+/// printed since nobody is reading it.
+pub async fn prefetch(client: WalrusClient, revision: Option<String>, background: bool) -> Result<()> {
+    let Some(_lock) = PrefetchLock::acquire(&lock_path()?, STALE_LOCK)? else {
+        if background {
+            return Ok(());
+        }
+        return Err(anyhow::anyhow!(
+            "Another prefetch is running ({} exists; delete it if no prefetch is running)",
+            lock_path()?.display()
+        ));
+    };
+
+    let pointers = match &revision {
+        Some(revision) => parse_pointer_lines(&git::tree_pointer_lines(revision)?),
+        None => {
+            let mut pointers = parse_pointer_lines(&git::index_pointer_lines()?);
+            // An unborn HEAD has no files to add
+            if let Ok(head) = git::tree_pointer_lines("HEAD") {
+                pointers.extend(parse_pointer_lines(&head));
+            }
+            pointers
+        }
+    };
+    let targets = unique_by_oid(pointers);
+
+    let cache = cache_dir()?;
+    tokio::fs::create_dir_all(&cache).await?;
+    let order = client.resolve_order();
+    let mappings = if !order.uses_mapping() {
+        Default::default()
+    } else {
+        load_mappings().await?
+    };
+
+    let mut fetched = 0;
+    let mut cached = 0;
+    let mut skipped = 0;
+    let mut failed = 0;
+    let mut errors = BulkErrors::new(false);
+    let mut small = Vec::new();
+    let mut large = Vec::new();
+    for (oid, (path, size, blob_id)) in &targets {
+        if cache.join(oid).is_file() {
+            cached += 1;
+            continue;
+        }
+        let Some(blob_id) = order.pick(
+            oid,
+            blob_id.as_deref(),
+            mappings.get(oid).map(|entry| entry.blob_id.as_str()),
+        ) else {
+            if !background {
+                println!("⚠️  {}: no Walrus blob ID in the pointer or mapping", path.display());
+            }
+            skipped += 1;
+            continue;
+        };
+        if *size <= BATCH_READ_MAX_SIZE {
+            small.push((oid, path, *size, blob_id));
+        } else {
+            large.push((oid, path, *size, blob_id));
+        }
+    }
+
+    // Small blobs come back from one concurrent batch, in order; large ones are read after
+    let blob_ids: Vec<&str> = small.iter().map(|(_, _, _, blob_id)| blob_id.as_str()).collect();
+    let mut contents = if blob_ids.is_empty() {
+        Vec::new()
+    } else {
+        client.read_blobs(&blob_ids).await
+    }
+    .into_iter();
+    for (oid, path, size, blob_id) in small.iter().chain(&large) {
+        let result = match contents.next() {
+            Some(Ok(content)) => cache_content(&cache, oid, *size, &content).await,
+            Some(Err(e)) => Err(e),
+            None => fetch_into_cache(&client, &cache, oid, *size, blob_id).await,
+        };
+        match result {
+            Ok(()) => {
+                if !background {
+                    println!("✅ {}", path.display());
+                }
+                fetched += 1;
+            }
+            Err(e) => {
+                if !background {
+                    println!("❌ {}: {}", path.display(), e);
+                }
+                failed += 1;
+                if errors.record(path, &e) {
+                    break;
+                }
+            }
+        }
+    }
+
+    if !background {
+        println!("\nSummary:");
+        println!("  Fetched: {}", fetched);
+        println!("  Already cached: {}", cached);
+        if skipped > 0 {
+            println!("  Skipped: {}", skipped);
+        }
+        if failed > 0 {
+            println!("  Errors: {}", failed);
+        }
+    }
+    errors.finish()?;
+    if failed > 0 {
+        return Err(anyhow::anyhow!("could not prefetch {} files", failed));
+    }
+    Ok(())
+}
+
+/// One download per OID, however many files share the content: the first path naming it (for
+/// messages), its size, and its inline blob ID if any pointer has one.
+fn unique_by_oid(
+    pointers: BTreeMap<PathBuf, PointerFields>,
+) -> BTreeMap<String, (PathBuf, u64, Option<String>)> {
+    let mut targets: BTreeMap<String, (PathBuf, u64, Option<String>)> = BTreeMap::new();
+    for (path, fields) in pointers {
+        let (Some(oid), Some(size)) = (fields.oid, fields.size) else {
+            continue; // Not a pointer
+        };
+        let target = targets.entry(oid).or_insert((path, size, None));
+        if target.2.is_none() {
+            target.2 = fields.blob_id;
+        }
+    }
+    targets
+}
+
+/// Writes content read in a batch next to its cache entry and moves it into place only once it
+/// matches the OID.
+async fn cache_content(cache: &Path, oid: &str, size: u64, content: &[u8]) -> Result<()> {
+    let partial = tempfile::Builder::new()
+        .prefix(&format!(".{}", oid))
+        .suffix(".part")
+        .tempfile_in(cache)?;
+    tokio::fs::write(partial.path(), content).await?;
+    verify_download(partial.path(), oid, size).await?;
+    partial.persist(cache.join(oid))?;
+    Ok(())
+}
+
+/// Reads the blob next to its cache entry and moves it into place only once it matches the
+/// OID, so smudge never serves a partial or wrong download.
+async fn fetch_into_cache(
+    client: &WalrusClient,
+    cache: &Path,
+    oid: &str,
+    size: u64,
+    blob_id: &str,
+) -> Result<()> {
+    let partial = tempfile::Builder::new()
+        .prefix(&format!(".{}", oid))
+        .suffix(".part")
+        .tempfile_in(cache)?;
+    client.read_blob(blob_id, partial.path()).await?;
+    verify_download(partial.path(), oid, size).await?;
+    partial.persist(cache.join(oid))?;
+    Ok(())
+}
+
+fn holds_live_lock(path: &Path, stale_after: Duration) -> bool {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .is_ok_and(|modified| modified.elapsed().unwrap_or_default() < stale_after)
+}
+
+/// Lock file making sure only one prefetch runs per repository; removed when dropped.
+struct PrefetchLock {
+    path: PathBuf,
+}
+
+impl PrefetchLock {
+    /// Takes the lock, or returns `None` if a live prefetch holds it.
+    fn acquire(path: &Path, stale_after: Duration) -> Result<Option<Self>> {
+        if path.exists() && !holds_live_lock(path, stale_after) {
+            let _ = std::fs::remove_file(path);
+        }
+        match OpenOptions::new().write(true).create_new(true).open(path) {
+            Ok(mut file) => {
+                let _ = writeln!(file, "{}", std::process::id());
+                Ok(Some(Self {
+                    path: path.to_path_buf(),
+                }))
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+impl Drop for PrefetchLock {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_one_holder_of_the_lock_until_it_is_dropped_or_stale() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("walrus-prefetch.lock");
+
+        let lock = PrefetchLock::acquire(&path, STALE_LOCK).unwrap().unwrap();
+        assert!(PrefetchLock::acquire(&path, STALE_LOCK).unwrap().is_none());
+        drop(lock);
+        assert!(!path.exists());
+
+        // A lock left behind by a killed prefetch is taken over once stale
+        std::fs::write(&path, "12345\n").unwrap();
+        assert!(PrefetchLock::acquire(&path, Duration::ZERO).unwrap().is_some());
+    }
+
+    #[test]
+    fn background_prefetches_use_the_same_walrus_and_network() {
+        let args = background_args(Some(Path::new("/opt/walrus")), Some("testnet"));
+        assert_eq!(
+            args,
+            ["--walrus-path", "/opt/walrus", "--network", "testnet", "prefetch", "--background"]
+        );
+        let args = background_args(None, None);
+        assert_eq!(args, ["--network", "default", "prefetch", "--background"]);
+    }
+
+    #[test]
+    fn files_sharing_content_are_fetched_once() {
+        let lines = "a.bin\0oid sha256:aaaa\na.bin\0size 3\n\
+                     b.bin\0oid sha256:aaaa\nb.bin\0size 3\nb.bin\0ext-0-walrus blob-a\n\
+                     notes.txt\0oid sha256:bbbb\n";
+        let targets = unique_by_oid(parse_pointer_lines(lines));
+        assert_eq!(
+            targets,
+            BTreeMap::from([(
+                "aaaa".to_string(),
+                (PathBuf::from("a.bin"), 3, Some("blob-a".to_string()))
+            )])
+        );
+    }
+}
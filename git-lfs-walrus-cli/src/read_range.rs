@@ -0,0 +1,27 @@
+use anyhow::Result;
+use std::path::PathBuf;
+
+use crate::walrus::WalrusClient;
+
+/// Writes part of a blob to `output` (or stdout).
+///
+/// The bytes are not checked against the file's SHA256, since the OID covers the whole file.
+pub async fn read_range(
+    client: WalrusClient,
+    blob_id: String,
+    start: u64,
+    length: u64,
+    output: Option<PathBuf>,
+) -> Result<()> {
+    match output {
+        Some(path) => {
+            let file = tokio::fs::File::create(&path).await?;
+            client.read_blob_range(&blob_id, start, length, file).await
+        }
+        None => {
+            client
+                .read_blob_range(&blob_id, start, length, tokio::io::stdout())
+                .await
+        }
+    }
+}
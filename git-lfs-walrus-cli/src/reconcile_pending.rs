@@ -0,0 +1,182 @@
+use anyhow::Result;
+use std::collections::HashSet;
+use tokio::io::AsyncWriteExt;
+
+use crate::chunks::{manifest_blob_id, manifest_reference};
+use crate::mapping::{load_mappings, remove_blob_mappings, store_blob_mapping, MappingEntry};
+use crate::migrate::format_bytes;
+use crate::oid::{HashAlgorithm, HashingWriter};
+use crate::orphans::repository_pointers;
+use crate::pending::{self, PendingStore};
+use crate::walrus::WalrusClient;
+
+/// Goes through the stores an interrupted run left pending. Blobs that a mapping entry or
+/// pointer refers to after all, or that are gone from Walrus, are simply resolved. The rest are
+/// listed; with `adopt` each is read back and mapped by the SHA-256 of its content, with
+/// `delete` each is deleted from Walrus.
+pub async fn reconcile_pending(client: WalrusClient, adopt: bool, delete: bool) -> Result<()> {
+    let mut records: Vec<PendingStore> = Vec::new();
+    for store in pending::load()? {
+        if !records.iter().any(|seen| seen.blob_id == store.blob_id) {
+            records.push(store);
+        }
+    }
+    if records.is_empty() {
+        println!("No pending stores");
+        return Ok(());
+    }
+    let referenced = referenced_blob_ids(&client, &records).await?;
+
+    let mut counts = Counts::default();
+    for store in &records {
+        if referenced.contains(&store.blob_id) {
+            println!("✅ {} - Referenced", store.blob_id);
+            pending::resolve(&[&store.blob_id]);
+            counts.referenced += 1;
+            continue;
+        }
+        let status = match client.blob_status(&store.blob_id).await {
+            Ok(Some(status)) => status,
+            Ok(None) => {
+                println!("✅ {} - No longer in Walrus", store.blob_id);
+                pending::resolve(&[&store.blob_id]);
+                counts.gone += 1;
+                continue;
+            }
+            Err(e) => {
+                println!("⚠️  {} - Could not get its status: {}", store.blob_id, e);
+                counts.failed += 1;
+                continue;
+            }
+        };
+
+        if delete {
+            match delete_store(&client, store).await {
+                Ok(()) => {
+                    println!("✅ {} - Deleted", store.blob_id);
+                    counts.deleted += 1;
+                }
+                Err(e) => {
+                    println!("❌ {} - Could not delete: {}", store.blob_id, e);
+                    counts.failed += 1;
+                }
+            }
+        } else if adopt && !store.chunk {
+            match adopt_store(&client, store).await {
+                Ok(sha256_hex) => {
+                    println!("✅ {} - Adopted as {}", store.blob_id, sha256_hex);
+                    counts.adopted += 1;
+                }
+                Err(e) => {
+                    println!("❌ {} - Could not adopt: {}", store.blob_id, e);
+                    counts.failed += 1;
+                }
+            }
+        } else {
+            let size = status
+                .blob_object
+                .as_ref()
+                .map_or("unknown size".to_string(), |blob| format_bytes(blob.size));
+            let kind = if store.chunk { " | Chunk" } else { "" };
+            println!(
+                "⚠️  {} - {} | Status: {} | Stored at {}{}",
+                store.blob_id, size, status.status, store.stored_at, kind
+            );
+            counts.unreferenced += 1;
+        }
+    }
+
+    println!("\nSummary:");
+    println!("  Pending stores: {}", records.len());
+    println!("  Referenced: {}", counts.referenced);
+    println!("  No longer in Walrus: {}", counts.gone);
+    if adopt {
+        println!("  Adopted: {}", counts.adopted);
+    }
+    if delete {
+        println!("  Deleted: {}", counts.deleted);
+    }
+    println!("  Unreferenced: {}", counts.unreferenced);
+    if counts.failed > 0 {
+        println!("  Failed: {}", counts.failed);
+        return Err(anyhow::anyhow!(
+            "{} pending stores could not be handled",
+            counts.failed
+        ));
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct Counts {
+    referenced: usize,
+    gone: usize,
+    adopted: usize,
+    deleted: usize,
+    unreferenced: usize,
+    failed: usize,
+}
+
+/// The blob IDs the mapping file or a pointer refers to, along with the chunks and manifest
+/// blobs behind them.
+async fn referenced_blob_ids(
+    client: &WalrusClient,
+    records: &[PendingStore],
+) -> Result<HashSet<String>> {
+    let mappings = load_mappings().await?;
+    let mut blob_ids: HashSet<String> = mappings.values().map(|entry| entry.blob_id.clone()).collect();
+    blob_ids.extend(repository_pointers()?.into_iter().filter_map(|fields| fields.blob_id));
+    let mut referenced = pending::covered_blob_ids(blob_ids.iter().map(String::as_str), records);
+
+    // Chunks whose manifest record is already gone can only be matched through the manifest
+    if records
+        .iter()
+        .any(|store| store.chunk && !referenced.contains(&store.blob_id))
+    {
+        for blob_id in &blob_ids {
+            let Some(manifest_id) = manifest_blob_id(blob_id) else {
+                continue;
+            };
+            if records.iter().any(|store| store.blob_id == manifest_id) {
+                continue;
+            }
+            match client.chunk_manifest(blob_id).await {
+                Ok(Some(manifest)) => {
+                    referenced.extend(manifest.chunks.into_iter().map(|chunk| chunk.blob_id))
+                }
+                Ok(None) => {}
+                Err(e) => eprintln!("Warning: could not read the chunks of {}: {:#}", blob_id, e),
+            }
+        }
+    }
+    Ok(referenced)
+}
+
+/// The ID a mapping entry would use for `store`: a manifest blob stands for its chunked content.
+fn content_blob_id(store: &PendingStore) -> String {
+    if store.chunks.is_empty() {
+        store.blob_id.clone()
+    } else {
+        manifest_reference(&store.blob_id)
+    }
+}
+
+/// Reads the content back and maps its SHA-256 to it, returning the hash.
+async fn adopt_store(client: &WalrusClient, store: &PendingStore) -> Result<String> {
+    let blob_id = content_blob_id(store);
+    let mut writer = HashingWriter::new(tokio::io::sink(), HashAlgorithm::Sha256);
+    client.read_blob_to_writer(&blob_id, &mut writer).await?;
+    writer.flush().await?;
+    let (sha256_hex, _) = writer.finish();
+    // Resolves the record, and those of its chunks
+    store_blob_mapping(&sha256_hex, &MappingEntry::from_blob_id(blob_id)).await?;
+    Ok(sha256_hex)
+}
+
+/// Deletes the blob and any mapping entries left for it.
+async fn delete_store(client: &WalrusClient, store: &PendingStore) -> Result<()> {
+    client.delete_blob(&store.blob_id).await?;
+    remove_blob_mappings(&content_blob_id(store)).await?;
+    pending::resolve(&[&store.blob_id]);
+    Ok(())
+}
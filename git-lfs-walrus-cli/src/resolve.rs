@@ -0,0 +1,234 @@
+use anyhow::Result;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::git;
+use crate::mapping::get_blob_id_from_sha;
+use crate::pointer::{extract_oid, extract_walrus_blob_id, is_pointer};
+
+/// Where a Walrus blob ID can come from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    /// The pointer's `ext-0-walrus` field (for a bare OID, the pointer staged in the index)
+    Inline,
+    /// The mapping file entry for the OID
+    Mapping,
+    /// The OID hash itself, for workflows whose objects are named by blob ID
+    Literal,
+}
+
+impl Source {
+    fn name(self) -> &'static str {
+        match self {
+            Source::Inline => "inline",
+            Source::Mapping => "mapping",
+            Source::Literal => "literal",
+        }
+    }
+}
+
+/// The order in which blob ID sources are tried (`lfs.walrus.resolveorder`), e.g.
+/// `inline,mapping`. With `lfs.walrus.selfcontained` the mapping is never consulted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolveOrder {
+    sources: Vec<Source>,
+    self_contained: bool,
+}
+
+impl Default for ResolveOrder {
+    fn default() -> Self {
+        Self {
+            sources: vec![Source::Inline, Source::Mapping],
+            self_contained: false,
+        }
+    }
+}
+
+impl FromStr for ResolveOrder {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        let mut sources = Vec::new();
+        for name in value.split(',').map(str::trim) {
+            let source = match name {
+                "inline" => Source::Inline,
+                "mapping" => Source::Mapping,
+                "literal" => Source::Literal,
+                _ => {
+                    return Err(anyhow::anyhow!(
+                        "{:?} is not a blob ID source; expected inline, mapping or literal",
+                        name
+                    ));
+                }
+            };
+            if sources.contains(&source) {
+                return Err(anyhow::anyhow!("{} is listed twice", name));
+            }
+            sources.push(source);
+        }
+        Ok(Self {
+            sources,
+            self_contained: false,
+        })
+    }
+}
+
+impl fmt::Display for ResolveOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<_> = self.sources.iter().map(|source| source.name()).collect();
+        f.write_str(&names.join(","))
+    }
+}
+
+impl ResolveOrder {
+    /// Reads `lfs.walrus.resolveorder`, falling back to the default `inline,mapping`.
+    pub fn from_config(self_contained: bool) -> Self {
+        let order = match git::config_get("lfs.walrus.resolveorder").ok().flatten() {
+            Some(value) => value.parse().unwrap_or_else(|err| {
+                eprintln!(
+                    "Warning: lfs.walrus.resolveorder {:?}: {}, using {}",
+                    value,
+                    err,
+                    Self::default()
+                );
+                Self::default()
+            }),
+            None => Self::default(),
+        };
+        Self {
+            self_contained,
+            ..order
+        }
+    }
+
+    /// Whether the mapping file is consulted at all.
+    pub fn uses_mapping(&self) -> bool {
+        !self.self_contained && self.sources.contains(&Source::Mapping)
+    }
+
+    /// Picks the blob ID for `oid` from sources already at hand, for commands that resolve
+    /// many objects at once.
+    pub fn pick(&self, oid: &str, inline: Option<&str>, mapping: Option<&str>) -> Option<String> {
+        self.sources.iter().find_map(|source| match source {
+            Source::Inline => inline.map(str::to_string),
+            Source::Mapping if self.uses_mapping() => mapping.map(str::to_string),
+            Source::Mapping => None,
+            Source::Literal => Some(oid.to_string()),
+        })
+    }
+
+    fn not_found(&self, oid: &str) -> anyhow::Error {
+        if self.self_contained && !self.sources.contains(&Source::Literal) {
+            return anyhow::anyhow!(
+                "LFS pointer has no ext-0-walrus field, which lfs.walrus.selfcontained requires; run `git-lfs-walrus-cli pointer-repair` where the mapping file is available"
+            );
+        }
+        anyhow::anyhow!(
+            "No Walrus blob ID found for {} (tried lfs.walrus.resolveorder {})",
+            oid,
+            self
+        )
+    }
+}
+
+/// Resolves the Walrus blob ID for a pointer, or for a bare OID hash (all the transfer agent
+/// gets from git-lfs), trying the sources in `order`.
+pub async fn resolve_blob_id(order: &ResolveOrder, oid_or_pointer: &str) -> Result<String> {
+    let pointer = is_pointer(oid_or_pointer.as_bytes()).then_some(oid_or_pointer);
+    let oid = match pointer {
+        Some(pointer) => extract_oid(pointer)?.hash,
+        None => oid_or_pointer.trim().to_string(),
+    };
+
+    for source in &order.sources {
+        let blob_id = match source {
+            Source::Inline => match pointer {
+                Some(pointer) => extract_walrus_blob_id(pointer).ok(),
+                None => git::index_pointer_for_oid(&oid)
+                    .ok()
+                    .flatten()
+                    .and_then(|pointer| extract_walrus_blob_id(&pointer).ok()),
+            },
+            Source::Mapping if order.uses_mapping() => get_blob_id_from_sha(&oid).await?,
+            Source::Mapping => None,
+            Source::Literal => Some(oid.clone()),
+        };
+        if let Some(blob_id) = blob_id {
+            return Ok(blob_id);
+        }
+    }
+    Err(order.not_found(&oid))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pointer::with_walrus_blob_id;
+
+    const OID: &str = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+
+    fn order(value: &str) -> ResolveOrder {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn orders_parse_and_reject_unknown_or_repeated_sources() {
+        assert_eq!(order(" mapping , inline ").to_string(), "mapping,inline");
+        assert_eq!(ResolveOrder::default().to_string(), "inline,mapping");
+        assert!("inline,pointer".parse::<ResolveOrder>().is_err());
+        assert!("inline,inline".parse::<ResolveOrder>().is_err());
+        assert!("".parse::<ResolveOrder>().is_err());
+    }
+
+    #[test]
+    fn each_order_picks_its_first_available_source() {
+        let both = (Some("inline-id"), Some("mapping-id"));
+        let pick = |value: &str, (inline, mapping): (Option<&str>, Option<&str>)| {
+            order(value).pick(OID, inline, mapping)
+        };
+
+        assert_eq!(pick("inline,mapping,literal", both).unwrap(), "inline-id");
+        assert_eq!(pick("mapping,inline,literal", both).unwrap(), "mapping-id");
+        assert_eq!(pick("literal,inline,mapping", both).unwrap(), OID);
+
+        assert_eq!(pick("inline,mapping", (None, Some("mapping-id"))).unwrap(), "mapping-id");
+        assert_eq!(pick("mapping,inline", (Some("inline-id"), None)).unwrap(), "inline-id");
+        assert_eq!(pick("inline,mapping,literal", (None, None)).unwrap(), OID);
+        assert_eq!(pick("inline,mapping", (None, None)), None);
+        assert_eq!(pick("inline", (None, Some("mapping-id"))), None);
+    }
+
+    #[test]
+    fn self_contained_orders_skip_the_mapping() {
+        let order = ResolveOrder {
+            self_contained: true,
+            ..order("mapping,inline")
+        };
+        assert_eq!(order.pick(OID, Some("inline-id"), Some("mapping-id")).unwrap(), "inline-id");
+        assert_eq!(order.pick(OID, None, Some("mapping-id")), None);
+    }
+
+    #[tokio::test]
+    async fn pointers_resolve_through_the_configured_sources() {
+        let pointer = format!(
+            "version https://git-lfs.github.com/spec/v1\noid sha256:{}\nsize 11\n",
+            OID
+        );
+        let inline = with_walrus_blob_id(&pointer, "blob-1");
+
+        assert_eq!(resolve_blob_id(&order("inline"), &inline).await.unwrap(), "blob-1");
+        assert_eq!(resolve_blob_id(&order("literal,inline"), &inline).await.unwrap(), OID);
+        assert_eq!(resolve_blob_id(&order("inline,literal"), &pointer).await.unwrap(), OID);
+        assert_eq!(resolve_blob_id(&order("literal"), OID).await.unwrap(), OID);
+
+        let self_contained = ResolveOrder {
+            self_contained: true,
+            ..ResolveOrder::default()
+        };
+        assert_eq!(resolve_blob_id(&self_contained, &inline).await.unwrap(), "blob-1");
+        let err = resolve_blob_id(&self_contained, &pointer).await.unwrap_err();
+        assert!(err.to_string().contains("lfs.walrus.selfcontained"), "{}", err);
+        let err = resolve_blob_id(&order("inline"), &pointer).await.unwrap_err();
+        assert!(err.to_string().contains("No Walrus blob ID found"), "{}", err);
+    }
+}
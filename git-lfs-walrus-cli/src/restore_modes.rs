@@ -0,0 +1,87 @@
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::git;
+use crate::pointer::parse_mode_line;
+
+/// Applies the permission bits recorded by `lfs.walrus.preservemode` to checked-out files.
+///
+/// git writes smudged content itself after the filter exits, so the mode can't be set from
+/// smudge; run this from a `post-checkout` hook instead.
+pub async fn restore_modes(files: Vec<PathBuf>) -> Result<()> {
+    let modes = parse_mode_lines(&git::index_pointer_lines()?);
+    let targets: Vec<(PathBuf, u32)> = if files.is_empty() {
+        modes.into_iter().collect()
+    } else {
+        files
+            .into_iter()
+            .filter_map(|file| modes.get(&file).map(|mode| (file, *mode)))
+            .collect()
+    };
+
+    let mut restored = 0;
+    let mut errors = 0;
+    for (file, mode) in &targets {
+        match set_mode(file, *mode) {
+            Ok(()) => {
+                println!("✅ {}: {:04o}", file.display(), mode);
+                restored += 1;
+            }
+            Err(e) => {
+                println!("❌ {}: {}", file.display(), e);
+                errors += 1;
+            }
+        }
+    }
+
+    println!("\nSummary:");
+    println!("  Restored: {}", restored);
+    if errors > 0 {
+        println!("  Errors: {}", errors);
+        return Err(anyhow::anyhow!("could not restore the mode of {} files", errors));
+    }
+
+    Ok(())
+}
+
+/// Parses `git grep --cached --null` output into the recorded mode per path.
+fn parse_mode_lines(output: &str) -> BTreeMap<PathBuf, u32> {
+    output
+        .lines()
+        .filter_map(|record| record.split_once('\0'))
+        .filter_map(|(path, line)| Some((PathBuf::from(path), parse_mode_line(line)?)))
+        .collect()
+}
+
+#[cfg(unix)]
+fn set_mode(file: &Path, mode: u32) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::set_permissions(file, std::fs::Permissions::from_mode(mode))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn set_mode(_file: &Path, _mode: u32) -> Result<()> {
+    Err(anyhow::anyhow!("file modes can only be restored on Unix"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mode_lines_are_collected_per_path() {
+        let output = "tool.bin\0oid sha256:aaa\n\
+                      tool.bin\0ext-1-walrus-mode 0755\n\
+                      data.bin\0ext-1-walrus-mode 0640\n\
+                      other.bin\0ext-1-walrus-mode rwx\n";
+
+        let modes = parse_mode_lines(output);
+
+        assert_eq!(modes.len(), 2);
+        assert_eq!(modes[Path::new("tool.bin")], 0o755);
+        assert_eq!(modes[Path::new("data.bin")], 0o640);
+    }
+}
@@ -1,13 +1,21 @@
 use anyhow::Result;
 use std::collections::HashMap;
-use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 
+use crate::git;
+use crate::oid::HashingWriter;
+use crate::pointer::extract_oid;
+use crate::prefetch;
+use crate::resolve::resolve_blob_id;
 use crate::walrus::WalrusClient;
 
+/// With `verify` off (`--no-verify`, or `lfs.walrus.verifysmudge` false), content is written
+/// without checking it against the pointer's OID, to recover data whose pointer doesn't match.
 pub async fn smudge(
     client: WalrusClient,
     mut input: impl AsyncRead + Unpin,
     mut output: impl AsyncWrite + Unpin,
+    verify: bool,
 ) -> Result<()> {
     // Read the LFS pointer content
     let mut pointer_content = String::new();
@@ -16,11 +24,53 @@ pub async fn smudge(
     // Parse the LFS pointer to extract metadata
     let _metadata = parse_lfs_pointer(&pointer_content)?;
 
-    // Extract the Walrus blob ID from the comment
-    let blob_id = extract_walrus_blob_id(&pointer_content)?;
+    // Serve content a prefetch already fetched and verified
+    if let Ok(oid) = extract_oid(&pointer_content)
+        && let Some(file) = prefetch::open_cached(&oid).await
+    {
+        client.copy_stream(file, &mut output).await?;
+        output.flush().await?;
+        return Ok(());
+    }
+    // Offline, a background prefetch could only fail where nobody sees it
+    if prefetch::enabled()
+        && !client.is_offline()
+        && let Err(e) = prefetch::start_in_background(&client)
+    {
+        eprintln!("Warning: could not start a background prefetch: {}", e);
+    }
 
-    // Retrieve the original file content from Walrus
-    client.read_blob_to_writer(&blob_id, &mut output).await?;
+    // The inline Walrus blob ID or the mapping file, as lfs.walrus.resolveorder says
+    let blob_id = resolve_blob_id(client.resolve_order(), &pointer_content).await?;
+
+    let verify = verify
+        && git::config_bool_or("lfs.walrus.verifysmudge", true);
+    if !verify {
+        eprintln!(
+            "Warning: writing blob {} without checking it against the pointer's OID (verification is disabled)",
+            blob_id
+        );
+        client.read_blob_to_writer(&blob_id, &mut output).await?;
+        output.flush().await?;
+        return Ok(());
+    }
+
+    // Retrieve the original file content from Walrus, checking it against the OID as it
+    // streams: it may have been reassembled from chunks or decompressed in transit
+    let oid = extract_oid(&pointer_content)?;
+    let mut writer = HashingWriter::new(&mut output, oid.algorithm);
+    client.read_blob_to_writer(&blob_id, &mut writer).await?;
+    writer.flush().await?;
+    let (hash, _) = writer.finish();
+    if hash != oid.hash {
+        return Err(anyhow::anyhow!(
+            "Content of blob {} has {} {}, expected {}",
+            blob_id,
+            oid.algorithm,
+            hash,
+            oid.hash
+        ));
+    }
 
     Ok(())
 }
@@ -41,21 +91,10 @@ fn parse_lfs_pointer(content: &str) -> Result<HashMap<String, String>> {
     Ok(metadata)
 }
 
-fn extract_walrus_blob_id(content: &str) -> Result<String> {
-    for line in content.lines() {
-        if line.starts_with("ext-0-walrus ") {
-            if let Some((_, blob_id)) = line.split_once(' ') {
-                return Ok(blob_id.trim().to_string());
-            }
-        }
-    }
-
-    Err(anyhow::anyhow!("No Walrus blob ID found in LFS pointer"))
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::pointer::extract_walrus_blob_id;
     use crate::walrus::client;
     use std::io::Cursor;
 
@@ -93,7 +132,7 @@ ext-0-walrus test-blob-id-123
     async fn smudge_converts_lfs_pointer_to_file_contents() {
         let client = client();
         let mut cursor = Cursor::new(vec![]);
-        smudge(client, LFS_POINTER.as_bytes(), &mut cursor)
+        smudge(client, LFS_POINTER.as_bytes(), &mut cursor, true)
             .await
             .unwrap();
 
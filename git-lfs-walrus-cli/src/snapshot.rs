@@ -0,0 +1,110 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+use crate::dedup_report::parse_pointer_lines;
+use crate::git;
+use crate::mapping::{load_mappings, store_blob_mappings, MappingEntry, Mappings};
+use crate::pending;
+use crate::walrus::WalrusClient;
+
+/// Version of the snapshot format written by this build.
+const SNAPSHOT_VERSION: u64 = 1;
+
+/// The mapping file, and optionally the staged pointers, as stored in a single Walrus blob so
+/// a fresh clone can bootstrap from its blob ID alone.
+#[derive(Debug, Serialize, Deserialize)]
+struct Snapshot {
+    version: u64,
+    mappings: Mappings,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pointers: Vec<SnapshotPointer>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SnapshotPointer {
+    path: PathBuf,
+    oid: String,
+    size: u64,
+    /// The pointer's inline `ext-0-walrus` blob ID
+    blob_id: Option<String>,
+}
+
+/// Stores the mapping file (and with `with_pointers`, every staged pointer) as one blob and
+/// prints its blob ID.
+pub async fn export_snapshot(client: WalrusClient, with_pointers: bool) -> Result<()> {
+    let pointers = if with_pointers {
+        parse_pointer_lines(&git::index_pointer_lines()?)
+            .into_iter()
+            .filter_map(|(path, fields)| {
+                Some(SnapshotPointer {
+                    path,
+                    oid: fields.oid?,
+                    size: fields.size?,
+                    blob_id: fields.blob_id,
+                })
+            })
+            .collect()
+    } else {
+        Vec::new()
+    };
+    let snapshot = Snapshot {
+        version: SNAPSHOT_VERSION,
+        mappings: load_mappings().await?,
+        pointers,
+    };
+
+    let outcome = client
+        .store_bytes(serde_json::to_string(&snapshot)?.as_bytes(), None)
+        .await?;
+    println!(
+        "✅ Stored a snapshot of {} mapping entries and {} pointers",
+        snapshot.mappings.len(),
+        snapshot.pointers.len()
+    );
+    println!("Blob ID: {}", outcome.blob_id);
+    pending::resolve(&[&outcome.blob_id]);
+    Ok(())
+}
+
+/// Reads a snapshot stored by [`export_snapshot`] and adds its entries to the mapping file,
+/// keeping any entry already there. Pointers in the snapshot fill in mappings for OIDs that
+/// have none.
+pub async fn import_snapshot(client: WalrusClient, blob_id: String) -> Result<()> {
+    let mut content = Vec::new();
+    client.read_blob_to_writer(&blob_id, &mut content).await?;
+    let snapshot: Snapshot = serde_json::from_slice(&content)
+        .map_err(|e| anyhow::anyhow!("blob {} is not a mapping snapshot: {}", blob_id, e))?;
+    if snapshot.version > SNAPSHOT_VERSION {
+        return Err(anyhow::anyhow!(
+            "snapshot version {} is newer than the supported version {}; upgrade git-lfs-walrus",
+            snapshot.version,
+            SNAPSHOT_VERSION
+        ));
+    }
+
+    let existing = load_mappings().await?;
+    let mut entries: Mappings = snapshot.mappings;
+    for pointer in snapshot.pointers {
+        if let Some(blob_id) = pointer.blob_id {
+            entries
+                .entry(pointer.oid)
+                .or_insert_with(|| MappingEntry::from_blob_id(blob_id));
+        }
+    }
+    let total = entries.len();
+    let new: Vec<(String, MappingEntry)> = entries
+        .into_iter()
+        .filter(|(sha256, _)| !existing.contains_key(sha256))
+        .collect();
+    let added = new.len();
+    if added > 0 {
+        store_blob_mappings(new).await?;
+    }
+
+    println!("✅ Imported snapshot {}", blob_id);
+    println!("\nSummary:");
+    println!("  Added: {}", added);
+    println!("  Already present: {}", total - added);
+    Ok(())
+}
@@ -0,0 +1,247 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use std::path::PathBuf;
+
+use crate::balance::format_wal;
+use crate::dedup_report::{parse_pointer_lines, PointerFields};
+use crate::git;
+use crate::mapping::{load_mappings, Mappings};
+use crate::migrate::format_bytes;
+use crate::resolve::ResolveOrder;
+use crate::walrus::WalrusClient;
+
+/// One LFS object referenced by a ref's tree.
+#[derive(Debug, PartialEq, Serialize)]
+struct TreeObject {
+    /// First path naming the object
+    path: PathBuf,
+    oid: String,
+    size: u64,
+    /// `None` when neither the pointer nor the mapping names a blob
+    blob_id: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct StorageDiff {
+    base: String,
+    head: String,
+    new_blobs: Vec<TreeObject>,
+    new_bytes: u64,
+    /// Dry-run storage cost of the new blobs, in FROST; `None` with `--no-cost`
+    estimated_cost: Option<u64>,
+}
+
+/// Reports the LFS content referenced at `head` but not at `base` (by blob ID or OID), its
+/// total size from the pointers, and what storing it would cost.
+pub async fn storage_diff(
+    client: WalrusClient,
+    base: String,
+    head: String,
+    json: bool,
+    no_cost: bool,
+) -> Result<()> {
+    let order = client.resolve_order();
+    let mappings = if order.uses_mapping() {
+        load_mappings().await?
+    } else {
+        Mappings::new()
+    };
+    let base_objects = tree_objects(&base, order, &mappings)?;
+    let head_objects = tree_objects(&head, order, &mappings)?;
+    let new_blobs = new_objects(&base_objects, head_objects);
+    let new_bytes = new_blobs.iter().map(|object| object.size).sum();
+
+    let estimated_cost = if no_cost {
+        None
+    } else {
+        Some(estimate_cost(&client, &new_blobs).await?)
+    };
+    let diff = StorageDiff {
+        base,
+        head,
+        new_blobs,
+        new_bytes,
+        estimated_cost,
+    };
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&diff)?);
+        return Ok(());
+    }
+
+    for object in &diff.new_blobs {
+        println!(
+            "{} - {} ({})",
+            object.path.display(),
+            format_bytes(object.size),
+            object.blob_id.as_deref().unwrap_or("no blob ID")
+        );
+    }
+    println!("\nSummary ({}..{}):", diff.base, diff.head);
+    println!("  New blobs: {}", diff.new_blobs.len());
+    println!("  New storage: {}", format_bytes(diff.new_bytes));
+    if let Some(cost) = diff.estimated_cost {
+        println!("  Estimated cost: {}", format_wal(cost));
+    }
+    Ok(())
+}
+
+/// The distinct LFS objects of `revision`'s tree, keyed by OID.
+fn tree_objects(
+    revision: &str,
+    order: &ResolveOrder,
+    mappings: &Mappings,
+) -> Result<BTreeMap<String, TreeObject>> {
+    git::stdout(&["rev-parse", "--verify", "--quiet", &format!("{}^{{tree}}", revision)])
+        .with_context(|| format!("{:?} is not a commit or tree", revision))?;
+    let prefix = format!("{}:", revision);
+    let pointers = parse_pointer_lines(&git::tree_pointer_lines(revision)?)
+        .into_iter()
+        .map(|(path, fields)| {
+            let path = match path.to_str().and_then(|path| path.strip_prefix(&prefix)) {
+                Some(relative) => PathBuf::from(relative),
+                None => path,
+            };
+            (path, fields)
+        });
+    Ok(objects_from_pointers(pointers, order, mappings))
+}
+
+fn objects_from_pointers(
+    pointers: impl IntoIterator<Item = (PathBuf, PointerFields)>,
+    order: &ResolveOrder,
+    mappings: &Mappings,
+) -> BTreeMap<String, TreeObject> {
+    let mut objects = BTreeMap::new();
+    for (path, fields) in pointers {
+        let (Some(oid), Some(size)) = (fields.oid, fields.size) else {
+            continue; // Not a pointer
+        };
+        let mapping = mappings.get(&oid).map(|entry| entry.blob_id.as_str());
+        let blob_id = order
+            .pick(&oid, fields.blob_id.as_deref(), mapping)
+            .filter(|blob_id| *blob_id != oid);
+        objects.entry(oid.clone()).or_insert(TreeObject {
+            path,
+            oid,
+            size,
+            blob_id,
+        });
+    }
+    objects
+}
+
+/// The objects of `head` whose content `base` doesn't already reference, by OID or blob ID.
+fn new_objects(
+    base: &BTreeMap<String, TreeObject>,
+    head: BTreeMap<String, TreeObject>,
+) -> Vec<TreeObject> {
+    let base_blob_ids: HashSet<&str> = base
+        .values()
+        .filter_map(|object| object.blob_id.as_deref())
+        .collect();
+    let mut seen_blob_ids = HashSet::new();
+    let mut new: Vec<TreeObject> = head
+        .into_values()
+        .filter(|object| !base.contains_key(&object.oid))
+        .filter(|object| match &object.blob_id {
+            Some(blob_id) => {
+                !base_blob_ids.contains(blob_id.as_str()) && seen_blob_ids.insert(blob_id.clone())
+            }
+            None => true,
+        })
+        .collect();
+    new.sort_by(|a, b| a.path.cmp(&b.path));
+    new
+}
+
+/// Sums dry-run cost estimates, one per distinct blob size.
+async fn estimate_cost(client: &WalrusClient, objects: &[TreeObject]) -> Result<u64> {
+    let mut cost_by_size = BTreeMap::new();
+    let mut total = 0;
+    for object in objects {
+        let cost = match cost_by_size.get(&object.size) {
+            Some(&cost) => cost,
+            None => {
+                let cost = client
+                    .estimate_store_cost_for_size(object.size)
+                    .await
+                    .with_context(|| {
+                        format!("Could not estimate the cost of {}", object.path.display())
+                    })?;
+                cost_by_size.insert(object.size, cost);
+                cost
+            }
+        };
+        total += cost;
+    }
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapping::MappingEntry;
+
+    const OID_A: &str = "4d7a214614ab2935c943f9e0ff69d22eadbb8f32b1258daaa5e2ca24d17e2393";
+    const OID_B: &str = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+    const OID_C: &str = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+
+    fn fields(oid: &str, size: u64, blob_id: Option<&str>) -> PointerFields {
+        PointerFields {
+            oid: Some(oid.to_string()),
+            size: Some(size),
+            blob_id: blob_id.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn only_content_missing_from_the_base_counts_as_new() {
+        let order = ResolveOrder::default();
+        let mappings = Mappings::from([(
+            OID_B.to_string(),
+            MappingEntry::from_blob_id("blob-b".to_string()),
+        )]);
+        let base = objects_from_pointers(
+            [(PathBuf::from("a.bin"), fields(OID_A, 10, Some("blob-a")))],
+            &order,
+            &mappings,
+        );
+        let head = objects_from_pointers(
+            [
+                (PathBuf::from("a.bin"), fields(OID_A, 10, Some("blob-a"))),
+                (PathBuf::from("b.bin"), fields(OID_B, 20, None)),
+                (PathBuf::from("copy-of-b.bin"), fields(OID_B, 20, None)),
+                (PathBuf::from("c.bin"), fields(OID_C, 30, None)),
+                (PathBuf::from("notes.txt"), PointerFields::default()),
+            ],
+            &order,
+            &mappings,
+        );
+
+        let new = new_objects(&base, head);
+        let summary: Vec<_> = new
+            .iter()
+            .map(|object| (object.path.to_str().unwrap(), object.size, object.blob_id.as_deref()))
+            .collect();
+        assert_eq!(summary, [("b.bin", 20, Some("blob-b")), ("c.bin", 30, None)]);
+    }
+
+    #[test]
+    fn a_blob_id_already_in_the_base_is_not_new_under_another_oid() {
+        let order = ResolveOrder::default();
+        let mappings = Mappings::new();
+        let base = objects_from_pointers(
+            [(PathBuf::from("a.bin"), fields(OID_A, 10, Some("blob-shared")))],
+            &order,
+            &mappings,
+        );
+        let head = objects_from_pointers(
+            [(PathBuf::from("b.bin"), fields(OID_B, 10, Some("blob-shared")))],
+            &order,
+            &mappings,
+        );
+        assert!(new_objects(&base, head).is_empty());
+    }
+}
@@ -1,65 +1,132 @@
 use anyhow::{Context, Result};
 use futures::{Stream, StreamExt};
+use std::collections::HashMap;
 use std::path::Path;
-use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWriteExt};
+use tokio::sync::mpsc;
 
-use crate::walrus::WalrusClient;
-use git_lfs_spec::transfer::custom::{self, Complete, Error, Event, Operation, Progress};
+use crate::error_log;
+use crate::oid::{HashAlgorithm, Oid};
+use crate::pending;
+use crate::prefetch;
+use crate::resolve::resolve_blob_id;
+use crate::walrus::{ProgressSink, WalrusClient, WalrusError};
+use git_lfs_spec::transfer::custom::{self, Complete, Error, Event, Init, Operation, Progress};
 
 pub fn read_events(input: impl AsyncBufRead + Unpin) -> impl Stream<Item = Result<Event>> {
     async_stream::stream! {
         let mut lines = input.lines();
         while let Some(line) = lines.next_line().await? {
-            let parsed = serde_json::from_str(&line).context("could not parse JSON");
-            yield parsed
+            match parse_event(&line) {
+                Ok(Some(event)) => yield Ok(event),
+                Ok(None) => continue,
+                Err(err) => yield Err(err),
+            }
         }
     }
 }
 
+/// Event types this agent understands; see [`parse_event`].
+const KNOWN_EVENTS: [&str; 6] = ["init", "upload", "download", "complete", "progress", "terminate"];
+
+/// Parses one protocol line, returning `None` for an event type this agent doesn't know.
+///
+/// Unknown fields are already ignored when deserializing; unknown event types (from a newer
+/// git-lfs) are logged and skipped so they don't end the session. A known event that doesn't
+/// parse is still an error.
+fn parse_event(line: &str) -> Result<Option<Event>> {
+    let value: serde_json::Value = serde_json::from_str(line).context("could not parse JSON")?;
+    let event_type = value
+        .get("event")
+        .and_then(serde_json::Value::as_str)
+        .map(str::to_string);
+    match serde_json::from_value(value) {
+        Ok(event) => Ok(Some(event)),
+        Err(_) if event_type.as_deref().is_some_and(|t| !KNOWN_EVENTS.contains(&t)) => {
+            eprintln!(
+                "Warning: ignoring unknown transfer event {:?}",
+                event_type.unwrap_or_default()
+            );
+            Ok(None)
+        }
+        Err(err) => Err(err).context("could not parse transfer event"),
+    }
+}
+
+const BAD_REQUEST: i32 = 400;
 const INTERNAL_SERVER_ERROR: i32 = 500;
 
+/// Behaviour toggles for a transfer session.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct TransferOptions {
+    /// Never invoke walrus; see [`transfer`]
+    pub dry_run: bool,
+    /// Fail uploads whose blob walrus reports as already certified
+    pub fail_on_already_certified: bool,
+    /// Only complete an upload once its blob is certified
+    pub wait_certified: bool,
+}
+
+/// Runs the custom transfer protocol over `input_event_stream`.
+///
+/// With `dry_run` set, walrus is never invoked: uploads complete immediately and downloads
+/// produce a placeholder file. This only exists to check that git-lfs is wired up to the agent.
+///
+/// Once an object fails with an error that would recur for every object (see
+/// [`WalrusError::is_fatal`]), the remaining objects are failed without invoking walrus.
 pub fn transfer(
     client: WalrusClient,
     input_event_stream: impl Stream<Item = Result<Event>>,
     download_folder: impl AsRef<Path>,
+    options: TransferOptions,
 ) -> impl Stream<Item = Result<Event>> {
-    let mut init_opt = None;
+    let TransferOptions {
+        dry_run,
+        fail_on_already_certified,
+        wait_certified,
+    } = options;
+    let mut state = TransferState::AwaitingInit;
+    let mut fatal_error: Option<String> = None;
+    let mut progress = ProgressTracker::default();
     async_stream::stream! {
         futures_util::pin_mut!(input_event_stream);
         while let Some(event) = input_event_stream.next().await.transpose()? {
-            match (init_opt.as_ref(), event) {
-                (None, Event::Init(init)) => {
-                    init_opt = Some(init);
+            match (&state, event) {
+                (TransferState::AwaitingInit, Event::Init(init)) => {
+                    state = TransferState::Active(init);
                     yield Ok(Event::AcknowledgeInit)
                 }
-                (None, event) => {
-                    yield Err(anyhow::anyhow!("Unexpected event: {:?}", event))
+                (TransferState::AwaitingInit, event) => {
+                    yield Err(ProtocolError::EventBeforeInit(event_name(&event)).into())
                 }
-                (Some(_), Event::Init(init)) => {
-                    yield Err(anyhow::anyhow!("Unexpected init event: {:?}", init))
+                (TransferState::Active(active), Event::Init(init)) => {
+                    yield Err(ProtocolError::InitWhileActive {
+                        active: describe_init(active),
+                        received: describe_init(&init),
+                    }
+                    .into())
                 }
-
-                (Some(_), Event::Terminate) => {
-                    break
+                (TransferState::Active(_), Event::Terminate) => {
+                    if let Some(reason) = &fatal_error {
+                        eprintln!("Transfer stopped after a fatal error: {}", reason);
+                    }
+                    break;
                 }
-                (Some(init), event) => {
+                (TransferState::Active(init), event) => {
                     match (event, &init.operation) {
                         (Event::Download(download), Operation::Download) => {
-                            // For download, we need to extract the Walrus blob ID from the OID
-                            // In practice, this would require a mapping between SHA256 and Walrus blob IDs
-                            // For now, we'll assume the OID contains the blob ID or we have a way to resolve it
-                            let blob_id = &download.object.oid; // Simplified - in reality needs mapping
-
-                            match download_blob(&client, blob_id, &download_folder).await {
+                            let oid = &download.object.oid;
+                            let result = if dry_run {
+                                dry_run_download(oid, &download_folder).await
+                            } else if let Some(reason) = &fatal_error {
+                                Err(skipped_after_fatal(reason))
+                            } else {
+                                download_object(&client, oid, download.object.size, &download_folder).await
+                            };
+                            match result {
                                 Ok((output_path, bytes_downloaded)) => {
-                                    yield Ok(Event::Progress(
-                                        Progress {
-                                            oid: download.object.oid.clone(),
-                                            bytes_so_far: bytes_downloaded,
-                                            bytes_since_last: bytes_downloaded,
-                                        }
-                                        .into()
-                                    ));
+                                    yield Ok(progress.event(oid, bytes_downloaded));
+                                    progress.finish(oid);
 
                                     yield Ok(Event::Complete(
                                         Complete {
@@ -70,6 +137,8 @@ pub fn transfer(
                                     ));
                                 }
                                 Err(err) => {
+                                    error_log::record("transfer download", &err);
+                                    record_fatal_error(&mut fatal_error, &err);
                                     yield Ok(Event::Complete(
                                         Complete {
                                             oid: download.object.oid.clone(),
@@ -85,7 +154,40 @@ pub fn transfer(
                         }
                         // Upload transfer - store file in Walrus
                         (Event::Upload(upload), Operation::Upload) => {
-                            match upload_blob(&client, &upload.path).await {
+                            let result = if dry_run {
+                                Ok(String::new())
+                            } else if let Some(reason) = &fatal_error {
+                                Err(skipped_after_fatal(reason))
+                            } else {
+                                // Forward progress from the store as it happens
+                                let (progress_tx, mut progress_rx) = mpsc::unbounded_channel();
+                                let mut sink = move |bytes_so_far| {
+                                    let _ = progress_tx.send(bytes_so_far);
+                                };
+                                let store = upload_blob(
+                                    &client,
+                                    &upload.path,
+                                    &mut sink,
+                                    fail_on_already_certified,
+                                    wait_certified,
+                                );
+                                futures_util::pin_mut!(store);
+                                loop {
+                                    tokio::select! {
+                                        Some(bytes_so_far) = progress_rx.recv() => {
+                                            yield Ok(progress.event(&upload.object.oid, bytes_so_far));
+                                        }
+                                        result = &mut store => {
+                                            while let Ok(bytes_so_far) = progress_rx.try_recv() {
+                                                yield Ok(progress.event(&upload.object.oid, bytes_so_far));
+                                            }
+                                            break result;
+                                        }
+                                    }
+                                }
+                            };
+                            progress.finish(&upload.object.oid);
+                            match result {
                                 Ok(_blob_id) => {
                                     yield Ok(Event::Complete(
                                         Complete {
@@ -96,6 +198,8 @@ pub fn transfer(
                                     ))
                                 }
                                 Err(err) => {
+                                    error_log::record("transfer upload", &err);
+                                    record_fatal_error(&mut fatal_error, &err);
                                     yield Ok(Event::Complete(
                                         Complete {
                                             oid: upload.object.oid.clone(),
@@ -109,8 +213,15 @@ pub fn transfer(
                                 }
                             }
                         }
+                        // git-lfs shouldn't send these, but one bad request needn't end the session
+                        (Event::Download(download), operation) => {
+                            yield Ok(operation_mismatch(&download.object.oid, "download", operation))
+                        }
+                        (Event::Upload(upload), operation) => {
+                            yield Ok(operation_mismatch(&upload.object.oid, "upload", operation))
+                        }
                         (event, _) => {
-                            yield Err(anyhow::anyhow!("Unexpected event: {:?}", event))
+                            yield Err(ProtocolError::AgentEvent(event_name(&event)).into())
                         }
                     };
                 }
@@ -119,33 +230,344 @@ pub fn transfer(
     }
 }
 
+/// Where a transfer session is in the protocol.
+///
+/// ```text
+/// AwaitingInit --init--> Active --terminate--> (session ends)
+/// ```
+///
+/// Uploads and downloads are only accepted while `Active`. Any other event while awaiting init,
+/// a second init while active, or an event only the agent sends is a [`ProtocolError`] that
+/// ends the session. The init is acknowledged as soon as it arrives, so nothing can arrive
+/// between an init and its acknowledgement. Terminate ends the session, and nothing after it is
+/// read.
+#[derive(Debug)]
+enum TransferState {
+    AwaitingInit,
+    Active(Init),
+}
+
+/// An event git-lfs may not send in the session's current [`TransferState`].
+#[derive(Debug)]
+pub enum ProtocolError {
+    /// An event other than init arrived first
+    EventBeforeInit(&'static str),
+    /// A second init arrived in a session that was already initialized
+    InitWhileActive { active: String, received: String },
+    /// git-lfs sent an event that only the transfer agent sends (`complete`, `progress`)
+    AgentEvent(&'static str),
+}
+
+impl std::fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProtocolError::EventBeforeInit(event) => write!(
+                f,
+                "transfer protocol error: received a {} event before init; the session must start with init",
+                event
+            ),
+            ProtocolError::InitWhileActive { active, received } => write!(
+                f,
+                "transfer protocol error: received a second init ({}) while the session is already initialized ({})",
+                received, active
+            ),
+            ProtocolError::AgentEvent(event) => write!(
+                f,
+                "transfer protocol error: received a {} event, which only the transfer agent sends",
+                event
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {}
+
+/// The protocol name of an event, for error messages.
+fn event_name(event: &Event) -> &'static str {
+    match event {
+        Event::Init(_) => "init",
+        Event::AcknowledgeInit => "init acknowledgement",
+        Event::Upload(_) => "upload",
+        Event::Download(_) => "download",
+        Event::Complete(_) => "complete",
+        Event::Progress(_) => "progress",
+        Event::Terminate => "terminate",
+    }
+}
+
+fn describe_init(init: &Init) -> String {
+    let operation = match init.operation {
+        Operation::Upload => "upload to",
+        Operation::Download => "download from",
+    };
+    format!("{} {}", operation, init.remote)
+}
+
+/// Remembers the first error that should stop the rest of the session.
+fn record_fatal_error(fatal_error: &mut Option<String>, err: &anyhow::Error) {
+    if fatal_error.is_none()
+        && err
+            .downcast_ref::<WalrusError>()
+            .is_some_and(WalrusError::is_fatal)
+    {
+        *fatal_error = Some(err.to_string());
+    }
+}
+
+fn skipped_after_fatal(reason: &str) -> anyhow::Error {
+    anyhow::anyhow!("Skipped after an earlier fatal error: {}", reason)
+}
+
+/// Fails one object whose request doesn't match the session's operation.
+fn operation_mismatch(oid: &str, requested: &str, operation: &Operation) -> Event {
+    let session = match operation {
+        Operation::Upload => "upload",
+        Operation::Download => "download",
+    };
+    Event::Complete(
+        Complete {
+            oid: oid.to_string(),
+            result: Some(custom::Result::Error(Error {
+                code: BAD_REQUEST,
+                message: format!(
+                    "Cannot {} {}: the transfer was initialized for {}",
+                    requested, oid, session
+                ),
+            })),
+        }
+        .into(),
+    )
+}
+
+/// The bytes last reported for each object in flight, so each `progress` event carries the
+/// cumulative count (`bytesSoFar`) and the delta since the previous event for that object
+/// (`bytesSinceLast`), which git-lfs sums for its transfer rate.
+#[derive(Debug, Default)]
+struct ProgressTracker {
+    reported: HashMap<String, u64>,
+}
+
+impl ProgressTracker {
+    fn event(&mut self, oid: &str, bytes_so_far: u64) -> Event {
+        let reported = self.reported.entry(oid.to_string()).or_default();
+        // Never negative, even if a source reports less than before
+        let bytes_since_last = bytes_so_far.saturating_sub(*reported);
+        *reported = (*reported).max(bytes_so_far);
+        Event::Progress(
+            Progress {
+                oid: oid.to_string(),
+                bytes_so_far: *reported,
+                bytes_since_last,
+            }
+            .into(),
+        )
+    }
+
+    /// Forgets a completed object, so a retry of the same OID starts from zero.
+    fn finish(&mut self, oid: &str) {
+        self.reported.remove(oid);
+    }
+}
+
+/// Downloads an object into `download_folder/<oid>`, copying it from the prefetch cache if a
+/// prefetch already fetched it, so offline mode can still serve cached objects.
+async fn download_object(
+    client: &WalrusClient,
+    oid: &str,
+    expected_size: u64,
+    download_folder: impl AsRef<Path>,
+) -> Result<(std::path::PathBuf, u64)> {
+    let cached = match HashAlgorithm::for_hash(oid) {
+        Some(algorithm) => {
+            let oid = Oid {
+                algorithm,
+                hash: oid.to_string(),
+            };
+            prefetch::open_cached(&oid).await
+        }
+        None => None,
+    };
+    if let Some(mut cached) = cached {
+        let partial = partial_download(download_folder.as_ref(), oid)?;
+        let mut output = tokio::fs::File::create(partial.path()).await?;
+        client.copy_stream(&mut cached, &mut output).await?;
+        output.flush().await?;
+        return publish_download(partial, oid, expected_size, download_folder.as_ref()).await;
+    }
+
+    let blob_id = resolve_blob_id(client.resolve_order(), oid).await?;
+    download_blob(client, &blob_id, oid, expected_size, download_folder).await
+}
+
+/// Downloads a blob into `download_folder/<oid>`. The content goes to a temporary file next to
+/// it first, and is only renamed into place once its size and SHA256 match the object, so git-lfs
+/// never sees a partial or corrupt download under the object's name.
 async fn download_blob(
     client: &WalrusClient,
     blob_id: &str,
+    oid: &str,
+    expected_size: u64,
     download_folder: impl AsRef<Path>,
 ) -> Result<(std::path::PathBuf, u64)> {
-    let output_path = download_folder.as_ref().join(blob_id);
+    let partial = partial_download(download_folder.as_ref(), oid)?;
+
+    if expected_size > 0 {
+        preallocate(partial.path(), expected_size).await?;
+    }
+
+    // Download the blob from Walrus, into the file sized above
+    client.read_blob_in_place(blob_id, partial.path()).await?;
 
-    // Download the blob from Walrus
-    client.read_blob(blob_id, &output_path).await?;
+    publish_download(partial, oid, expected_size, download_folder.as_ref()).await
+}
 
-    // Get the file size for progress reporting
-    let metadata = tokio::fs::metadata(&output_path).await?;
-    let bytes_downloaded = metadata.len();
+/// The temporary file a download is written to, next to where the object goes. It is removed
+/// on drop, so every early return cleans up after itself.
+fn partial_download(download_folder: &Path, oid: &str) -> Result<tempfile::NamedTempFile> {
+    Ok(tempfile::Builder::new()
+        .prefix(&format!(".{}", oid))
+        .suffix(".part")
+        .tempfile_in(download_folder)?)
+}
+
+/// Renames a finished download to `download_folder/<oid>` once it matches the object.
+async fn publish_download(
+    partial: tempfile::NamedTempFile,
+    oid: &str,
+    expected_size: u64,
+    download_folder: &Path,
+) -> Result<(std::path::PathBuf, u64)> {
+    let output_path = download_folder.join(oid);
+    let bytes_downloaded = verify_download(partial.path(), oid, expected_size).await?;
+    partial
+        .persist(&output_path)
+        .map_err(|err| anyhow::Error::new(err.error))
+        .with_context(|| format!("Could not move the download into {}", output_path.display()))?;
 
     Ok((output_path, bytes_downloaded))
 }
 
-async fn upload_blob(client: &WalrusClient, file_path: &std::path::Path) -> Result<String> {
+/// Checks a downloaded file against the object's size and hash, returning its size. git-lfs
+/// only passes the hash, so the algorithm is inferred from its length.
+pub async fn verify_download(path: &Path, oid: &str, expected_size: u64) -> Result<u64> {
+    let algorithm = HashAlgorithm::for_hash(oid)
+        .ok_or_else(|| anyhow::anyhow!("Cannot tell which hash algorithm produced OID {}", oid))?;
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut hasher = algorithm.hasher();
+    let mut buffer = vec![0; 64 * 1024];
+    let mut size = 0;
+    loop {
+        let read = file.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        size += read as u64;
+    }
+
+    if size != expected_size {
+        return Err(anyhow::anyhow!(
+            "Downloaded {} bytes for {}, expected {}",
+            size,
+            oid,
+            expected_size
+        ));
+    }
+    let hash = hasher.finalize_hex();
+    if hash != oid {
+        return Err(anyhow::anyhow!(
+            "Downloaded content for {} has {} {}",
+            oid,
+            algorithm,
+            hash
+        ));
+    }
+    Ok(size)
+}
+
+/// Reserves disk blocks for the output file up front, so a full disk fails before Walrus is
+/// asked for the blob. Where the platform or filesystem can't reserve space, the file is only
+/// sized, and a full disk shows up while the download is written.
+async fn preallocate(path: &Path, size: u64) -> Result<()> {
+    let file = tokio::fs::File::create(path).await?.into_std().await;
+    let result = tokio::task::spawn_blocking(move || reserve(&file, size)).await?;
+    result.map_err(|err| {
+        if err.kind() == std::io::ErrorKind::StorageFull {
+            anyhow::anyhow!(
+                "Insufficient disk space to download {} bytes to {}",
+                size,
+                path.display()
+            )
+        } else {
+            anyhow::Error::new(err).context(format!(
+                "Could not allocate {} bytes for {}",
+                size,
+                path.display()
+            ))
+        }
+    })
+}
+
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd",
+    target_vendor = "apple"
+))]
+fn reserve(file: &std::fs::File, size: u64) -> std::io::Result<()> {
+    match rustix::fs::fallocate(file, rustix::fs::FallocateFlags::empty(), 0, size) {
+        Ok(()) => Ok(()),
+        // Not every filesystem can reserve space
+        Err(rustix::io::Errno::OPNOTSUPP) => file.set_len(size),
+        Err(err) => Err(err.into()),
+    }
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd",
+    target_vendor = "apple"
+)))]
+fn reserve(file: &std::fs::File, size: u64) -> std::io::Result<()> {
+    file.set_len(size)
+}
+
+async fn dry_run_download(
+    oid: &str,
+    download_folder: impl AsRef<Path>,
+) -> Result<(std::path::PathBuf, u64)> {
+    let output_path = download_folder.as_ref().join(oid);
+    let placeholder = format!("git-lfs-walrus dry-run placeholder for {}\n", oid);
+    tokio::fs::write(&output_path, placeholder.as_bytes()).await?;
+    Ok((output_path, placeholder.len() as u64))
+}
+
+async fn upload_blob(
+    client: &WalrusClient,
+    file_path: &std::path::Path,
+    progress: &mut dyn ProgressSink,
+    fail_on_already_certified: bool,
+    wait_certified: bool,
+) -> Result<String> {
     // Store the file in Walrus
-    let blob_id = client.store_file(file_path).await?;
-    Ok(blob_id)
+    let outcome = client.store_file(file_path, Some(progress)).await?;
+    if fail_on_already_certified {
+        outcome.require_newly_created()?;
+    }
+    if wait_certified {
+        client.wait_until_certified(&outcome.blob_id).await?;
+    }
+    pending::resolve(&[&outcome.blob_id]);
+    Ok(outcome.blob_id)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::walrus::client;
+    use sha2::Digest;
+    use std::path::PathBuf;
     use git_lfs_spec::{
         transfer::custom::{Download, Event, Init, Result, Upload},
         Object,
@@ -175,6 +597,39 @@ mod tests {
         assert_eq!(events, &[init]);
     }
 
+    #[tokio::test]
+    async fn read_events_tolerates_unknown_fields_and_event_types() {
+        let input: &[u8] = br#"{"event":"init","operation":"download","remote":"origin","concurrent":false,"concurrenttransfers":null,"action":{"href":"x"}}
+{"event":"pause","seconds":5}
+{"event":"terminate"}
+"#;
+        let stream = read_events(input);
+        futures::pin_mut!(stream);
+        let mut events = vec![];
+        while let Some(output) = stream.next().await {
+            events.push(output.unwrap());
+        }
+        assert_eq!(
+            events,
+            &[
+                Event::Init(Init {
+                    operation: Operation::Download,
+                    remote: "origin".to_string(),
+                    concurrent: false,
+                    concurrenttransfers: None,
+                }),
+                Event::Terminate,
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_known_events_are_still_errors() {
+        assert!(parse_event(r#"{"event":"download","oid":"abc"}"#).is_err());
+        assert!(parse_event(r#"{"operation":"download"}"#).is_err());
+        assert!(parse_event("not json").is_err());
+    }
+
     #[tokio::test]
     #[ignore] // Requires Walrus to be installed and configured
     async fn transfer_handles_upload_events() {
@@ -207,6 +662,7 @@ mod tests {
             client,
             futures::stream::iter(input_events.iter().cloned().map(anyhow::Result::Ok)),
             temp_dir.path(),
+            TransferOptions::default(),
         );
 
         futures_util::pin_mut!(output_stream);
@@ -220,4 +676,351 @@ mod tests {
         assert!(matches!(events[0], Event::AcknowledgeInit));
         assert!(matches!(events[1], Event::Complete(_)));
     }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn preallocate_fails_when_the_disk_cannot_hold_the_download() {
+        use std::os::unix::fs::MetadataExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("blob");
+        preallocate(&path, 1024 * 1024).await.unwrap();
+        // Blocks are reserved, not just a sparse length
+        assert!(std::fs::metadata(&path).unwrap().blocks() * 512 >= 1024 * 1024);
+
+        let stats = rustix::fs::statvfs(dir.path()).unwrap();
+        let available = stats.f_bavail * stats.f_frsize;
+        let err = preallocate(&path, available + 1024 * 1024 * 1024)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().starts_with("Insufficient disk space"), "{:#}", err);
+    }
+
+    /// A client whose walrus writes `content` for every read.
+    fn client_reading(dir: &Path, content: &str) -> WalrusClient {
+        let stub = dir.join("walrus");
+        std::fs::write(
+            &stub,
+            format!(
+                "#!/bin/sh\nwhile [ $# -gt 0 ]; do [ \"$1\" = --out ] && out=$2; shift; done\nprintf '{}' > \"$out\"\n",
+                content
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&stub, std::os::unix::fs::PermissionsExt::from_mode(0o755))
+            .unwrap();
+        WalrusClient::with_path(stub)
+    }
+
+    #[tokio::test]
+    async fn download_only_publishes_verified_content() {
+        let stub_dir = tempdir().unwrap();
+        let download_dir = tempdir().unwrap();
+        let oid = hex::encode(sha2::Sha256::digest(FILE));
+
+        let client = client_reading(stub_dir.path(), "hello world");
+        let (path, size) = download_blob(&client, "blob-1", &oid, SIZE, download_dir.path())
+            .await
+            .unwrap();
+        assert_eq!(path, download_dir.path().join(&oid));
+        assert_eq!(size, SIZE);
+        assert_eq!(std::fs::read(&path).unwrap(), FILE);
+
+        std::fs::remove_file(&path).unwrap();
+        let client = client_reading(stub_dir.path(), "hello wurld");
+        let err = download_blob(&client, "blob-1", &oid, SIZE, download_dir.path())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("has sha256"));
+        // Neither the object nor the partial file is left behind
+        assert_eq!(std::fs::read_dir(download_dir.path()).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn transfer_dry_run_completes_without_walrus() {
+        let temp_dir = tempdir().unwrap();
+        let input_events = [
+            Event::Init(Init {
+                operation: Operation::Download,
+                remote: "origin".to_string(),
+                concurrent: true,
+                concurrenttransfers: Some(3),
+            }),
+            Event::Download(
+                Download {
+                    object: Object {
+                        oid: OID.to_string(),
+                        size: SIZE,
+                    },
+                }
+                .into(),
+            ),
+            Event::Terminate,
+        ];
+
+        let output_stream = transfer(
+            client(),
+            futures::stream::iter(input_events.iter().cloned().map(anyhow::Result::Ok)),
+            temp_dir.path(),
+            TransferOptions {
+                dry_run: true,
+                ..Default::default()
+            },
+        );
+
+        futures_util::pin_mut!(output_stream);
+
+        let mut events = vec![];
+        while let Some(event) = output_stream.next().await {
+            events.push(event.unwrap());
+        }
+
+        assert!(matches!(events[0], Event::AcknowledgeInit));
+        assert!(matches!(events[1], Event::Progress(_)));
+        let Event::Complete(complete) = &events[2] else {
+            panic!("expected a complete event, got {:?}", events[2]);
+        };
+        assert_eq!(
+            complete.result,
+            Some(Result::Path(temp_dir.path().join(OID)))
+        );
+        assert!(temp_dir.path().join(OID).exists());
+    }
+
+    #[test]
+    fn progress_deltas_of_each_object_sum_to_its_total() {
+        let mut tracker = ProgressTracker::default();
+        let updates = [("a", 10), ("b", 5), ("a", 25), ("b", 5), ("a", 40), ("b", 30)];
+
+        let mut deltas: HashMap<&str, u64> = HashMap::new();
+        for (oid, bytes_so_far) in updates {
+            let Event::Progress(progress) = tracker.event(oid, bytes_so_far) else {
+                unreachable!();
+            };
+            assert_eq!(progress.bytes_so_far, bytes_so_far);
+            *deltas.entry(oid).or_default() += progress.bytes_since_last;
+        }
+        assert_eq!(deltas["a"], 40);
+        assert_eq!(deltas["b"], 30);
+
+        // A retry after completion counts from zero again
+        tracker.finish("a");
+        let Event::Progress(progress) = tracker.event("a", 7) else {
+            unreachable!();
+        };
+        assert_eq!(progress.bytes_since_last, 7);
+    }
+
+    #[tokio::test]
+    async fn transfer_fails_only_the_object_with_a_mismatched_operation() {
+        let temp_dir = tempdir().unwrap();
+        let input_events = [
+            Event::Init(Init {
+                operation: Operation::Upload,
+                remote: "origin".to_string(),
+                concurrent: true,
+                concurrenttransfers: Some(3),
+            }),
+            Event::Download(
+                Download {
+                    object: Object {
+                        oid: OID.to_string(),
+                        size: SIZE,
+                    },
+                }
+                .into(),
+            ),
+            Event::Upload(
+                Upload {
+                    object: Object {
+                        oid: "second".to_string(),
+                        size: SIZE,
+                    },
+                    path: temp_dir.path().join("second"),
+                }
+                .into(),
+            ),
+            Event::Terminate,
+        ];
+
+        let output_stream = transfer(
+            client(),
+            futures::stream::iter(input_events.iter().cloned().map(anyhow::Result::Ok)),
+            temp_dir.path(),
+            TransferOptions {
+                dry_run: true,
+                ..Default::default()
+            },
+        );
+
+        futures_util::pin_mut!(output_stream);
+
+        let mut events = vec![];
+        while let Some(event) = output_stream.next().await {
+            events.push(event.unwrap());
+        }
+
+        assert_eq!(events.len(), 3);
+        let Event::Complete(mismatched) = &events[1] else {
+            panic!("expected a complete event, got {:?}", events[1]);
+        };
+        let Some(Result::Error(error)) = &mismatched.result else {
+            panic!("expected an error, got {:?}", mismatched.result);
+        };
+        assert_eq!(error.code, BAD_REQUEST);
+        assert_eq!(error.message, format!("Cannot download {}: the transfer was initialized for upload", OID));
+        let Event::Complete(uploaded) = &events[2] else {
+            panic!("expected a complete event, got {:?}", events[2]);
+        };
+        assert_eq!(uploaded.oid, "second");
+        assert_eq!(uploaded.result, None);
+    }
+
+    /// Runs a dry-run session over `events`, returning what it produced up to the first error.
+    async fn run_session(events: Vec<Event>) -> (Vec<Event>, Option<anyhow::Error>) {
+        let temp_dir = tempdir().unwrap();
+        let output_stream = transfer(
+            client(),
+            futures::stream::iter(events.into_iter().map(anyhow::Result::Ok)),
+            temp_dir.path(),
+            TransferOptions {
+                dry_run: true,
+                ..Default::default()
+            },
+        );
+        futures_util::pin_mut!(output_stream);
+
+        let mut produced = vec![];
+        while let Some(event) = output_stream.next().await {
+            match event {
+                Ok(event) => produced.push(event),
+                Err(err) => return (produced, Some(err)),
+            }
+        }
+        (produced, None)
+    }
+
+    #[tokio::test]
+    async fn invalid_transitions_are_reported_precisely() {
+        let init = |operation, remote: &str| {
+            Event::Init(Init {
+                operation,
+                remote: remote.to_string(),
+                concurrent: false,
+                concurrenttransfers: None,
+            })
+        };
+
+        let (produced, err) = run_session(vec![Event::Terminate]).await;
+        assert!(produced.is_empty());
+        assert_eq!(
+            err.unwrap().to_string(),
+            "transfer protocol error: received a terminate event before init; the session must start with init"
+        );
+
+        let (produced, err) = run_session(vec![
+            init(Operation::Upload, "origin"),
+            init(Operation::Download, "backup"),
+        ])
+        .await;
+        assert_eq!(produced, vec![Event::AcknowledgeInit]);
+        assert_eq!(
+            err.unwrap().to_string(),
+            "transfer protocol error: received a second init (download from backup) while the session is already initialized (upload to origin)"
+        );
+
+        let progress = Event::Progress(
+            Progress {
+                oid: OID.to_string(),
+                bytes_so_far: 1,
+                bytes_since_last: 1,
+            }
+            .into(),
+        );
+        let (_, err) = run_session(vec![init(Operation::Upload, "origin"), progress]).await;
+        assert!(matches!(
+            err.unwrap().downcast_ref::<ProtocolError>(),
+            Some(ProtocolError::AgentEvent("progress"))
+        ));
+    }
+
+    #[tokio::test]
+    async fn events_after_terminate_are_never_read() {
+        let upload = Event::Upload(
+            Upload {
+                object: Object {
+                    oid: OID.to_string(),
+                    size: SIZE,
+                },
+                path: PathBuf::from("/nonexistent"),
+            }
+            .into(),
+        );
+        let (produced, err) = run_session(vec![
+            Event::Init(Init {
+                operation: Operation::Upload,
+                remote: "origin".to_string(),
+                concurrent: false,
+                concurrenttransfers: None,
+            }),
+            Event::Terminate,
+            upload,
+            Event::Terminate,
+        ])
+        .await;
+        assert_eq!(produced, vec![Event::AcknowledgeInit]);
+        assert!(err.is_none());
+    }
+
+    #[tokio::test]
+    async fn transfer_skips_remaining_objects_after_a_fatal_error() {
+        let temp_dir = tempdir().unwrap();
+        let download = |oid: &str| {
+            Event::Download(
+                Download {
+                    object: Object {
+                        oid: oid.to_string(),
+                        size: SIZE,
+                    },
+                }
+                .into(),
+            )
+        };
+        let input_events = [
+            Event::Init(Init {
+                operation: Operation::Download,
+                remote: "origin".to_string(),
+                concurrent: true,
+                concurrenttransfers: Some(3),
+            }),
+            download("first"),
+            download("second"),
+            Event::Terminate,
+        ];
+
+        // A missing walrus binary fails every object the same way
+        let mut client = WalrusClient::with_path(PathBuf::from("/nonexistent/walrus"));
+        client.set_resolve_order("literal".parse().unwrap());
+        let output_stream = transfer(
+            client,
+            futures::stream::iter(input_events.iter().cloned().map(anyhow::Result::Ok)),
+            temp_dir.path(),
+            TransferOptions::default(),
+        );
+
+        futures_util::pin_mut!(output_stream);
+
+        let mut messages = vec![];
+        while let Some(event) = output_stream.next().await {
+            if let Event::Complete(complete) = event.unwrap()
+                && let Some(Result::Error(error)) = &complete.result
+            {
+                messages.push(error.message.clone());
+            }
+        }
+
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].starts_with("could not run walrus"));
+        assert!(messages[1].starts_with("Skipped after an earlier fatal error"));
+    }
 }
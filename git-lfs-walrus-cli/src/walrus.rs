@@ -1,14 +1,42 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use base64::Engine;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
 use std::process::Stdio;
-use tokio::io::{AsyncWrite, AsyncWriteExt};
+use futures::StreamExt;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWrite, AsyncWriteExt, BufReader, BufWriter};
 use tokio::process::Command;
 
+use crate::aggregator::Aggregator;
+use crate::blob_id;
+use crate::chunks::{
+    chunk_sizes, manifest_blob_id, manifest_reference, Chunk, ChunkManifest, DEFAULT_MAX_BLOB_SIZE,
+    MAX_INLINE_MANIFEST_LEN,
+};
+use crate::duration::{duration_to_epochs, format_duration, StorageDuration};
+use crate::git;
+use crate::metrics;
+use crate::oid::{HashAlgorithm, HashingWriter};
+use crate::pending::{self, PendingStore};
+use crate::resolve::ResolveOrder;
+use crate::walrus_trace;
+
 #[derive(Debug, Serialize)]
 struct StoreCommand {
     config: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    wallet: Option<String>,
+    /// Passed to walrus as `--gas-budget`
+    #[serde(rename = "gasBudget", skip_serializing_if = "Option::is_none")]
+    gas_budget: Option<u64>,
+    /// Passed to walrus as `--gas-coin`
+    #[serde(rename = "gasCoin", skip_serializing_if = "Option::is_none")]
+    gas_coin: Option<String>,
     command: StoreRequest,
 }
 
@@ -20,12 +48,33 @@ struct StoreRequest {
 #[derive(Debug, Serialize)]
 struct StoreParams {
     files: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     epochs: Option<u64>,
+    /// Extra store options from `lfs.walrus.attributes`
+    #[serde(flatten)]
+    attributes: serde_json::Map<String, serde_json::Value>,
+}
+
+impl StoreParams {
+    fn new(
+        file_path: &Path,
+        epochs: u64,
+        attributes: serde_json::Map<String, serde_json::Value>,
+    ) -> Self {
+        Self {
+            files: vec![file_path.to_string_lossy().to_string()],
+            // An explicit "epochs" attribute takes precedence over the default
+            epochs: (!attributes.contains_key("epochs")).then_some(epochs),
+            attributes,
+        }
+    }
 }
 
 #[derive(Debug, Serialize)]
 struct ReadCommand {
     config: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    wallet: Option<String>,
     command: ReadRequest,
 }
 
@@ -61,20 +110,22 @@ struct BlobResult {
     blob_object: Option<BlobObject>,
     #[serde(rename = "blobId")]
     blob_id: Option<String>,
-    // event: EventInfo,
-    // #[serde(rename = "endEpoch")]
-    // end_epoch: u64,
+    event: Option<EventInfo>,
+    /// Reported for already certified blobs
+    #[serde(rename = "endEpoch")]
+    end_epoch: Option<u64>,
 }
 
 #[derive(Debug, Deserialize)]
 struct EventInfo {
-    // #[serde(rename = "txDigest")]
-    // tx_digest: String,
+    #[serde(rename = "txDigest")]
+    tx_digest: Option<String>,
     // #[serde(rename = "eventSeq")]
     // event_seq: String,
 }
 
 #[derive(Debug, Deserialize)]
+#[allow(dead_code)]
 struct BlobInfo {
     // #[serde(rename = "blobObject")]
     // blob_object: BlobObject,
@@ -86,7 +137,8 @@ struct BlobInfo {
 struct BlobObject {
     #[serde(rename = "blobId")]
     blob_id: String,
-    // id: String,
+    /// The Sui object holding the blob, which attributes are set on
+    id: Option<String>,
     // #[serde(rename = "storedEpoch")]
     // stored_epoch: u64,
     // size: u64,
@@ -94,7 +146,7 @@ struct BlobObject {
     // erasure_code_type: String,
     // #[serde(rename = "certifiedEpoch")]
     // certified_epoch: u64,
-    // storage: Storage,
+    storage: Option<Storage>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -102,284 +154,1785 @@ struct Storage {
     // id: String,
     // #[serde(rename = "startEpoch")]
     // start_epoch: u64,
-    // #[serde(rename = "endEpoch")]
-    // end_epoch: u64,
+    #[serde(rename = "endEpoch")]
+    end_epoch: u64,
     // #[serde(rename = "storageSize")]
     // storage_size: u64,
 }
 
 #[derive(Debug, Deserialize)]
+#[allow(dead_code)]
 struct ResourceOperation {
     // #[serde(rename = "RegisterFromScratch")]
     // register_from_scratch: Option<RegisterFromScratch>,
 }
 
 #[derive(Debug, Deserialize)]
+#[allow(dead_code)]
 struct RegisterFromScratch {
     // #[serde(rename = "encoded_length")]
     // encoded_length: u64,
     // epochs: u64,
 }
 
+/// How blob content is fetched from the walrus CLI.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadMode {
+    /// `walrus read <blob-id> --out <path>`: raw bytes are written straight to disk.
+    File,
+    /// `walrus json` read: the blob comes back base64-encoded inside the JSON response,
+    /// so the whole blob is held in memory (twice). Kept as a fallback for walrus builds
+    /// where `--out` misbehaves.
+    Json,
+    /// `GET /v1/blobs/<blob-id>` from `lfs.walrus.aggregatorurl`, compressed in transit when
+    /// the aggregator supports it.
+    Http,
+}
+
+impl ReadMode {
+    fn from_config() -> Self {
+        match git::config_get("lfs.walrus.readmode").ok().flatten().as_deref() {
+            Some("json") => ReadMode::Json,
+            Some("http") => ReadMode::Http,
+            _ => ReadMode::File,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[allow(dead_code)]
+pub struct BlobStatusResponse {
+    #[serde(rename = "blobObject")]
+    pub blob_object: Option<BlobObjectStatus>,
+    pub status: String,
+}
+
+impl BlobStatusResponse {
+    pub fn is_certified(&self) -> bool {
+        self.blob_object
+            .as_ref()
+            .is_some_and(|blob| blob.certified_epoch.is_some())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[allow(dead_code)]
+pub struct BlobObjectStatus {
+    pub id: String,
+    #[serde(rename = "storedEpoch")]
+    pub stored_epoch: u64,
+    #[serde(rename = "blobId")]
+    pub blob_id: String,
+    pub size: u64,
+    /// Unset until the blob is certified on-chain
+    #[serde(rename = "certifiedEpoch", default)]
+    pub certified_epoch: Option<u64>,
+    pub storage: StorageStatus,
+}
+
+#[derive(Debug, Deserialize)]
+#[allow(dead_code)]
+pub struct StorageStatus {
+    pub id: String,
+    #[serde(rename = "startEpoch")]
+    pub start_epoch: u64,
+    #[serde(rename = "endEpoch")]
+    pub end_epoch: u64,
+    #[serde(rename = "storageSize")]
+    pub storage_size: u64,
+}
+
+/// Why a walrus operation failed.
+#[derive(Debug)]
+pub enum WalrusError {
+    /// The walrus executable could not be run at all
+    NotAvailable(std::io::Error),
+    /// Offline mode refused an operation that needs the network
+    Offline { operation: String },
+    /// The wallet's SUI or WAL balance can't cover the transaction; amounts are in the
+    /// smallest unit, when walrus reports them
+    InsufficientBalance {
+        command: &'static str,
+        stderr: String,
+        required: Option<u64>,
+        available: Option<u64>,
+    },
+    /// The transaction needs more gas than `lfs.walrus.gasbudget` allows
+    InsufficientGas { command: &'static str, stderr: String },
+    /// A walrus command exited unsuccessfully
+    CommandFailed { command: &'static str, stderr: String },
+    /// A walrus command ran longer than `lfs.walrus.timeoutsecs` and was killed
+    TimedOut { command: &'static str, timeout: Duration },
+    /// A stored blob was still not certified when `--wait-certified` gave up
+    NotCertified { blob_id: String, timeout: Duration },
+    /// A walrus command printed more than `lfs.walrus.maxresponsebytes` and was killed
+    ResponseTooLarge { command: &'static str, limit: u64 },
+}
+
+impl WalrusError {
+    /// Classifies a failed walrus invocation from its stderr.
+    fn failed(command: &'static str, stderr: &str) -> Self {
+        let lower = stderr.to_lowercase();
+        let stderr = stderr.to_string();
+        // Checked first: "no gas coin sufficient for the gas budget" is an empty wallet
+        if is_balance_error(&lower) {
+            WalrusError::InsufficientBalance {
+                command,
+                required: amount_after(&lower, &["required", "needed amount", "needed"]),
+                available: amount_after(&lower, &["available", "balance"]),
+                stderr,
+            }
+        } else if lower.contains("insufficientgas")
+            || lower.contains("insufficient gas")
+            || lower.contains("gas budget")
+        {
+            WalrusError::InsufficientGas { command, stderr }
+        } else {
+            WalrusError::CommandFailed { command, stderr }
+        }
+    }
+
+    /// A stable name for the kind of failure, for the error log.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            WalrusError::NotAvailable(_) => "not_available",
+            WalrusError::Offline { .. } => "offline",
+            WalrusError::InsufficientBalance { .. } => "insufficient_balance",
+            WalrusError::InsufficientGas { .. } => "insufficient_gas",
+            WalrusError::CommandFailed { .. } => "command_failed",
+            WalrusError::TimedOut { .. } => "timed_out",
+            WalrusError::NotCertified { .. } => "not_certified",
+            WalrusError::ResponseTooLarge { .. } => "response_too_large",
+        }
+    }
+
+    /// The walrus subcommand that failed, if one ran.
+    pub fn command(&self) -> Option<&'static str> {
+        match self {
+            WalrusError::InsufficientBalance { command, .. }
+            | WalrusError::InsufficientGas { command, .. }
+            | WalrusError::CommandFailed { command, .. }
+            | WalrusError::TimedOut { command, .. }
+            | WalrusError::ResponseTooLarge { command, .. } => Some(command),
+            _ => None,
+        }
+    }
+
+    /// What walrus printed on stderr, if it ran and failed.
+    pub fn stderr(&self) -> Option<&str> {
+        match self {
+            WalrusError::InsufficientBalance { stderr, .. }
+            | WalrusError::InsufficientGas { stderr, .. }
+            | WalrusError::CommandFailed { stderr, .. } => Some(stderr),
+            _ => None,
+        }
+    }
+
+    /// Whether the error will recur for every other object, so a session should stop trying.
+    pub fn is_fatal(&self) -> bool {
+        matches!(
+            self,
+            WalrusError::NotAvailable(_)
+                | WalrusError::Offline { .. }
+                | WalrusError::InsufficientBalance { .. }
+                | WalrusError::InsufficientGas { .. }
+        )
+    }
+}
+
+/// Only a shortfall counts: other gas coin errors (a locked coin, a bad `--gas-coin`) say
+/// nothing about the wallet's funds.
+fn is_balance_error(lower: &str) -> bool {
+    (lower.contains("balance") || lower.contains("gas coin"))
+        && ["sufficient", "not enough", "lower than", "too low"]
+            .iter()
+            .any(|phrase| lower.contains(phrase))
+        || lower.contains("insufficient funds")
+        || lower.contains("not enough wal")
+}
+
+/// Finds the first number directly following one of `keywords` (e.g. `required: 100`).
+fn amount_after(lower: &str, keywords: &[&str]) -> Option<u64> {
+    keywords.iter().find_map(|keyword| {
+        lower.match_indices(keyword).find_map(|(index, _)| {
+            let digits: String = lower[index + keyword.len()..]
+                .trim_start_matches([' ', ':', '='])
+                .chars()
+                .take_while(char::is_ascii_digit)
+                .collect();
+            digits.parse().ok()
+        })
+    })
+}
+
+impl fmt::Display for WalrusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WalrusError::NotAvailable(err) => write!(
+                f,
+                "could not run walrus ({}); make sure it is installed, or pass --walrus-path",
+                err
+            ),
+            WalrusError::Offline { operation } => write!(
+                f,
+                "Cannot {} in offline mode (lfs.walrus.offline / LFS_WALRUS_OFFLINE is set)",
+                operation
+            ),
+            WalrusError::InsufficientBalance {
+                command,
+                stderr,
+                required,
+                available,
+            } => {
+                write!(f, "Walrus {} command failed: the wallet's balance is too low", command)?;
+                match (required, available) {
+                    (Some(required), Some(available)) => {
+                        write!(f, " (required {}, available {})", required, available)?
+                    }
+                    (Some(required), None) => write!(f, " (required {})", required)?,
+                    (None, Some(available)) => write!(f, " (available {})", available)?,
+                    (None, None) => {}
+                }
+                write!(
+                    f,
+                    "\nhint: fund the wallet walrus uses with SUI for gas and WAL for storage (on testnet, `sui client faucet` and `walrus get-wal`), then retry\n{}",
+                    stderr.trim()
+                )
+            }
+            WalrusError::InsufficientGas { command, stderr } => write!(
+                f,
+                "Walrus {} command failed: {}\nhint: the transaction needs more gas; raise the budget with `git config lfs.walrus.gasbudget <MIST>`",
+                command, stderr
+            ),
+            WalrusError::CommandFailed { command, stderr } => {
+                write!(f, "Walrus {} command failed: {}", command, stderr)
+            }
+            WalrusError::TimedOut { command, timeout } => write!(
+                f,
+                "Walrus {} command timed out after {}s (lfs.walrus.timeoutsecs)",
+                command,
+                timeout.as_secs()
+            ),
+            WalrusError::NotCertified { blob_id, timeout } => write!(
+                f,
+                "blob {} was not certified within {}s (lfs.walrus.certifytimeoutsecs)",
+                blob_id,
+                timeout.as_secs()
+            ),
+            WalrusError::ResponseTooLarge { command, limit } => write!(
+                f,
+                "Walrus {} command printed more than {} bytes (lfs.walrus.maxresponsebytes) and was stopped",
+                command, limit
+            ),
+        }
+    }
+}
+
+impl std::error::Error for WalrusError {}
+
+/// The result of a successful store.
+#[derive(Debug, Clone)]
+pub struct StoreOutcome {
+    pub blob_id: String,
+    pub tx_digest: Option<String>,
+    /// Epochs the blob was stored for
+    pub epochs: u64,
+    /// Epoch at which the blob's storage ends, when walrus reports it
+    pub end_epoch: Option<u64>,
+    /// False when walrus reported the blob as already certified
+    pub newly_created: bool,
+}
+
+impl StoreOutcome {
+    /// Fails if walrus found the blob already certified, for flows where every store is
+    /// expected to be fresh.
+    pub fn require_newly_created(&self) -> Result<()> {
+        if !self.newly_created {
+            return Err(anyhow::anyhow!(
+                "Blob {} was already certified (--fail-on-already-certified is set)",
+                self.blob_id
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// What `walrus info` reports about the network. Fields the response lacks are `None`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NetworkInfo {
+    pub current_epoch: Option<u64>,
+    pub epoch_duration: Option<Duration>,
+    /// The most epochs ahead a blob can be stored for
+    pub max_epochs: Option<u64>,
+}
+
+impl NetworkInfo {
+    fn from_info(info: &serde_json::Value) -> Self {
+        let u64_at = |pointer| info.pointer(pointer).and_then(|v| v.as_u64());
+        Self {
+            current_epoch: u64_at("/epochInfo/currentEpoch"),
+            epoch_duration: parse_epoch_duration(info.pointer("/epochInfo/epochDuration")),
+            max_epochs: u64_at("/epochInfo/maxEpochsAhead"),
+        }
+    }
+}
+
+/// Receives the number of bytes sent so far while a blob is being stored.
+pub trait ProgressSink: Send {
+    fn bytes_sent(&mut self, bytes_so_far: u64);
+}
+
+impl<F: FnMut(u64) + Send> ProgressSink for F {
+    fn bytes_sent(&mut self, bytes_so_far: u64) {
+        self(bytes_so_far)
+    }
+}
+
 pub struct WalrusClient {
+    /// `lfs.walrus.config`, or the walrus client config found in a standard location
     config_path: Option<String>,
+    /// `lfs.walrus.wallet`: the Sui wallet config, instead of the one the walrus config names
+    wallet_path: Option<String>,
     walrus_path: Option<PathBuf>,
     default_epochs: u64,
+    read_mode: ReadMode,
+    aggregator: Option<Aggregator>,
+    /// Raw `lfs.walrus.attributes` JSON, validated when a store needs it
+    store_attributes: Option<String>,
+    /// Refuse anything that would need the network, instead of letting walrus hang
+    offline: bool,
+    /// `lfs.walrus.gasbudget`, for store transactions
+    gas_budget: Option<u64>,
+    /// `lfs.walrus.gascoin` or `--gas-coin`: the SUI coin object store transactions pay from
+    gas_coin: Option<String>,
+    /// `lfs.walrus.selfcontained`: pointers alone must be enough to fetch content
+    self_contained: bool,
+    /// `lfs.walrus.resolveorder`: where blob IDs are looked up, in order
+    resolve_order: ResolveOrder,
+    /// Blobs stored by this process, whose reads are retried while they propagate
+    stored_blob_ids: Mutex<HashSet<String>>,
+    /// `lfs.walrus.verifyafterstore` or `--verify-after-store`: read every stored blob back
+    /// and check its SHA256
+    verify_after_store: bool,
+    /// `lfs.walrus.freshreadretries`: how often to retry reading a just-stored blob
+    fresh_read_retries: u32,
+    fresh_read_backoff: Duration,
+    /// `lfs.walrus.timeoutsecs`: limit for store and read commands
+    timeout: Option<Duration>,
+    /// `lfs.walrus.certifyintervalsecs`: how often `--wait-certified` polls blob-status
+    certify_interval: Duration,
+    /// `lfs.walrus.certifytimeoutsecs`: how long `--wait-certified` waits in total
+    certify_timeout: Duration,
+    /// `lfs.walrus.tmpdir`: where scratch copies of blobs go, instead of the system temp
+    tmpdir: Option<PathBuf>,
+    /// `lfs.walrus.defaultduration` or `--duration`: storage time to convert into epochs,
+    /// taking precedence over `default_epochs`
+    storage_duration: Option<StorageDuration>,
+    /// The epochs `storage_duration` came to, looked up at the first store
+    duration_epochs: tokio::sync::OnceCell<u64>,
+    /// `walrus info`, looked up the first time anything needs it
+    network_info: tokio::sync::OnceCell<NetworkInfo>,
+    /// `lfs.walrus.maxblobsize`: larger content is stored as several chunk blobs
+    max_blob_size: u64,
+    /// `lfs.walrus.chunkconcurrency`: how many chunks of one file are downloaded at once
+    chunk_concurrency: usize,
+    /// `lfs.walrus.readconcurrency`: how many blobs [`WalrusClient::read_blobs`] reads at once
+    read_concurrency: usize,
+    /// `--tag` or the cleaned filename: set as the `tag` attribute of newly stored blobs
+    blob_tag: Option<String>,
+    /// `lfs.walrus.iobufsize`: buffer size for the streaming copies in stores and reads
+    io_buf_size: usize,
+    /// `lfs.walrus.maxresponsebytes`: most stdout a walrus command whose JSON is parsed may
+    /// print before it is killed
+    max_response_bytes: u64,
+    /// `--trace-walrus`: directory recording each JSON command's request and response
+    trace_dir: Option<PathBuf>,
+    /// `lfs.walrus.env.<name>`: variables set on every walrus process, whatever the ambient
+    /// environment (e.g. in git hooks)
+    env: Vec<(String, String)>,
+    /// Where newly created blobs are recorded, instead of the repository's pending list
+    pending_file: Option<PathBuf>,
 }
 
 impl WalrusClient {
     pub fn new() -> Self {
         Self {
-            config_path: None,
+            config_path: Self::get_config_path(),
+            wallet_path: git::config_get_path("lfs.walrus.wallet").ok().flatten(),
             walrus_path: None,
             default_epochs: Self::get_default_epochs(),
+            read_mode: ReadMode::from_config(),
+            aggregator: Aggregator::from_config(),
+            store_attributes: git::config_get("lfs.walrus.attributes").ok().flatten(),
+            offline: offline_from_config(),
+            gas_budget: Self::get_gas_budget(),
+            gas_coin: Self::get_gas_coin(),
+            self_contained: Self::get_self_contained(),
+            resolve_order: ResolveOrder::from_config(Self::get_self_contained()),
+            stored_blob_ids: Mutex::new(HashSet::new()),
+            verify_after_store: Self::get_verify_after_store(),
+            fresh_read_retries: Self::get_fresh_read_retries(),
+            fresh_read_backoff: Duration::from_millis(500),
+            timeout: Self::get_timeout(),
+            certify_interval: Self::get_seconds("lfs.walrus.certifyintervalsecs", 2),
+            certify_timeout: Self::get_seconds("lfs.walrus.certifytimeoutsecs", 120),
+            tmpdir: git::config_get("lfs.walrus.tmpdir")
+                .ok()
+                .flatten()
+                .map(PathBuf::from),
+            storage_duration: Self::get_storage_duration(),
+            duration_epochs: tokio::sync::OnceCell::new(),
+            network_info: tokio::sync::OnceCell::new(),
+            max_blob_size: Self::get_max_blob_size(),
+            chunk_concurrency: Self::get_concurrency("lfs.walrus.chunkconcurrency"),
+            read_concurrency: Self::get_concurrency("lfs.walrus.readconcurrency"),
+            blob_tag: None,
+            io_buf_size: Self::get_io_buf_size(),
+            max_response_bytes: Self::get_max_response_bytes(),
+            trace_dir: None,
+            env: Self::get_env(),
+            pending_file: None,
         }
     }
 
     pub fn with_path(path: PathBuf) -> Self {
         Self {
-            config_path: None,
             walrus_path: Some(path),
-            default_epochs: Self::get_default_epochs(),
+            ..Self::new()
         }
     }
 
     fn get_default_epochs() -> u64 {
-        let Ok(toplevel) = std::process::Command::new("git")
-            .args(&["rev-parse", "--show-toplevel"])
-            .output() else {
-                return 50;
-            };
+        // Try to get from git config, fall back to the default
+        match git::config_get("lfs.walrus.defaultepochs") {
+            Ok(Some(value)) => parse_epochs(&value).unwrap_or_else(|| {
+                eprintln!(
+                    "Warning: lfs.walrus.defaultepochs {:?} is not a positive number of epochs, using {}",
+                    value, DEFAULT_EPOCHS
+                );
+                DEFAULT_EPOCHS
+            }),
+            Ok(None) => DEFAULT_EPOCHS,
+            Err(err) => {
+                eprintln!("Warning: could not read lfs.walrus.defaultepochs: {}", err);
+                DEFAULT_EPOCHS
+            }
+        }
+    }
 
-        let toplevel_path = String::from_utf8(toplevel.stdout).unwrap();
-
-        // Try to get from git config, fall back to 50
-        std::process::Command::new("git")
-            .current_dir(toplevel_path.trim())
-            .args(&["config", "--get", "lfs.walrus.defaultepochs"])
-            .output()
-            .ok()
-            .and_then(|output| {
-                if output.status.success() {
-                    String::from_utf8(output.stdout)
-                        .ok()
-                        .and_then(|s| s.trim().parse().ok())
-                } else {
-                    None
-                }
-            })
-            .unwrap_or(50)
+    fn get_storage_duration() -> Option<StorageDuration> {
+        let value = git::config_get("lfs.walrus.defaultduration").ok().flatten()?;
+        match value.parse() {
+            Ok(duration) => Some(duration),
+            Err(err) => {
+                eprintln!(
+                    "Warning: lfs.walrus.defaultduration: {}; using lfs.walrus.defaultepochs",
+                    err
+                );
+                None
+            }
+        }
     }
 
-    // pub fn with_config(config_path: String) -> Self {
-    //     Self {
-    //         config_path: Some(config_path),
-    //     }
-    // }
+    /// Stores for `duration` (from `--duration`) instead of the configured epochs.
+    pub fn set_storage_duration(&mut self, duration: StorageDuration) {
+        self.storage_duration = Some(duration);
+        self.duration_epochs = tokio::sync::OnceCell::new();
+    }
 
-    pub async fn store_file(&self, file_path: &Path) -> Result<String> {
-        let store_cmd = StoreCommand {
-            config: self.config_path.clone(),
-            command: StoreRequest {
-                store: StoreParams {
-                    files: vec![file_path.to_string_lossy().to_string()],
-                    epochs: Some(self.default_epochs),
-                },
+    fn get_fresh_read_retries() -> u32 {
+        const DEFAULT_RETRIES: u32 = 3;
+        match git::config_get("lfs.walrus.freshreadretries") {
+            Ok(Some(value)) => value.trim().parse().unwrap_or_else(|_| {
+                eprintln!(
+                    "Warning: lfs.walrus.freshreadretries {:?} is not a number, using {}",
+                    value, DEFAULT_RETRIES
+                );
+                DEFAULT_RETRIES
+            }),
+            _ => DEFAULT_RETRIES,
+        }
+    }
+
+    fn get_timeout() -> Option<Duration> {
+        let value = git::config_get("lfs.walrus.timeoutsecs").ok().flatten()?;
+        match value.trim().parse() {
+            Ok(secs) if secs > 0 => Some(Duration::from_secs(secs)),
+            _ => {
+                eprintln!(
+                    "Warning: lfs.walrus.timeoutsecs {:?} is not a positive number of seconds, not limiting walrus commands",
+                    value
+                );
+                None
+            }
+        }
+    }
+
+    fn get_seconds(key: &str, default: u64) -> Duration {
+        let secs = match git::config_get(key) {
+            Ok(Some(value)) => match value.trim().parse() {
+                Ok(secs) if secs > 0 => secs,
+                _ => {
+                    eprintln!(
+                        "Warning: {} {:?} is not a positive number of seconds, using {}",
+                        key, value, default
+                    );
+                    default
+                }
             },
+            _ => default,
         };
+        Duration::from_secs(secs)
+    }
 
-        let json_input = serde_json::to_string(&store_cmd)?;
+    fn get_max_blob_size() -> u64 {
+        let Some(value) = git::config_get("lfs.walrus.maxblobsize").ok().flatten() else {
+            return DEFAULT_MAX_BLOB_SIZE;
+        };
+        match value.trim().parse() {
+            Ok(size) if size > 0 => size,
+            _ => {
+                eprintln!(
+                    "Warning: lfs.walrus.maxblobsize {:?} is not a positive number of bytes, using {}",
+                    value, DEFAULT_MAX_BLOB_SIZE
+                );
+                DEFAULT_MAX_BLOB_SIZE
+            }
+        }
+    }
 
-        let mut child = Command::new(
-            self.walrus_path
-                .as_deref()
-                .unwrap_or_else(|| "walrus".as_ref()),
-        )
-        .args(&["json"])
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()?;
-
-        if let Some(stdin) = child.stdin.as_mut() {
-            stdin.write_all(json_input.as_bytes()).await?;
-            stdin.shutdown().await?;
+    fn get_max_response_bytes() -> u64 {
+        let Some(value) = git::config_get("lfs.walrus.maxresponsebytes").ok().flatten() else {
+            return DEFAULT_MAX_RESPONSE_BYTES;
+        };
+        match value.trim().parse() {
+            Ok(size) if size > 0 => size,
+            _ => {
+                eprintln!(
+                    "Warning: lfs.walrus.maxresponsebytes {:?} is not a positive number of bytes, using {}",
+                    value, DEFAULT_MAX_RESPONSE_BYTES
+                );
+                DEFAULT_MAX_RESPONSE_BYTES
+            }
         }
+    }
 
-        let output = child.wait_with_output().await?;
+    fn get_io_buf_size() -> usize {
+        match git::config_get("lfs.walrus.iobufsize").ok().flatten() {
+            Some(value) => parse_io_buf_size(&value),
+            None => DEFAULT_IO_BUF_SIZE,
+        }
+    }
 
-        if !output.status.success() {
-            return Err(anyhow::anyhow!(
-                "Walrus store command failed: {}",
-                String::from_utf8_lossy(&output.stderr)
-            ));
+    fn get_env() -> Vec<(String, String)> {
+        match git::config_entries_under("lfs.walrus.env") {
+            Ok(entries) => entries
+                .into_iter()
+                .map(|(name, value)| (env_var_name(&name), value))
+                .collect(),
+            Err(err) => {
+                eprintln!("Warning: could not read lfs.walrus.env.*: {}", err);
+                Vec::new()
+            }
         }
+    }
 
-        let response_text = String::from_utf8(output.stdout)?;
-        let responses: Vec<StoreResponse> = serde_json::from_str(&response_text)?;
+    fn get_verify_after_store() -> bool {
+        git::config_flag("lfs.walrus.verifyafterstore")
+    }
 
-        if responses.is_empty() {
-            return Err(anyhow::anyhow!("No response from Walrus store command"));
+    fn get_self_contained() -> bool {
+        git::config_flag("lfs.walrus.selfcontained")
+    }
+
+    fn get_concurrency(key: &str) -> usize {
+        const DEFAULT_CONCURRENCY: usize = 4;
+        match git::config_get(key) {
+            Ok(Some(value)) => match value.trim().parse() {
+                Ok(concurrency) if concurrency > 0 => concurrency,
+                _ => {
+                    eprintln!(
+                        "Warning: {} {:?} is not a positive number, using {}",
+                        key, value, DEFAULT_CONCURRENCY
+                    );
+                    DEFAULT_CONCURRENCY
+                }
+            },
+            _ => DEFAULT_CONCURRENCY,
         }
+    }
 
-        let response = &responses[0];
+    fn get_gas_coin() -> Option<String> {
+        let value = git::config_get("lfs.walrus.gascoin").ok().flatten()?;
+        match parse_gas_coin(&value) {
+            Ok(coin) => Some(coin),
+            Err(err) => {
+                eprintln!("Warning: lfs.walrus.gascoin: {}, letting walrus pick the coin", err);
+                None
+            }
+        }
+    }
 
-        // Extract blob ID from either newly created or already certified
-        let blob_id = if let Some(newly_created) = &response.blob_store_result.newly_created {
-            extract_blob_id_from_result(newly_created)?
-        } else if let Some(already_certified) = &response.blob_store_result.already_certified {
-            extract_blob_id_from_result(already_certified)?
-        } else {
-            return Err(anyhow::anyhow!("No blob ID found in response"));
-        };
+    /// Pays for stores from `coin` (from `--gas-coin`) instead of the configured one.
+    pub fn set_gas_coin(&mut self, coin: String) {
+        self.gas_coin = Some(coin);
+    }
 
-        Ok(blob_id)
+    /// Records the request and verbatim response of every walrus command whose output is
+    /// parsed as JSON to files in `dir`.
+    pub fn set_trace_dir(&mut self, dir: PathBuf) {
+        self.trace_dir = Some(dir);
     }
 
-    pub async fn read_blob(&self, blob_id: &str, output_path: &Path) -> Result<()> {
-        let read_cmd = ReadCommand {
-            config: self.config_path.clone(),
-            command: ReadRequest {
-                read: ReadParams {
-                    blob_id: blob_id.to_string(),
-                },
-            },
-        };
+    /// Reads every blob back after storing it (`--verify-after-store`).
+    pub fn set_verify_after_store(&mut self) {
+        self.verify_after_store = true;
+    }
 
-        let json_input = serde_json::to_string(&read_cmd)?;
+    /// Labels every blob this client newly stores with a `tag` attribute, so it can be
+    /// identified when browsing Walrus outside git.
+    pub fn set_blob_tag(&mut self, tag: String) {
+        self.blob_tag = Some(tag);
+    }
 
-        let mut child = Command::new(
-            self.walrus_path
-                .as_deref()
-                .unwrap_or_else(|| "walrus".as_ref()),
-        )
-        .args(&["json"])
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()?;
-
-        if let Some(stdin) = child.stdin.as_mut() {
-            stdin.write_all(json_input.as_bytes()).await?;
-            stdin.shutdown().await?;
+    fn get_gas_budget() -> Option<u64> {
+        let value = git::config_get("lfs.walrus.gasbudget").ok().flatten()?;
+        match value.trim().parse() {
+            Ok(budget) => Some(budget),
+            Err(_) => {
+                eprintln!(
+                    "Warning: lfs.walrus.gasbudget {:?} is not a number of MIST, using walrus's default",
+                    value
+                );
+                None
+            }
+        }
+    }
+
+    /// The epochs to store for: the configured duration or epochs, unless they exceed the
+    /// network's maximum. The maximum is only looked up when a non-default value is configured.
+    async fn store_epochs(&self) -> u64 {
+        if let Some(duration) = &self.storage_duration {
+            return *self
+                .duration_epochs
+                .get_or_init(|| self.epochs_for_duration(duration))
+                .await;
+        }
+        if self.default_epochs == DEFAULT_EPOCHS {
+            return DEFAULT_EPOCHS;
         }
+        match self.max_epochs().await {
+            Ok(max) if self.default_epochs > max => {
+                // The default may itself be over a network's limit
+                let epochs = DEFAULT_EPOCHS.min(max);
+                eprintln!(
+                    "Warning: lfs.walrus.defaultepochs {} exceeds the network maximum of {}, using {}",
+                    self.default_epochs, max, epochs
+                );
+                epochs
+            }
+            // If walrus can't tell us the limit, let the store itself reject the value
+            _ => self.default_epochs,
+        }
+    }
 
-        let output = child.wait_with_output().await?;
+    /// Converts a storage duration into the nearest number of the network's epochs, warning
+    /// when it rounds and capping it at the network maximum. If walrus can't report its epoch
+    /// length, the configured epochs are used instead.
+    async fn epochs_for_duration(&self, duration: &StorageDuration) -> u64 {
+        let epoch = match self.epoch_duration().await {
+            Ok(epoch) => epoch,
+            Err(err) => {
+                eprintln!(
+                    "Warning: could not look up the epoch length to store for {} ({}), using {} epochs",
+                    duration, err, self.default_epochs
+                );
+                return self.default_epochs;
+            }
+        };
 
-        if !output.status.success() {
-            return Err(anyhow::anyhow!(
-                "Walrus read command failed: {}",
-                String::from_utf8_lossy(&output.stderr)
-            ));
+        let (epochs, exact) = duration_to_epochs(duration.duration(), epoch);
+        if !exact {
+            eprintln!(
+                "Warning: {} is not a whole number of {} epochs, storing for {} epochs ({})",
+                duration,
+                format_duration(epoch),
+                epochs,
+                format_duration(epoch.saturating_mul(u32::try_from(epochs).unwrap_or(u32::MAX)))
+            );
         }
+        match self.max_epochs().await {
+            Ok(max) if epochs > max => {
+                eprintln!(
+                    "Warning: {} exceeds the network maximum of {} epochs ({}), storing for {} epochs",
+                    duration,
+                    max,
+                    format_duration(epoch.saturating_mul(u32::try_from(max).unwrap_or(u32::MAX))),
+                    max
+                );
+                max
+            }
+            _ => epochs,
+        }
+    }
 
-        // Parse the JSON response and decode the base64 blob
-        let response_text = String::from_utf8(output.stdout)?;
-        let blob_response: serde_json::Value = serde_json::from_str(&response_text)?;
+    /// Parses `lfs.walrus.attributes`: a JSON object merged into every store request.
+    fn store_attributes(&self) -> Result<serde_json::Map<String, serde_json::Value>> {
+        let Some(raw) = &self.store_attributes else {
+            return Ok(serde_json::Map::new());
+        };
+        parse_store_attributes(raw)
+    }
 
-        if let Some(blob_base64) = blob_response.get("blob").and_then(|v| v.as_str()) {
-            let blob_data = base64::engine::general_purpose::STANDARD.decode(blob_base64)?;
-            tokio::fs::write(output_path, &blob_data).await?;
-        } else {
-            return Err(anyhow::anyhow!("No blob data found in Walrus response"));
+    /// Records a newly created blob until the run is done with it; see [`pending`].
+    fn record_pending(&self, store: &PendingStore) {
+        match &self.pending_file {
+            Some(path) => pending::record_in(path, store),
+            None => pending::record(store),
         }
+    }
 
+    fn ensure_online(&self, operation: &str) -> Result<()> {
+        if self.offline {
+            return Err(WalrusError::Offline {
+                operation: operation.to_string(),
+            }
+            .into());
+        }
         Ok(())
     }
 
-    pub async fn store_bytes(&self, data: &[u8]) -> Result<String> {
-        // Create a temporary file to store the data
-        let temp_dir = tempfile::tempdir()?;
-        let temp_path = temp_dir.path().join("temp_blob");
-        tokio::fs::write(&temp_path, data).await?;
+    /// Waits for a walrus process, killing it (by dropping it) if it outlives the timeout.
+    async fn wait_for<T>(
+        &self,
+        command: &'static str,
+        process: impl std::future::Future<Output = std::io::Result<T>>,
+    ) -> Result<T> {
+        let result = match self.timeout {
+            Some(timeout) => tokio::time::timeout(timeout, process)
+                .await
+                .map_err(|_| WalrusError::TimedOut { command, timeout })?,
+            None => process.await,
+        };
+        Ok(result.map_err(WalrusError::NotAvailable)?)
+    }
 
-        self.store_file(&temp_path).await
+    /// Runs a command whose stdout is parsed as JSON, writing `request` to its stdin, and
+    /// collects its output under [`Self::wait_for`]. Once stdout passes
+    /// `lfs.walrus.maxresponsebytes` the command is killed instead of buffering the rest. With
+    /// `--trace-walrus`, the request and the verbatim response are recorded.
+    async fn json_output(
+        &self,
+        command: &'static str,
+        mut cmd: Command,
+        request: Option<&str>,
+    ) -> Result<std::process::Output> {
+        let args: Vec<String> = cmd
+            .as_std()
+            .get_args()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect();
+        let mut child = cmd
+            .stdin(if request.is_some() {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            })
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(WalrusError::NotAvailable)?;
+        if let (Some(request), Some(mut stdin)) = (request, child.stdin.take()) {
+            stdin.write_all(request.as_bytes()).await?;
+            stdin.shutdown().await?;
+        }
+
+        let limit = self.max_response_bytes;
+        let stdout = child.stdout.take();
+        let mut stderr = child.stderr.take();
+        // Drained alongside, so a chatty stderr can't block the command
+        let stderr = tokio::spawn(async move {
+            let mut buffer = Vec::new();
+            if let Some(stderr) = stderr.as_mut() {
+                let _ = stderr.read_to_end(&mut buffer).await;
+            }
+            buffer
+        });
+
+        let output = self
+            .wait_for(command, async {
+                let mut buffer = Vec::new();
+                if let Some(stdout) = stdout {
+                    stdout.take(limit + 1).read_to_end(&mut buffer).await?;
+                }
+                if buffer.len() as u64 > limit {
+                    return Ok(None);
+                }
+                let status = child.wait().await?;
+                let stderr = stderr.await.unwrap_or_default();
+                Ok(Some(std::process::Output {
+                    status,
+                    stdout: buffer,
+                    stderr,
+                }))
+            })
+            .await;
+
+        if let Some(dir) = &self.trace_dir {
+            let (output, aborted) = match &output {
+                Ok(Some(output)) => (Some(output), None),
+                Ok(None) => (None, Some(format!("stdout passed {} bytes", limit))),
+                Err(e) => (None, Some(e.to_string())),
+            };
+            walrus_trace::record(
+                dir,
+                walrus_trace::Invocation {
+                    command,
+                    args,
+                    request,
+                    output,
+                    aborted,
+                },
+            );
+        }
+        // Dropping the child kills it
+        output?.ok_or_else(|| WalrusError::ResponseTooLarge { command, limit }.into())
     }
 
-    pub async fn store_bytes_dry_run(&self, data: &[u8]) -> Result<String> {
-        // Create a temporary file to store the data
-        let temp_dir = tempfile::tempdir()?;
-        let temp_path = temp_dir.path().join("temp_blob");
-        tokio::fs::write(&temp_path, data).await?;
+    /// Creates a scratch directory for blob copies, under `lfs.walrus.tmpdir` if set. Stores
+    /// and reads need room there for a whole blob.
+    pub fn scratch_dir(&self) -> Result<tempfile::TempDir> {
+        match &self.tmpdir {
+            Some(base) => tempfile::Builder::new()
+                .prefix("git-lfs-walrus")
+                .tempdir_in(base)
+                .with_context(|| {
+                    format!(
+                        "Could not create a scratch directory in {} (lfs.walrus.tmpdir)",
+                        base.display()
+                    )
+                }),
+            None => Ok(tempfile::tempdir()?),
+        }
+    }
 
+    fn command(&self) -> Command {
         let mut cmd = Command::new(
             self.walrus_path
                 .as_deref()
                 .unwrap_or_else(|| "walrus".as_ref()),
         );
-        cmd.args(&["store", "--dry-run", "--json", "--epochs", &self.default_epochs.to_string(), &temp_path.to_string_lossy()]);
+        // A caller that gives up on a command (e.g. after a timeout) shouldn't leave it running
+        cmd.kill_on_drop(true);
+        self.apply_env(&mut cmd);
+        cmd
+    }
 
-        let output = cmd.output().await?;
+    /// Sets the `lfs.walrus.env.<name>` variables on `cmd`, for walrus and the Sui tools
+    /// alike.
+    pub fn apply_env(&self, cmd: &mut Command) {
+        cmd.envs(self.env.iter().map(|(name, value)| (name, value)));
+    }
 
-        if !output.status.success() {
+    /// Points a walrus command at the configured or discovered config, and the wallet.
+    fn global_args(&self, cmd: &mut Command) {
+        if let Some(config) = &self.config_path {
+            cmd.args(["--config", config]);
+        }
+        if let Some(wallet) = &self.wallet_path {
+            cmd.args(["--wallet", wallet]);
+        }
+    }
+
+    /// `lfs.walrus.config`, or else the first walrus client config in the locations walrus
+    /// itself searches. Passing it explicitly keeps walrus working when it runs with a
+    /// different working directory or environment than the user's shell, e.g. in git hooks.
+    fn get_config_path() -> Option<String> {
+        if let Some(path) = git::config_get_path("lfs.walrus.config").ok().flatten() {
+            return Some(path);
+        }
+        let cwd = std::env::current_dir().ok();
+        standard_config_paths(cwd.as_deref(), dirs::config_dir().as_deref(), dirs::home_dir().as_deref())
+            .into_iter()
+            .find(|path| path.is_file())
+            .map(|path| path.display().to_string())
+    }
+
+    /// Stores the file at `file_path`, reporting upload progress to `progress` if given.
+    ///
+    /// The walrus CLI gives no incremental progress, so the sink only hears about the whole
+    /// file once the store has completed. A file larger than `lfs.walrus.maxblobsize` is
+    /// stored in chunks, and the outcome's blob ID is their manifest; the sink then hears the
+    /// cumulative size after each chunk.
+    ///
+    /// With `lfs.walrus.verifyafterstore`, the stored content is then read back and must hash
+    /// to the file's SHA256, or the store fails.
+    pub async fn store_file(
+        &self,
+        file_path: &Path,
+        progress: Option<&mut dyn ProgressSink>,
+    ) -> Result<StoreOutcome> {
+        self.ensure_online("store a blob")?;
+        let len = tokio::fs::metadata(file_path).await?.len();
+        let outcome = if len > self.max_blob_size {
+            self.store_file_chunked(file_path, len, progress).await?
+        } else {
+            let outcome = self.store_single_file(file_path, progress).await?;
+            // A blob that was already certified isn't this run's to clean up
+            if outcome.newly_created {
+                self.record_pending(&PendingStore::new(outcome.blob_id.clone()));
+            }
+            outcome
+        };
+        if self.verify_after_store {
+            self.verify_stored(&outcome.blob_id, file_path).await?;
+        }
+        Ok(outcome)
+    }
+
+    /// Reads `blob_id` back and checks that it hashes to the content of `file_path`. Reads of
+    /// a blob this client just stored are retried while it propagates.
+    async fn verify_stored(&self, blob_id: &str, file_path: &Path) -> Result<()> {
+        let _timer = metrics::Timer::start("verify-after-store");
+        let mut expected = HashingWriter::new(tokio::io::sink(), HashAlgorithm::Sha256);
+        self.copy_stream(tokio::fs::File::open(file_path).await?, &mut expected)
+            .await?;
+        let (expected, _) = expected.finish();
+
+        let mut read_back = HashingWriter::new(tokio::io::sink(), HashAlgorithm::Sha256);
+        self.read_blob_to_writer(blob_id, &mut read_back)
+            .await
+            .with_context(|| {
+                format!(
+                    "Blob {} was stored but could not be read back (lfs.walrus.verifyafterstore)",
+                    blob_id
+                )
+            })?;
+        let (actual, _) = read_back.finish();
+        if actual != expected {
             return Err(anyhow::anyhow!(
-                "Walrus store dry-run command failed: {}",
-                String::from_utf8_lossy(&output.stderr)
+                "Blob {} was stored but reads back with sha256 {}, expected {} (lfs.walrus.verifyafterstore)",
+                blob_id,
+                actual,
+                expected
             ));
         }
+        Ok(())
+    }
 
-        Ok(String::from_utf8(output.stdout)?)
+    /// Stores consecutive slices of the file as separate blobs, recording each one's size and
+    /// SHA256 in the manifest. A manifest too long to inline is stored as a blob too. The
+    /// outcome covers all of them: newly created only if every blob was, and ending with the
+    /// earliest-ending one.
+    async fn store_file_chunked(
+        &self,
+        file_path: &Path,
+        len: u64,
+        mut progress: Option<&mut dyn ProgressSink>,
+    ) -> Result<StoreOutcome> {
+        let temp_dir = self.scratch_dir()?;
+        let chunk_path = temp_dir.path().join("chunk");
+        let mut file = tokio::fs::File::open(file_path).await?;
+
+        let mut chunks = Vec::new();
+        let mut outcomes = Vec::new();
+        let mut bytes_stored = 0;
+        for size in chunk_sizes(len, self.max_blob_size) {
+            let chunk_file = tokio::fs::File::create(&chunk_path).await?;
+            let mut writer = HashingWriter::new(chunk_file, HashAlgorithm::Sha256);
+            self.copy_stream((&mut file).take(size), &mut writer).await?;
+            writer.flush().await?;
+            let (sha256, _) = writer.finish();
+
+            let outcome = self
+                .store_single_file(&chunk_path, None)
+                .await
+                .with_context(|| format!("Could not store chunk {} of {}", chunks.len() + 1, file_path.display()))?;
+            if outcome.newly_created {
+                self.record_pending(&PendingStore {
+                    chunk: true,
+                    ..PendingStore::new(outcome.blob_id.clone())
+                });
+            }
+            bytes_stored += size;
+            if let Some(progress) = progress.as_deref_mut() {
+                progress.bytes_sent(bytes_stored);
+            }
+            chunks.push(Chunk {
+                blob_id: outcome.blob_id.clone(),
+                size,
+                sha256: Some(sha256),
+            });
+            outcomes.push(outcome);
+        }
+
+        let chunk_ids: Vec<String> = chunks.iter().map(|chunk| chunk.blob_id.clone()).collect();
+        let mut blob_id = ChunkManifest { chunks }.to_string();
+        if blob_id.len() > MAX_INLINE_MANIFEST_LEN {
+            let manifest_path = temp_dir.path().join("manifest");
+            tokio::fs::write(&manifest_path, &blob_id).await?;
+            let outcome = self
+                .store_single_file(&manifest_path, None)
+                .await
+                .with_context(|| {
+                    format!("Could not store the chunk manifest of {}", file_path.display())
+                })?;
+            if outcome.newly_created {
+                self.record_pending(&PendingStore {
+                    chunks: chunk_ids,
+                    ..PendingStore::new(outcome.blob_id.clone())
+                });
+            }
+            blob_id = manifest_reference(&outcome.blob_id);
+            outcomes.push(outcome);
+        }
+
+        Ok(StoreOutcome {
+            blob_id,
+            tx_digest: outcomes.first().and_then(|outcome| outcome.tx_digest.clone()),
+            epochs: outcomes.first().map_or(0, |outcome| outcome.epochs),
+            end_epoch: outcomes
+                .iter()
+                .map(|outcome| outcome.end_epoch)
+                .min()
+                .flatten(),
+            newly_created: outcomes.iter().all(|outcome| outcome.newly_created),
+        })
     }
 
-    pub async fn read_blob_to_writer(
+    async fn store_single_file(
         &self,
-        blob_id: &str,
-        mut writer: impl AsyncWrite + Unpin,
-    ) -> Result<()> {
-        let read_cmd = ReadCommand {
+        file_path: &Path,
+        progress: Option<&mut (dyn ProgressSink + '_)>,
+    ) -> Result<StoreOutcome> {
+        let epochs = self.store_epochs().await;
+        let _timer = metrics::Timer::start("store");
+        let store_cmd = StoreCommand {
             config: self.config_path.clone(),
-            command: ReadRequest {
-                read: ReadParams {
-                    blob_id: blob_id.to_string(),
-                },
+            wallet: self.wallet_path.clone(),
+            gas_budget: self.gas_budget,
+            gas_coin: self.gas_coin.clone(),
+            command: StoreRequest {
+                store: StoreParams::new(file_path, epochs, self.store_attributes()?),
             },
         };
 
-        let json_input = serde_json::to_string(&read_cmd)?;
-
-        let mut child = Command::new(
-            self.walrus_path
-                .as_deref()
-                .unwrap_or_else(|| "walrus".as_ref()),
-        )
-        .args(&["json"])
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()?;
-
-        if let Some(stdin) = child.stdin.as_mut() {
-            stdin.write_all(json_input.as_bytes()).await?;
-            stdin.shutdown().await?;
-        }
+        let json_input = serde_json::to_string(&store_cmd)?;
 
-        let output = child.wait_with_output().await?;
+        let mut cmd = self.command();
+        cmd.arg("json");
+        let output = self.json_output("store", cmd, Some(&json_input)).await?;
 
         if !output.status.success() {
-            return Err(anyhow::anyhow!(
-                "Walrus read command failed: {}",
-                String::from_utf8_lossy(&output.stderr)
-            ));
+            return Err(
+                WalrusError::failed("store", &String::from_utf8_lossy(&output.stderr)).into(),
+            );
         }
 
-        // Parse the JSON response and decode the base64 blob
         let response_text = String::from_utf8(output.stdout)?;
-        let blob_response: serde_json::Value = serde_json::from_str(&response_text)?;
+        let responses: Vec<StoreResponse> = parse_response(&response_text, "store")?;
 
-        if let Some(blob_base64) = blob_response.get("blob").and_then(|v| v.as_str()) {
-            let blob_data = base64::engine::general_purpose::STANDARD.decode(blob_base64)?;
-            writer.write_all(&blob_data).await?;
-        } else {
-            return Err(anyhow::anyhow!("No blob data found in Walrus response"));
+        if responses.is_empty() {
+            return Err(anyhow::anyhow!("No response from Walrus store command"));
         }
 
-        Ok(())
-    }
-}
+        let response = &responses[0];
 
-impl Default for WalrusClient {
-    fn default() -> Self {
-        Self::new()
+        // Extract blob ID from either newly created or already certified
+        let result = if let Some(newly_created) = &response.blob_store_result.newly_created {
+            newly_created
+        } else if let Some(already_certified) = &response.blob_store_result.already_certified {
+            already_certified
+        } else {
+            return Err(anyhow::anyhow!("No blob ID found in response"));
+        };
+
+        let blob_id = extract_blob_id_from_result(result)?;
+
+        if let Some(tag) = &self.blob_tag {
+            self.tag_stored_blob(&blob_id, result, tag).await;
+        }
+
+        if let Some(progress) = progress {
+            progress.bytes_sent(tokio::fs::metadata(file_path).await?.len());
+        }
+
+        if let Ok(mut stored) = self.stored_blob_ids.lock() {
+            stored.insert(blob_id.clone());
+        }
+
+        Ok(StoreOutcome {
+            blob_id,
+            tx_digest: result
+                .event
+                .as_ref()
+                .and_then(|event| event.tx_digest.clone()),
+            epochs,
+            end_epoch: end_epoch_from_result(result),
+            newly_created: response.blob_store_result.newly_created.is_some(),
+        })
+    }
+
+    /// Sets the `tag` attribute on a stored blob's object. The content is stored either way, so
+    /// failures are only warned about. Already certified blobs come back without an object of
+    /// ours to label.
+    async fn tag_stored_blob(&self, blob_id: &str, result: &BlobResult, tag: &str) {
+        let Some(object_id) = result.blob_object.as_ref().and_then(|object| object.id.as_deref())
+        else {
+            eprintln!(
+                "Warning: not tagging blob {} as {:?}: walrus reported no blob object for it",
+                blob_id, tag
+            );
+            return;
+        };
+        if let Err(err) = self.set_blob_attribute(object_id, "tag", tag).await {
+            eprintln!("Warning: could not tag blob {} as {:?}: {}", blob_id, tag, err);
+        }
+    }
+
+    async fn set_blob_attribute(&self, object_id: &str, key: &str, value: &str) -> Result<()> {
+        let _timer = metrics::Timer::start("set-blob-attribute");
+        let mut cmd = self.command();
+        cmd.args(["set-blob-attribute", object_id, "--attr", key, value]);
+        self.global_args(&mut cmd);
+
+        let output = self.wait_for("set-blob-attribute", cmd.output()).await?;
+        if !output.status.success() {
+            return Err(WalrusError::failed(
+                "set-blob-attribute",
+                &String::from_utf8_lossy(&output.stderr),
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// The attributes set on a blob object (`walrus get-blob-attribute`), e.g. its `tag`.
+    pub async fn blob_attributes(&self, object_id: &str) -> Result<BTreeMap<String, String>> {
+        let _timer = metrics::Timer::start("get-blob-attribute");
+        let mut cmd = self.command();
+        cmd.args(["get-blob-attribute", "--json", object_id]);
+        self.global_args(&mut cmd);
+
+        let output = self.json_output("get-blob-attribute", cmd, None).await?;
+        if !output.status.success() {
+            return Err(WalrusError::failed(
+                "get-blob-attribute",
+                &String::from_utf8_lossy(&output.stderr),
+            )
+            .into());
+        }
+        let response: serde_json::Value =
+            parse_response(&String::from_utf8(output.stdout)?, "get-blob-attribute")?;
+        Ok(attribute_pairs(&response))
+    }
+
+    /// Retries a read that failed with "not found" for a blob this process just stored, since
+    /// a fresh blob can take a moment to become readable. Other blobs are never retried.
+    async fn retry_fresh_blob<T, F, Fut>(&self, blob_id: &str, mut read: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: std::future::Future<Output = Result<T>>,
+    {
+        let mut delay = self.fresh_read_backoff;
+        let mut attempt = 0;
+        loop {
+            match read().await {
+                Err(err)
+                    if attempt < self.fresh_read_retries
+                        && is_not_found(&err)
+                        && self.stored_here(blob_id) =>
+                {
+                    attempt += 1;
+                    eprintln!(
+                        "Blob {} was just stored but isn't readable yet, retrying ({}/{})",
+                        blob_id, attempt, self.fresh_read_retries
+                    );
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn stored_here(&self, blob_id: &str) -> bool {
+        self.stored_blob_ids
+            .lock()
+            .is_ok_and(|stored| stored.contains(blob_id))
+    }
+
+    /// Downloads the blob to `output_path`. The content is written to a temporary file next to
+    /// it and renamed into place, so a concurrent reader of `output_path`, or a concurrent
+    /// download to it, never sees a partial file.
+    pub async fn read_blob(&self, blob_id: &str, output_path: &Path) -> Result<()> {
+        self.ensure_online(&format!("download blob {} (not cached locally)", blob_id))?;
+        let dir = output_path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        let partial = tempfile::Builder::new()
+            .prefix(".walrus-read")
+            .suffix(".part")
+            .tempfile_in(dir)?;
+        match self.chunk_manifest(blob_id).await? {
+            Some(manifest) => self.read_chunks_to_file(&manifest, partial.path()).await?,
+            None => self.read_single_blob(blob_id, partial.path()).await?,
+        }
+        partial.persist(output_path)?;
+        Ok(())
+    }
+
+    /// Downloads the blob into `output_path`, a file the caller has already created and keeps
+    /// private, writing over it rather than replacing it so that space reserved for it is
+    /// used. The file is truncated to the blob's size afterwards.
+    pub async fn read_blob_in_place(&self, blob_id: &str, output_path: &Path) -> Result<()> {
+        self.ensure_online(&format!("download blob {} (not cached locally)", blob_id))?;
+        if let Some(manifest) = self.chunk_manifest(blob_id).await? {
+            let file = tokio::fs::OpenOptions::new().write(true).open(output_path).await?;
+            file.set_len(manifest.size()).await?;
+            drop(file);
+            return self.read_chunks_into(&manifest, output_path).await;
+        }
+        let mut file = tokio::fs::OpenOptions::new().write(true).open(output_path).await?;
+        self.read_single_blob_to_writer(blob_id, &mut file).await?;
+        file.flush().await?;
+        let written = file.stream_position().await?;
+        file.set_len(written).await?;
+        Ok(())
+    }
+
+    /// The manifest of chunked content, if `blob_id` is one: parsed inline, or read from the
+    /// blob a `chunked-blob:` reference names.
+    pub async fn chunk_manifest(&self, blob_id: &str) -> Result<Option<ChunkManifest>> {
+        let Some(manifest_blob_id) = manifest_blob_id(blob_id) else {
+            return ChunkManifest::parse(blob_id).transpose();
+        };
+        let temp_dir = self.scratch_dir()?;
+        let manifest_path = temp_dir.path().join("manifest");
+        self.read_single_blob(manifest_blob_id, &manifest_path)
+            .await
+            .with_context(|| format!("Could not read chunk manifest blob {}", manifest_blob_id))?;
+        let text = tokio::fs::read_to_string(&manifest_path).await?;
+        match ChunkManifest::parse(text.trim()) {
+            Some(manifest) => manifest.map(Some),
+            None => Err(anyhow::anyhow!(
+                "Blob {} does not hold a chunk manifest",
+                manifest_blob_id
+            )),
+        }
+    }
+
+    async fn read_single_blob(&self, blob_id: &str, output_path: &Path) -> Result<()> {
+        match self.read_mode {
+            ReadMode::File => self.read_blob_to_file(blob_id, output_path).await,
+            ReadMode::Json => match self.read_blob_json(blob_id).await {
+                Ok(blob_data) => {
+                    tokio::fs::write(output_path, &blob_data).await?;
+                    Ok(())
+                }
+                Err(err) if is_response_too_large(&err) => {
+                    warn_streaming_instead(blob_id, &err);
+                    self.read_blob_to_file(blob_id, output_path).await
+                }
+                Err(err) => Err(err),
+            },
+            ReadMode::Http => {
+                let file = tokio::fs::File::create(output_path).await?;
+                self.read_blob_http(blob_id, file).await
+            }
+        }
+    }
+
+    async fn read_blob_http(&self, blob_id: &str, writer: impl AsyncWrite + Unpin) -> Result<()> {
+        let Some(aggregator) = &self.aggregator else {
+            return Err(anyhow::anyhow!(
+                "lfs.walrus.readmode http needs an aggregator; set lfs.walrus.aggregatorurl"
+            ));
+        };
+        let _timer = metrics::Timer::start("read");
+        // Response chunks are often small; gather them into lfs.walrus.iobufsize writes
+        let mut writer = BufWriter::with_capacity(self.io_buf_size, writer);
+        aggregator.read_blob(blob_id, &mut writer).await
+    }
+
+    /// Downloads the chunks of chunked content into `output_path`, up to
+    /// `lfs.walrus.chunkconcurrency` at a time, each written at its own offset of the
+    /// pre-sized file. Chunks that fail are retried on their own, up to
+    /// [`CHUNK_ATTEMPTS`] times. Each chunk's size and, when the manifest records it, SHA256
+    /// are checked, so a corrupt chunk is named; the caller checks the whole against its OID.
+    async fn read_chunks_to_file(&self, manifest: &ChunkManifest, output_path: &Path) -> Result<()> {
+        let file = tokio::fs::File::create(output_path).await?;
+        file.set_len(manifest.size()).await?;
+        drop(file);
+        self.read_chunks_into(manifest, output_path).await
+    }
+
+    /// Like [`Self::read_chunks_to_file`], into an existing file of the manifest's size.
+    async fn read_chunks_into(&self, manifest: &ChunkManifest, output_path: &Path) -> Result<()> {
+        let temp_dir = self.scratch_dir()?;
+        let mut offset = 0;
+        let mut pending = Vec::new();
+        for (i, chunk) in manifest.chunks.iter().enumerate() {
+            pending.push((i, offset));
+            offset += chunk.size;
+        }
+
+        let mut attempt = 1;
+        loop {
+            let failed: Vec<_> = futures::stream::iter(pending)
+                .map(|(i, offset)| {
+                    let chunk_path = temp_dir.path().join(format!("chunk-{}", i));
+                    async move {
+                        let result = self
+                            .read_chunk_at(manifest, i, &chunk_path, output_path, offset)
+                            .await;
+                        let _ = tokio::fs::remove_file(&chunk_path).await;
+                        result.err().map(|err| ((i, offset), err))
+                    }
+                })
+                .buffer_unordered(self.chunk_concurrency)
+                .filter_map(|failure| async move { failure })
+                .collect()
+                .await;
+
+            let Some((_, err)) = failed.first() else {
+                return Ok(());
+            };
+            if attempt == CHUNK_ATTEMPTS {
+                return Err(anyhow::anyhow!(
+                    "{} of {} chunks could not be read after {} attempts, e.g. {:#}",
+                    failed.len(),
+                    manifest.chunks.len(),
+                    CHUNK_ATTEMPTS,
+                    err
+                ));
+            }
+            eprintln!(
+                "Retrying {} of {} chunks ({}/{}): {:#}",
+                failed.len(),
+                manifest.chunks.len(),
+                attempt,
+                CHUNK_ATTEMPTS - 1,
+                err
+            );
+            attempt += 1;
+            pending = failed.into_iter().map(|(chunk, _)| chunk).collect();
+        }
+    }
+
+    /// Reads chunk `i` of `manifest` to `chunk_path`, then copies it to `offset` in the output.
+    async fn read_chunk_at(
+        &self,
+        manifest: &ChunkManifest,
+        i: usize,
+        chunk_path: &Path,
+        output_path: &Path,
+        offset: u64,
+    ) -> Result<()> {
+        let chunk = &manifest.chunks[i];
+        self.read_single_blob(&chunk.blob_id, chunk_path).await?;
+        let size = tokio::fs::metadata(chunk_path).await?.len();
+        if size != chunk.size {
+            return Err(anyhow::anyhow!(
+                "Chunk {} of {} ({}) has {} bytes, expected {}",
+                i + 1,
+                manifest.chunks.len(),
+                chunk.blob_id,
+                size,
+                chunk.size
+            ));
+        }
+
+        let mut output = tokio::fs::OpenOptions::new().write(true).open(output_path).await?;
+        output.seek(std::io::SeekFrom::Start(offset)).await?;
+        let file = tokio::fs::File::open(chunk_path).await?;
+        let mut writer = HashingWriter::new(output, HashAlgorithm::Sha256);
+        self.copy_stream(file, &mut writer).await?;
+        writer.flush().await?;
+        let (sha256, _) = writer.finish();
+        if let Some(expected) = &chunk.sha256
+            && sha256 != *expected
+        {
+            return Err(anyhow::anyhow!(
+                "Chunk {} of {} ({}) has sha256 {}, expected {}",
+                i + 1,
+                manifest.chunks.len(),
+                chunk.blob_id,
+                sha256,
+                expected
+            ));
+        }
+        Ok(())
+    }
+
+    async fn read_blob_to_file(&self, blob_id: &str, output_path: &Path) -> Result<()> {
+        self.retry_fresh_blob(blob_id, || self.read_blob_to_file_once(blob_id, output_path))
+            .await
+    }
+
+    async fn read_blob_to_file_once(&self, blob_id: &str, output_path: &Path) -> Result<()> {
+        let _timer = metrics::Timer::start("read");
+        let mut cmd = self.command();
+        cmd.arg("read")
+            .arg(blob_id)
+            .arg("--out")
+            .arg(output_path);
+        self.global_args(&mut cmd);
+
+        cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+        let output = match self.wait_for("read", cmd.output()).await {
+            Ok(output) => output,
+            Err(err) => {
+                // walrus writes --out as it reads, so a killed read leaves a truncated file
+                if err.is::<WalrusError>() {
+                    let _ = tokio::fs::remove_file(output_path).await;
+                }
+                return Err(err);
+            }
+        };
+
+        if !output.status.success() {
+            return Err(
+                WalrusError::failed("read", &String::from_utf8_lossy(&output.stderr)).into(),
+            );
+        }
+
+        Ok(())
+    }
+
+    async fn read_blob_json(&self, blob_id: &str) -> Result<Vec<u8>> {
+        self.retry_fresh_blob(blob_id, || self.read_blob_json_once(blob_id))
+            .await
+    }
+
+    async fn read_blob_json_once(&self, blob_id: &str) -> Result<Vec<u8>> {
+        let _timer = metrics::Timer::start("read");
+        let read_cmd = ReadCommand {
+            config: self.config_path.clone(),
+            wallet: self.wallet_path.clone(),
+            command: ReadRequest {
+                read: ReadParams {
+                    blob_id: blob_id.to_string(),
+                },
+            },
+        };
+
+        let json_input = serde_json::to_string(&read_cmd)?;
+
+        let mut cmd = self.command();
+        cmd.arg("json");
+        let output = self.json_output("read", cmd, Some(&json_input)).await?;
+
+        if !output.status.success() {
+            return Err(
+                WalrusError::failed("read", &String::from_utf8_lossy(&output.stderr)).into(),
+            );
+        }
+
+        // Parse the JSON response and decode the base64 blob
+        let response_text = String::from_utf8(output.stdout)?;
+        let blob_response: serde_json::Value = parse_response(&response_text, "read")?;
+
+        if let Some(blob_base64) = blob_response.get("blob").and_then(|v| v.as_str()) {
+            Ok(base64::engine::general_purpose::STANDARD.decode(blob_base64)?)
+        } else {
+            Err(anyhow::anyhow!("No blob data found in Walrus response"))
+        }
+    }
+
+    pub async fn store_bytes(
+        &self,
+        data: &[u8],
+        progress: Option<&mut dyn ProgressSink>,
+    ) -> Result<StoreOutcome> {
+        // Create a temporary file to store the data
+        let temp_dir = self.scratch_dir()?;
+        let temp_path = temp_dir.path().join("temp_blob");
+        tokio::fs::write(&temp_path, data).await?;
+
+        self.store_file(&temp_path, progress).await
+    }
+
+    /// Writes `length` bytes of the blob starting at `start` to `writer`.
+    ///
+    /// The walrus CLI can only read whole blobs, so this needs an aggregator
+    /// (`lfs.walrus.aggregatorurl`) that honors HTTP range requests.
+    pub async fn read_blob_range(
+        &self,
+        blob_id: &str,
+        start: u64,
+        length: u64,
+        writer: impl AsyncWrite + Unpin,
+    ) -> Result<()> {
+        self.ensure_online(&format!("download blob {} (not cached locally)", blob_id))?;
+        let Some(aggregator) = &self.aggregator else {
+            return Err(anyhow::anyhow!(
+                "Ranged reads need an aggregator; set lfs.walrus.aggregatorurl"
+            ));
+        };
+        let _timer = metrics::Timer::start("read-range");
+        aggregator.read_range(blob_id, start, length, writer).await
+    }
+
+    /// Queries `walrus blob-status`, returning `None` if Walrus doesn't know the blob.
+    pub async fn blob_status(&self, blob_id: &str) -> Result<Option<BlobStatusResponse>> {
+        let _timer = metrics::Timer::start("blob-status");
+        let mut cmd = self.command();
+        cmd.args(["blob-status", "--json", "--blob-id", blob_id]);
+        self.global_args(&mut cmd);
+
+        let output = self.json_output("blob-status", cmd, None).await?;
+
+        if !output.status.success() {
+            let error_msg = String::from_utf8_lossy(&output.stderr);
+            if error_msg.contains("not found") || error_msg.contains("does not exist") {
+                return Ok(None);
+            }
+            return Err(WalrusError::failed("blob-status", &error_msg).into());
+        }
+
+        let response_text = String::from_utf8(output.stdout)?;
+        Ok(Some(parse_response(&response_text, "blob-status")?))
+    }
+
+    /// Deletes a blob stored as deletable by this wallet (`walrus delete`).
+    pub async fn delete_blob(&self, blob_id: &str) -> Result<()> {
+        self.ensure_online(&format!("delete blob {}", blob_id))?;
+        let _timer = metrics::Timer::start("delete");
+        let mut cmd = self.command();
+        cmd.args(["delete", "--blob-id", blob_id, "--yes"]);
+        self.global_args(&mut cmd);
+
+        let output = self.wait_for("delete", cmd.output()).await?;
+
+        if !output.status.success() {
+            return Err(
+                WalrusError::failed("delete", &String::from_utf8_lossy(&output.stderr)).into(),
+            );
+        }
+        Ok(())
+    }
+
+    /// Polls blob-status until the blob is certified, failing once `lfs.walrus.certifytimeoutsecs`
+    /// has passed. For pipelines that read a blob straight after pushing it.
+    pub async fn wait_until_certified(&self, blob_id: &str) -> Result<()> {
+        if let Some(manifest) = self.chunk_manifest(blob_id).await? {
+            for chunk in manifest.chunks {
+                self.wait_until_blob_certified(&chunk.blob_id).await?;
+            }
+            if let Some(manifest_blob_id) = manifest_blob_id(blob_id) {
+                self.wait_until_blob_certified(manifest_blob_id).await?;
+            }
+            return Ok(());
+        }
+        self.wait_until_blob_certified(blob_id).await
+    }
+
+    async fn wait_until_blob_certified(&self, blob_id: &str) -> Result<()> {
+        let started = std::time::Instant::now();
+        loop {
+            if let Some(status) = self.blob_status(blob_id).await?
+                && status.is_certified()
+            {
+                return Ok(());
+            }
+            if started.elapsed() + self.certify_interval > self.certify_timeout {
+                return Err(WalrusError::NotCertified {
+                    blob_id: blob_id.to_string(),
+                    timeout: self.certify_timeout,
+                }
+                .into());
+            }
+            tokio::time::sleep(self.certify_interval).await;
+        }
+    }
+
+    /// Returns the network's current epoch, as reported by `walrus info`.
+    pub async fn current_epoch(&self) -> Result<u64> {
+        let info = self.network_info().await?;
+        info_field(info.current_epoch, "current epoch")
+    }
+
+    /// Returns the most epochs ahead a blob can be stored for, as reported by `walrus info`.
+    pub async fn max_epochs(&self) -> Result<u64> {
+        let info = self.network_info().await?;
+        info_field(info.max_epochs, "maximum epochs")
+    }
+
+    /// Returns the length of an epoch, as reported by `walrus info`.
+    pub async fn epoch_duration(&self) -> Result<Duration> {
+        let info = self.network_info().await?;
+        info_field(info.epoch_duration, "epoch duration")
+    }
+
+    /// Runs `walrus info` once per client and keeps the result for the rest of the run. A
+    /// failed lookup isn't kept, so the next caller tries again.
+    pub async fn network_info(&self) -> Result<&NetworkInfo> {
+        self.network_info
+            .get_or_try_init(|| async { Ok(NetworkInfo::from_info(&self.info().await?)) })
+            .await
+    }
+
+    async fn info(&self) -> Result<serde_json::Value> {
+        let _timer = metrics::Timer::start("info");
+        let mut cmd = self.command();
+        cmd.args(["info", "--json"]);
+        self.global_args(&mut cmd);
+
+        let output = self.json_output("info", cmd, None).await?;
+
+        if !output.status.success() {
+            return Err(
+                WalrusError::failed("info", &String::from_utf8_lossy(&output.stderr)).into(),
+            );
+        }
+
+        parse_response(&String::from_utf8(output.stdout)?, "info")
+    }
+
+    pub async fn store_bytes_dry_run(&self, data: &[u8]) -> Result<String> {
+        // Create a temporary file to store the data
+        let temp_dir = self.scratch_dir()?;
+        let temp_path = temp_dir.path().join("temp_blob");
+        tokio::fs::write(&temp_path, data).await?;
+
+        self.store_file_dry_run(&temp_path).await
+    }
+
+    async fn store_file_dry_run(&self, temp_path: &Path) -> Result<String> {
+        self.ensure_online("estimate a store")?;
+        let epochs = self.store_epochs().await;
+        let _timer = metrics::Timer::start("store-dry-run");
+
+        let mut cmd = self.command();
+        cmd.args(["store", "--dry-run", "--json", "--epochs", &epochs.to_string(), &temp_path.to_string_lossy()]);
+        self.global_args(&mut cmd);
+
+        let output = self.json_output("store dry-run", cmd, None).await?;
+
+        if !output.status.success() {
+            return Err(
+                WalrusError::failed("store dry-run", &String::from_utf8_lossy(&output.stderr)).into(),
+            );
+        }
+
+        Ok(String::from_utf8(output.stdout)?)
+    }
+
+    /// Estimates the storage cost (in FROST) of storing `data`, via a dry-run store (one per
+    /// chunk when `data` exceeds the maximum blob size).
+    pub async fn estimate_store_cost(&self, data: &[u8]) -> Result<u64> {
+        if data.len() as u64 > self.max_blob_size {
+            let mut cost = 0;
+            for chunk in data.chunks(self.max_blob_size as usize) {
+                cost += self.estimate_single_store_cost(chunk).await?;
+            }
+            return Ok(cost);
+        }
+        self.estimate_single_store_cost(data).await
+    }
+
+    /// Estimates the storage cost (in FROST) of `size` bytes of content that isn't at hand,
+    /// e.g. from a pointer. Walrus prices by size alone, so a sparse scratch file of that size
+    /// stands in for each blob.
+    pub async fn estimate_store_cost_for_size(&self, size: u64) -> Result<u64> {
+        let sizes = if size > self.max_blob_size {
+            chunk_sizes(size, self.max_blob_size)
+        } else {
+            vec![size]
+        };
+        let temp_dir = self.scratch_dir()?;
+        let temp_path = temp_dir.path().join("sized_blob");
+        let mut cost = 0;
+        for size in sizes {
+            tokio::fs::File::create(&temp_path).await?.set_len(size).await?;
+            cost += parse_store_cost(&self.store_file_dry_run(&temp_path).await?)?;
+        }
+        Ok(cost)
+    }
+
+    async fn estimate_single_store_cost(&self, data: &[u8]) -> Result<u64> {
+        parse_store_cost(&self.store_bytes_dry_run(data).await?)
+    }
+
+    /// Reads several blobs into memory, returning their contents in the order of `blob_ids`.
+    ///
+    /// walrus's JSON interface runs one command per invocation, so there is no batched read to
+    /// use; instead up to `lfs.walrus.readconcurrency` reads run at once, which hides most of
+    /// the per-process startup cost for many small blobs.
+    pub async fn read_blobs(&self, blob_ids: &[&str]) -> Vec<Result<Vec<u8>>> {
+        futures::stream::iter(blob_ids)
+            .map(|blob_id| async move {
+                let mut content = Vec::new();
+                self.read_blob_to_writer(blob_id, &mut content).await?;
+                Ok(content)
+            })
+            .buffered(self.read_concurrency)
+            .collect()
+            .await
+    }
+
+    pub async fn read_blob_to_writer(
+        &self,
+        blob_id: &str,
+        mut writer: impl AsyncWrite + Unpin,
+    ) -> Result<()> {
+        self.ensure_online(&format!("download blob {} (not cached locally)", blob_id))?;
+        if let Some(manifest) = self.chunk_manifest(blob_id).await? {
+            let temp_dir = self.scratch_dir()?;
+            let temp_path = temp_dir.path().join("content");
+            self.read_chunks_to_file(&manifest, &temp_path).await?;
+
+            let file = tokio::fs::File::open(&temp_path).await?;
+            self.copy_stream(file, &mut writer).await?;
+            writer.flush().await?;
+            return Ok(());
+        }
+        self.read_single_blob_to_writer(blob_id, &mut writer).await
+    }
+
+    async fn read_single_blob_to_writer(
+        &self,
+        blob_id: &str,
+        writer: &mut (impl AsyncWrite + Unpin),
+    ) -> Result<()> {
+        match self.read_mode {
+            ReadMode::File => self.read_blob_via_file(blob_id, writer).await?,
+            ReadMode::Json => match self.read_blob_json(blob_id).await {
+                Ok(blob_data) => writer.write_all(&blob_data).await?,
+                Err(err) if is_response_too_large(&err) => {
+                    warn_streaming_instead(blob_id, &err);
+                    self.read_blob_via_file(blob_id, writer).await?
+                }
+                Err(err) => return Err(err),
+            },
+            ReadMode::Http => self.read_blob_http(blob_id, writer).await?,
+        }
+
+        Ok(())
+    }
+
+    /// Lets walrus write the raw bytes to a scratch file and streams that, so the blob is
+    /// never held in memory as a base64 string.
+    async fn read_blob_via_file(
+        &self,
+        blob_id: &str,
+        writer: &mut (impl AsyncWrite + Unpin),
+    ) -> Result<()> {
+        let temp_dir = self.scratch_dir()?;
+        let temp_path = temp_dir.path().join("blob");
+        self.read_blob_to_file(blob_id, &temp_path).await?;
+
+        let file = tokio::fs::File::open(&temp_path).await?;
+        self.copy_stream(file, writer).await?;
+        Ok(())
+    }
+}
+
+impl Default for WalrusClient {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -391,26 +1944,1057 @@ impl Default for WalrusClient {
 //     Ok(sha256_str.to_string())
 // }
 
+const DEFAULT_EPOCHS: u64 = 50;
+
+/// Whether `err` is walrus reporting that it doesn't know the blob.
+pub fn is_not_found(err: &anyhow::Error) -> bool {
+    matches!(
+        err.downcast_ref::<WalrusError>(),
+        Some(WalrusError::CommandFailed { stderr, .. })
+            if stderr.contains("not found") || stderr.contains("does not exist")
+    )
+}
+
+/// Offline mode is on if `LFS_WALRUS_OFFLINE` is truthy or `lfs.walrus.offline` is true.
+fn offline_from_config() -> bool {
+    if let Ok(value) = std::env::var("LFS_WALRUS_OFFLINE") {
+        return matches!(
+            value.trim().to_ascii_lowercase().as_str(),
+            "1" | "true" | "yes" | "on"
+        );
+    }
+    git::config_flag("lfs.walrus.offline")
+}
+
+/// Parses the epoch length from `walrus info`: milliseconds, or a serialized `Duration`.
+fn parse_epoch_duration(value: Option<&serde_json::Value>) -> Option<Duration> {
+    let duration = match value? {
+        serde_json::Value::Number(millis) => Duration::from_millis(millis.as_u64()?),
+        serde_json::Value::Object(fields) => {
+            let secs = fields.get("secs")?.as_u64()?;
+            let nanos = fields.get("nanos").and_then(|v| v.as_u64()).unwrap_or(0);
+            Duration::new(secs, u32::try_from(nanos).ok()?)
+        }
+        _ => return None,
+    };
+    (!duration.is_zero()).then_some(duration)
+}
+
+fn info_field<T: Copy>(value: Option<T>, description: &str) -> Result<T> {
+    value.ok_or_else(|| anyhow::anyhow!("No {} found in Walrus info response", description))
+}
+
+/// Parses an epoch count, rejecting anything that isn't a positive integer.
+fn parse_epochs(value: &str) -> Option<u64> {
+    value.trim().parse().ok().filter(|&epochs| epochs > 0)
+}
+
+fn parse_store_attributes(raw: &str) -> Result<serde_json::Map<String, serde_json::Value>> {
+    let attributes = match serde_json::from_str(raw) {
+        Ok(serde_json::Value::Object(attributes)) => attributes,
+        _ => {
+            return Err(anyhow::anyhow!(
+                "lfs.walrus.attributes must be a JSON object, got: {}",
+                raw
+            ));
+        }
+    };
+    if attributes.contains_key("files") {
+        return Err(anyhow::anyhow!(
+            "lfs.walrus.attributes cannot set \"files\"; it is managed by git-lfs-walrus"
+        ));
+    }
+    Ok(attributes)
+}
+
+/// Walrus sometimes reports failures as `{"error": ...}` on stdout with a zero exit status.
+fn error_envelope_message(response: &serde_json::Value) -> Option<String> {
+    let error = response.as_object()?.get("error")?;
+    Some(match error {
+        serde_json::Value::String(message) => message.clone(),
+        error => error
+            .get("message")
+            .and_then(|message| message.as_str())
+            .map(str::to_string)
+            .unwrap_or_else(|| error.to_string()),
+    })
+}
+
+/// Parses the JSON printed by a walrus `command`, surfacing error envelopes as errors.
+///
+/// Some walrus builds print progress text on stdout even in JSON mode; the JSON is then picked
+/// out of the surrounding lines, with a warning that they were discarded.
+fn parse_response<T: DeserializeOwned>(response_text: &str, command: &str) -> Result<T> {
+    let parse_error = |err: serde_json::Error| {
+        anyhow::anyhow!(
+            "Could not parse Walrus {} response ({}): {}",
+            command,
+            err,
+            response_text
+        )
+    };
+
+    let response: serde_json::Value = match serde_json::from_str(response_text) {
+        Ok(response) => response,
+        Err(err) => {
+            let (response, discarded) = extract_json(response_text).ok_or_else(|| parse_error(err))?;
+            eprintln!(
+                "Warning: discarded {} non-JSON lines from the walrus {} output",
+                discarded, command
+            );
+            response
+        }
+    };
+    if let Some(message) = error_envelope_message(&response) {
+        return Err(anyhow::anyhow!(
+            "Walrus {} command returned an error: {}",
+            command,
+            message
+        ));
+    }
+
+    serde_json::from_value(response).map_err(parse_error)
+}
+
+/// Collects the key/value pairs of a `get-blob-attribute` response: walrus wraps them as
+/// `{"attribute": {"metadata": {"contents": [{"key": .., "value": ..}]}}}`, with a null
+/// attribute when none are set. A plain object of strings is accepted too.
+fn attribute_pairs(value: &serde_json::Value) -> BTreeMap<String, String> {
+    let Some(object) = value.as_object() else {
+        return BTreeMap::new();
+    };
+    for wrapper in ["attribute", "metadata"] {
+        if let Some(inner) = object.get(wrapper) {
+            return attribute_pairs(inner);
+        }
+    }
+    if let Some(contents) = object.get("contents").and_then(|contents| contents.as_array()) {
+        return contents
+            .iter()
+            .filter_map(|entry| {
+                Some((
+                    entry.get("key")?.as_str()?.to_string(),
+                    entry.get("value")?.as_str()?.to_string(),
+                ))
+            })
+            .collect();
+    }
+    object
+        .iter()
+        .filter_map(|(key, value)| Some((key.clone(), value.as_str()?.to_string())))
+        .collect()
+}
+
+/// Finds the last complete JSON value starting at the beginning of a line in `text`, and
+/// counts the other non-empty lines (and any earlier JSON values) it is mixed with.
+fn extract_json(text: &str) -> Option<(serde_json::Value, usize)> {
+    let mut found = None;
+    let mut discarded = 0;
+    let mut pos = 0;
+    while pos < text.len() {
+        let line_end = text[pos..].find('\n').map_or(text.len(), |i| pos + i + 1);
+        let line = text[pos..line_end].trim();
+        // Scanning forward, a pretty-printed value is parsed from its first line, never from
+        // an object nested inside it
+        if line.starts_with(['{', '[']) {
+            let mut values =
+                serde_json::Deserializer::from_str(&text[pos..]).into_iter::<serde_json::Value>();
+            if let Some(Ok(value)) = values.next() {
+                if found.replace(value).is_some() {
+                    discarded += 1;
+                }
+                pos += values.byte_offset();
+                continue;
+            }
+        }
+        if !line.is_empty() {
+            discarded += 1;
+        }
+        pos = line_end;
+    }
+    found.map(|value| (value, discarded))
+}
+
 fn extract_blob_id_from_result(result: &BlobResult) -> anyhow::Result<String> {
     // Try new format first (with blobObject)
     if let Some(blob_object) = &result.blob_object {
-        return Ok(blob_object.blob_id.clone());
+        return Ok(blob_id::normalize(&blob_object.blob_id));
     }
     
     // Fall back to old format (direct blobId)
     if let Some(blob_id) = &result.blob_id {
-        return Ok(blob_id.clone());
+        return Ok(blob_id::normalize(blob_id));
     }
     
     Err(anyhow::anyhow!("No blob ID found in result"))
 }
 
+/// The storage end epoch of a newly created blob's object, or of an already certified blob.
+fn end_epoch_from_result(result: &BlobResult) -> Option<u64> {
+    result
+        .blob_object
+        .as_ref()
+        .and_then(|blob_object| blob_object.storage.as_ref())
+        .map(|storage| storage.end_epoch)
+        .or(result.end_epoch)
+}
+
 impl WalrusClient {
-    pub fn walrus_path(&self) -> Option<&PathBuf> {
-        self.walrus_path.as_ref()
+    /// The settings this client resolved from git config, the environment and its defaults,
+    /// keyed by their git config name.
+    pub fn settings(&self) -> Vec<(&'static str, String)> {
+        let optional = |value: Option<String>| value.unwrap_or_else(|| "(not set)".to_string());
+        vec![
+            ("lfs.walrus.defaultepochs", self.default_epochs.to_string()),
+            (
+                "lfs.walrus.defaultduration",
+                optional(self.storage_duration.as_ref().map(ToString::to_string)),
+            ),
+            (
+                "lfs.walrus.readmode",
+                match self.read_mode {
+                    ReadMode::File => "file".to_string(),
+                    ReadMode::Json => "json".to_string(),
+                    ReadMode::Http => "http".to_string(),
+                },
+            ),
+            (
+                "lfs.walrus.aggregatorurl",
+                optional(self.aggregator.as_ref().map(|aggregator| aggregator.url().to_string())),
+            ),
+            ("lfs.walrus.attributes", optional(self.store_attributes.clone())),
+            ("lfs.walrus.offline", self.offline.to_string()),
+            (
+                "lfs.walrus.gasbudget",
+                optional(self.gas_budget.map(|budget| budget.to_string())),
+            ),
+            ("lfs.walrus.config", optional(self.config_path.clone())),
+            ("lfs.walrus.wallet", optional(self.wallet_path.clone())),
+            ("lfs.walrus.gascoin", optional(self.gas_coin.clone())),
+            ("lfs.walrus.selfcontained", self.self_contained.to_string()),
+            ("lfs.walrus.verifyafterstore", self.verify_after_store.to_string()),
+            ("lfs.walrus.resolveorder", self.resolve_order.to_string()),
+            ("lfs.walrus.freshreadretries", self.fresh_read_retries.to_string()),
+            (
+                "lfs.walrus.timeoutsecs",
+                optional(self.timeout.map(|timeout| timeout.as_secs().to_string())),
+            ),
+            (
+                "lfs.walrus.certifyintervalsecs",
+                self.certify_interval.as_secs().to_string(),
+            ),
+            (
+                "lfs.walrus.certifytimeoutsecs",
+                self.certify_timeout.as_secs().to_string(),
+            ),
+            (
+                "lfs.walrus.tmpdir",
+                optional(self.tmpdir.as_ref().map(|dir| dir.display().to_string())),
+            ),
+            ("lfs.walrus.maxblobsize", self.max_blob_size.to_string()),
+            ("lfs.walrus.chunkconcurrency", self.chunk_concurrency.to_string()),
+            ("lfs.walrus.readconcurrency", self.read_concurrency.to_string()),
+            ("lfs.walrus.iobufsize", self.io_buf_size.to_string()),
+            ("lfs.walrus.maxresponsebytes", self.max_response_bytes.to_string()),
+            (
+                "lfs.walrus.env",
+                if self.env.is_empty() {
+                    "(not set)".to_string()
+                } else {
+                    let vars: Vec<_> =
+                        self.env.iter().map(|(name, value)| format!("{}={}", name, value)).collect();
+                    vars.join(" ")
+                },
+            ),
+        ]
+    }
+
+    /// Where blob IDs are looked up, in order (`lfs.walrus.resolveorder`), skipping the mapping
+    /// under `lfs.walrus.selfcontained`.
+    pub fn resolve_order(&self) -> &ResolveOrder {
+        &self.resolve_order
+    }
+
+    #[cfg(test)]
+    pub fn set_resolve_order(&mut self, order: ResolveOrder) {
+        self.resolve_order = order;
+    }
+
+    /// Copies `reader` to `writer` through a buffer of `lfs.walrus.iobufsize` bytes, returning
+    /// how many bytes were copied.
+    pub async fn copy_stream(
+        &self,
+        reader: impl AsyncRead + Unpin,
+        writer: &mut (impl AsyncWrite + Unpin + ?Sized),
+    ) -> std::io::Result<u64> {
+        let mut reader = BufReader::with_capacity(self.io_buf_size, reader);
+        tokio::io::copy_buf(&mut reader, writer).await
+    }
+
+    /// The walrus binary given by `--walrus-path`, if any.
+    pub fn walrus_path(&self) -> Option<&Path> {
+        self.walrus_path.as_deref()
+    }
+
+    /// Whether `lfs.walrus.offline` or `LFS_WALRUS_OFFLINE` keeps this client from Walrus.
+    pub fn is_offline(&self) -> bool {
+        self.offline
+    }
+}
+
+/// The `storageCost` of the first blob in a dry-run store's output.
+fn parse_store_cost(dry_run_output: &str) -> Result<u64> {
+    let json_output: serde_json::Value = parse_response(dry_run_output, "store dry-run")?;
+    Ok(json_output
+        .as_array()
+        .and_then(|array| array.first())
+        .and_then(|first_item| first_item["storageCost"].as_u64())
+        .unwrap_or(0))
+}
+
+/// The environment variable an `lfs.walrus.env.<name>` entry sets. git config lowercases
+/// names and doesn't allow underscores in them, so `sui-config-dir` sets `SUI_CONFIG_DIR`.
+fn env_var_name(config_name: &str) -> String {
+    config_name.to_ascii_uppercase().replace('-', "_")
+}
+
+fn is_response_too_large(err: &anyhow::Error) -> bool {
+    matches!(
+        err.downcast_ref::<WalrusError>(),
+        Some(WalrusError::ResponseTooLarge { .. })
+    )
+}
+
+/// A blob too large for a JSON read is read again with `walrus read --out`.
+fn warn_streaming_instead(blob_id: &str, err: &anyhow::Error) {
+    eprintln!(
+        "Warning: {}; reading blob {} to a file instead (set lfs.walrus.readmode file for large blobs)",
+        err, blob_id
+    );
+}
+
+/// Far more than any JSON status or store response; only a base64 blob read gets near it.
+const DEFAULT_MAX_RESPONSE_BYTES: u64 = 256 * 1024 * 1024;
+
+/// The buffer `tokio::io::copy` uses, kept as the default streaming buffer size.
+const DEFAULT_IO_BUF_SIZE: usize = 8 * 1024;
+
+/// Smaller buffers mean a syscall every few bytes; larger ones only cost memory per stream.
+const IO_BUF_SIZE_RANGE: std::ops::RangeInclusive<usize> = 4 * 1024..=16 * 1024 * 1024;
+
+/// Parses `lfs.walrus.iobufsize` (bytes), clamping it to [`IO_BUF_SIZE_RANGE`].
+fn parse_io_buf_size(value: &str) -> usize {
+    let Ok(size) = value.trim().parse::<usize>() else {
+        eprintln!(
+            "Warning: lfs.walrus.iobufsize {:?} is not a number of bytes, using {}",
+            value, DEFAULT_IO_BUF_SIZE
+        );
+        return DEFAULT_IO_BUF_SIZE;
+    };
+    let clamped = size.clamp(*IO_BUF_SIZE_RANGE.start(), *IO_BUF_SIZE_RANGE.end());
+    if clamped != size {
+        eprintln!(
+            "Warning: lfs.walrus.iobufsize {} is outside {}..={} bytes, using {}",
+            size,
+            IO_BUF_SIZE_RANGE.start(),
+            IO_BUF_SIZE_RANGE.end(),
+            clamped
+        );
+    }
+    clamped
+}
+
+/// How often a chunk of chunked content is tried before the read fails.
+const CHUNK_ATTEMPTS: u32 = 3;
+
+/// Where walrus looks for its client config, in order: the working directory, the XDG config
+/// directory (`~/.config` unless `XDG_CONFIG_HOME` says otherwise), `~/.config` on platforms
+/// where that differs, and `~/.walrus`.
+fn standard_config_paths(
+    cwd: Option<&Path>,
+    config_dir: Option<&Path>,
+    home: Option<&Path>,
+) -> Vec<PathBuf> {
+    const CONFIG_FILE: &str = "client_config.yaml";
+    let mut paths = Vec::new();
+    if let Some(cwd) = cwd {
+        paths.push(cwd.join(CONFIG_FILE));
+    }
+    if let Some(config_dir) = config_dir {
+        paths.push(config_dir.join("walrus").join(CONFIG_FILE));
+    }
+    if let Some(home) = home {
+        for path in [
+            home.join(".config").join("walrus").join(CONFIG_FILE),
+            home.join(".walrus").join(CONFIG_FILE),
+        ] {
+            if !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
     }
+    paths
+}
+
+/// Checks that `value` is a Sui object ID (`0x` and up to 64 hex digits), lowercasing it.
+pub fn parse_gas_coin(value: &str) -> Result<String> {
+    let value = value.trim().to_ascii_lowercase();
+    let valid = value.strip_prefix("0x").is_some_and(|hex| {
+        !hex.is_empty() && hex.len() <= 64 && hex.chars().all(|c| c.is_ascii_hexdigit())
+    });
+    if !valid {
+        return Err(anyhow::anyhow!(
+            "{:?} is not a Sui object ID; expected 0x followed by hex digits",
+            value
+        ));
+    }
+    Ok(value)
 }
 
 pub fn client() -> WalrusClient {
     WalrusClient::default()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A client running `script`, a `#!/bin/sh` stand-in for walrus kept in the returned
+    /// directory, along with the client's pending list.
+    fn stub_walrus(script: &str) -> (tempfile::TempDir, WalrusClient) {
+        let dir = tempfile::tempdir().unwrap();
+        let stub = dir.path().join("walrus");
+        std::fs::write(&stub, script).unwrap();
+        std::fs::set_permissions(&stub, std::os::unix::fs::PermissionsExt::from_mode(0o755))
+            .unwrap();
+        let mut client = WalrusClient::with_path(stub);
+        client.pending_file = Some(dir.path().join("walrus-pending.jsonl"));
+        (dir, client)
+    }
+
+    #[test]
+    fn parse_response_surfaces_error_envelopes() {
+        let err = parse_response::<Vec<StoreResponse>>(
+            r#"{"error": {"kind": "NotEnoughGas", "message": "insufficient gas for transaction"}}"#,
+            "store",
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Walrus store command returned an error: insufficient gas for transaction"
+        );
+
+        let err = parse_response::<serde_json::Value>(r#"{"error": "blob not found"}"#, "read")
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Walrus read command returned an error: blob not found"
+        );
+    }
+
+    #[test]
+    fn store_results_yield_canonical_blob_ids_in_either_format() {
+        let responses: Vec<StoreResponse> = serde_json::from_str(
+            r#"[
+                {"blobStoreResult": {"newlyCreated": {"blobObject": {"blobId": "M4hsZGQ1oCktdzegB6HnI6Mi28S2nqOPHxK+W7/4BUk="}}}},
+                {"blobStoreResult": {"alreadyCertified": {"blobId": "M4hsZGQ1oCktdzegB6HnI6Mi28S2nqOPHxK-W7_4BUk", "endEpoch": 17}}}
+            ]"#,
+        )
+        .unwrap();
+        for response in &responses {
+            let result = &response.blob_store_result;
+            let result = result
+                .newly_created
+                .as_ref()
+                .or(result.already_certified.as_ref())
+                .unwrap();
+            assert_eq!(
+                extract_blob_id_from_result(result).unwrap(),
+                "M4hsZGQ1oCktdzegB6HnI6Mi28S2nqOPHxK-W7_4BUk"
+            );
+        }
+    }
+
+    #[test]
+    fn end_epoch_is_read_from_either_store_result() {
+        let responses: Vec<StoreResponse> = serde_json::from_str(
+            r#"[
+                {"blobStoreResult": {"newlyCreated": {"blobObject": {"blobId": "a", "storage": {"endEpoch": 42}}}}},
+                {"blobStoreResult": {"alreadyCertified": {"blobId": "b", "endEpoch": 17}}},
+                {"blobStoreResult": {"newlyCreated": {"blobObject": {"blobId": "c"}}}}
+            ]"#,
+        )
+        .unwrap();
+        let end_epochs: Vec<Option<u64>> = responses
+            .iter()
+            .map(|response| {
+                let result = &response.blob_store_result;
+                end_epoch_from_result(
+                    result
+                        .newly_created
+                        .as_ref()
+                        .or(result.already_certified.as_ref())
+                        .unwrap(),
+                )
+            })
+            .collect();
+        assert_eq!(end_epochs, [Some(42), Some(17), None]);
+    }
+
+    #[test]
+    fn store_attributes_are_merged_into_the_store_request() {
+        let store = StoreParams::new(
+            Path::new("/tmp/blob"),
+            5,
+            parse_store_attributes(r#"{"share": true, "epochs": 10}"#).unwrap(),
+        );
+        assert_eq!(
+            serde_json::to_string(&store).unwrap(),
+            r#"{"files":["/tmp/blob"],"epochs":10,"share":true}"#
+        );
+
+        assert!(parse_store_attributes(r#"{"files": []}"#).is_err());
+        assert!(parse_store_attributes("[1]").is_err());
+    }
+
+    #[test]
+    fn store_command_carries_the_gas_budget() {
+        let store_cmd = StoreCommand {
+            config: None,
+            wallet: None,
+            gas_budget: Some(500_000_000),
+            gas_coin: None,
+            command: StoreRequest {
+                store: StoreParams::new(Path::new("/tmp/blob"), 5, serde_json::Map::new()),
+            },
+        };
+        assert_eq!(
+            serde_json::to_string(&store_cmd).unwrap(),
+            r#"{"config":null,"gasBudget":500000000,"command":{"store":{"files":["/tmp/blob"],"epochs":5}}}"#
+        );
+    }
+
+    #[test]
+    fn store_command_carries_the_pinned_gas_coin() {
+        let coin = parse_gas_coin(" 0x5D3a ").unwrap();
+        assert_eq!(coin, "0x5d3a");
+        let store_cmd = StoreCommand {
+            config: None,
+            wallet: None,
+            gas_budget: None,
+            gas_coin: Some(coin),
+            command: StoreRequest {
+                store: StoreParams::new(Path::new("/tmp/blob"), 5, serde_json::Map::new()),
+            },
+        };
+        assert_eq!(
+            serde_json::to_string(&store_cmd).unwrap(),
+            r#"{"config":null,"gasCoin":"0x5d3a","command":{"store":{"files":["/tmp/blob"],"epochs":5}}}"#
+        );
+
+        assert!(parse_gas_coin("5d3a").is_err());
+        assert!(parse_gas_coin("0x").is_err());
+        assert!(parse_gas_coin("0xnothex").is_err());
+        assert!(parse_gas_coin(&format!("0x{}", "a".repeat(65))).is_err());
+    }
+
+    #[test]
+    fn walrus_config_is_looked_up_where_walrus_looks_for_it() {
+        let home = Path::new("/home/me");
+        assert_eq!(
+            standard_config_paths(
+                Some(Path::new("/repo")),
+                Some(&home.join(".config")),
+                Some(home)
+            ),
+            [
+                PathBuf::from("/repo/client_config.yaml"),
+                PathBuf::from("/home/me/.config/walrus/client_config.yaml"),
+                PathBuf::from("/home/me/.walrus/client_config.yaml"),
+            ]
+        );
+        // A custom XDG_CONFIG_HOME (or macOS' config dir) comes before the home fallbacks
+        assert_eq!(
+            standard_config_paths(None, Some(Path::new("/xdg")), Some(home)),
+            [
+                PathBuf::from("/xdg/walrus/client_config.yaml"),
+                PathBuf::from("/home/me/.config/walrus/client_config.yaml"),
+                PathBuf::from("/home/me/.walrus/client_config.yaml"),
+            ]
+        );
+        assert!(standard_config_paths(None, None, None).is_empty());
+    }
+
+    #[test]
+    fn walrus_errors_are_classified_from_stderr() {
+        let out_of_gas =
+            WalrusError::failed("store", "Error: InsufficientGas: gas budget exceeded");
+        assert!(out_of_gas.is_fatal());
+        assert!(out_of_gas.to_string().contains("lfs.walrus.gasbudget"));
+
+        let unfunded = WalrusError::failed(
+            "store",
+            "Error: could not find WAL coins with sufficient balance (required: 2500, available: 300)",
+        );
+        assert!(unfunded.is_fatal());
+        assert!(matches!(
+            unfunded,
+            WalrusError::InsufficientBalance {
+                required: Some(2500),
+                available: Some(300),
+                ..
+            }
+        ));
+        assert!(unfunded.to_string().contains("(required 2500, available 300)"));
+
+        let no_gas_coin = WalrusError::failed(
+            "store",
+            "Cannot find gas coin for signer address 0x1 with amount sufficient for the required gas budget 500",
+        );
+        assert!(matches!(
+            no_gas_coin,
+            WalrusError::InsufficientBalance {
+                required: None,
+                available: None,
+                ..
+            }
+        ));
+
+        let locked_coin = WalrusError::failed("store", "Error: gas coin 0x2a is locked");
+        assert!(matches!(locked_coin, WalrusError::CommandFailed { .. }));
+        assert!(!locked_coin.is_fatal());
+
+        let not_found = WalrusError::failed("read", "blob not found");
+        assert!(!not_found.is_fatal());
+        assert!(!not_found.to_string().contains("hint"));
+    }
+
+    #[tokio::test]
+    async fn only_blobs_stored_here_are_retried_when_not_found() {
+        let mut client = WalrusClient::with_path(PathBuf::from("/nonexistent/walrus"));
+        client.fresh_read_retries = 2;
+        client.fresh_read_backoff = Duration::ZERO;
+        client.stored_blob_ids.lock().unwrap().insert("fresh".to_string());
+
+        let attempts = std::cell::Cell::new(0);
+        let not_found = || async {
+            attempts.set(attempts.get() + 1);
+            Err::<(), _>(WalrusError::failed("read", "blob not found").into())
+        };
+
+        assert!(client.retry_fresh_blob("fresh", not_found).await.is_err());
+        assert_eq!(attempts.get(), 3);
+
+        attempts.set(0);
+        assert!(client.retry_fresh_blob("old", not_found).await.is_err());
+        assert_eq!(attempts.get(), 1);
+    }
+
+    #[test]
+    fn already_certified_outcomes_can_be_rejected() {
+        let mut outcome = StoreOutcome {
+            blob_id: "blob-1".to_string(),
+            tx_digest: None,
+            epochs: 5,
+            end_epoch: None,
+            newly_created: true,
+        };
+        assert!(outcome.require_newly_created().is_ok());
+
+        outcome.newly_created = false;
+        assert!(outcome.require_newly_created().is_err());
+    }
+
+    #[tokio::test]
+    async fn timed_out_read_removes_partial_output() {
+        let (dir, mut client) = stub_walrus(
+            "#!/bin/sh\nwhile [ $# -gt 0 ]; do [ \"$1\" = --out ] && out=$2; shift; done\nprintf partial > \"$out\"\nexec sleep 30\n",
+        );
+
+        client.timeout = Some(Duration::from_millis(500));
+        let output_path = dir.path().join("blob");
+
+        let err = client
+            .read_blob_to_file_once("blob-1", &output_path)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            err.downcast_ref::<WalrusError>(),
+            Some(WalrusError::TimedOut { .. })
+        ));
+        assert!(!output_path.exists());
+    }
+
+    #[tokio::test]
+    async fn oversized_json_responses_are_cut_off() {
+        let (_dir, mut client) = stub_walrus("#!/bin/sh\nexec yes '{\"blobId\": \"x\"}'\n");
+        client.max_response_bytes = 4096;
+
+        let err = client.blob_status("blob-1").await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<WalrusError>(),
+            Some(WalrusError::ResponseTooLarge {
+                command: "blob-status",
+                limit: 4096
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn oversized_json_reads_fall_back_to_a_file_read() {
+        // `walrus json` floods stdout; `walrus read --out` writes the blob
+        let (_dir, mut client) = stub_walrus(
+            "#!/bin/sh\n[ \"$1\" = json ] && exec yes padding\nwhile [ $# -gt 0 ]; do [ \"$1\" = --out ] && out=$2; shift; done\nprintf content > \"$out\"\n",
+        );
+
+        client.read_mode = ReadMode::Json;
+        client.max_response_bytes = 4096;
+
+        let mut content = Vec::new();
+        client.read_blob_to_writer("blob-1", &mut content).await.unwrap();
+        assert_eq!(content, b"content");
+    }
+
+    #[tokio::test]
+    async fn verify_after_store_fails_when_the_blob_reads_back_differently() {
+        let (_dir, mut client) = stub_walrus(
+            "#!/bin/sh\nif [ \"$1\" = json ]; then cat > /dev/null; echo '[{\"blobStoreResult\":{\"newlyCreated\":{\"blobObject\":{\"blobId\":\"blob-1\"}}}}]'; exit; fi\nwhile [ $# -gt 0 ]; do [ \"$1\" = --out ] && out=$2; shift; done\nprintf tampered > \"$out\"\n",
+        );
+
+        client.read_mode = ReadMode::File;
+        client.store_bytes(b"content", None).await.unwrap();
+
+        client.set_verify_after_store();
+        let err = client.store_bytes(b"content", None).await.unwrap_err();
+        assert!(
+            err.to_string().starts_with("Blob blob-1 was stored but reads back with sha256"),
+            "{}",
+            err
+        );
+        client.store_bytes(b"tampered", None).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn chunked_stores_report_cumulative_progress() {
+        let (dir, mut client) = stub_walrus(
+            "#!/bin/sh\ncat > /dev/null\nprintf '[{\"blobStoreResult\":{\"newlyCreated\":{\"blobObject\":{\"blobId\":\"blob-%s\"}}}}]' $$\n",
+        );
+        let file = dir.path().join("file");
+        std::fs::write(&file, [0; 25]).unwrap();
+
+        client.offline = false;
+        client.max_blob_size = 10;
+        let mut reported = Vec::new();
+        let mut sink = |bytes_so_far| reported.push(bytes_so_far);
+        client.store_file(&file, Some(&mut sink)).await.unwrap();
+
+        assert_eq!(reported, [10, 20, 25]);
+    }
+
+    #[tokio::test]
+    async fn only_newly_created_blobs_are_recorded_as_pending() {
+        // The first store creates its blob; later ones find theirs already certified
+        let (dir, mut client) = stub_walrus(
+            "#!/bin/sh\ncat > /dev/null\ndir=$(dirname \"$0\")\nif [ -f \"$dir/stored\" ]; then echo '[{\"blobStoreResult\":{\"alreadyCertified\":{\"blobId\":\"blob-old\",\"endEpoch\":100}}}]'; exit; fi\ntouch \"$dir/stored\"\necho '[{\"blobStoreResult\":{\"newlyCreated\":{\"blobObject\":{\"blobId\":\"blob-new\"}}}}]'\n",
+        );
+
+        client.offline = false;
+        client.store_bytes(b"new", None).await.unwrap();
+        client.store_bytes(b"old", None).await.unwrap();
+
+        let pending = std::fs::read_to_string(dir.path().join("walrus-pending.jsonl")).unwrap();
+        assert_eq!(pending.lines().count(), 1);
+        assert!(pending.contains("\"blob-new\""), "{}", pending);
+    }
+
+    #[tokio::test]
+    async fn chunks_are_read_concurrently_and_only_failed_ones_retried() {
+        // Each chunk's content is its blob ID; "bbb" fails on its first read
+        let (dir, mut client) = stub_walrus(
+            "#!/bin/sh\nid=$2\ndir=$(dirname \"$0\")\nwhile [ $# -gt 0 ]; do [ \"$1\" = --out ] && out=$2; shift; done\necho $id >> \"$dir/calls\"\nif [ $id = bbb ] && [ ! -f \"$dir/failed\" ]; then touch \"$dir/failed\"; echo boom >&2; exit 1; fi\nsleep 0.$(printf %s $id | wc -c)\nprintf %s $id > \"$out\"\n",
+        );
+
+        client.offline = false;
+        client.read_mode = ReadMode::File;
+        let manifest = ChunkManifest::parse("chunked:aaa:3,bbb:3,cccc:4,dd:2")
+            .unwrap()
+            .unwrap();
+        let output_path = dir.path().join("content");
+        client
+            .read_blob(&manifest.to_string(), &output_path)
+            .await
+            .unwrap();
+
+        assert_eq!(std::fs::read(&output_path).unwrap(), b"aaabbbccccdd");
+        let calls = std::fs::read_to_string(dir.path().join("calls")).unwrap();
+        let mut calls: Vec<_> = calls.lines().collect();
+        calls.sort();
+        assert_eq!(calls, ["aaa", "bbb", "bbb", "cccc", "dd"]);
+    }
+
+    #[tokio::test]
+    async fn batched_reads_come_back_in_input_order() {
+        // Longer blob IDs finish sooner; "missing" fails
+        let (_dir, mut client) = stub_walrus(
+            "#!/bin/sh\nid=$2\nwhile [ $# -gt 0 ]; do [ \"$1\" = --out ] && out=$2; shift; done\nif [ $id = missing ]; then echo \"blob $id not found\" >&2; exit 1; fi\nsleep 0.$((6 - $(printf %s $id | wc -c)))\nprintf %s $id > \"$out\"\n",
+        );
+
+        client.offline = false;
+        client.read_mode = ReadMode::File;
+        client.read_concurrency = 3;
+        let results = client.read_blobs(&["a", "bb", "missing", "cccc"]).await;
+
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[0].as_ref().unwrap(), b"a");
+        assert_eq!(results[1].as_ref().unwrap(), b"bb");
+        let err = results[2].as_ref().unwrap_err().to_string();
+        assert!(err.contains("not found"), "{}", err);
+        assert_eq!(results[3].as_ref().unwrap(), b"cccc");
+    }
+
+    #[tokio::test]
+    async fn in_place_reads_write_over_the_callers_file() {
+        use std::os::unix::fs::MetadataExt;
+
+        let (dir, mut client) = stub_walrus(
+            "#!/bin/sh\nwhile [ $# -gt 0 ]; do [ \"$1\" = --out ] && out=$2; shift; done\nprintf content > \"$out\"\n",
+        );
+        client.offline = false;
+        client.read_mode = ReadMode::File;
+        let output_path = dir.path().join("blob");
+        std::fs::write(&output_path, [b'x'; 100]).unwrap();
+        let inode = std::fs::metadata(&output_path).unwrap().ino();
+
+        client.read_blob_in_place("blob-1", &output_path).await.unwrap();
+        assert_eq!(std::fs::read(&output_path).unwrap(), b"content");
+        assert_eq!(std::fs::metadata(&output_path).unwrap().ino(), inode);
+    }
+
+    #[tokio::test]
+    async fn concurrent_reads_of_one_blob_never_expose_a_partial_file() {
+        // Writes the blob in two steps, so a non-atomic read would be observable half-done
+        let (dir, client) = stub_walrus(
+            "#!/bin/sh\nwhile [ $# -gt 0 ]; do [ \"$1\" = --out ] && out=$2; shift; done\nprintf first- > \"$out\"\nsleep 0.05\nprintf second >> \"$out\"\n",
+        );
+
+        let client = std::sync::Arc::new(client);
+        let output_path = dir.path().join("blob");
+        let reads: Vec<_> = (0..16)
+            .map(|_| {
+                let client = client.clone();
+                let output_path = output_path.clone();
+                tokio::spawn(async move { client.read_blob("blob-1", &output_path).await })
+            })
+            .collect();
+
+        let watcher = {
+            let output_path = output_path.clone();
+            tokio::spawn(async move {
+                let mut seen = 0;
+                for _ in 0..200 {
+                    if let Ok(content) = tokio::fs::read(&output_path).await {
+                        assert_eq!(content, b"first-second");
+                        seen += 1;
+                    }
+                    tokio::time::sleep(Duration::from_millis(1)).await;
+                }
+                seen
+            })
+        };
+
+        for read in reads {
+            read.await.unwrap().unwrap();
+        }
+        assert!(watcher.await.unwrap() > 0);
+        assert_eq!(std::fs::read(&output_path).unwrap(), b"first-second");
+        let leftovers = std::fs::read_dir(dir.path())
+            .unwrap()
+            .filter(|entry| {
+                entry
+                    .as_ref()
+                    .unwrap()
+                    .file_name()
+                    .to_string_lossy()
+                    .ends_with(".part")
+            })
+            .count();
+        assert_eq!(leftovers, 0);
+    }
+
+    #[tokio::test]
+    async fn waiting_for_certification_polls_blob_status() {
+        // Certified from the third blob-status call on
+        let (dir, mut client) = stub_walrus(
+            "#!/bin/sh\ncalls=\"$(dirname \"$0\")/calls\"\necho x >> \"$calls\"\nif [ $(wc -l < \"$calls\") -ge 3 ]; then epoch=7; else epoch=null; fi\n\
+             printf '{\"status\":\"permanent\",\"blobObject\":{\"id\":\"o\",\"storedEpoch\":1,\"blobId\":\"%s\",\"size\":1,\"certifiedEpoch\":%s,\"storage\":{\"id\":\"s\",\"startEpoch\":1,\"endEpoch\":9,\"storageSize\":1}}}' \"$5\" $epoch\n",
+        );
+        client.certify_interval = Duration::from_millis(10);
+        client.certify_timeout = Duration::from_secs(10);
+        client.wait_until_certified("blob-1").await.unwrap();
+        let calls = std::fs::read_to_string(dir.path().join("calls")).unwrap();
+        assert_eq!(calls.lines().count(), 3);
+
+        std::fs::write(dir.path().join("calls"), "").unwrap();
+        client.certify_timeout = Duration::from_millis(15);
+        let err = client.wait_until_certified("blob-1").await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<WalrusError>(),
+            Some(WalrusError::NotCertified { .. })
+        ));
+    }
+
+    #[test]
+    fn scratch_dirs_go_under_the_configured_tmpdir() {
+        let base = tempfile::tempdir().unwrap();
+        let mut client = WalrusClient::with_path(PathBuf::from("/nonexistent/walrus"));
+        client.tmpdir = Some(base.path().to_path_buf());
+
+        let scratch = client.scratch_dir().unwrap();
+        assert_eq!(scratch.path().parent(), Some(base.path()));
+
+        client.tmpdir = Some(base.path().join("missing"));
+        let err = client.scratch_dir().unwrap_err();
+        assert!(err.to_string().contains("lfs.walrus.tmpdir"));
+    }
+
+    #[tokio::test]
+    async fn storage_durations_are_converted_with_the_network_epoch_length() {
+        let (_dir, mut client) = stub_walrus(
+            "#!/bin/sh\necho '{\"epochInfo\": {\"epochDuration\": {\"secs\": 86400, \"nanos\": 0}, \"maxEpochsAhead\": 53}}'\n",
+        );
+        client.set_storage_duration("36h".parse().unwrap());
+        assert_eq!(client.store_epochs().await, 2);
+        client.set_storage_duration("365d".parse().unwrap());
+        assert_eq!(client.store_epochs().await, 53);
+    }
+
+    #[tokio::test]
+    async fn configured_epochs_over_the_maximum_fall_back_within_it() {
+        let (_dir, mut client) = stub_walrus(
+            "#!/bin/sh\necho '{\"epochInfo\": {\"epochDuration\": 86400000, \"maxEpochsAhead\": 30}}'\n",
+        );
+        client.default_epochs = 100;
+        assert_eq!(client.store_epochs().await, 30);
+        client.default_epochs = 20;
+        assert_eq!(client.store_epochs().await, 20);
+    }
+
+    #[tokio::test]
+    async fn network_info_is_looked_up_once_per_client() {
+        let (dir, client) = stub_walrus(
+            "#!/bin/sh\necho x >> \"$(dirname \"$0\")/calls\"\necho '{\"epochInfo\": {\"currentEpoch\": 12, \"epochDuration\": 86400000, \"maxEpochsAhead\": 53}}'\n",
+        );
+        assert_eq!(client.current_epoch().await.unwrap(), 12);
+        assert_eq!(client.max_epochs().await.unwrap(), 53);
+        assert_eq!(client.epoch_duration().await.unwrap(), Duration::from_secs(86400));
+        assert_eq!(std::fs::read_to_string(dir.path().join("calls")).unwrap(), "x\n");
+    }
+
+    #[test]
+    fn epoch_duration_is_read_as_millis_or_a_serialized_duration() {
+        let day = Duration::from_secs(86400);
+        let millis = serde_json::json!(86_400_000);
+        let serialized = serde_json::json!({"secs": 86400, "nanos": 0});
+        assert_eq!(parse_epoch_duration(Some(&millis)), Some(day));
+        assert_eq!(parse_epoch_duration(Some(&serialized)), Some(day));
+        assert_eq!(parse_epoch_duration(Some(&serde_json::json!(0))), None);
+        assert_eq!(parse_epoch_duration(None), None);
+    }
+
+    #[test]
+    fn parse_epochs_rejects_typos_and_zero() {
+        assert_eq!(parse_epochs("25"), Some(25));
+        assert_eq!(parse_epochs("5o"), None);
+        assert_eq!(parse_epochs("0"), None);
+        assert_eq!(parse_epochs("-3"), None);
+    }
+
+    #[test]
+    fn blob_attributes_are_read_from_walrus_and_plain_shapes() {
+        let wrapped = serde_json::json!({
+            "attribute": {"metadata": {"contents": [
+                {"key": "tag", "value": "assets/logo.png"},
+                {"key": "version", "value": "v2"}
+            ]}}
+        });
+        let pairs = attribute_pairs(&wrapped);
+        assert_eq!(pairs["tag"], "assets/logo.png");
+        assert_eq!(pairs["version"], "v2");
+
+        assert!(attribute_pairs(&serde_json::json!({"attribute": null})).is_empty());
+        let plain = attribute_pairs(&serde_json::json!({"tag": "a.bin", "size": 3}));
+        assert_eq!(plain.len(), 1);
+        assert_eq!(plain["tag"], "a.bin");
+    }
+
+    #[tokio::test]
+    async fn configured_env_reaches_every_walrus_process() {
+        assert_eq!(env_var_name("sui-config-dir"), "SUI_CONFIG_DIR");
+        assert_eq!(env_var_name("walrus-config"), "WALRUS_CONFIG");
+
+        let (_dir, mut client) = stub_walrus("#!/bin/sh\nprintf '%s' \"$SUI_CONFIG_DIR\"\n");
+        client.env = vec![("SUI_CONFIG_DIR".to_string(), "/pinned/sui".to_string())];
+
+        let output = client.command().output().await.unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "/pinned/sui");
+    }
+
+    #[test]
+    fn io_buffer_sizes_are_clamped_to_the_supported_range() {
+        assert_eq!(parse_io_buf_size("65536"), 65536);
+        assert_eq!(parse_io_buf_size(" 1048576 "), 1024 * 1024);
+        assert_eq!(parse_io_buf_size("512"), 4 * 1024);
+        assert_eq!(parse_io_buf_size("1073741824"), 16 * 1024 * 1024);
+        assert_eq!(parse_io_buf_size("64k"), DEFAULT_IO_BUF_SIZE);
+    }
+
+    #[tokio::test]
+    async fn streaming_copies_are_complete_with_any_buffer_size() {
+        let content: Vec<u8> = (0..100_000u32).map(|i| i as u8).collect();
+        for size in [4 * 1024, 64 * 1024] {
+            let mut client = WalrusClient::with_path(PathBuf::from("walrus"));
+            client.io_buf_size = size;
+            let mut output = Vec::new();
+            let copied = client.copy_stream(&content[..], &mut output).await.unwrap();
+            assert_eq!(copied, content.len() as u64);
+            assert_eq!(output, content);
+        }
+    }
+
+    #[test]
+    fn json_is_extracted_from_progress_text() {
+        let chatty = "Uploading blob... 50%\n[INFO] encoding slivers\n[\n  {\n    \"blobStoreResult\": {\"alreadyCertified\": {\"blobId\": \"abc\"}}\n  }\n]\nDone.\n";
+        let (value, discarded) = extract_json(chatty).unwrap();
+        assert_eq!(value[0]["blobStoreResult"]["alreadyCertified"]["blobId"], "abc");
+        assert_eq!(discarded, 3);
+
+        let responses: Vec<StoreResponse> = parse_response(chatty, "store").unwrap();
+        let result = responses[0].blob_store_result.already_certified.as_ref().unwrap();
+        assert_eq!(extract_blob_id_from_result(result).unwrap(), "abc");
+
+        // The last of several values wins
+        let (value, discarded) = extract_json("{\"progress\": 1}\n{\"blob\": \"aGk=\"}\n").unwrap();
+        assert_eq!(value["blob"], "aGk=");
+        assert_eq!(discarded, 1);
+
+        assert!(extract_json("no json here\n[INFO] still none\n").is_none());
+        let err = parse_response::<serde_json::Value>("[INFO] only progress\n", "read").unwrap_err();
+        assert!(err.to_string().contains("Could not parse Walrus read response"), "{}", err);
+    }
+
+    #[test]
+    fn parse_response_parses_success_shape() {
+        let responses: Vec<StoreResponse> = parse_response(
+            r#"[{"blobStoreResult": {"alreadyCertified": {"blobId": "abc", "event": {"txDigest": "digest"}}}}]"#,
+            "store",
+        )
+        .unwrap();
+        let result = responses[0]
+            .blob_store_result
+            .already_certified
+            .as_ref()
+            .unwrap();
+        assert_eq!(extract_blob_id_from_result(result).unwrap(), "abc");
+    }
+}
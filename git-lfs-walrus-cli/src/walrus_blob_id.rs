@@ -1,44 +1,111 @@
 use anyhow::Result;
+use serde::Serialize;
 use std::path::PathBuf;
+use std::str::FromStr;
 
+use crate::git;
+use crate::pointer::{extract_content_type, extract_oid, is_pointer};
+use crate::resolve::resolve_blob_id;
 use crate::walrus::WalrusClient;
-use crate::clean::get_blob_id_from_sha;
 
-pub async fn walrus_blob_id(_client: WalrusClient, file: PathBuf) -> Result<()> {
+/// How `walrus-blob-id` prints what it found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlobIdFormat {
+    /// Only the blob ID, for `$(...)` capture
+    Id,
+    /// The fields as a JSON object
+    Json,
+    /// The human-readable report
+    Full,
+}
+
+impl FromStr for BlobIdFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "id" => Ok(BlobIdFormat::Id),
+            "json" => Ok(BlobIdFormat::Json),
+            "full" => Ok(BlobIdFormat::Full),
+            _ => Err(anyhow::anyhow!(
+                "unknown format {:?}, expected \"id\", \"json\" or \"full\"",
+                value
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct BlobIdReport {
+    file: PathBuf,
+    sha256: String,
+    /// `None` when no source has a blob ID for the file
+    blob_id: Option<String>,
+    content_type: Option<String>,
+}
+
+/// Prints the Walrus blob ID of `file` in `format`, failing if none is found so scripts can
+/// tell.
+pub async fn walrus_blob_id(
+    client: WalrusClient,
+    file: PathBuf,
+    format: BlobIdFormat,
+) -> Result<()> {
     // Get the SHA256 from the LFS pointer
     let sha256 = extract_sha256_from_lfs_pointer(&file).await?;
-    
-    // Look up the actual Walrus blob ID
-    match get_blob_id_from_sha(&sha256).await? {
-        Some(blob_id) => {
-            println!("File: {}", file.display());
-            println!("SHA256: {}", sha256);
-            println!("Walrus Blob ID: {}", blob_id);
-        }
-        None => {
-            println!("No Walrus blob ID found for file: {}", file.display());
-            println!("SHA256: {}", sha256);
-            println!("This file may not have been processed by git-lfs-walrus");
+    let pointer = git::show_head(&file)?.filter(|content| is_pointer(content.as_bytes()));
+
+    // Look up the actual Walrus blob ID, from the committed pointer if there is one
+    let blob_id = resolve_blob_id(client.resolve_order(), pointer.as_deref().unwrap_or(&sha256))
+        .await
+        .ok();
+    let report = BlobIdReport {
+        content_type: pointer.as_deref().and_then(extract_content_type).map(str::to_string),
+        file,
+        sha256,
+        blob_id,
+    };
+
+    match format {
+        BlobIdFormat::Id => {
+            if let Some(blob_id) = &report.blob_id {
+                println!("{}", blob_id);
+            }
         }
+        BlobIdFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+        BlobIdFormat::Full => match &report.blob_id {
+            Some(blob_id) => {
+                println!("File: {}", report.file.display());
+                println!("SHA256: {}", report.sha256);
+                println!("Walrus Blob ID: {}", blob_id);
+                if let Some(mime) = &report.content_type {
+                    println!("Content type: {}", mime);
+                }
+            }
+            None => {
+                println!("No Walrus blob ID found for file: {}", report.file.display());
+                println!("SHA256: {}", report.sha256);
+                println!("This file may not have been processed by git-lfs-walrus");
+            }
+        },
+    }
+
+    if report.blob_id.is_none() {
+        return Err(anyhow::anyhow!(
+            "no Walrus blob ID found for {}",
+            report.file.display()
+        ));
     }
-    
     Ok(())
 }
 
 async fn extract_sha256_from_lfs_pointer(file: &PathBuf) -> Result<String> {
     // First try to get it from the git object
-    let output = std::process::Command::new("git")
-        .args(&["show", &format!("HEAD:{}", file.display())])
-        .output()?;
-    
-    if output.status.success() {
-        let content = String::from_utf8(output.stdout)?;
-        
+    if let Some(content) = git::show_head(file)? {
+
         // Parse the LFS pointer
-        for line in content.lines() {
-            if line.starts_with("oid sha256:") {
-                return Ok(line.strip_prefix("oid sha256:").unwrap().to_string());
-            }
+        if let Ok(oid) = extract_oid(&content) {
+            return Ok(oid.hash);
         }
     }
     
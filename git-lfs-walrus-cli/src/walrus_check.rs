@@ -1,58 +1,160 @@
 use anyhow::Result;
-use std::path::PathBuf;
-use std::process::Stdio;
-use tokio::process::Command;
-use serde::{Deserialize};
-
-use crate::walrus::WalrusClient;
-
-#[derive(Debug, Deserialize)]
-#[allow(dead_code)]
-struct BlobStatusResponse {
-    #[serde(rename = "blobObject")]
-    blob_object: Option<BlobObjectStatus>,
-    status: String,
-}
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::bulk::BulkErrors;
+use crate::chunks::manifest_blob_id;
+use crate::duration::{format_duration, StorageDuration};
+use crate::git;
+use crate::pointer::{extract_end_epoch, is_pointer};
+use crate::resolve::resolve_blob_id;
+use crate::walrus::{BlobStatusResponse, WalrusClient};
+
+/// With `fast`, files whose pointer records its storage end epoch are judged against a single
+/// current-epoch query instead of a blob-status call each. With `lfs.walrus.checkcachettl`
+/// set, blob-status results younger than that are reused unless `no_cache`. With `show_tags`,
+/// each status also shows the blob's `tag` attribute.
+pub async fn walrus_check(
+    client: WalrusClient,
+    files: Vec<PathBuf>,
+    keep_going: bool,
+    fast: bool,
+    no_cache: bool,
+    show_tags: bool,
+) -> Result<()> {
+    let current_epoch = if fast {
+        Some(client.current_epoch().await?)
+    } else {
+        None
+    };
+    // Cached statuses may have been recorded without tags
+    let mut cache = StatusCache::load(no_cache || show_tags, show_tags).await?;
 
-#[derive(Debug, Deserialize)]
-#[allow(dead_code)]
-struct BlobObjectStatus {
-    id: String,
-    #[serde(rename = "storedEpoch")]
-    stored_epoch: u64,
-    #[serde(rename = "blobId")]
-    blob_id: String,
-    size: u64,
-    #[serde(rename = "certifiedEpoch")]
-    certified_epoch: u64,
-    storage: StorageStatus,
-}
-
-#[derive(Debug, Deserialize)]
-#[allow(dead_code)]
-struct StorageStatus {
-    id: String,
-    #[serde(rename = "startEpoch")]
-    start_epoch: u64,
-    #[serde(rename = "endEpoch")]
-    end_epoch: u64,
-    #[serde(rename = "storageSize")]
-    storage_size: u64,
-}
-
-pub async fn walrus_check(client: WalrusClient, files: Vec<PathBuf>) -> Result<()> {
-    if files.is_empty() {
+    let result = if files.is_empty() {
         println!("Checking all LFS files for expiration...");
-        check_all_lfs_files(&client).await
+        check_all_lfs_files(&client, keep_going, current_epoch, &mut cache).await
     } else {
         println!("Checking {} files for expiration...", files.len());
-        check_specific_files(&client, files).await
+        check_specific_files(&client, files, keep_going, current_epoch, &mut cache).await
+    };
+    if let Err(e) = cache.save().await {
+        eprintln!("Warning: could not save the check cache: {}", e);
     }
+    result
+}
+
+/// Recent blob-status results (`.git/walrus-check-cache.json`), keyed by blob ID, so frequent
+/// checks only query walrus for blobs not checked within `lfs.walrus.checkcachettl`.
+struct StatusCache {
+    path: PathBuf,
+    /// `None` when the TTL isn't configured, which turns the cache off
+    ttl: Option<Duration>,
+    /// Skip cached results, but still record fresh ones
+    bypass: bool,
+    /// Fresh statuses also look up the blob's `tag` attribute
+    with_tags: bool,
+    entries: BTreeMap<String, CachedStatus>,
+    changed: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedStatus {
+    /// Unix timestamp of the check
+    checked_at: u64,
+    status: String,
 }
 
-async fn check_all_lfs_files(client: &WalrusClient) -> Result<()> {
+impl StatusCache {
+    async fn load(bypass: bool, with_tags: bool) -> Result<Self> {
+        let ttl = match git::config_get("lfs.walrus.checkcachettl")? {
+            Some(value) => match value.parse::<StorageDuration>() {
+                Ok(ttl) => Some(ttl.duration()),
+                Err(e) => {
+                    eprintln!(
+                        "Warning: lfs.walrus.checkcachettl {:?}: {}; not caching results",
+                        value, e
+                    );
+                    None
+                }
+            },
+            None => None,
+        };
+        let path = git::git_dir()?.join("walrus-check-cache.json");
+        let entries = match ttl {
+            Some(_) => match tokio::fs::read(&path).await {
+                // An unreadable cache only costs fresh queries
+                Ok(content) => serde_json::from_slice(&content).unwrap_or_default(),
+                Err(_) => BTreeMap::new(),
+            },
+            None => BTreeMap::new(),
+        };
+        Ok(Self {
+            path,
+            ttl,
+            bypass,
+            with_tags,
+            entries,
+            changed: false,
+        })
+    }
+
+    /// The cached status of `blob_id` and its age, if it was checked within the TTL.
+    fn get(&self, blob_id: &str, now: u64) -> Option<(&str, Duration)> {
+        let ttl = self.ttl?;
+        if self.bypass {
+            return None;
+        }
+        let cached = self.entries.get(blob_id)?;
+        let age = Duration::from_secs(now.checked_sub(cached.checked_at)?);
+        (age < ttl).then_some((cached.status.as_str(), age))
+    }
+
+    fn insert(&mut self, blob_id: &str, status: &str, now: u64) {
+        if self.ttl.is_some() {
+            self.entries.insert(
+                blob_id.to_string(),
+                CachedStatus {
+                    checked_at: now,
+                    status: status.to_string(),
+                },
+            );
+            self.changed = true;
+        }
+    }
+
+    /// Writes the cache back, dropping entries past the TTL.
+    async fn save(&mut self) -> Result<()> {
+        let Some(ttl) = self.ttl else {
+            return Ok(());
+        };
+        if !self.changed {
+            return Ok(());
+        }
+        let now = unix_now();
+        self.entries
+            .retain(|_, cached| now.saturating_sub(cached.checked_at) < ttl.as_secs());
+        tokio::fs::write(&self.path, serde_json::to_string_pretty(&self.entries)?).await?;
+        Ok(())
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+async fn check_all_lfs_files(
+    client: &WalrusClient,
+    keep_going: bool,
+    current_epoch: Option<u64>,
+    cache: &mut StatusCache,
+) -> Result<()> {
     // Get all LFS files in the repository
-    let lfs_files = get_lfs_files().await?;
+    let lfs_files = git::lfs_files()?;
     
     if lfs_files.is_empty() {
         println!("No LFS files found in repository.");
@@ -61,12 +163,14 @@ async fn check_all_lfs_files(client: &WalrusClient) -> Result<()> {
 
     println!("Found {} LFS files to check:", lfs_files.len());
     
+    let timeout = status_timeout();
     let mut expired_count = 0;
     let mut valid_count = 0;
     let mut error_count = 0;
+    let mut errors = BulkErrors::new(keep_going);
 
     for file_path in lfs_files {
-        match check_lfs_file(client, &file_path).await {
+        match check_lfs_file_with_timeout(client, &file_path, timeout, current_epoch, cache).await {
             Ok(status) => {
                 if status.contains("expired") || status.contains("invalid") {
                     expired_count += 1;
@@ -79,6 +183,9 @@ async fn check_all_lfs_files(client: &WalrusClient) -> Result<()> {
             Err(e) => {
                 error_count += 1;
                 println!("⚠️  {} - Error: {}", file_path.display(), e);
+                if errors.record(&file_path, &e) {
+                    break;
+                }
             }
         }
     }
@@ -88,16 +195,24 @@ async fn check_all_lfs_files(client: &WalrusClient) -> Result<()> {
     println!("  Expired/Invalid: {}", expired_count);
     println!("  Errors: {}", error_count);
 
-    Ok(())
+    errors.finish()
 }
 
-async fn check_specific_files(client: &WalrusClient, files: Vec<PathBuf>) -> Result<()> {
+async fn check_specific_files(
+    client: &WalrusClient,
+    files: Vec<PathBuf>,
+    keep_going: bool,
+    current_epoch: Option<u64>,
+    cache: &mut StatusCache,
+) -> Result<()> {
+    let timeout = status_timeout();
     let mut expired_count = 0;
     let mut valid_count = 0;
     let mut error_count = 0;
+    let mut errors = BulkErrors::new(keep_going);
 
     for file_path in files {
-        match check_lfs_file(client, &file_path).await {
+        match check_lfs_file_with_timeout(client, &file_path, timeout, current_epoch, cache).await {
             Ok(status) => {
                 if status.contains("expired") || status.contains("invalid") {
                     expired_count += 1;
@@ -110,6 +225,9 @@ async fn check_specific_files(client: &WalrusClient, files: Vec<PathBuf>) -> Res
             Err(e) => {
                 error_count += 1;
                 println!("⚠️  {} - Error: {}", file_path.display(), e);
+                if errors.record(&file_path, &e) {
+                    break;
+                }
             }
         }
     }
@@ -119,99 +237,162 @@ async fn check_specific_files(client: &WalrusClient, files: Vec<PathBuf>) -> Res
     println!("  Expired/Invalid: {}", expired_count);
     println!("  Errors: {}", error_count);
 
-    Ok(())
+    errors.finish()
 }
 
-async fn get_lfs_files() -> Result<Vec<PathBuf>> {
-    let output = Command::new("git")
-        .args(&["lfs", "ls-files", "--name-only"])
-        .output()
-        .await?;
-
-    if !output.status.success() {
-        return Err(anyhow::anyhow!(
-            "Failed to list LFS files: {}",
-            String::from_utf8_lossy(&output.stderr)
-        ));
-    }
-
-    let files_output = String::from_utf8(output.stdout)?;
-    let files: Vec<PathBuf> = files_output
-        .lines()
-        .filter(|line| !line.trim().is_empty())
-        .map(|line| PathBuf::from(line.trim()))
-        .collect();
+/// Per-file limit for a status check, from `lfs.walrus.statustimeoutsecs` (default 30).
+pub fn status_timeout() -> Duration {
+    const DEFAULT_SECS: u64 = 30;
+    let secs = match git::config_get("lfs.walrus.statustimeoutsecs") {
+        Ok(Some(value)) => match value.parse() {
+            Ok(secs) if secs > 0 => secs,
+            _ => {
+                eprintln!(
+                    "Warning: invalid lfs.walrus.statustimeoutsecs {:?}, using {}",
+                    value, DEFAULT_SECS
+                );
+                DEFAULT_SECS
+            }
+        },
+        _ => DEFAULT_SECS,
+    };
+    Duration::from_secs(secs)
+}
 
-    Ok(files)
+/// Checks one file, reporting a hung status call as an error instead of stalling the scan.
+async fn check_lfs_file_with_timeout(
+    client: &WalrusClient,
+    file_path: &Path,
+    timeout: Duration,
+    current_epoch: Option<u64>,
+    cache: &mut StatusCache,
+) -> Result<String> {
+    tokio::time::timeout(timeout, check_lfs_file(client, file_path, current_epoch, cache))
+        .await
+        .map_err(|_| anyhow::anyhow!("Timed out after {}s", timeout.as_secs()))?
 }
 
-async fn check_lfs_file(client: &WalrusClient, file_path: &PathBuf) -> Result<String> {
-    // Try to get blob ID from mapping file first
-    if let Some(blob_id) = get_blob_id_from_mapping(file_path).await? {
-        return check_blob_status(client, &blob_id).await;
+async fn check_lfs_file(
+    client: &WalrusClient,
+    file_path: &Path,
+    current_epoch: Option<u64>,
+    cache: &mut StatusCache,
+) -> Result<String> {
+    if let Some(current_epoch) = current_epoch
+        && let Some(end_epoch) = recorded_end_epoch(file_path).await?
+    {
+        return Ok(format_recorded_expiry(end_epoch, current_epoch));
     }
 
-    // Fallback: try to extract from LFS pointer directly
-    if file_path.exists() {
-        let content = tokio::fs::read_to_string(file_path).await?;
-        if let Ok(blob_id) = extract_walrus_blob_id(&content) {
-            return check_blob_status(client, &blob_id).await;
-        }
+    let Some(pointer) = file_pointer(file_path).await? else {
+        return Ok("Not an LFS pointer (file may not be stored in Walrus)".to_string());
+    };
+    match resolve_blob_id(client.resolve_order(), &pointer).await {
+        Ok(blob_id) => check_blob_status(client, &blob_id, cache).await,
+        Err(err) => Ok(format!("{} (file may not be stored in Walrus)", err)),
     }
-
-    Ok("No Walrus blob ID found (file may not be stored in Walrus)".to_string())
 }
 
-async fn get_blob_id_from_mapping(file_path: &PathBuf) -> Result<Option<String>> {
-    // Extract SHA256 from git LFS pointer
-    let output = std::process::Command::new("git")
-        .args(&["show", &format!("HEAD:{}", file_path.display())])
-        .output()?;
-
-    if output.status.success() {
-        let content = String::from_utf8(output.stdout)?;
-        
-        // Parse the LFS pointer to get SHA256
-        for line in content.lines() {
-            if line.starts_with("oid sha256:") {
-                let sha256 = line.strip_prefix("oid sha256:").unwrap();
-                // Look up in mapping file
-                return crate::clean::get_blob_id_from_sha(sha256).await;
-            }
-        }
+/// The file's pointer, from HEAD or else the working tree.
+async fn file_pointer(file_path: &Path) -> Result<Option<String>> {
+    if let Some(content) = git::show_head(file_path)?
+        && is_pointer(content.as_bytes())
+    {
+        return Ok(Some(content));
     }
+    let Ok(content) = tokio::fs::read(file_path).await else {
+        return Ok(None);
+    };
+    if !is_pointer(&content) {
+        return Ok(None);
+    }
+    Ok(Some(String::from_utf8_lossy(&content).into_owned()))
+}
 
-    Ok(None)
+/// The end epoch recorded in the file's pointer, from HEAD or else the working tree.
+async fn recorded_end_epoch(file_path: &Path) -> Result<Option<u64>> {
+    if let Some(content) = git::show_head(file_path)?
+        && let Some(end_epoch) = extract_end_epoch(&content)
+    {
+        return Ok(Some(end_epoch));
+    }
+    let Ok(content) = tokio::fs::read(file_path).await else {
+        return Ok(None);
+    };
+    if !is_pointer(&content) {
+        return Ok(None);
+    }
+    Ok(extract_end_epoch(&String::from_utf8_lossy(&content)))
 }
 
-async fn check_blob_status(client: &WalrusClient, blob_id: &str) -> Result<String> {
-    let mut cmd = Command::new(
-        client.walrus_path()
-            .map(|p| p.as_os_str())
-            .unwrap_or_else(|| "walrus".as_ref()),
-    );
-    
-    cmd.args(&["blob-status", "--json", "--blob-id", blob_id])
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped());
+/// Judges expiry from the recorded end epoch alone. The blob may have been extended since it
+/// was recorded, so an expired verdict is only likely.
+fn format_recorded_expiry(end_epoch: u64, current_epoch: u64) -> String {
+    if end_epoch <= current_epoch {
+        format!(
+            "Likely expired: storage ended at epoch {} (now {}, recorded in pointer)",
+            end_epoch, current_epoch
+        )
+    } else {
+        format!(
+            "Storage until epoch: {} ({} epochs left, recorded in pointer)",
+            end_epoch,
+            end_epoch - current_epoch
+        )
+    }
+}
 
-    let output = cmd.output().await?;
+async fn check_blob_status(
+    client: &WalrusClient,
+    blob_id: &str,
+    cache: &mut StatusCache,
+) -> Result<String> {
+    let Some(manifest) = client.chunk_manifest(blob_id).await? else {
+        return check_single_blob_status(client, blob_id, cache).await;
+    };
 
-    if !output.status.success() {
-        let error_msg = String::from_utf8_lossy(&output.stderr);
-        if error_msg.contains("not found") || error_msg.contains("does not exist") {
-            return Ok("Blob not found in Walrus".to_string());
-        }
-        return Err(anyhow::anyhow!(
-            "Walrus blob-status command failed: {}",
-            error_msg
-        ));
+    // Chunked content is only as available as its least available chunk
+    let mut statuses = Vec::new();
+    if let Some(manifest_blob_id) = manifest_blob_id(blob_id) {
+        let status = check_single_blob_status(client, manifest_blob_id, cache).await?;
+        statuses.push(format!("manifest: {}", status));
     }
+    for (i, chunk) in manifest.chunks.iter().enumerate() {
+        let status = check_single_blob_status(client, &chunk.blob_id, cache).await?;
+        statuses.push(format!("chunk {}: {}", i + 1, status));
+    }
+    Ok(format!(
+        "Chunked into {} blobs, {} bytes | {}",
+        manifest.chunks.len(),
+        manifest.size(),
+        statuses.join(" | ")
+    ))
+}
 
-    let response_text = String::from_utf8(output.stdout)?;
-    let status_response: BlobStatusResponse = serde_json::from_str(&response_text)?;
-
-    Ok(format_blob_status(&status_response))
+async fn check_single_blob_status(
+    client: &WalrusClient,
+    blob_id: &str,
+    cache: &mut StatusCache,
+) -> Result<String> {
+    let now = unix_now();
+    if let Some((status, age)) = cache.get(blob_id, now) {
+        return Ok(format!("{} (cached, checked {} ago)", status, format_duration(age)));
+    }
+    let status = match client.blob_status(blob_id).await? {
+        Some(status_response) => {
+            let mut status = format_blob_status(&status_response);
+            if cache.with_tags
+                && let Some(blob_obj) = &status_response.blob_object
+            {
+                let attributes = client.blob_attributes(&blob_obj.id).await;
+                status.push_str(&format_tag(blob_id, attributes));
+            }
+            status
+        }
+        None => "Blob not found in Walrus".to_string(),
+    };
+    cache.insert(blob_id, &status, now);
+    Ok(status)
 }
 
 fn format_blob_status(status: &BlobStatusResponse) -> String {
@@ -230,13 +411,55 @@ fn format_blob_status(status: &BlobStatusResponse) -> String {
     }
 }
 
-fn extract_walrus_blob_id(content: &str) -> Result<String> {
-    for line in content.lines() {
-        if line.starts_with("ext-0-walrus ") {
-            if let Some((_, blob_id)) = line.split_once(' ') {
-                return Ok(blob_id.trim().to_string());
-            }
+fn format_tag(blob_id: &str, attributes: Result<BTreeMap<String, String>>) -> String {
+    match attributes {
+        Ok(attributes) => match attributes.get("tag") {
+            Some(tag) => format!(" | Tag: {}", tag),
+            None => " | Tag: (none)".to_string(),
+        },
+        Err(e) => {
+            eprintln!("Warning: could not read the attributes of blob {}: {}", blob_id, e);
+            " | Tag: (unavailable)".to_string()
         }
     }
-    Err(anyhow::anyhow!("No Walrus blob ID found in LFS pointer"))
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cached_statuses_are_reused_only_within_the_ttl() {
+        let mut cache = StatusCache {
+            path: PathBuf::new(),
+            ttl: Some(Duration::from_secs(3600)),
+            bypass: false,
+            with_tags: false,
+            entries: BTreeMap::new(),
+            changed: false,
+        };
+        cache.insert("blob", "Status: permanent", 1000);
+
+        assert_eq!(
+            cache.get("blob", 1000 + 60),
+            Some(("Status: permanent", Duration::from_secs(60)))
+        );
+        assert_eq!(cache.get("blob", 1000 + 3600), None);
+        assert_eq!(cache.get("other", 1000), None);
+
+        cache.bypass = true;
+        assert_eq!(cache.get("blob", 1000 + 60), None);
+        cache.bypass = false;
+        cache.ttl = None;
+        assert_eq!(cache.get("blob", 1000 + 60), None);
+    }
+
+    #[test]
+    fn recorded_expiry_is_judged_against_the_current_epoch() {
+        assert!(format_recorded_expiry(10, 10).contains("expired"));
+        assert!(format_recorded_expiry(9, 10).contains("expired"));
+        let valid = format_recorded_expiry(15, 10);
+        assert!(!valid.contains("expired"), "{}", valid);
+        assert!(valid.contains("5 epochs left"));
+    }
+}
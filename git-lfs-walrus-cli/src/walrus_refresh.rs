@@ -1,132 +1,198 @@
 use anyhow::Result;
+use serde::Serialize;
+use std::fs::OpenOptions;
+use std::io::Write;
 use std::path::PathBuf;
-use std::process::Stdio;
-use tokio::process::Command;
-use serde::{Deserialize};
+use std::time::{SystemTime, UNIX_EPOCH};
 
+use crate::bulk::BulkErrors;
+use crate::git;
+use crate::pending;
+use crate::pointer::{canonical_pointer, END_EPOCH_EXTENSION};
+use crate::resolve::resolve_blob_id;
 use crate::walrus::WalrusClient;
 
-#[derive(Debug, Deserialize)]
-#[allow(dead_code)]
-struct BlobStatusResponse {
-    #[serde(rename = "blobObject")]
-    blob_object: Option<BlobObjectStatus>,
-    status: String,
+/// Only refresh blobs with fewer than `threshold` epochs of storage left.
+struct ExpiryWindow {
+    current_epoch: u64,
+    threshold: u64,
 }
 
-#[derive(Debug, Deserialize)]
-#[allow(dead_code)]
-struct BlobObjectStatus {
-    id: String,
-    #[serde(rename = "storedEpoch")]
-    stored_epoch: u64,
-    #[serde(rename = "blobId")]
-    blob_id: String,
-    size: u64,
-    #[serde(rename = "certifiedEpoch")]
-    certified_epoch: u64,
-    storage: StorageStatus,
-}
-
-#[derive(Debug, Deserialize)]
-#[allow(dead_code)]
-struct StorageStatus {
-    id: String,
-    #[serde(rename = "startEpoch")]
-    start_epoch: u64,
-    #[serde(rename = "endEpoch")]
-    end_epoch: u64,
-    #[serde(rename = "storageSize")]
-    storage_size: u64,
-}
-
-pub async fn walrus_refresh(client: WalrusClient, files: Vec<PathBuf>) -> Result<()> {
-    if files.is_empty() {
-        println!("Refreshing all expired LFS files...");
-        refresh_all_expired_files(&client).await
+pub async fn walrus_refresh(
+    client: WalrusClient,
+    files: Vec<PathBuf>,
+    expiring_within: Option<u64>,
+    keep_going: bool,
+    force: bool,
+    json: bool,
+    log: bool,
+) -> Result<()> {
+    let window = match expiring_within {
+        Some(threshold) => Some(ExpiryWindow {
+            current_epoch: client.current_epoch().await?,
+            threshold,
+        }),
+        None => None,
+    };
+
+    let files = if files.is_empty() {
+        if !json {
+            println!("Refreshing all expired LFS files...");
+        }
+        let lfs_files = git::lfs_files()?;
+        if lfs_files.is_empty() {
+            if !json {
+                println!("No LFS files found in repository.");
+            }
+            if json {
+                print_json(&RefreshReport::default())?;
+            }
+            return Ok(());
+        }
+        if !json {
+            println!("Found {} LFS files to check for expiration:", lfs_files.len());
+        }
+        lfs_files
     } else {
-        println!("Refreshing {} files...", files.len());
-        refresh_specific_files(&client, files).await
-    }
-}
-
-async fn refresh_all_expired_files(client: &WalrusClient) -> Result<()> {
-    // Get all LFS files in the repository
-    let lfs_files = get_lfs_files().await?;
-    
-    if lfs_files.is_empty() {
-        println!("No LFS files found in repository.");
-        return Ok(());
-    }
-
-    println!("Found {} LFS files to check for expiration:", lfs_files.len());
-    
-    let mut refreshed_count = 0;
-    let mut skipped_count = 0;
-    let mut error_count = 0;
+        if !json {
+            println!("Refreshing {} files...", files.len());
+        }
+        files
+    };
 
-    for file_path in lfs_files {
-        match check_and_refresh_file(client, &file_path).await {
-            Ok(RefreshResult::Refreshed) => {
-                refreshed_count += 1;
-                println!("🔄 {} - Refreshed", file_path.display());
+    let mut report = RefreshReport::default();
+    let mut errors = BulkErrors::new(keep_going);
+    for file_path in files {
+        // Without --force, a blob that is still live was refreshed already, e.g. by an earlier
+        // run of a retried job, and isn't stored again
+        let result = if force {
+            refresh_file(&client, &file_path).await
+        } else {
+            check_and_refresh_file(&client, &file_path, window.as_ref()).await
+        };
+        match result {
+            Ok(RefreshResult::Refreshed(change)) => {
+                report.refreshed += 1;
+                if !json {
+                    println!(
+                        "🔄 {} - Refreshed ({} -> {})",
+                        file_path.display(),
+                        change.old_blob_id,
+                        change.new_blob_id
+                    );
+                }
+                if log || git::config_flag("lfs.walrus.refreshlog") {
+                    append_to_log(&change);
+                }
+                report.changes.push(change);
             }
             Ok(RefreshResult::NotNeeded) => {
-                skipped_count += 1;
-                println!("✅ {} - No refresh needed", file_path.display());
+                report.skipped += 1;
+                if !json {
+                    println!("✅ {} - No refresh needed", file_path.display());
+                }
+            }
+            Ok(RefreshResult::NotYetExpiring { epochs_left }) => {
+                report.skipped += 1;
+                if !json {
+                    println!(
+                        "✅ {} - Not yet expiring ({} epochs left)",
+                        file_path.display(),
+                        epochs_left
+                    );
+                }
             }
             Err(e) => {
-                error_count += 1;
-                println!("⚠️  {} - Error: {}", file_path.display(), e);
+                report.errors += 1;
+                if !json {
+                    println!("⚠️  {} - Error: {}", file_path.display(), e);
+                }
+                if errors.record(&file_path, &e) {
+                    break;
+                }
             }
         }
     }
 
-    println!("\nSummary:");
-    println!("  Refreshed: {}", refreshed_count);
-    println!("  Skipped (valid): {}", skipped_count);
-    println!("  Errors: {}", error_count);
+    if json {
+        print_json(&report)?;
+    } else {
+        println!("\nSummary:");
+        println!("  Refreshed: {}", report.refreshed);
+        println!("  Skipped (valid): {}", report.skipped);
+        println!("  Errors: {}", report.errors);
+    }
 
-    Ok(())
+    errors.finish()
 }
 
-async fn refresh_specific_files(client: &WalrusClient, files: Vec<PathBuf>) -> Result<()> {
-    let mut refreshed_count = 0;
-    let mut skipped_count = 0;
-    let mut error_count = 0;
+/// Counts, and the blob ID each refreshed file moved from and to.
+#[derive(Debug, Default, Serialize)]
+struct RefreshReport {
+    refreshed: usize,
+    skipped: usize,
+    errors: usize,
+    changes: Vec<BlobIdChange>,
+}
 
-    for file_path in files {
-        match refresh_file(client, &file_path).await {
-            Ok(RefreshResult::Refreshed) => {
-                refreshed_count += 1;
-                println!("🔄 {} - Refreshed", file_path.display());
-            }
-            Ok(RefreshResult::NotNeeded) => {
-                skipped_count += 1;
-                println!("✅ {} - No refresh needed", file_path.display());
-            }
-            Err(e) => {
-                error_count += 1;
-                println!("⚠️  {} - Error: {}", file_path.display(), e);
-            }
-        }
-    }
+/// A file whose pointer now names a new blob.
+#[derive(Debug, Serialize)]
+struct BlobIdChange {
+    file: PathBuf,
+    old_blob_id: String,
+    new_blob_id: String,
+}
 
-    println!("\nSummary:");
-    println!("  Refreshed: {}", refreshed_count);
-    println!("  Skipped (valid): {}", skipped_count);
-    println!("  Errors: {}", error_count);
+/// A line of `.git/walrus-refresh-log.jsonl`.
+#[derive(Serialize)]
+struct LogLine<'a> {
+    /// Milliseconds since the Unix epoch
+    timestamp_ms: u128,
+    #[serde(flatten)]
+    change: &'a BlobIdChange,
+}
 
+fn print_json(report: &RefreshReport) -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(report)?);
     Ok(())
 }
 
+fn refresh_log_path() -> Result<PathBuf> {
+    Ok(git::git_dir()?.join("walrus-refresh-log.jsonl"))
+}
+
+/// Appends `change` to the refresh log. The pointer is already rewritten, so a failure is
+/// only a warning.
+fn append_to_log(change: &BlobIdChange) {
+    let append = || -> Result<()> {
+        let line = serde_json::to_string(&LogLine {
+            timestamp_ms: SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis(),
+            change,
+        })?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(refresh_log_path()?)?;
+        writeln!(file, "{}", line)?;
+        Ok(())
+    };
+    if let Err(err) = append() {
+        eprintln!("Warning: could not write to the refresh log: {}", err);
+    }
+}
+
 #[derive(Debug)]
 enum RefreshResult {
-    Refreshed,
+    Refreshed(BlobIdChange),
     NotNeeded,
+    NotYetExpiring { epochs_left: u64 },
 }
 
-async fn check_and_refresh_file(client: &WalrusClient, file_path: &PathBuf) -> Result<RefreshResult> {
+async fn check_and_refresh_file(
+    client: &WalrusClient,
+    file_path: &PathBuf,
+    window: Option<&ExpiryWindow>,
+) -> Result<RefreshResult> {
     // Check if file exists
     if !file_path.exists() {
         return Err(anyhow::anyhow!("File does not exist locally"));
@@ -134,16 +200,12 @@ async fn check_and_refresh_file(client: &WalrusClient, file_path: &PathBuf) -> R
 
     // Read the LFS pointer to get the blob ID
     let content = tokio::fs::read_to_string(file_path).await?;
-    let blob_id = extract_walrus_blob_id(&content)?;
+    let blob_id = resolve_blob_id(client.resolve_order(), &content).await?;
 
     // Check blob status in Walrus
-    let needs_refresh = check_blob_needs_refresh(client, &blob_id).await?;
-    
-    if needs_refresh {
-        refresh_blob(client, file_path, &blob_id).await?;
-        Ok(RefreshResult::Refreshed)
-    } else {
-        Ok(RefreshResult::NotNeeded)
+    match check_blob_needs_refresh(client, &blob_id, window).await? {
+        Some(skipped) => Ok(skipped),
+        None => refresh_blob(client, file_path, blob_id).await,
     }
 }
 
@@ -155,63 +217,78 @@ async fn refresh_file(client: &WalrusClient, file_path: &PathBuf) -> Result<Refr
 
     // Read the LFS pointer to get the blob ID
     let content = tokio::fs::read_to_string(file_path).await?;
-    let blob_id = extract_walrus_blob_id(&content)?;
+    let blob_id = resolve_blob_id(client.resolve_order(), &content).await?;
 
-    // Always refresh the specific file
-    refresh_blob(client, file_path, &blob_id).await?;
-    Ok(RefreshResult::Refreshed)
+    // Refresh whatever the blob's status (--force)
+    refresh_blob(client, file_path, blob_id).await
 }
 
-async fn check_blob_needs_refresh(client: &WalrusClient, blob_id: &str) -> Result<bool> {
-    let mut cmd = Command::new(
-        client.walrus_path()
-            .map(|p| p.as_os_str())
-            .unwrap_or_else(|| "walrus".as_ref()),
-    );
-    
-    cmd.args(&["blob-status", "--json", "--blob-id", blob_id])
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped());
-
-    let output = cmd.output().await?;
+/// Returns the reason the blob can be skipped, or `None` when it is missing or about to
+/// expire and should be refreshed.
+async fn check_blob_needs_refresh(
+    client: &WalrusClient,
+    blob_id: &str,
+    window: Option<&ExpiryWindow>,
+) -> Result<Option<RefreshResult>> {
+    let Some(status_response) = client.blob_status(blob_id).await? else {
+        return Ok(None); // Blob not found, needs refresh
+    };
 
-    if !output.status.success() {
-        let error_msg = String::from_utf8_lossy(&output.stderr);
-        if error_msg.contains("not found") || error_msg.contains("does not exist") {
-            return Ok(true); // Blob not found, needs refresh
+    // Check if blob is expired or invalid
+    let blob_object = match status_response.blob_object {
+        Some(blob_object)
+            if !status_response.status.contains("expired")
+                && !status_response.status.contains("invalid") =>
+        {
+            blob_object
         }
-        return Err(anyhow::anyhow!(
-            "Walrus blob-status command failed: {}",
-            error_msg
-        ));
+        _ => return Ok(None),
+    };
+
+    let Some(window) = window else {
+        return Ok(Some(RefreshResult::NotNeeded));
+    };
+
+    let epochs_left = blob_object
+        .storage
+        .end_epoch
+        .saturating_sub(window.current_epoch);
+    if epochs_left < window.threshold {
+        Ok(None)
+    } else {
+        Ok(Some(RefreshResult::NotYetExpiring { epochs_left }))
     }
-
-    let response_text = String::from_utf8(output.stdout)?;
-    let status_response: BlobStatusResponse = serde_json::from_str(&response_text)?;
-
-    // Check if blob is expired or invalid
-    let needs_refresh = status_response.status.contains("expired") 
-        || status_response.status.contains("invalid")
-        || status_response.blob_object.is_none();
-
-    Ok(needs_refresh)
 }
 
-async fn refresh_blob(client: &WalrusClient, file_path: &PathBuf, _old_blob_id: &str) -> Result<()> {
+async fn refresh_blob(
+    client: &WalrusClient,
+    file_path: &PathBuf,
+    old_blob_id: String,
+) -> Result<RefreshResult> {
     // Read the original file content from the working directory
     // This assumes the file has been checked out from LFS
     let file_content = tokio::fs::read(file_path).await?;
     
     // Store the file content in Walrus again to get a new blob ID
-    let new_blob_id = client.store_bytes(&file_content).await?;
+    let outcome = client.store_bytes(&file_content, None).await?;
     
     // Update the LFS pointer with the new blob ID
-    update_lfs_pointer(file_path, &new_blob_id, file_content.len()).await?;
+    update_lfs_pointer(file_path, &outcome.blob_id, file_content.len(), outcome.end_epoch).await?;
+    pending::resolve(&[&outcome.blob_id]);
     
-    Ok(())
+    Ok(RefreshResult::Refreshed(BlobIdChange {
+        file: file_path.clone(),
+        old_blob_id,
+        new_blob_id: outcome.blob_id,
+    }))
 }
 
-async fn update_lfs_pointer(file_path: &PathBuf, new_blob_id: &str, file_size: usize) -> Result<()> {
+async fn update_lfs_pointer(
+    file_path: &PathBuf,
+    new_blob_id: &str,
+    file_size: usize,
+    end_epoch: Option<u64>,
+) -> Result<()> {
     use sha2::{Digest, Sha256};
     
     // Read the current file to calculate its SHA256
@@ -224,49 +301,20 @@ async fn update_lfs_pointer(file_path: &PathBuf, new_blob_id: &str, file_size: u
     let sha256_hex = hex::encode(hash);
 
     // Create new LFS pointer with new Walrus blob ID
-    let lfs_pointer = format!(
-        "version https://git-lfs.github.com/spec/v1\noid sha256:{}\nsize {}\next-0-walrus {}\n",
-        sha256_hex,
-        file_size,
-        new_blob_id
-    );
+    let mut fields = vec![
+        format!("oid sha256:{}", sha256_hex),
+        format!("size {}", file_size),
+        format!("ext-0-walrus {}", new_blob_id),
+    ];
+    if git::config_flag("lfs.walrus.recordendepoch")
+        && let Some(end_epoch) = end_epoch
+    {
+        fields.push(format!("{} {}", END_EPOCH_EXTENSION, end_epoch));
+    }
+    let lfs_pointer = canonical_pointer(fields);
 
     // Write the new LFS pointer back to the file
     tokio::fs::write(file_path, lfs_pointer.as_bytes()).await?;
     
     Ok(())
 }
-
-async fn get_lfs_files() -> Result<Vec<PathBuf>> {
-    let output = Command::new("git")
-        .args(&["lfs", "ls-files", "--name-only"])
-        .output()
-        .await?;
-
-    if !output.status.success() {
-        return Err(anyhow::anyhow!(
-            "Failed to list LFS files: {}",
-            String::from_utf8_lossy(&output.stderr)
-        ));
-    }
-
-    let files_output = String::from_utf8(output.stdout)?;
-    let files: Vec<PathBuf> = files_output
-        .lines()
-        .filter(|line| !line.trim().is_empty())
-        .map(|line| PathBuf::from(line.trim()))
-        .collect();
-
-    Ok(files)
-}
-
-fn extract_walrus_blob_id(content: &str) -> Result<String> {
-    for line in content.lines() {
-        if line.starts_with("ext-0-walrus ") {
-            if let Some((_, blob_id)) = line.split_once(' ') {
-                return Ok(blob_id.trim().to_string());
-            }
-        }
-    }
-    Err(anyhow::anyhow!("No Walrus blob ID found in LFS pointer"))
-}
\ No newline at end of file
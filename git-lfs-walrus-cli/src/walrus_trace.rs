@@ -0,0 +1,80 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::path::Path;
+use std::process::Output;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Orders the invocations of one process whose timestamps collide.
+static SEQUENCE: AtomicUsize = AtomicUsize::new(0);
+
+/// How walrus was invoked, written as `<prefix>.request.json`.
+#[derive(Serialize)]
+struct Request<'a> {
+    command: &'a str,
+    args: Vec<String>,
+    /// The JSON written to walrus' stdin, for `walrus json` commands
+    stdin: Option<serde_json::Value>,
+    exit_code: Option<i32>,
+    /// Set when the command was stopped before finishing its response
+    aborted: Option<String>,
+}
+
+/// One walrus invocation as `--trace-walrus` records it.
+pub struct Invocation<'a> {
+    pub command: &'a str,
+    pub args: Vec<String>,
+    pub request: Option<&'a str>,
+    pub output: Option<&'a Output>,
+    pub aborted: Option<String>,
+}
+
+/// Writes `invocation` to `dir` as `<millis>-<pid>-<seq>-<command>.request.json`, with its
+/// verbatim stdout and stderr beside it in `.response` and `.stderr`. Nothing goes to our own
+/// stdout, which git and git-lfs read.
+pub fn record(dir: &Path, invocation: Invocation<'_>) {
+    if let Err(e) = write(dir, &invocation) {
+        eprintln!(
+            "Warning: could not write a walrus trace to {}: {:#}",
+            dir.display(),
+            e
+        );
+    }
+}
+
+fn write(dir: &Path, invocation: &Invocation<'_>) -> Result<()> {
+    std::fs::create_dir_all(dir)
+        .with_context(|| format!("Could not create {}", dir.display()))?;
+    let millis = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis());
+    let prefix = format!(
+        "{}-{}-{:04}-{}",
+        millis,
+        std::process::id(),
+        SEQUENCE.fetch_add(1, Ordering::Relaxed),
+        invocation.command.replace(' ', "-")
+    );
+
+    let request = Request {
+        command: invocation.command,
+        args: invocation.args.clone(),
+        // Kept as text if it somehow isn't JSON, so the trace is still verbatim
+        stdin: invocation.request.map(|request| {
+            serde_json::from_str(request)
+                .unwrap_or_else(|_| serde_json::Value::String(request.to_string()))
+        }),
+        exit_code: invocation.output.and_then(|output| output.status.code()),
+        aborted: invocation.aborted.clone(),
+    };
+    std::fs::write(
+        dir.join(format!("{}.request.json", prefix)),
+        serde_json::to_string_pretty(&request)?,
+    )?;
+    if let Some(output) = invocation.output {
+        std::fs::write(dir.join(format!("{}.response", prefix)), &output.stdout)?;
+        if !output.stderr.is_empty() {
+            std::fs::write(dir.join(format!("{}.stderr", prefix)), &output.stderr)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,119 @@
+#!/bin/sh
+# A stand-in for the walrus CLI used by the integration tests.
+#
+# Blobs are kept as files in $FAKE_WALRUS_STORE, named by their blob ID:
+# "fake-" followed by the SHA256 of their content, so blob IDs never equal OIDs.
+# Storing content that is already there reports it as already certified.
+# A blob's object ID is "obj-" followed by its blob ID, and the attributes set on
+# it are kept next to the store, one file per key.
+set -e
+
+store="${FAKE_WALRUS_STORE:?FAKE_WALRUS_STORE must be set}"
+attributes="$store-attributes"
+mkdir -p "$store"
+
+store_file() {
+    blob_id="fake-$(sha256sum "$1" | cut -d ' ' -f 1)"
+    if [ -f "$store/$blob_id" ]; then
+        printf '[{"blobStoreResult":{"alreadyCertified":{"blobId":"%s","endEpoch":100}}}]\n' "$blob_id"
+        return
+    fi
+    cp "$1" "$store/$blob_id"
+    printf '[{"blobStoreResult":{"newlyCreated":{"blobObject":{"id":"obj-%s","blobId":"%s"}}}}]\n' "$blob_id" "$blob_id"
+}
+
+case "$1" in
+    json)
+        request=$(cat)
+        read_id=$(printf '%s' "$request" | sed -n 's/.*"read":{"blobId":"\([^"]*\)".*/\1/p')
+        if [ -n "$read_id" ]; then
+            if [ ! -f "$store/$read_id" ]; then
+                echo "fake walrus: blob $read_id not found" >&2
+                exit 1
+            fi
+            printf '{"blobId":"%s","blob":"%s"}\n' "$read_id" "$(base64 -w0 < "$store/$read_id")"
+            exit 0
+        fi
+        file=$(printf '%s' "$request" | sed -n 's/.*"files":\["\([^"]*\)"\].*/\1/p')
+        if [ -z "$file" ]; then
+            echo "fake walrus: unsupported json request: $request" >&2
+            exit 1
+        fi
+        store_file "$file"
+        ;;
+    store)
+        # Only the dry run is invoked directly; real stores go through `json`
+        echo '[{"storageCost":0}]'
+        ;;
+    read)
+        blob_id="$2"
+        shift 2
+        while [ $# -gt 0 ]; do
+            case "$1" in
+                --out) out="$2"; shift 2 ;;
+                *) shift ;;
+            esac
+        done
+        if [ ! -f "$store/$blob_id" ]; then
+            echo "fake walrus: blob $blob_id not found" >&2
+            exit 1
+        fi
+        cp "$store/$blob_id" "$out"
+        ;;
+    delete)
+        while [ $# -gt 0 ]; do
+            case "$1" in
+                --blob-id) blob_id="$2"; shift 2 ;;
+                *) shift ;;
+            esac
+        done
+        if [ ! -f "$store/$blob_id" ]; then
+            echo "fake walrus: blob $blob_id not found" >&2
+            exit 1
+        fi
+        rm "$store/$blob_id"
+        ;;
+    blob-status)
+        # Stored blobs are certified and live until epoch 100
+        while [ $# -gt 0 ]; do
+            case "$1" in
+                --blob-id) blob_id="$2"; shift 2 ;;
+                *) shift ;;
+            esac
+        done
+        if [ ! -f "$store/$blob_id" ]; then
+            echo "fake walrus: blob $blob_id not found" >&2
+            exit 1
+        fi
+        size=$(wc -c < "$store/$blob_id")
+        printf '{"status":"permanent","blobObject":{"id":"obj-%s","storedEpoch":1,"blobId":"%s","size":%s,"certifiedEpoch":1,"storage":{"id":"0x2","startEpoch":1,"endEpoch":100,"storageSize":%s}}}\n' "$blob_id" "$blob_id" "$size" "$size"
+        ;;
+    set-blob-attribute)
+        object_id="$2"
+        shift 2
+        while [ $# -gt 0 ]; do
+            case "$1" in
+                --attr) mkdir -p "$attributes/$object_id"; printf '%s' "$3" > "$attributes/$object_id/$2"; shift 3 ;;
+                *) shift ;;
+            esac
+        done
+        ;;
+    get-blob-attribute)
+        # Values are printed as-is, so tests should stick to names without quotes
+        for arg in "$@"; do object_id="$arg"; done
+        if [ ! -d "$attributes/$object_id" ]; then
+            echo '{"attribute":null}'
+            exit 0
+        fi
+        contents=""
+        for file in "$attributes/$object_id"/*; do
+            entry=$(printf '{"key":"%s","value":"%s"}' "$(basename "$file")" "$(cat "$file")")
+            contents="${contents:+$contents,}$entry"
+        done
+        printf '{"attribute":{"metadata":{"contents":[%s]}}}\n' "$contents"
+        ;;
+    *)
+        echo "fake walrus: unsupported command: $*" >&2
+        exit 1
+        ;;
+esac
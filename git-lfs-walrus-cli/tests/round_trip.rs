@@ -0,0 +1,1481 @@
+//! Drives the clean and smudge filters through a real git repository, backed by
+//! `tests/fake_walrus.sh` instead of a Walrus network.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+use sha2::{Digest, Sha256};
+use tempfile::TempDir;
+
+const BIN: &str = env!("CARGO_BIN_EXE_git-lfs-walrus-cli");
+const FAKE_WALRUS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fake_walrus.sh");
+
+struct TestRepo {
+    _temp_dir: TempDir,
+    path: PathBuf,
+    store: PathBuf,
+}
+
+impl TestRepo {
+    /// Creates a repository whose `*.bin` files go through the walrus filter.
+    fn new() -> Self {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("repo");
+        let store = temp_dir.path().join("store");
+        std::fs::create_dir(&path).unwrap();
+
+        let repo = Self {
+            _temp_dir: temp_dir,
+            path,
+            store,
+        };
+        repo.git(&["init", "-q"]);
+        repo.git(&["config", "user.name", "Test"]);
+        repo.git(&["config", "user.email", "test@example.com"]);
+        repo.git(&["config", "filter.walrus.clean", &filter_command("clean")]);
+        repo.git(&["config", "filter.walrus.smudge", &filter_command("smudge")]);
+        repo.git(&["config", "filter.walrus.required", "true"]);
+        repo.write(".gitattributes", b"*.bin filter=walrus -text\n");
+        repo
+    }
+
+    fn git(&self, args: &[&str]) -> Output {
+        let output = Command::new("git")
+            .args(args)
+            .current_dir(&self.path)
+            .env("FAKE_WALRUS_STORE", &self.store)
+            .output()
+            .unwrap();
+        assert!(
+            output.status.success(),
+            "git {:?} failed: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        output
+    }
+
+    /// Runs git-lfs-walrus-cli in the repository against the fake walrus, without checking
+    /// that it succeeded.
+    fn cli_command(&self, args: &[&str], envs: &[(&str, &str)]) -> Output {
+        Command::new(BIN)
+            .arg("--walrus-path")
+            .arg(FAKE_WALRUS)
+            .args(args)
+            .current_dir(&self.path)
+            .env("FAKE_WALRUS_STORE", &self.store)
+            .envs(envs.iter().copied())
+            .output()
+            .unwrap()
+    }
+
+    /// Runs git-lfs-walrus-cli in the repository against the fake walrus.
+    fn cli(&self, args: &[&str]) -> Output {
+        let output = self.cli_command(args, &[]);
+        assert!(
+            output.status.success(),
+            "git-lfs-walrus-cli {:?} failed: {}",
+            args,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        output
+    }
+
+    /// Downloads one object through the transfer agent into the repository's working
+    /// directory, checking that it completed.
+    fn transfer_download(&self, oid: &str, size: usize, envs: &[(&str, &str)]) {
+        let mut child = Command::new(BIN)
+            .arg("--walrus-path")
+            .arg(FAKE_WALRUS)
+            .arg("transfer")
+            .current_dir(&self.path)
+            .env("FAKE_WALRUS_STORE", &self.store)
+            .envs(envs.iter().copied())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let events = format!(
+            "{}\n{}\n{}\n",
+            r#"{"event":"init","operation":"download","remote":"origin","concurrent":false}"#,
+            format_args!(r#"{{"event":"download","oid":"{}","size":{}}}"#, oid, size),
+            r#"{"event":"terminate"}"#
+        );
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(events.as_bytes())
+            .unwrap();
+        let output = child.wait_with_output().unwrap();
+        assert!(output.status.success());
+
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(!stdout.contains("\"error\""), "transfer failed: {}", stdout);
+    }
+
+    fn write(&self, name: &str, content: &[u8]) {
+        std::fs::write(self.path.join(name), content).unwrap();
+    }
+
+    fn read(&self, name: &str) -> Vec<u8> {
+        std::fs::read(self.path.join(name)).unwrap()
+    }
+
+    fn file(&self, name: &str) -> PathBuf {
+        self.path.join(name)
+    }
+
+    /// Clones the repository next to it with the walrus filter configured, so the checkout
+    /// goes through smudge. Extra `-c` settings are applied to the clone.
+    fn clone(&self, name: &str, config: &[&str]) -> PathBuf {
+        let clone_path = self.path.parent().unwrap().join(name);
+        let mut settings = vec![
+            format!("filter.walrus.clean={}", filter_command("clean")),
+            format!("filter.walrus.smudge={}", filter_command("smudge")),
+            "filter.walrus.required=true".to_string(),
+        ];
+        settings.extend(config.iter().map(|setting| setting.to_string()));
+
+        let mut args = vec!["clone".to_string(), "-q".to_string()];
+        for setting in settings {
+            args.push("-c".to_string());
+            args.push(setting);
+        }
+        args.push(self.path.display().to_string());
+        args.push(clone_path.display().to_string());
+
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        self.git(&args);
+        clone_path
+    }
+}
+
+fn filter_command(filter: &str) -> String {
+    format!("{} --walrus-path {} {} %f", BIN, FAKE_WALRUS, filter)
+}
+
+fn stored_blobs(store: &Path) -> usize {
+    std::fs::read_dir(store).map(|dir| dir.count()).unwrap_or(0)
+}
+
+#[test]
+fn clean_commit_smudge_round_trips_file_content() {
+    let repo = TestRepo::new();
+    let content = b"large file content that should live in walrus\n";
+    repo.write("large.bin", content);
+
+    repo.git(&["add", ".gitattributes", "large.bin"]);
+    repo.git(&["commit", "-q", "-m", "Add large file"]);
+
+    // The committed blob is a pointer, and the content went to the store
+    let pointer = String::from_utf8(repo.git(&["show", "HEAD:large.bin"]).stdout).unwrap();
+    assert!(pointer.starts_with("version https://git-lfs.github.com/spec/v1\n"));
+    assert!(pointer.contains(&format!("size {}\n", content.len())));
+    assert!(pointer.contains("ext-0-walrus "));
+    assert_eq!(stored_blobs(&repo.store), 1);
+
+    // Checking the file out again goes through smudge
+    std::fs::remove_file(repo.file("large.bin")).unwrap();
+    repo.git(&["checkout", "--", "large.bin"]);
+    assert_eq!(repo.read("large.bin"), content);
+}
+
+#[test]
+fn clean_writes_the_pointer_in_canonical_order() {
+    let repo = TestRepo::new();
+    repo.git(&["config", "lfs.walrus.contenttype", "true"]);
+    repo.write(".gitattributes", b"*.png filter=walrus -text\n");
+    repo.write("data.png", b"\x89PNG\r\n\x1a\n");
+    repo.git(&["add", ".gitattributes", "data.png"]);
+
+    let sha256 = "4c4b6a3be1314ab86138bef4314dde022e600960d8689a2c8f8631802d20dab6";
+    let pointer = String::from_utf8(repo.git(&["show", ":data.png"]).stdout).unwrap();
+    assert_eq!(
+        pointer,
+        format!(
+            "version https://git-lfs.github.com/spec/v1\n\
+             ext-0-walrus fake-{sha256}\n\
+             ext-2-walrus-content-type image/png\n\
+             oid sha256:{sha256}\n\
+             size 8\n"
+        )
+    );
+}
+
+#[test]
+fn pointer_sha_mapping_matches_the_pointer_git_stores_under_crlf() {
+    let repo = TestRepo::new();
+    repo.git(&["config", "lfs.walrus.pointereol", "crlf"]);
+    let mapped = |path: &str| {
+        let pointer = repo.git(&["show", &format!(":{}", path)]).stdout;
+        let mappings: serde_json::Value =
+            serde_json::from_slice(&repo.read(".git/walrus-mapping.json")).unwrap();
+        let pointer_sha = hex::encode(Sha256::digest(&pointer));
+        (String::from_utf8(pointer).unwrap(), mappings["entries"][&pointer_sha].is_object())
+    };
+
+    // A -text path is stored exactly as clean wrote it
+    repo.write("binary.bin", b"hello world");
+    repo.git(&["add", ".gitattributes", "binary.bin"]);
+    let (pointer, is_mapped) = mapped("binary.bin");
+    assert!(pointer.ends_with("size 11\r\n"), "{:?}", pointer);
+    assert!(is_mapped);
+    repo.git(&["commit", "-q", "-m", "crlf pointer"]);
+    std::fs::remove_file(repo.file("binary.bin")).unwrap();
+    repo.git(&["checkout", "--", "binary.bin"]);
+    assert_eq!(repo.read("binary.bin"), b"hello world");
+
+    // With core.autocrlf, git turns the pointer back into LF
+    repo.write(".gitattributes", b"*.txt filter=walrus\n");
+    repo.git(&["config", "core.autocrlf", "true"]);
+    repo.write("text.txt", b"text content");
+    repo.git(&["add", ".gitattributes", "text.txt"]);
+    let (pointer, is_mapped) = mapped("text.txt");
+    assert!(!pointer.contains('\r'), "{:?}", pointer);
+    assert!(is_mapped);
+}
+
+#[test]
+fn clean_emits_the_pointer_to_a_file_for_debugging() {
+    let repo = TestRepo::new();
+    repo.write("data.bin", b"hello world");
+
+    let output = repo.cli(&["clean", "data.bin", "--emit-pointer", "data.pointer"]);
+    assert!(output.stdout.is_empty());
+
+    let sha256 = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+    let pointer = String::from_utf8(repo.read("data.pointer")).unwrap();
+    assert!(pointer.contains(&format!("oid sha256:{sha256}\n")), "{}", pointer);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains(&format!("Blob ID: fake-{sha256}")), "{}", stderr);
+    assert!(stderr.contains("Estimated cost: 0 FROST"), "{}", stderr);
+}
+
+#[test]
+fn clean_refuses_content_over_the_configured_size_unless_allowed() {
+    let repo = TestRepo::new();
+    repo.git(&["config", "lfs.walrus.maxcleanbytes", "10"]);
+    repo.write("small.bin", b"tiny\n");
+    repo.write("huge.bin", b"more than ten bytes\n");
+
+    repo.cli(&["clean", "small.bin", "--emit-pointer", "small.pointer"]);
+    let refused = repo.cli_command(&["clean", "huge.bin", "--emit-pointer", "huge.pointer"], &[]);
+    assert!(!refused.status.success());
+    let stderr = String::from_utf8_lossy(&refused.stderr);
+    assert!(stderr.contains("huge.bin") && stderr.contains("lfs.walrus.maxcleanbytes"), "{}", stderr);
+    assert_eq!(stored_blobs(&repo.store), 1);
+
+    let allowed = repo.cli_command(
+        &["clean", "huge.bin", "--emit-pointer", "huge.pointer"],
+        &[("LFS_WALRUS_ALLOW_LARGE_CLEAN", "1")],
+    );
+    assert!(allowed.status.success(), "{}", String::from_utf8_lossy(&allowed.stderr));
+    assert_eq!(stored_blobs(&repo.store), 2);
+}
+
+#[test]
+fn cat_writes_committed_file_content() {
+    let repo = TestRepo::new();
+    let content = b"content fetched without a checkout\n";
+    repo.write("asset.bin", content);
+    repo.git(&["add", ".gitattributes", "asset.bin"]);
+    repo.git(&["commit", "-q", "-m", "Add asset"]);
+
+    assert_eq!(repo.cli(&["cat", "asset.bin"]).stdout, content);
+
+    repo.cli(&["cat", "asset.bin", "--output", "copy.out"]);
+    assert_eq!(repo.read("copy.out"), content);
+}
+
+#[test]
+fn cat_output_does_not_clobber_local_changes() {
+    let repo = TestRepo::new();
+    let content = b"committed asset content\n";
+    repo.write("asset.bin", content);
+    repo.git(&["add", ".gitattributes", "asset.bin"]);
+    repo.git(&["commit", "-q", "-m", "Add asset"]);
+
+    // The checked-out copy already matches, so rewriting it is harmless
+    repo.cli(&["cat", "asset.bin", "--output", "asset.bin"]);
+    assert_eq!(repo.read("asset.bin"), content);
+
+    repo.write("asset.bin", b"local edits not yet cleaned\n");
+    let output = repo.cli_command(&["cat", "asset.bin", "--output", "asset.bin"], &[]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("refusing to overwrite asset.bin"), "{}", stderr);
+    assert_eq!(repo.read("asset.bin"), b"local edits not yet cleaned\n");
+
+    repo.cli(&["cat", "asset.bin", "--output", "asset.bin", "--force"]);
+    assert_eq!(repo.read("asset.bin"), content);
+}
+
+#[test]
+fn walrus_blob_id_formats_for_scripts() {
+    let repo = TestRepo::new();
+    let sha256 = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+    repo.write("asset.bin", b"hello world");
+    repo.git(&["add", ".gitattributes", "asset.bin"]);
+    repo.git(&["commit", "-q", "-m", "Add asset"]);
+    let blob_id = format!("fake-{sha256}");
+
+    let stdout = repo.cli(&["walrus-blob-id", "--format", "id", "asset.bin"]).stdout;
+    assert_eq!(String::from_utf8(stdout).unwrap(), format!("{}\n", blob_id));
+
+    let stdout = repo.cli(&["walrus-blob-id", "--format", "json", "asset.bin"]).stdout;
+    let report: serde_json::Value = serde_json::from_slice(&stdout).unwrap();
+    assert_eq!(report["blob_id"], blob_id.as_str());
+    assert_eq!(report["sha256"], sha256);
+
+    // Nothing was stored for an untracked file: no output to capture, and a failing exit
+    repo.write("loose.bin", b"never cleaned\n");
+    let output = repo.cli_command(&["walrus-blob-id", "--format", "id", "loose.bin"], &[]);
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn pointer_repair_restores_the_inline_blob_id() {
+    let repo = TestRepo::new();
+    repo.write("asset.bin", b"asset whose pointer lost its walrus line\n");
+    repo.git(&["add", ".gitattributes", "asset.bin"]);
+    repo.git(&["commit", "-q", "-m", "Add asset"]);
+
+    let pointer = String::from_utf8(repo.git(&["show", "HEAD:asset.bin"]).stdout).unwrap();
+    let stripped: String = pointer
+        .lines()
+        .filter(|line| !line.starts_with("ext-0-walrus "))
+        .map(|line| format!("{}\n", line))
+        .collect();
+    repo.write("asset.bin", stripped.as_bytes());
+
+    repo.cli(&["pointer-repair", "asset.bin"]);
+    assert_eq!(String::from_utf8(repo.read("asset.bin")).unwrap(), pointer);
+}
+
+#[test]
+fn offline_mode_refuses_downloads() {
+    let repo = TestRepo::new();
+    repo.write("asset.bin", b"content that can't be fetched offline\n");
+    repo.git(&["add", ".gitattributes", "asset.bin"]);
+    repo.git(&["commit", "-q", "-m", "Add asset"]);
+
+    let output = repo.cli_command(&["cat", "asset.bin"], &[("LFS_WALRUS_OFFLINE", "1")]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("offline mode"));
+}
+
+#[test]
+fn self_contained_clone_round_trips_without_a_mapping_file() {
+    let repo = TestRepo::new();
+    repo.git(&["config", "lfs.walrus.selfcontained", "true"]);
+    let content = b"content recoverable from the pointer alone\n";
+    repo.write("asset.bin", content);
+    repo.git(&["add", ".gitattributes", "asset.bin"]);
+    repo.git(&["commit", "-q", "-m", "Add asset"]);
+
+    let clone = repo.clone("clone", &["lfs.walrus.selfcontained=true"]);
+
+    assert!(!clone.join(".git/walrus-mapping.json").exists());
+    assert_eq!(std::fs::read(clone.join("asset.bin")).unwrap(), content);
+}
+
+#[test]
+fn transfer_download_resolves_blob_id_from_the_staged_pointer() {
+    let repo = TestRepo::new();
+    let content = b"content downloaded by the transfer agent\n";
+    repo.write("asset.bin", content);
+    repo.git(&["add", ".gitattributes", "asset.bin"]);
+    repo.git(&["commit", "-q", "-m", "Add asset"]);
+
+    // Without the mapping file, only the staged pointer knows the blob ID
+    std::fs::remove_file(repo.file(".git/walrus-mapping.json")).unwrap();
+    let pointer = String::from_utf8(repo.git(&["show", ":asset.bin"]).stdout).unwrap();
+    let oid = pointer
+        .lines()
+        .find_map(|line| line.strip_prefix("oid sha256:"))
+        .unwrap_or_else(|| panic!("not a pointer: {:?}", pointer));
+
+    repo.transfer_download(oid, content.len(), &[]);
+    assert_eq!(repo.read(oid), content);
+}
+
+#[test]
+fn offline_transfer_download_serves_prefetched_content() {
+    let repo = TestRepo::new();
+    let content = b"content prefetched before going offline\n";
+    repo.write("asset.bin", content);
+    repo.git(&["add", ".gitattributes", "asset.bin"]);
+    repo.git(&["commit", "-q", "-m", "Add asset"]);
+    repo.cli(&["prefetch"]);
+    let oid = hex::encode(Sha256::digest(content));
+
+    std::fs::remove_dir_all(&repo.store).unwrap();
+    repo.transfer_download(&oid, content.len(), &[("LFS_WALRUS_OFFLINE", "1")]);
+    assert_eq!(repo.read(&oid), content);
+}
+
+#[cfg(unix)]
+#[test]
+fn preserved_mode_is_restored_after_checkout() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let repo = TestRepo::new();
+    repo.git(&["config", "lfs.walrus.preservemode", "true"]);
+    repo.write("secret.bin", b"content only its owner group may read\n");
+    let mode = |repo: &TestRepo| {
+        std::fs::metadata(repo.file("secret.bin")).unwrap().permissions().mode() & 0o7777
+    };
+    std::fs::set_permissions(repo.file("secret.bin"), PermissionsExt::from_mode(0o640)).unwrap();
+    repo.git(&["add", ".gitattributes", "secret.bin"]);
+    repo.git(&["commit", "-q", "-m", "Add secret"]);
+
+    let pointer = String::from_utf8(repo.git(&["show", "HEAD:secret.bin"]).stdout).unwrap();
+    assert!(pointer.contains("\next-1-walrus-mode 0640\noid "), "{}", pointer);
+
+    std::fs::remove_file(repo.file("secret.bin")).unwrap();
+    repo.git(&["checkout", "--", "secret.bin"]);
+    repo.cli(&["restore-modes"]);
+    assert_eq!(mode(&repo), 0o640);
+}
+
+#[test]
+fn content_type_is_recorded_and_reported_by_cat() {
+    let repo = TestRepo::new();
+    repo.git(&["config", "lfs.walrus.contenttype", "true"]);
+    repo.write(".gitattributes", b"*.bin filter=walrus -text\n*.png filter=walrus -text\n");
+    repo.write("logo.png", b"\x89PNG\r\n\x1a\nnot really an image\n");
+    repo.git(&["add", ".gitattributes", "logo.png"]);
+    repo.git(&["commit", "-q", "-m", "Add logo"]);
+
+    let pointer = String::from_utf8(repo.git(&["show", "HEAD:logo.png"]).stdout).unwrap();
+    assert!(pointer.contains("\next-2-walrus-content-type image/png\noid "), "{}", pointer);
+    assert_eq!(repo.cli(&["cat", "logo.png", "--content-type"]).stdout, b"image/png\n");
+}
+
+#[test]
+fn mapping_audit_reports_and_fixes_drift() {
+    let repo = TestRepo::new();
+    repo.write("asset.bin", b"content whose mapping entry drifted\n");
+    repo.git(&["add", ".gitattributes", "asset.bin"]);
+    repo.git(&["commit", "-q", "-m", "Add asset"]);
+
+    let pointer = String::from_utf8(repo.git(&["show", ":asset.bin"]).stdout).unwrap();
+    let blob_id = pointer
+        .lines()
+        .find_map(|line| line.strip_prefix("ext-0-walrus "))
+        .unwrap()
+        .to_string();
+    let sha256 = pointer
+        .lines()
+        .find_map(|line| line.strip_prefix("oid sha256:"))
+        .unwrap();
+    repo.cli(&["mapping-set", sha256, "stale-blob-id"]);
+
+    let output = repo.cli_command(&["mapping-audit", "asset.bin"], &[]);
+    assert!(!output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains(&format!("Pointer has {}, mapping has stale-blob-id", blob_id)));
+
+    repo.cli(&["mapping-audit", "asset.bin", "--fix", "--prefer", "pointer"]);
+    let mapping = String::from_utf8(repo.read(".git/walrus-mapping.json")).unwrap();
+    assert!(!mapping.contains("stale-blob-id"));
+    repo.cli(&["mapping-audit", "asset.bin"]);
+}
+
+#[test]
+fn migrate_records_every_mapping_and_skips_repeated_content() {
+    let repo = TestRepo::new();
+    repo.write("a.bin", b"first file to migrate\n");
+    repo.write("b.bin", b"second file to migrate\n");
+    repo.write("c.bin", b"first file to migrate\n");
+
+    let output = repo.cli(&["migrate", "a.bin", "b.bin", "c.bin"]);
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("c.bin - Already in Walrus"), "{}", stdout);
+    assert_eq!(stored_blobs(&repo.store), 2);
+    let mapping: serde_json::Value =
+        serde_json::from_slice(&repo.read(".git/walrus-mapping.json")).unwrap();
+    assert_eq!(mapping["entries"].as_object().unwrap().len(), 2);
+}
+
+#[test]
+fn migrate_plan_reports_uploads_and_skips_without_storing_anything() {
+    let repo = TestRepo::new();
+    repo.write("stored.bin", b"migrated earlier\n");
+    repo.cli(&["migrate", "stored.bin"]);
+    repo.write("a.bin", b"first file to migrate\n");
+    repo.write("b.bin", b"first file to migrate\n");
+    repo.write(
+        "pointer.bin",
+        b"version https://git-lfs.github.com/spec/v1\noid sha256:0000\nsize 1\n",
+    );
+    let files = ["a.bin", "b.bin", "stored.bin", "pointer.bin"];
+
+    let mut args = vec!["migrate", "--plan"];
+    args.extend(files);
+    let stdout = String::from_utf8(repo.cli(&args).stdout).unwrap();
+    assert!(stdout.contains("a.bin - Would upload (22 B, est. cost"), "{}", stdout);
+    assert!(stdout.contains("b.bin - Same content as a.bin, uploaded once"), "{}", stdout);
+    assert!(stdout.contains("stored.bin - Already in Walrus"), "{}", stdout);
+    assert!(stdout.contains("pointer.bin - Would skip"), "{}", stdout);
+    assert!(stdout.contains("Would upload 1 files, 22 B"), "{}", stdout);
+    assert!(stdout.contains("Skipped: 3"), "{}", stdout);
+
+    args.insert(2, "--json");
+    let plan: serde_json::Value = serde_json::from_slice(&repo.cli(&args).stdout).unwrap();
+    let actions: Vec<&str> = plan["files"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|file| file["action"].as_str().unwrap())
+        .collect();
+    assert_eq!(actions, ["upload", "duplicate", "already_stored", "not_checked_out"]);
+    assert_eq!(plan["upload_bytes"], 22);
+
+    // Only the earlier real run stored anything
+    assert_eq!(stored_blobs(&repo.store), 1);
+    let mapping: serde_json::Value =
+        serde_json::from_slice(&repo.read(".git/walrus-mapping.json")).unwrap();
+    assert_eq!(mapping["entries"].as_object().unwrap().len(), 1);
+}
+
+#[test]
+fn prefetched_content_is_smudged_without_walrus() {
+    let repo = TestRepo::new();
+    let content = b"content fetched ahead of checkout\n";
+    repo.write("a.bin", content);
+    repo.write("b.bin", b"another prefetched file\n");
+    repo.git(&["add", ".gitattributes", "a.bin", "b.bin"]);
+    repo.git(&["commit", "-q", "-m", "Add assets"]);
+
+    let output = repo.cli(&["prefetch"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("Fetched: 2"), "{}", stdout);
+    assert!(!repo.file(".git/walrus-prefetch.lock").exists());
+
+    // With the store gone, only the cache can provide the content
+    std::fs::remove_dir_all(&repo.store).unwrap();
+    std::fs::remove_file(repo.file("a.bin")).unwrap();
+    repo.git(&["checkout", "--", "a.bin"]);
+    assert_eq!(repo.read("a.bin"), content);
+}
+
+#[test]
+fn metrics_report_the_prefetch_cache_hit_rate() {
+    let repo = TestRepo::new();
+    let hello = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+    let test = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+    repo.write("hello.bin", b"hello world");
+    repo.git(&["add", ".gitattributes", "hello.bin"]);
+    repo.git(&["commit", "-q", "-m", "Add hello"]);
+    repo.cli(&["prefetch"]);
+    repo.write("test.bin", b"test");
+    repo.git(&["add", "test.bin"]);
+    repo.git(&["commit", "-q", "-m", "Add test"]);
+
+    let cache_summary = |oid: &str| {
+        repo.cli(&["--metrics", "metrics.json", "download", &format!("sha256:{}", oid)]);
+        let summary: serde_json::Value =
+            serde_json::from_slice(&repo.read("metrics.json")).unwrap();
+        summary["cache"].clone()
+    };
+
+    let cache = cache_summary(hello);
+    assert_eq!((cache["hits"].as_u64(), cache["misses"].as_u64()), (Some(1), Some(0)));
+    assert_eq!(cache["hit_rate_percent"], 100.0);
+
+    // Content that was never prefetched is read from Walrus
+    let cache = cache_summary(test);
+    assert_eq!((cache["hits"].as_u64(), cache["misses"].as_u64()), (Some(0), Some(1)));
+    assert_eq!(cache["hit_rate_percent"], 0.0);
+}
+
+#[test]
+fn cache_clean_evicts_old_then_least_recently_used_entries() {
+    let repo = TestRepo::new();
+    repo.write("a.bin", &[b'a'; 100]);
+    repo.write("b.bin", &[b'b'; 200]);
+    repo.write("c.bin", &[b'c'; 300]);
+    repo.git(&["add", ".gitattributes", "a.bin", "b.bin", "c.bin"]);
+    repo.git(&["commit", "-q", "-m", "Add assets"]);
+    repo.cli(&["prefetch"]);
+
+    // Last used: c.bin two days ago, b.bin an hour ago, a.bin now
+    let cache = repo.file(".git/walrus-cache");
+    let entry_of_size = |size: u64| {
+        std::fs::read_dir(&cache)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .find(|path| std::fs::metadata(path).unwrap().len() == size)
+    };
+    let now = std::time::SystemTime::now();
+    let set_last_used = |size: u64, ago: u64| {
+        std::fs::File::options()
+            .write(true)
+            .open(entry_of_size(size).unwrap())
+            .unwrap()
+            .set_modified(now - std::time::Duration::from_secs(ago))
+            .unwrap();
+    };
+    set_last_used(300, 2 * 24 * 60 * 60);
+    set_last_used(200, 60 * 60);
+
+    let output = repo.cli(&["cache-clean", "--max-age", "1d"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("Bytes reclaimed: 300"), "{}", stdout);
+    assert!(entry_of_size(300).is_none());
+
+    let output = repo.cli(&["cache-clean", "--max-size", "150"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("Bytes reclaimed: 200"), "{}", stdout);
+    assert!(stdout.contains("Remaining: 1 (100 bytes)"), "{}", stdout);
+
+    let output = repo.cli(&["cache-clean", "--all"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("Bytes reclaimed: 100"), "{}", stdout);
+    assert_eq!(std::fs::read_dir(&cache).unwrap().count(), 0);
+}
+
+#[test]
+fn check_reuses_recent_statuses_within_the_cache_ttl() {
+    let repo = TestRepo::new();
+    repo.git(&["config", "lfs.walrus.checkcachettl", "1h"]);
+    repo.write("data.bin", b"content checked hourly\n");
+    repo.git(&["add", ".gitattributes", "data.bin"]);
+    repo.git(&["commit", "-q", "-m", "Add data"]);
+
+    let stdout = String::from_utf8(repo.cli(&["walrus-check", "data.bin"]).stdout).unwrap();
+    assert!(stdout.contains("✅ data.bin - Status: permanent"), "{}", stdout);
+    assert!(!stdout.contains("cached"), "{}", stdout);
+
+    // With the blob gone, a cached run still reports the earlier result
+    for blob in std::fs::read_dir(&repo.store).unwrap() {
+        std::fs::remove_file(blob.unwrap().path()).unwrap();
+    }
+    let stdout = String::from_utf8(repo.cli(&["walrus-check", "data.bin"]).stdout).unwrap();
+    assert!(stdout.contains("Status: permanent"), "{}", stdout);
+    assert!(stdout.contains("(cached, checked"), "{}", stdout);
+
+    let stdout =
+        String::from_utf8(repo.cli(&["walrus-check", "--no-cache", "data.bin"]).stdout).unwrap();
+    assert!(stdout.contains("Blob not found in Walrus"), "{}", stdout);
+}
+
+#[test]
+fn trace_walrus_records_requests_and_verbatim_responses() {
+    let hello = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+    let repo = TestRepo::new();
+    repo.git(&["config", "lfs.walrus.readmode", "json"]);
+    repo.write("hello.bin", b"hello world");
+    repo.git(&["add", ".gitattributes", "hello.bin"]);
+    repo.git(&["commit", "-q", "-m", "Add hello"]);
+
+    let output = repo.cli(&["--trace-walrus", "trace", "download", &format!("sha256:{}", hello)]);
+    // Traces never mix into the content on stdout
+    assert_eq!(output.stdout, b"hello world");
+    repo.cli(&["--trace-walrus", "trace", "walrus-check", "hello.bin"]);
+
+    let mut names: Vec<String> = std::fs::read_dir(repo.file("trace"))
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    let trace = |suffix: &str| {
+        let name = names.iter().find(|name| name.ends_with(suffix)).unwrap();
+        std::fs::read(repo.file("trace").join(name)).unwrap()
+    };
+
+    let read: serde_json::Value = serde_json::from_slice(&trace("-read.request.json")).unwrap();
+    assert_eq!(read["args"], serde_json::json!(["json"]));
+    assert_eq!(read["stdin"]["command"]["read"]["blobId"], format!("fake-{}", hello));
+    assert_eq!(read["exit_code"], 0);
+    assert!(String::from_utf8(trace("-read.response")).unwrap().contains("\"blob\""));
+
+    let status: serde_json::Value =
+        serde_json::from_slice(&trace("-blob-status.request.json")).unwrap();
+    assert_eq!(status["args"][0], "blob-status");
+    assert!(status["stdin"].is_null());
+    assert!(String::from_utf8(trace("-blob-status.response")).unwrap().contains("permanent"));
+}
+
+#[test]
+fn stored_blobs_are_tagged_with_their_filename_or_an_explicit_tag() {
+    let repo = TestRepo::new();
+    repo.git(&["config", "lfs.walrus.tagfilenames", "true"]);
+    repo.write("model.bin", b"weights, version one\n");
+    repo.git(&["add", ".gitattributes", "model.bin"]);
+    repo.git(&["commit", "-q", "-m", "Add model"]);
+    let check = |file| {
+        String::from_utf8(repo.cli(&["walrus-check", "--show-tags", file]).stdout).unwrap()
+    };
+
+    let stdout = check("model.bin");
+    assert!(stdout.contains("| Tag: model.bin"), "{}", stdout);
+
+    // --tag takes precedence over the filename
+    repo.write("release.bin", b"release artifact\n");
+    let args = ["clean", "--tag", "v1.0", "--emit-pointer", "release.pointer", "release.bin"];
+    repo.cli(&args);
+    let stdout = check("release.pointer");
+    assert!(stdout.contains("| Tag: v1.0"), "{}", stdout);
+
+    repo.git(&["config", "lfs.walrus.tagfilenames", "false"]);
+    repo.write("plain.bin", b"untagged content\n");
+    repo.cli(&["clean", "--emit-pointer", "plain.pointer", "plain.bin"]);
+    let stdout = check("plain.pointer");
+    assert!(stdout.contains("| Tag: (none)"), "{}", stdout);
+}
+
+#[test]
+fn requiremapping_fails_clean_when_the_mapping_cannot_be_written() {
+    let repo = TestRepo::new();
+    let mapping = repo.path.join(".git/walrus-mapping.json");
+    let _ = std::fs::remove_file(&mapping);
+    std::fs::create_dir(&mapping).unwrap();
+    repo.write("data.bin", b"content needing a mapping\n");
+    let args = ["clean", "--emit-pointer", "data.pointer", "data.bin"];
+
+    // By default the pointer is still written, with a warning
+    let output = repo.cli(&args);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Warning: Could not store blob mapping"), "{}", stderr);
+
+    repo.git(&["config", "lfs.walrus.requiremapping", "true"]);
+    std::fs::remove_file(repo.path.join("data.pointer")).unwrap();
+    let output = repo.cli_command(&args, &[]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("lfs.walrus.requiremapping is set"), "{}", stderr);
+    assert!(std::fs::read(repo.path.join("data.pointer")).unwrap_or_default().is_empty());
+
+    let mut args = args.to_vec();
+    args.insert(1, "--continue-on-mapping-error");
+    repo.cli(&args);
+    assert!(String::from_utf8(repo.read("data.pointer")).unwrap().contains("ext-0-walrus "));
+}
+
+#[test]
+fn reconcile_pending_adopts_or_deletes_blobs_a_failed_clean_left_behind() {
+    let repo = TestRepo::new();
+    let pending = repo.path.join(".git/walrus-pending.jsonl");
+    let mapping = repo.path.join(".git/walrus-mapping.json");
+    repo.write("kept.bin", b"hello world");
+    repo.cli(&["clean", "--emit-pointer", "kept.pointer", "kept.bin"]);
+    assert!(!pending.exists());
+
+    // Stored, but the clean fails before anything refers to the blob
+    let _ = std::fs::remove_file(&mapping);
+    std::fs::create_dir(&mapping).unwrap();
+    repo.git(&["config", "lfs.walrus.requiremapping", "true"]);
+    let fail_clean = |name: &str, content: &[u8]| {
+        repo.write(name, content);
+        let output = repo.cli_command(&["clean", "--emit-pointer", "out.pointer", name], &[]);
+        assert!(!output.status.success());
+    };
+    fail_clean("adopted.bin", b"adopted content\n");
+    fail_clean("deleted.bin", b"deleted content\n");
+    // Content walrus already had isn't this run's to clean up
+    fail_clean("again.bin", b"hello world");
+    std::fs::remove_dir(&mapping).unwrap();
+    let adopted_oid = "30dd0dfeae23c73b760755cf4c773d440d758e682986c9092c67efa4789ebfbb";
+    let records = std::fs::read_to_string(&pending).unwrap();
+    assert_eq!(records.lines().count(), 2, "{}", records);
+
+    let stdout = String::from_utf8(repo.cli(&["reconcile-pending"]).stdout).unwrap();
+    assert!(stdout.contains("Unreferenced: 2"), "{}", stdout);
+    assert!(stdout.contains("| Status: permanent"), "{}", stdout);
+
+    // Deleting one leaves the other pending
+    let deleted_id = records.lines().nth(1).unwrap();
+    let deleted_id = serde_json::from_str::<serde_json::Value>(deleted_id).unwrap()["blob_id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    std::fs::write(&pending, format!("{}\n", records.lines().nth(1).unwrap())).unwrap();
+    let stdout = String::from_utf8(repo.cli(&["reconcile-pending", "--delete"]).stdout).unwrap();
+    assert!(stdout.contains("Deleted: 1"), "{}", stdout);
+    assert!(!repo.store.join(&deleted_id).exists());
+    assert!(!pending.exists());
+
+    std::fs::write(&pending, format!("{}\n", records.lines().next().unwrap())).unwrap();
+    let stdout = String::from_utf8(repo.cli(&["reconcile-pending", "--adopt"]).stdout).unwrap();
+    assert!(stdout.contains(&format!("Adopted as {}", adopted_oid)), "{}", stdout);
+    assert!(!pending.exists());
+    let mappings: serde_json::Value =
+        serde_json::from_slice(&repo.read(".git/walrus-mapping.json")).unwrap();
+    assert_eq!(mappings["entries"][adopted_oid]["blob_id"], format!("fake-{}", adopted_oid));
+
+    // Once mapped, a leftover record is just resolved
+    std::fs::write(&pending, records).unwrap();
+    let stdout = String::from_utf8(repo.cli(&["reconcile-pending"]).stdout).unwrap();
+    assert!(stdout.contains("Referenced: 1") && stdout.contains("No longer in Walrus: 1"), "{}", stdout);
+    assert!(!pending.exists());
+}
+
+#[test]
+fn refresh_reports_and_logs_each_blob_id_change() {
+    let repo = TestRepo::new();
+    repo.write(
+        "pointer.txt",
+        b"version https://git-lfs.github.com/spec/v1\noid sha256:b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9\nsize 11\next-0-walrus fake-old\n",
+    );
+
+    let output = repo.cli(&["walrus-refresh", "--json", "--log", "pointer.txt"]);
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["refreshed"], 1);
+    let change = &report["changes"][0];
+    assert_eq!(change["file"], "pointer.txt");
+    assert_eq!(change["old_blob_id"], "fake-old");
+    let new_blob_id = change["new_blob_id"].as_str().unwrap();
+    assert!(new_blob_id.starts_with("fake-"), "{}", new_blob_id);
+    assert!(String::from_utf8(repo.read("pointer.txt")).unwrap().contains(new_blob_id));
+
+    let log = String::from_utf8(repo.read(".git/walrus-refresh-log.jsonl")).unwrap();
+    let line: serde_json::Value = serde_json::from_str(log.lines().next().unwrap()).unwrap();
+    assert_eq!(line["old_blob_id"], "fake-old");
+    assert_eq!(line["new_blob_id"], new_blob_id);
+    assert!(line["timestamp_ms"].is_u64());
+
+    // A re-run finds the new blob live and stores nothing, unless forced
+    let blobs = stored_blobs(&repo.store);
+    let output = repo.cli(&["walrus-refresh", "--json", "--log", "pointer.txt"]);
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["refreshed"], 0);
+    assert_eq!(report["skipped"], 1);
+    assert_eq!(stored_blobs(&repo.store), blobs);
+    assert_eq!(repo.read(".git/walrus-refresh-log.jsonl").iter().filter(|&&b| b == b'\n').count(), 1);
+
+    let output = repo.cli(&["walrus-refresh", "--json", "--force", "pointer.txt"]);
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["refreshed"], 1);
+}
+
+#[test]
+fn first_smudge_of_a_clone_starts_a_background_prefetch() {
+    let repo = TestRepo::new();
+    for name in ["a.bin", "b.bin", "c.bin"] {
+        repo.write(name, format!("content of {}\n", name).as_bytes());
+    }
+    repo.git(&["add", ".gitattributes", "a.bin", "b.bin", "c.bin"]);
+    repo.git(&["commit", "-q", "-m", "Add assets"]);
+
+    let clone = repo.clone("clone", &["lfs.walrus.prefetch=true"]);
+
+    let cache = clone.join(".git/walrus-cache");
+    // Partial downloads are dot-files next to the finished entries
+    let cached = || {
+        std::fs::read_dir(&cache)
+            .map(|dir| {
+                dir.filter_map(Result::ok)
+                    .filter(|entry| !entry.file_name().to_string_lossy().starts_with('.'))
+                    .count()
+            })
+            .unwrap_or(0)
+    };
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(30);
+    while cached() < 3 && std::time::Instant::now() < deadline {
+        std::thread::sleep(std::time::Duration::from_millis(100));
+    }
+    assert_eq!(cached(), 3);
+    assert_eq!(std::fs::read(clone.join("b.bin")).unwrap(), b"content of b.bin\n");
+}
+
+#[test]
+fn migrate_resumes_after_the_last_completed_file() {
+    let repo = TestRepo::new();
+    repo.write("a.bin", b"migrated before the failure\n");
+    // A directory can't be read, so the first run stops making progress here
+    std::fs::create_dir(repo.file("b.bin")).unwrap();
+    repo.write("c.bin", b"migrated after the failure\n");
+
+    let output = repo.cli(&["migrate", "a.bin", "b.bin", "c.bin"]);
+    assert!(String::from_utf8(output.stdout).unwrap().contains("Errors: 1"));
+    let state: serde_json::Value =
+        serde_json::from_slice(&repo.read(".git/walrus-migrate-state.json")).unwrap();
+    assert_eq!(state["last_completed"], "a.bin");
+
+    std::fs::remove_dir(repo.file("b.bin")).unwrap();
+    repo.write("b.bin", b"fixed before the rerun\n");
+    let output = repo.cli(&["migrate", "a.bin", "b.bin", "c.bin"]);
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("Resuming after a.bin"), "{}", stdout);
+    assert!(!stdout.contains("a.bin -"), "{}", stdout);
+    assert!(stdout.contains("b.bin - Uploaded"), "{}", stdout);
+    assert!(stdout.contains("c.bin - Already in Walrus"), "{}", stdout);
+    assert_eq!(stored_blobs(&repo.store), 3);
+    assert!(!repo.file(".git/walrus-migrate-state.json").exists());
+}
+
+#[test]
+fn migrate_balance_preflight_aborts_before_uploading() {
+    let repo = TestRepo::new();
+    repo.git(&["config", "lfs.walrus.suipath", "/nonexistent/sui"]);
+    repo.write("a.bin", b"content that must not be uploaded\n");
+
+    let output = repo.cli_command(&["migrate", "--check-balance", "a.bin"], &[]);
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("wallet balance"));
+    assert_eq!(stored_blobs(&repo.store), 0);
+}
+
+#[test]
+fn download_streams_a_blob_by_blob_id_or_oid() {
+    let repo = TestRepo::new();
+    let content = b"content fetched without a transfer session\n";
+    repo.write("asset.bin", content);
+    repo.git(&["add", ".gitattributes", "asset.bin"]);
+
+    let pointer = String::from_utf8(repo.git(&["show", ":asset.bin"]).stdout).unwrap();
+    let field = |key: &str| {
+        pointer
+            .lines()
+            .find_map(|line| line.strip_prefix(key))
+            .unwrap()
+            .to_string()
+    };
+    let oid = field("oid ");
+    let blob_id = field("ext-0-walrus ");
+
+    assert_eq!(repo.cli(&["download", &blob_id]).stdout, content);
+    assert_eq!(repo.cli(&["download", &oid]).stdout, content);
+    let bare_hash = oid.trim_start_matches("sha256:");
+    assert_eq!(repo.cli(&["download", bare_hash]).stdout, content);
+
+    let unknown = repo.cli_command(&["download", &format!("sha256:{}", "0".repeat(64))], &[]);
+    assert!(!unknown.status.success());
+    assert!(String::from_utf8_lossy(&unknown.stderr).contains("No Walrus blob ID found"));
+}
+
+#[test]
+fn failures_are_appended_to_the_error_log_when_asked() {
+    let repo = TestRepo::new();
+    let log = repo.file("errors.jsonl");
+
+    let failed = repo.cli_command(&["download", "fake-missing"], &[]);
+    assert!(!failed.status.success());
+    assert!(!log.exists());
+
+    let log_env = [("LFS_WALRUS_ERRORLOG", log.to_str().unwrap())];
+    let failed = repo.cli_command(&["download", "fake-missing"], &log_env);
+    assert!(!failed.status.success());
+
+    let content = std::fs::read_to_string(&log).unwrap();
+    let records: Vec<serde_json::Value> = content
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(records.len(), 1, "{}", content);
+    assert_eq!(records[0]["operation"], "download");
+    assert_eq!(records[0]["kind"], "command_failed");
+    assert_eq!(records[0]["walrus_command"], "read");
+    assert!(
+        records[0]["stderr"].as_str().unwrap().contains("blob fake-missing not found"),
+        "{}",
+        content
+    );
+}
+
+#[test]
+fn mapping_snapshot_round_trips_through_walrus() {
+    let repo = TestRepo::new();
+    repo.write("data.bin", b"content recorded in the mapping\n");
+    repo.git(&["add", ".gitattributes", "data.bin"]);
+    let pointer = String::from_utf8(repo.git(&["show", ":data.bin"]).stdout).unwrap();
+    let oid = pointer
+        .lines()
+        .find_map(|line| line.strip_prefix("oid sha256:"))
+        .unwrap()
+        .to_string();
+
+    let stdout =
+        String::from_utf8(repo.cli(&["export-snapshot", "--with-pointers"]).stdout).unwrap();
+    assert!(stdout.contains("2 mapping entries and 1 pointers"), "{}", stdout);
+    let snapshot = stdout
+        .lines()
+        .find_map(|line| line.strip_prefix("Blob ID: "))
+        .unwrap()
+        .to_string();
+
+    std::fs::remove_file(repo.file(".git/walrus-mapping.json")).unwrap();
+    let stdout = String::from_utf8(repo.cli(&["import-snapshot", &snapshot]).stdout).unwrap();
+    assert!(stdout.contains("Added: 2"), "{}", stdout);
+    let mapping: serde_json::Value =
+        serde_json::from_slice(&repo.read(".git/walrus-mapping.json")).unwrap();
+    assert!(mapping["entries"][&oid]["blob_id"].as_str().unwrap().starts_with("fake-"));
+
+    let stdout = String::from_utf8(repo.cli(&["import-snapshot", &snapshot]).stdout).unwrap();
+    assert!(stdout.contains("Added: 0") && stdout.contains("Already present: 2"), "{}", stdout);
+}
+
+#[test]
+fn mapping_rebuild_recovers_inline_blob_ids_after_the_mapping_is_lost() {
+    let repo = TestRepo::new();
+    repo.write("data.bin", b"content whose mapping gets lost\n");
+    repo.git(&["add", ".gitattributes", "data.bin"]);
+    repo.git(&["commit", "-q", "-m", "Add data"]);
+    let pointer = String::from_utf8(repo.git(&["show", "HEAD:data.bin"]).stdout).unwrap();
+    let oid = pointer
+        .lines()
+        .find_map(|line| line.strip_prefix("oid sha256:"))
+        .unwrap()
+        .to_string();
+    let blob_id = pointer
+        .lines()
+        .find_map(|line| line.strip_prefix("ext-0-walrus "))
+        .unwrap()
+        .to_string();
+
+    // A pointer committed without an inline blob ID can't be recovered
+    let bare_oid = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+    let legacy = format!(
+        "version https://git-lfs.github.com/spec/v1\noid sha256:{}\nsize 11\n",
+        bare_oid
+    );
+    repo.write("legacy.txt", legacy.as_bytes());
+    repo.git(&["add", "legacy.txt"]);
+    repo.git(&["commit", "-q", "-m", "Add a legacy pointer"]);
+
+    std::fs::remove_file(repo.file(".git/walrus-mapping.json")).unwrap();
+    let stdout = String::from_utf8(repo.cli(&["mapping-rebuild"]).stdout).unwrap();
+    assert!(stdout.contains("Recovered: 1"), "{}", stdout);
+    assert!(stdout.contains("Without inline blob ID: 1"), "{}", stdout);
+    let warning = format!("legacy.txt - No inline ext-0-walrus blob ID for sha256 {}", bare_oid);
+    assert!(stdout.contains(&warning), "{}", stdout);
+    assert!(!stdout.contains("Previous mapping file"), "{}", stdout);
+    let mapping: serde_json::Value =
+        serde_json::from_slice(&repo.read(".git/walrus-mapping.json")).unwrap();
+    assert_eq!(mapping["entries"][&oid]["blob_id"], blob_id.as_str());
+
+    // Rebuilding over an existing mapping keeps a copy of it
+    let stdout = String::from_utf8(repo.cli(&["mapping-rebuild"]).stdout).unwrap();
+    assert!(stdout.contains("Previous mapping file:"), "{}", stdout);
+    assert!(repo.file(".git/walrus-mapping.json.bak").exists());
+}
+
+#[test]
+fn storage_diff_reports_only_content_new_at_head() {
+    let repo = TestRepo::new();
+    repo.write("base.bin", b"content already on the base branch\n");
+    repo.git(&["add", ".gitattributes", "base.bin"]);
+    repo.git(&["commit", "-q", "-m", "Base"]);
+    repo.write("copy.bin", b"content already on the base branch\n");
+    repo.write("asset.bin", b"a large asset added by the feature\n");
+    repo.git(&["add", "copy.bin", "asset.bin"]);
+    repo.git(&["commit", "-q", "-m", "Feature"]);
+
+    let output = repo.cli(&["storage-diff", "--json", "HEAD~1", "HEAD"]);
+    let diff: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let new_blobs = diff["new_blobs"].as_array().unwrap();
+    assert_eq!(new_blobs.len(), 1, "{}", diff);
+    assert_eq!(new_blobs[0]["path"], "asset.bin");
+    assert!(new_blobs[0]["blob_id"].as_str().unwrap().starts_with("fake-"));
+    assert_eq!(diff["new_bytes"], 35);
+    assert_eq!(diff["estimated_cost"], 0);
+
+    let stdout = String::from_utf8(repo.cli(&["storage-diff", "HEAD", "HEAD~1"]).stdout).unwrap();
+    assert!(stdout.contains("New blobs: 0"), "{}", stdout);
+    let output = repo.cli_command(&["storage-diff", "no-such-ref", "HEAD"], &[]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn configured_env_overrides_the_ambient_environment_of_walrus() {
+    let repo = TestRepo::new();
+    let pinned = repo.path.join("pinned-store");
+    repo.git(&["config", "lfs.walrus.env.fake-walrus-store", pinned.to_str().unwrap()]);
+    repo.write("data.bin", b"content for the pinned store\n");
+
+    repo.cli(&["clean", "--emit-pointer", "data.pointer", "data.bin"]);
+    assert_eq!(stored_blobs(&pinned), 1);
+    assert_eq!(stored_blobs(&repo.store), 0);
+
+    let stdout = String::from_utf8(repo.cli(&["config"]).stdout).unwrap();
+    let line = stdout.lines().find(|line| line.starts_with("lfs.walrus.env ")).unwrap();
+    assert!(line.contains(&format!("FAKE_WALRUS_STORE={}", pinned.display())), "{}", line);
+    assert!(line.contains("(git config (lfs.walrus.env.*))"), "{}", line);
+}
+
+#[test]
+fn check_worktree_reports_content_that_drifted_from_its_pointer() {
+    let repo = TestRepo::new();
+    repo.write("kept.bin", b"content as cleaned\n");
+    repo.write("edited.bin", b"content as cleaned, then edited\n");
+    repo.write("unsmudged.bin", b"content never checked out\n");
+    repo.git(&["add", ".gitattributes", "kept.bin", "edited.bin", "unsmudged.bin"]);
+    repo.cli(&["check-worktree"]);
+
+    repo.write("edited.bin", b"edited without the filter\n");
+    let pointer = repo.git(&["show", ":unsmudged.bin"]).stdout;
+    repo.write("unsmudged.bin", &pointer);
+
+    let output = repo.cli_command(&["check-worktree"], &[]);
+    assert!(!output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("✅ kept.bin"), "{}", stdout);
+    assert!(stdout.contains("❌ edited.bin"), "{}", stdout);
+    assert!(!stdout.contains("unsmudged.bin"), "{}", stdout);
+    assert!(stdout.contains("Not checked out: 1"), "{}", stdout);
+    assert!(stdout.contains("Differing: 1"), "{}", stdout);
+}
+
+#[test]
+fn orphans_lists_and_deletes_blobs_no_pointer_refers_to() {
+    let repo = TestRepo::new();
+    repo.write("kept.bin", b"content that stays\n");
+    repo.git(&["add", ".gitattributes", "kept.bin"]);
+    repo.git(&["commit", "-q", "-m", "Add kept"]);
+    // Stored and mapped, but never committed
+    repo.write("loose.bin", b"test");
+    repo.cli(&["clean", "--emit-pointer", "loose.pointer", "loose.bin"]);
+    let orphan = "fake-9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+    assert_eq!(stored_blobs(&repo.store), 2);
+
+    let stdout = String::from_utf8(repo.cli(&["orphans"]).stdout).unwrap();
+    assert!(stdout.contains(&format!("⚠️  {} - 4 B | Status: permanent", orphan)), "{}", stdout);
+    assert!(stdout.contains("Orphaned blobs: 1"), "{}", stdout);
+
+    let stdout = String::from_utf8(repo.cli(&["orphans", "--delete"]).stdout).unwrap();
+    assert!(stdout.contains(&format!("✅ {} - Deleted", orphan)), "{}", stdout);
+    assert_eq!(stored_blobs(&repo.store), 1);
+
+    let stdout = String::from_utf8(repo.cli(&["orphans"]).stdout).unwrap();
+    assert!(stdout.contains("Orphaned blobs: 0"), "{}", stdout);
+}
+
+#[test]
+fn delete_blob_refuses_referenced_blobs_and_scrubs_the_mapping() {
+    let repo = TestRepo::new();
+    repo.write("kept.bin", b"content that stays\n");
+    repo.write("dropped.bin", b"content that goes\n");
+    repo.git(&["add", ".gitattributes", "kept.bin", "dropped.bin"]);
+    let blob_id = |name: &str| {
+        let pointer = String::from_utf8(repo.git(&["show", &format!(":{name}")]).stdout).unwrap();
+        pointer
+            .lines()
+            .find_map(|line| line.strip_prefix("ext-0-walrus "))
+            .unwrap()
+            .to_string()
+    };
+    let dropped = blob_id("dropped.bin");
+    let mapped_blob_ids = || {
+        let mapping: serde_json::Value =
+            serde_json::from_slice(&repo.read(".git/walrus-mapping.json")).unwrap();
+        mapping["entries"]
+            .as_object()
+            .unwrap()
+            .values()
+            .map(|entry| entry["blob_id"].as_str().unwrap().to_string())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(mapped_blob_ids().iter().filter(|id| **id == dropped).count(), 2);
+
+    let refused = repo.cli_command(&["delete-blob", &dropped], &[]);
+    assert!(!refused.status.success());
+    assert!(String::from_utf8_lossy(&refused.stderr).contains("dropped.bin"));
+    assert_eq!(stored_blobs(&repo.store), 2);
+
+    let output = String::from_utf8(repo.cli(&["delete-blob", "--force", &dropped]).stdout).unwrap();
+    assert!(output.contains("Mapping entries removed: 2"), "{}", output);
+    assert_eq!(stored_blobs(&repo.store), 1);
+    let remaining = mapped_blob_ids();
+    assert!(!remaining.contains(&dropped));
+    assert!(remaining.contains(&blob_id("kept.bin")));
+}
+
+#[test]
+fn fsck_reports_missing_and_corrupt_objects() {
+    let repo = TestRepo::new();
+    repo.write("kept.bin", b"content kept on the branch\n");
+    repo.write("old.bin", b"content only in history\n");
+    repo.git(&["add", ".gitattributes", "kept.bin", "old.bin"]);
+    repo.git(&["commit", "-q", "-m", "Add files"]);
+    repo.git(&["rm", "-q", "old.bin"]);
+    repo.git(&["commit", "-q", "-m", "Remove old file"]);
+
+    let blob_id = |name: &str| {
+        let pointer = String::from_utf8(repo.git(&["show", &format!("HEAD~:{}", name)]).stdout)
+            .unwrap();
+        pointer
+            .lines()
+            .find_map(|line| line.strip_prefix("ext-0-walrus "))
+            .unwrap()
+            .to_string()
+    };
+    let stdout = String::from_utf8(repo.cli(&["fsck", "--all"]).stdout).unwrap();
+    assert!(stdout.contains("OK: 2"), "{}", stdout);
+
+    // An object only reachable from history is checked with --all
+    std::fs::remove_file(repo.store.join(blob_id("old.bin"))).unwrap();
+    repo.cli(&["fsck"]);
+    let output = repo.cli_command(&["fsck", "--all", "--jobs", "1"], &[]);
+    assert!(!output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("❌ old.bin - Missing blob"), "{}", stdout);
+
+    std::fs::write(repo.store.join(blob_id("kept.bin")), b"tampered\n").unwrap();
+    let output = repo.cli_command(&["fsck"], &[]);
+    assert!(!output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("❌ kept.bin - Corrupt blob"), "{}", stdout);
+    assert!(stdout.contains("Corrupt: 1"), "{}", stdout);
+}
+
+#[test]
+fn smudge_refuses_mismatched_content_unless_verification_is_off() {
+    let repo = TestRepo::new();
+    repo.write("a.bin", b"original content\n");
+    repo.git(&["add", ".gitattributes", "a.bin"]);
+    repo.git(&["commit", "-q", "-m", "Add a file"]);
+    let pointer = String::from_utf8(repo.git(&["show", "HEAD:a.bin"]).stdout).unwrap();
+    let blob_id = pointer
+        .lines()
+        .find_map(|line| line.strip_prefix("ext-0-walrus "))
+        .unwrap();
+    std::fs::write(repo.store.join(blob_id), b"transformed content\n").unwrap();
+    std::fs::remove_file(repo.file("a.bin")).unwrap();
+
+    let output = Command::new("git")
+        .args(["checkout", "--", "a.bin"])
+        .current_dir(&repo.path)
+        .env("FAKE_WALRUS_STORE", &repo.store)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("expected"));
+
+    let output = repo.git(&["-c", "lfs.walrus.verifysmudge=false", "checkout", "--", "a.bin"]);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("verification is disabled"));
+    assert_eq!(repo.read("a.bin"), b"transformed content\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn clean_stores_a_symlink_target_only_when_asked() {
+    let repo = TestRepo::new();
+    let content = b"content behind a symlink\n";
+    repo.write("target.bin", content);
+    std::os::unix::fs::symlink("target.bin", repo.file("link.bin")).unwrap();
+
+    let refused = repo.cli_command(&["clean", "link.bin"], &[]);
+    assert!(!refused.status.success());
+    assert!(String::from_utf8_lossy(&refused.stderr).contains("is a symlink"));
+    assert_eq!(stored_blobs(&repo.store), 0);
+
+    let pointer =
+        String::from_utf8(repo.cli(&["clean", "--dereference-symlinks", "link.bin"]).stdout)
+            .unwrap();
+    assert!(pointer.contains(&format!("size {}\n", content.len())), "{}", pointer);
+    assert_eq!(stored_blobs(&repo.store), 1);
+}
+
+#[test]
+fn oversized_content_is_stored_in_chunks_and_reassembled() {
+    let repo = TestRepo::new();
+    repo.git(&["config", "lfs.walrus.maxblobsize", "10"]);
+    let content = b"twenty-five bytes long!!\n";
+    repo.write("large.bin", content);
+    repo.git(&["add", ".gitattributes", "large.bin"]);
+    repo.git(&["commit", "-q", "-m", "Add large file"]);
+
+    let pointer = String::from_utf8(repo.git(&["show", "HEAD:large.bin"]).stdout).unwrap();
+    let manifest = pointer
+        .lines()
+        .find_map(|line| line.strip_prefix("ext-0-walrus chunked:"))
+        .unwrap_or_else(|| panic!("{}", pointer));
+    let sizes: Vec<&str> = manifest
+        .split(',')
+        .map(|chunk| chunk.split(':').nth(1).unwrap())
+        .collect();
+    assert_eq!(sizes, ["10", "10", "5"]);
+    assert_eq!(stored_blobs(&repo.store), 3);
+
+    std::fs::remove_file(repo.file("large.bin")).unwrap();
+    repo.git(&["checkout", "--", "large.bin"]);
+    assert_eq!(repo.read("large.bin"), content);
+    assert_eq!(repo.cli(&["cat", "large.bin"]).stdout, content);
+    repo.cli(&["fsck"]);
+
+    // A chunk swapped for other bytes of the same size is caught by its own hash
+    let first_chunk = manifest.split(',').next().unwrap().split(':').next().unwrap();
+    std::fs::write(repo.store.join(first_chunk), b"0123456789").unwrap();
+    let output = repo.cli_command(&["fsck"], &[]);
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains(&format!("Chunk 1 of 3 ({}) has sha256", first_chunk)), "{}", stdout);
+}
+
+#[test]
+fn verify_after_store_reads_every_store_back() {
+    let repo = TestRepo::new();
+    repo.git(&["config", "lfs.walrus.maxblobsize", "10"]);
+    repo.write("large.bin", b"twenty-five bytes long!!\n");
+    repo.write("small.bin", b"hello world");
+
+    let verified = |file: &str, args: &[&str]| {
+        let pointer = format!("{}.pointer", file);
+        let mut cli_args = vec!["--metrics", "metrics.json"];
+        cli_args.extend(args);
+        cli_args.extend(["clean", file, "--emit-pointer", &pointer]);
+        repo.cli(&cli_args);
+        let summary: serde_json::Value =
+            serde_json::from_slice(&repo.read("metrics.json")).unwrap();
+        summary["verify-after-store"]["count"].as_u64()
+    };
+    assert_eq!(verified("small.bin", &[]), None);
+    assert_eq!(verified("small.bin", &["--verify-after-store"]), Some(1));
+
+    // Chunked content is verified once, reassembled
+    repo.git(&["config", "lfs.walrus.verifyafterstore", "true"]);
+    assert_eq!(verified("large.bin", &[]), Some(1));
+}
+
+#[test]
+fn long_chunk_manifests_are_stored_as_their_own_blob() {
+    let repo = TestRepo::new();
+    repo.git(&["config", "lfs.walrus.maxblobsize", "1"]);
+    let content = b"abcde";
+    repo.write("many.bin", content);
+    repo.git(&["add", ".gitattributes", "many.bin"]);
+    repo.git(&["commit", "-q", "-m", "Add file of many chunks"]);
+
+    let pointer = String::from_utf8(repo.git(&["show", "HEAD:many.bin"]).stdout).unwrap();
+    assert!(pointer.contains("\next-0-walrus chunked-blob:fake-"), "{}", pointer);
+    // Five chunks and the manifest
+    assert_eq!(stored_blobs(&repo.store), 6);
+
+    std::fs::remove_file(repo.file("many.bin")).unwrap();
+    repo.git(&["checkout", "--", "many.bin"]);
+    assert_eq!(repo.read("many.bin"), content);
+    repo.cli(&["fsck"]);
+}
+
+#[test]
+fn each_network_records_its_blob_ids_in_its_own_mapping_file() {
+    let hello = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+    let repo = TestRepo::new();
+    repo.write("hello.bin", b"hello world");
+    let mapping = |name: &str| {
+        let path = repo.file(".git").join(name);
+        std::fs::read_to_string(&path).unwrap_or_default()
+    };
+
+    repo.cli(&["clean", "hello.bin", "--emit-pointer", "hello.pointer"]);
+    assert!(mapping("walrus-mapping.json").contains(hello));
+
+    repo.git(&["config", "lfs.walrus.network", "testnet"]);
+    repo.cli(&["--network", "mainnet", "clean", "hello.bin", "--emit-pointer", "hello.pointer"]);
+    assert!(mapping("walrus-mapping.mainnet.json").contains(hello));
+    assert!(mapping("walrus-mapping.testnet.json").is_empty());
+
+    let stdout = String::from_utf8(repo.cli(&["config"]).stdout).unwrap();
+    assert!(stdout.contains("walrus-mapping.testnet.json"), "{}", stdout);
+
+    // A name that isn't a plain word never becomes a path
+    repo.git(&["config", "lfs.walrus.network", "../elsewhere"]);
+    let output = repo.cli_command(&["config"], &[]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("lfs.walrus.network"));
+}
+
+#[test]
+fn lfsconfig_settings_apply_below_git_config() {
+    let repo = TestRepo::new();
+    repo.write(
+        ".lfsconfig",
+        b"[lfs \"walrus\"]\n\tmaxcleanbytes = 10\n\tdefaultepochs = 3\n\tsuipath = /tmp/not-sui\n\
+          \tproxy = http://proxy.example:3128\n",
+    );
+    repo.git(&["config", "lfs.walrus.defaultepochs", "7"]);
+
+    let stdout = String::from_utf8(repo.cli(&["config"]).stdout).unwrap();
+    let line = |key: &str| {
+        stdout
+            .lines()
+            .find(|line| line.starts_with(&format!("{} ", key)))
+            .unwrap_or_else(|| panic!("{} missing from:\n{}", key, stdout))
+            .to_string()
+    };
+    assert!(line("lfs.walrus.maxcleanbytes").ends_with("= 10  (.lfsconfig)"), "{}", stdout);
+    assert!(line("lfs.walrus.defaultepochs").ends_with("= 7  (git config (local))"));
+    // Machine-local settings are never taken from the shared file
+    assert!(line("lfs.walrus.suipath").ends_with("= sui  (default)"), "{}", stdout);
+    assert!(line("lfs.walrus.proxy").ends_with("(default)"), "{}", stdout);
+
+    repo.write("huge.bin", b"more than ten bytes\n");
+    let refused = repo.cli_command(&["clean", "huge.bin", "--emit-pointer", "huge.pointer"], &[]);
+    assert!(!refused.status.success());
+    assert!(String::from_utf8_lossy(&refused.stderr).contains("lfs.walrus.maxcleanbytes"));
+}
+
+#[test]
+fn malformed_git_config_booleans_are_reported_not_ignored() {
+    let repo = TestRepo::new();
+    repo.write(".lfsconfig", b"[lfs \"walrus\"]\n\tverifysmudge = true\n");
+    repo.git(&["config", "lfs.walrus.verifysmudge", "flase"]);
+
+    let output = repo.cli_command(&["config"], &[]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("lfs.walrus.verifysmudge \"flase\" is not a boolean"),
+        "{}",
+        stderr
+    );
+
+    // Filters warn and carry on with the default
+    repo.git(&["config", "lfs.walrus.prefetch", "maybee"]);
+    let output = repo.cli_command(&["smudge", "missing.bin"], &[]);
+    assert!(String::from_utf8_lossy(&output.stderr).contains("\"maybee\" is not a boolean"));
+}
+
+#[test]
+fn install_check_reports_and_fixes_missing_git_config() {
+    let repo = TestRepo::new();
+    repo.git(&["config", "lfs.customtransfer.walrus.path", "/usr/bin/some-other-agent"]);
+
+    let output = repo.cli_command(&["install-check"], &[]);
+    assert!(!output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    // The test repository uses a direct filter, which stands in for the LFS extension
+    assert!(stdout.contains("✅ filter.walrus.clean = "), "{}", stdout);
+    assert!(stdout.contains("❌ lfs.standalonetransferagent is not set"), "{}", stdout);
+    assert!(stdout.contains("⚠️  lfs.customtransfer.walrus.path = /usr/bin/some-other-agent"), "{}", stdout);
+
+    repo.cli(&["install-check", "--fix"]);
+    let stdout = String::from_utf8(repo.cli(&["install-check"]).stdout).unwrap();
+    assert!(stdout.contains("Missing: 0") && stdout.contains("Pointing elsewhere: 0"), "{}", stdout);
+    let agent = repo.git(&["config", "lfs.customtransfer.walrus.path"]).stdout;
+    assert_eq!(
+        Path::new(String::from_utf8(agent).unwrap().trim()).canonicalize().unwrap(),
+        Path::new(BIN).canonicalize().unwrap()
+    );
+    let args = String::from_utf8(repo.git(&["config", "lfs.customtransfer.walrus.args"]).stdout).unwrap();
+    assert_eq!(args.trim(), format!("--walrus-path {} transfer", FAKE_WALRUS));
+}
+
+#[test]
+fn config_shows_each_setting_with_its_source() {
+    let repo = TestRepo::new();
+    repo.git(&["config", "lfs.walrus.defaultepochs", "7"]);
+
+    let output = repo.cli_command(&["config"], &[("LFS_WALRUS_OFFLINE", "1")]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let line = |key: &str| {
+        stdout
+            .lines()
+            .find(|line| line.starts_with(&format!("{} ", key)))
+            .unwrap_or_else(|| panic!("{} missing from:\n{}", key, stdout))
+            .to_string()
+    };
+    assert!(line("lfs.walrus.defaultepochs").ends_with("= 7  (git config (local))"));
+    assert!(line("lfs.walrus.offline").ends_with("= true  (environment (LFS_WALRUS_OFFLINE))"));
+    assert!(line("lfs.walrus.maxblobsize").ends_with("(default)"));
+    assert!(line("walrus path").ends_with("(command line (--walrus-path))"));
+}